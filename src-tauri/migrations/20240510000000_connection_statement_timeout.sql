@@ -0,0 +1,2 @@
+-- Default statement timeout per connection, NULL means no timeout
+ALTER TABLE connections ADD COLUMN statement_timeout_ms INTEGER;
@@ -0,0 +1,34 @@
+-- Tie connections to their project so deleting a project removes its connections.
+-- SQLite cannot add a foreign key to an existing table, so the table is rebuilt.
+-- Connections whose project no longer exists are unreachable and are dropped.
+CREATE TABLE connections_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    connection_name TEXT NOT NULL,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    db_type TEXT NOT NULL,
+    encrypted_host BLOB NOT NULL,
+    encrypted_port BLOB NOT NULL,
+    encrypted_username BLOB NOT NULL,
+    encrypted_password BLOB NOT NULL,
+    encrypted_database BLOB,  -- Can be NULL if not applicable
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    updated_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    statement_timeout_ms INTEGER
+);
+
+INSERT INTO connections_new (
+    id, connection_name, project_id, db_type,
+    encrypted_host, encrypted_port, encrypted_username, encrypted_password, encrypted_database,
+    created_at, updated_at, statement_timeout_ms
+)
+SELECT
+    id, connection_name, project_id, db_type,
+    encrypted_host, encrypted_port, encrypted_username, encrypted_password, encrypted_database,
+    created_at, updated_at, statement_timeout_ms
+FROM connections
+WHERE project_id IN (SELECT id FROM projects);
+
+DROP TABLE connections;
+ALTER TABLE connections_new RENAME TO connections;
+
+CREATE INDEX IF NOT EXISTS idx_connections_project_id ON connections(project_id);
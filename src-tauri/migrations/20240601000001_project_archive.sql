@@ -0,0 +1,2 @@
+-- Archived projects are hidden from the project list but keep their connections
+ALTER TABLE projects ADD COLUMN archived_at INTEGER;
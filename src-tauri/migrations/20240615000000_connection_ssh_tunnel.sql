@@ -0,0 +1,2 @@
+-- SSH tunnel settings, stored as encrypted JSON; NULL for direct connections
+ALTER TABLE connections ADD COLUMN encrypted_ssh_tunnel BLOB;
@@ -0,0 +1,5 @@
+-- TLS settings per connection; a NULL mode keeps the driver defaults
+ALTER TABLE connections ADD COLUMN tls_mode TEXT;
+ALTER TABLE connections ADD COLUMN tls_ca_cert_path TEXT;
+ALTER TABLE connections ADD COLUMN tls_client_cert_path TEXT;
+ALTER TABLE connections ADD COLUMN tls_client_key_path TEXT;
@@ -0,0 +1,2 @@
+-- Extra driver options as a JSON object of URI query parameters, e.g. MongoDB's authSource
+ALTER TABLE connections ADD COLUMN options TEXT;
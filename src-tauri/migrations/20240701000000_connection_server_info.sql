@@ -0,0 +1,3 @@
+-- What the last successful connection test learned about the server, as a JSON object
+ALTER TABLE connections ADD COLUMN server_info TEXT;
+ALTER TABLE connections ADD COLUMN server_checked_at INTEGER;
@@ -0,0 +1,2 @@
+-- Whether Dewey refuses writes on the connection's sessions
+ALTER TABLE connections ADD COLUMN read_only BOOLEAN NOT NULL DEFAULT 0;
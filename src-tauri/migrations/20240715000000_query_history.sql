@@ -0,0 +1,32 @@
+-- Every statement run on a connection, kept so it can be found and run again.
+-- The FTS5 index covers the statement text and is kept in sync by triggers.
+CREATE TABLE query_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    connection_id INTEGER NOT NULL REFERENCES connections(id) ON DELETE CASCADE,
+    statement TEXT NOT NULL,
+    params TEXT,  -- JSON array of bound parameters, NULL when there were none
+    started_at INTEGER NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    rows_affected INTEGER,
+    rows_returned INTEGER,
+    success BOOLEAN NOT NULL,
+    error_category TEXT,  -- JSON error category, NULL when the statement succeeded
+    error_message TEXT
+);
+
+CREATE INDEX idx_query_history_connection ON query_history(connection_id, started_at);
+CREATE INDEX idx_query_history_started_at ON query_history(started_at);
+
+CREATE VIRTUAL TABLE query_history_fts USING fts5(
+    statement,
+    content = 'query_history',
+    content_rowid = 'id'
+);
+
+CREATE TRIGGER query_history_fts_insert AFTER INSERT ON query_history BEGIN
+    INSERT INTO query_history_fts (rowid, statement) VALUES (new.id, new.statement);
+END;
+
+CREATE TRIGGER query_history_fts_delete AFTER DELETE ON query_history BEGIN
+    INSERT INTO query_history_fts (query_history_fts, rowid, statement) VALUES ('delete', old.id, old.statement);
+END;
@@ -0,0 +1,18 @@
+-- Queries saved to a project, filed in slash-separated folders and tagged.
+-- Tags and parameters are JSON arrays; the default connection is cleared when it is deleted.
+CREATE TABLE saved_queries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    folder TEXT NOT NULL DEFAULT '',  -- Empty for the project's top level
+    name TEXT NOT NULL,
+    description TEXT,
+    statement TEXT NOT NULL,
+    tags TEXT NOT NULL DEFAULT '[]',
+    connection_id INTEGER REFERENCES connections(id) ON DELETE SET NULL,
+    parameters TEXT NOT NULL DEFAULT '[]',
+    created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    updated_at INTEGER NOT NULL DEFAULT (unixepoch()),
+    UNIQUE (project_id, folder, name)
+);
+
+CREATE INDEX idx_saved_queries_connection ON saved_queries(connection_id);
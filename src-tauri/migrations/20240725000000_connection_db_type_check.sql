@@ -0,0 +1,16 @@
+-- Store database types in the lowercase form Dewey reads them as.
+-- Rows with a type Dewey does not know are left as they are and skipped when listed;
+-- new rows and updates must use a known type.
+UPDATE connections SET db_type = lower(trim(db_type));
+
+CREATE TRIGGER connections_db_type_insert BEFORE INSERT ON connections
+WHEN new.db_type NOT IN ('postgres', 'mysql', 'sqlite', 'mongodb', 'mssql', 'redis', 'clickhouse', 'duckdb')
+BEGIN
+    SELECT RAISE(ABORT, 'unknown database type');
+END;
+
+CREATE TRIGGER connections_db_type_update BEFORE UPDATE OF db_type ON connections
+WHEN new.db_type NOT IN ('postgres', 'mysql', 'sqlite', 'mongodb', 'mssql', 'redis', 'clickhouse', 'duckdb')
+BEGIN
+    SELECT RAISE(ABORT, 'unknown database type');
+END;
@@ -0,0 +1,167 @@
+use crate::commands::database::{connection_not_found, load_connection};
+use crate::error::categories::{DatabaseSubcategory, ErrorCategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::database::uri::{self, ParsedUri};
+use crate::services::storage::repositories::connections::{
+    Connection, ConnectionRepository, ConnectionUpdate,
+};
+use crate::state::AppState;
+use snafu::ResultExt;
+use tauri::State;
+use tracing::info;
+
+/// Command to fetch a single saved connection
+///
+/// # Errors
+/// Returns an error if the connection could not be read or does not exist
+#[tauri::command]
+pub async fn get_connection(
+    connection_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<Connection> {
+    load_connection(&state, connection_id).await
+}
+
+/// Command to change a saved connection
+///
+/// Only the fields present in `update` are changed. Changing anything other than
+/// the name closes the connection's live session, so the next use reconnects
+/// with the new settings.
+///
+/// # Errors
+/// Returns an error if the connection does not exist or could not be saved
+#[tauri::command]
+pub async fn update_connection(
+    connection_id: i64,
+    update: ConnectionUpdate,
+    state: State<'_, AppState>,
+) -> AppResult<Connection> {
+    info!("Updating connection: {}", connection_id);
+
+    let connection_repo = ConnectionRepository::new(state.db.clone());
+    let updated = connection_repo
+        .update(connection_id, &update)
+        .await
+        .context(AppError::new(
+            format!("Failed to update connection {}", connection_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?;
+    if !updated {
+        return Err(connection_not_found(connection_id));
+    }
+
+    // Sessions of a read-only connection are opened differently, so reopen them too
+    if update.changes_target() || update.read_only.is_some() {
+        close_sessions(&state, connection_id).await;
+    }
+    load_connection(&state, connection_id).await
+}
+
+/// Command to rename a saved connection
+///
+/// # Errors
+/// Returns an error if the connection does not exist or could not be saved
+#[tauri::command]
+pub async fn rename_connection(
+    connection_id: i64,
+    connection_name: String,
+    state: State<'_, AppState>,
+) -> AppResult<Connection> {
+    let update = ConnectionUpdate {
+        connection_name: Some(connection_name),
+        ..ConnectionUpdate::default()
+    };
+    update_connection(connection_id, update, state).await
+}
+
+/// Command to copy a saved connection within its project
+///
+/// The copy is named `connection_name`, or the original name with " (copy)" appended.
+///
+/// # Errors
+/// Returns an error if the connection does not exist or could not be copied
+#[tauri::command]
+pub async fn duplicate_connection(
+    connection_id: i64,
+    connection_name: Option<String>,
+    state: State<'_, AppState>,
+) -> AppResult<Connection> {
+    info!("Duplicating connection: {}", connection_id);
+
+    let connection_repo = ConnectionRepository::new(state.db.clone());
+    let copy_id = connection_repo
+        .duplicate(connection_id, connection_name.as_deref())
+        .await
+        .context(AppError::new(
+            format!("Failed to duplicate connection {}", connection_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?
+        .ok_or_else(|| connection_not_found(connection_id))?;
+
+    load_connection(&state, copy_id).await
+}
+
+/// Command to delete a saved connection, closing its live session first
+///
+/// # Errors
+/// Returns an error if the connection does not exist or could not be deleted
+#[tauri::command]
+pub async fn delete_connection(
+    connection_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    info!("Deleting connection: {}", connection_id);
+
+    close_sessions(&state, connection_id).await;
+
+    let connection_repo = ConnectionRepository::new(state.db.clone());
+    let deleted = connection_repo
+        .delete(connection_id)
+        .await
+        .context(AppError::new(
+            format!("Failed to delete connection {}", connection_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?;
+    if deleted {
+        Ok(())
+    } else {
+        Err(connection_not_found(connection_id))
+    }
+}
+
+/// Command to read connection settings from a pasted connection URI
+///
+/// The settings are not saved; query parameters without an equivalent setting are listed
+/// in `ignored_parameters`.
+///
+/// # Errors
+/// Returns an error if the URI is malformed or uses an unsupported scheme
+#[tauri::command]
+pub async fn parse_connection_uri(uri: String) -> AppResult<ParsedUri> {
+    uri::parse(&uri)
+}
+
+/// Command to build a connection URI for a saved connection
+///
+/// The password is replaced with a placeholder unless `include_password` is set.
+///
+/// # Errors
+/// Returns an error if the connection does not exist or its database type has no URI form
+#[tauri::command]
+pub async fn get_connection_uri(
+    connection_id: i64,
+    include_password: Option<bool>,
+    state: State<'_, AppState>,
+) -> AppResult<String> {
+    let connection = load_connection(&state, connection_id).await?;
+    uri::to_uri(&connection, include_password.unwrap_or(false))
+}
+
+/// Stop the connection's running queries and close its live session
+pub(crate) async fn close_sessions(state: &AppState, connection_id: i64) {
+    state.queries.cancel_for_connection(connection_id).await;
+    state.connection_manager.close_all_for(connection_id).await;
+}
@@ -1,30 +1,160 @@
-use crate::error::categories::{DatabaseSubcategory, ErrorCategory};
-use crate::error::{AppError, ErrorSeverity};
-use crate::services::database;
+use crate::error::categories::{ConnectionSubcategory, DatabaseSubcategory, ErrorCategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::database::diagnostics::{self, ConnectionReport};
+use crate::services::database::pool::SessionInfo;
+use crate::services::storage::repositories::connections::{
+    Connection, ConnectionRepository, DbType, NewConnection, SshTunnel, TlsSettings,
+};
+use std::collections::BTreeMap;
+use crate::state::AppState;
+use snafu::ResultExt;
+use tauri::State;
+use tracing::{info, warn};
 
+/// Command to test the settings of a connection that may not be saved yet
+///
+/// A failed test is not an error: the report names the layer that failed and why.
+#[allow(clippy::too_many_arguments)]
 #[tauri::command]
 pub async fn test_connection(
-    db_type: String,
+    db_type: DbType,
     host: String,
     port: String,
     username: String,
     password: String,
     database: String,
-) -> Result<(), AppError> {
-    database::test_connection(
-        &db_type,
-        &host,
-        &port,
-        &username,
-        &password,
-        &database,
+    ssh_tunnel: Option<SshTunnel>,
+    tls: Option<TlsSettings>,
+    options: Option<BTreeMap<String, String>>,
+) -> AppResult<ConnectionReport> {
+    let connection = NewConnection {
+        connection_name: String::new(),
+        project_id: None,
+        db_type,
+        host,
+        port,
+        username,
+        password,
+        database,
+        statement_timeout_ms: None,
+        read_only: false,
+        ssh_tunnel,
+        tls,
+        options: options.unwrap_or_default(),
+    };
+    Ok(diagnostics::diagnose(&connection).await)
+}
+
+/// Command to test a saved connection with the settings stored for it
+///
+/// A failed test is not an error, as for `test_connection`. What a successful test
+/// learned about the server is recorded on the connection.
+///
+/// # Errors
+/// Returns an error if the connection does not exist or could not be loaded
+#[tauri::command]
+pub async fn test_saved_connection(connection_id: i64, state: State<'_, AppState>) -> AppResult<ConnectionReport> {
+    info!("Testing saved connection: {}", connection_id);
+
+    let saved = load_connection(&state, connection_id).await?;
+    let connection = NewConnection {
+        connection_name: saved.connection_name,
+        project_id: Some(saved.project_id),
+        db_type: saved.db_type,
+        host: saved.host,
+        port: saved.port,
+        username: saved.username,
+        password: saved.password,
+        database: saved.database,
+        statement_timeout_ms: saved.statement_timeout_ms,
+        read_only: saved.read_only,
+        ssh_tunnel: saved.ssh_tunnel,
+        tls: saved.tls,
+        options: saved.options,
+    };
+    let report = diagnostics::diagnose(&connection).await;
+
+    if let Some(server) = &report.server {
+        let connection_repo = ConnectionRepository::new(state.db.clone());
+        // The test itself succeeded, so failing to record its findings is only logged
+        if let Err(e) = connection_repo.set_server_info(connection_id, server).await {
+            warn!("Failed to record server info for connection {}: {}", connection_id, e);
+        }
+    }
+    Ok(report)
+}
+
+/// The error reported for a saved connection that does not exist
+pub(crate) fn connection_not_found(connection_id: i64) -> AppError {
+    AppError::new(
+        format!("Connection {} does not exist", connection_id),
+        ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+        ErrorSeverity::Error,
     )
-    .await
-    .map_err(|msg| {
-        AppError::new(
-            msg,
-            ErrorCategory::Database(DatabaseSubcategory::ConnectionFailed),
+}
+
+/// Load a saved connection, failing if it does not exist
+///
+/// # Errors
+/// Returns an error if the connection could not be read or does not exist
+pub(crate) async fn load_connection(
+    state: &AppState,
+    connection_id: i64,
+) -> AppResult<Connection> {
+    let connection_repo = ConnectionRepository::new(state.db.clone());
+
+    connection_repo
+        .get_by_id(connection_id)
+        .await
+        .context(AppError::new(
+            format!("Failed to load connection {}", connection_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
             ErrorSeverity::Error,
-        )
-    })
+        ))?
+        .ok_or_else(|| connection_not_found(connection_id))
+}
+
+/// Command to open a live session for a saved connection
+///
+/// Opening an already open connection reuses its pool and adds a reference.
+///
+/// # Errors
+/// Returns an error if the connection does not exist or the server could not be reached
+#[tauri::command]
+pub async fn open_connection(
+    connection_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<SessionInfo> {
+    info!("Opening connection: {}", connection_id);
+
+    let connection = load_connection(&state, connection_id).await?;
+    state.connection_manager.open(&connection).await
+}
+
+/// Command to release a live session for a saved connection
+///
+/// Returns the session if other references keep it open, or `None` once it is closed.
+///
+/// # Errors
+/// Returns an error if the connection has no open session
+#[tauri::command]
+pub async fn close_connection(
+    connection_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<Option<SessionInfo>> {
+    info!("Closing connection: {}", connection_id);
+
+    let remaining = state.connection_manager.close(connection_id).await?;
+    if remaining.is_none() {
+        state.queries.cancel_for_connection(connection_id).await;
+    }
+    Ok(remaining)
+}
+
+/// Command to list all open sessions
+#[tauri::command]
+pub async fn list_open_connections(
+    state: State<'_, AppState>,
+) -> AppResult<Vec<SessionInfo>> {
+    Ok(state.connection_manager.list().await)
 }
@@ -0,0 +1,161 @@
+use crate::commands::database::load_connection;
+use crate::commands::query::statement_timeout;
+use crate::error::AppResult;
+use crate::services::database::cursor::QueryHandle;
+use crate::services::database::documents::{
+    self, CollectionInfo, DocumentSet, FindRequest, SchemaSample,
+};
+use crate::services::database::pool::DatabasePool;
+use crate::services::database::read_only;
+use crate::state::AppState;
+use serde_json::Value as JsonValue;
+use tauri::State;
+use tracing::info;
+
+/// Command to list the databases on a MongoDB connection
+///
+/// # Errors
+/// Returns an error if the connection could not be opened or is not a MongoDB connection
+#[tauri::command]
+pub async fn list_mongo_databases(
+    connection_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<String>> {
+    let pool = acquire(&state, connection_id).await?;
+    documents::list_databases(documents::client(&pool)?).await
+}
+
+/// Command to list the collections and views in a MongoDB database
+///
+/// # Errors
+/// Returns an error if the connection could not be opened or is not a MongoDB connection
+#[tauri::command]
+pub async fn list_collections(
+    connection_id: i64,
+    database: String,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<CollectionInfo>> {
+    let pool = acquire(&state, connection_id).await?;
+    documents::list_collections(documents::client(&pool)?, &database).await
+}
+
+/// Command to infer a collection's fields from a sample of its documents
+///
+/// # Errors
+/// Returns an error if the connection could not be opened or the sample failed
+#[tauri::command]
+pub async fn infer_collection_schema(
+    connection_id: i64,
+    database: String,
+    collection: String,
+    sample_size: Option<u32>,
+    state: State<'_, AppState>,
+) -> AppResult<SchemaSample> {
+    info!("Sampling {}.{} on connection: {}", database, collection, connection_id);
+
+    let pool = acquire(&state, connection_id).await?;
+    documents::infer_schema(documents::client(&pool)?, &database, &collection, sample_size).await
+}
+
+/// Command to run a `find` query against a collection
+///
+/// `filter`, `projection` and `sort` are Extended JSON objects. Pass a handle from
+/// `reserve_query_handle` as `query_handle` to be able to cancel the query.
+///
+/// # Errors
+/// Returns an error if the connection could not be opened, the JSON is malformed,
+/// or the query failed, was cancelled or timed out
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn find_documents(
+    connection_id: i64,
+    database: String,
+    collection: String,
+    filter: Option<JsonValue>,
+    projection: Option<JsonValue>,
+    sort: Option<JsonValue>,
+    skip: Option<u64>,
+    limit: Option<usize>,
+    query_handle: Option<QueryHandle>,
+    state: State<'_, AppState>,
+) -> AppResult<DocumentSet> {
+    info!("Finding documents in {}.{} on connection: {}", database, collection, connection_id);
+
+    let connection = load_connection(&state, connection_id).await?;
+    let pool = state.connection_manager.acquire(&connection).await?;
+    let client = documents::client(&pool)?;
+    let timeout = statement_timeout(&connection);
+
+    let (handle, slot) = state.queries.track(connection_id, query_handle).await?;
+    let request = FindRequest {
+        filter,
+        projection,
+        sort,
+        skip,
+        limit,
+    };
+    let result = documents::find(
+        client,
+        &database,
+        &collection,
+        request,
+        operation_comment(handle),
+        &slot,
+        timeout,
+    )
+    .await;
+    state.queries.untrack(handle).await;
+    result
+}
+
+/// Command to run an aggregation pipeline against a collection
+///
+/// Each stage is an Extended JSON object. Cancellation works as for `find_documents`.
+/// On a read-only connection, pipelines ending in `$out` or `$merge` are refused.
+///
+/// # Errors
+/// Returns an error if the connection could not be opened, the JSON is malformed,
+/// the pipeline writes on a read-only connection, or it failed, was cancelled or timed out
+#[tauri::command]
+pub async fn aggregate_documents(
+    connection_id: i64,
+    database: String,
+    collection: String,
+    pipeline: Vec<JsonValue>,
+    limit: Option<usize>,
+    query_handle: Option<QueryHandle>,
+    state: State<'_, AppState>,
+) -> AppResult<DocumentSet> {
+    info!("Aggregating {}.{} on connection: {}", database, collection, connection_id);
+
+    let connection = load_connection(&state, connection_id).await?;
+    read_only::check_pipeline(&connection, &pipeline)?;
+    let pool = state.connection_manager.acquire(&connection).await?;
+    let client = documents::client(&pool)?;
+    let timeout = statement_timeout(&connection);
+
+    let (handle, slot) = state.queries.track(connection_id, query_handle).await?;
+    let result = documents::aggregate(
+        client,
+        &database,
+        &collection,
+        pipeline,
+        limit,
+        operation_comment(handle),
+        &slot,
+        timeout,
+    )
+    .await;
+    state.queries.untrack(handle).await;
+    result
+}
+
+async fn acquire(state: &AppState, connection_id: i64) -> AppResult<DatabasePool> {
+    let connection = load_connection(state, connection_id).await?;
+    state.connection_manager.acquire(&connection).await
+}
+
+/// The comment a tracked operation is tagged with, so `killOp` can find it
+fn operation_comment(handle: QueryHandle) -> String {
+    format!("dewey:query:{}", handle)
+}
@@ -0,0 +1,108 @@
+use crate::commands::database::load_connection;
+use crate::commands::history;
+use crate::commands::query::statement_timeout;
+use crate::error::categories::{DatabaseSubcategory, ErrorCategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::database::cursor::QueryHandle;
+use crate::services::database::edit::{self, EditResult, EditStatement, RowEdit};
+use crate::services::database::pool::DatabasePool;
+use crate::services::database::read_only;
+use crate::services::introspection;
+use crate::services::storage::repositories::connections::Connection;
+use crate::services::storage::repositories::query_history::NewHistoryEntry;
+use crate::state::AppState;
+use chrono::Utc;
+use tauri::State;
+use tracing::info;
+
+/// Command to generate the statements that would apply edits made to a table's rows, without running them
+///
+/// # Errors
+/// Returns an error if the connection could not be opened, the table does not exist
+/// or has no primary key, or an edit does not match its columns
+#[tauri::command]
+pub async fn preview_table_edits(
+    connection_id: i64,
+    schema: String,
+    table: String,
+    edits: Vec<RowEdit>,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<EditStatement>> {
+    info!("Previewing {} edits to {}.{} on connection {}", edits.len(), schema, table, connection_id);
+
+    let (_, _, statements) = plan(&state, connection_id, &schema, &table, &edits).await?;
+    Ok(statements)
+}
+
+/// Command to apply edits made to a table's rows in a single transaction
+///
+/// Each edit becomes the statement `preview_table_edits` shows. Updates and deletes
+/// check the original values they were given, so if any row was changed or deleted
+/// since it was read, nothing is applied. Cancel the transaction with `cancel_query`
+/// and a handle from `reserve_query_handle`. Applied statements are recorded in the
+/// query history, each with its own duration.
+///
+/// # Errors
+/// Returns an error if the connection is read-only or could not be opened, the edits
+/// could not be planned, a statement failed, or a row no longer matched its original values
+#[tauri::command]
+pub async fn apply_table_edits(
+    connection_id: i64,
+    schema: String,
+    table: String,
+    edits: Vec<RowEdit>,
+    query_handle: Option<QueryHandle>,
+    state: State<'_, AppState>,
+) -> AppResult<EditResult> {
+    info!("Applying {} edits to {}.{} on connection {}", edits.len(), schema, table, connection_id);
+
+    let (connection, pool, statements) = plan(&state, connection_id, &schema, &table, &edits).await?;
+    for statement in &statements {
+        read_only::check_statement(&connection, &statement.sql)?;
+    }
+
+    let (handle, slot) = state.queries.track(connection_id, query_handle).await?;
+    let started_at = Utc::now().timestamp();
+    let result = edit::apply(&pool, statements, &slot, statement_timeout(&connection)).await;
+    state.queries.untrack(handle).await;
+
+    if let Ok(result) = &result {
+        for (statement, duration_ms) in result.statements.iter().zip(&result.statement_durations_ms) {
+            history::record(
+                state.db.clone(),
+                NewHistoryEntry {
+                    connection_id,
+                    statement: statement.sql.clone(),
+                    params: statement.params.clone(),
+                    started_at,
+                    duration_ms: *duration_ms as i64,
+                    rows_affected: Some(1),
+                    rows_returned: None,
+                    error: None,
+                },
+            );
+        }
+    }
+    result
+}
+
+/// Look up the table through introspection and generate the statements for the edits
+async fn plan(
+    state: &AppState,
+    connection_id: i64,
+    schema: &str,
+    table: &str,
+    edits: &[RowEdit],
+) -> AppResult<(Connection, DatabasePool, Vec<EditStatement>)> {
+    let connection = load_connection(state, connection_id).await?;
+    let pool = state.connection_manager.acquire(&connection).await?;
+    let info = introspection::table(&pool, schema, table).await?.ok_or_else(|| {
+        AppError::new(
+            format!("Table {}.{} was not found", schema, table),
+            ErrorCategory::Database(DatabaseSubcategory::NotFound),
+            ErrorSeverity::Error,
+        )
+    })?;
+    let statements = edit::statements(pool.db_type(), schema, &info, edits)?;
+    Ok((connection, pool, statements))
+}
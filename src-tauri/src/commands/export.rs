@@ -0,0 +1,98 @@
+use crate::commands::database::load_connection;
+use crate::commands::history;
+use crate::commands::query::statement_timeout;
+use crate::error::AppResult;
+use crate::services::database::cursor::QueryHandle;
+use crate::services::database::read_only;
+use crate::services::export::{self, ExportEvent, ExportFormat, ExportJob, ExportSource, SqlOptions};
+use crate::services::storage::repositories::query_history::NewHistoryEntry;
+use crate::state::AppState;
+use chrono::Utc;
+use std::path::PathBuf;
+use std::time::Instant;
+use tauri::ipc::Channel;
+use tauri::State;
+use tracing::{info, warn};
+
+/// Command to export the results of a statement, or a whole table, to a file
+///
+/// Rows are streamed to `path` a page at a time and progress is reported on `on_event`
+/// after every page. Cancel the export with `cancel_query` and the returned handle. SQL
+/// exports of a table insert into a table of the same name unless another is chosen.
+/// The statement is recorded in the query history once the export finishes or fails.
+///
+/// # Errors
+/// Returns an error if the connection could not be opened or does not speak SQL, the
+/// statement would write on a read-only connection, the format's options are invalid or
+/// the file could not be created
+#[tauri::command]
+pub async fn start_export(
+    connection_id: i64,
+    source: ExportSource,
+    format: ExportFormat,
+    path: PathBuf,
+    on_event: Channel<ExportEvent>,
+    state: State<'_, AppState>,
+) -> AppResult<QueryHandle> {
+    info!("Exporting from connection {} to {}", connection_id, path.display());
+
+    let connection = load_connection(&state, connection_id).await?;
+    let (sql, params) = source.statement(connection.db_type);
+    read_only::check_statement(&connection, &sql)?;
+    let format = match (format, &source) {
+        (ExportFormat::Sql(options), ExportSource::Table { schema, table }) if options.table.is_none() => {
+            ExportFormat::Sql(SqlOptions {
+                table: Some(table.clone()),
+                schema: options.schema.or_else(|| schema.clone()),
+                ..options
+            })
+        }
+        (format, _) => format,
+    };
+    let pool = state.connection_manager.acquire(&connection).await?;
+
+    let db = state.db.clone();
+    let entry = NewHistoryEntry {
+        connection_id,
+        statement: sql.clone(),
+        params: params.clone(),
+        started_at: Utc::now().timestamp(),
+        duration_ms: 0,
+        rows_affected: None,
+        rows_returned: None,
+        error: None,
+    };
+    let started = Instant::now();
+    let job = ExportJob {
+        connection_id,
+        sql,
+        params,
+        timeout: statement_timeout(&connection),
+        format,
+        path,
+    };
+
+    export::start(&state.queries, state.connection_manager.clone(), pool, job, move |event| {
+        match &event {
+            ExportEvent::Done { rows_written, duration_ms, .. } => {
+                history::record(db.clone(), NewHistoryEntry {
+                    duration_ms: *duration_ms as i64,
+                    rows_returned: Some(*rows_written as i64),
+                    ..entry.clone()
+                });
+            }
+            ExportEvent::Error { error, .. } => {
+                history::record(db.clone(), NewHistoryEntry {
+                    duration_ms: started.elapsed().as_millis() as i64,
+                    error: Some((error.category, error.message.clone())),
+                    ..entry.clone()
+                });
+            }
+            ExportEvent::Progress { .. } | ExportEvent::Cancelled { .. } => {}
+        }
+        if let Err(e) = on_event.send(event) {
+            warn!("Failed to deliver export event: {}", e);
+        }
+    })
+    .await
+}
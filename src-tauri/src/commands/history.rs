@@ -0,0 +1,147 @@
+use crate::commands::query::run_statement;
+use crate::constants;
+use crate::error::categories::{DatabaseSubcategory, ErrorCategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::database::cursor::QueryHandle;
+use crate::services::database::query::ResultSet;
+use crate::services::storage::repositories::query_history::{
+    HistoryEntry, HistoryFilter, NewHistoryEntry, QueryHistoryRepository,
+};
+use crate::state::AppState;
+use chrono::Utc;
+use snafu::ResultExt;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use tauri::State;
+use tracing::{info, warn};
+
+/// Record a statement in the query history without holding up its caller
+///
+/// The statement has already run, so failing to record it is only logged.
+pub(crate) fn record(db: Arc<SqlitePool>, entry: NewHistoryEntry) {
+    tokio::spawn(async move {
+        if let Err(e) = QueryHistoryRepository::new(db).record(&entry).await {
+            warn!("Failed to record query history for connection {}: {}", entry.connection_id, e);
+        }
+    });
+}
+
+async fn list_history(
+    state: &AppState,
+    filter: &HistoryFilter,
+    limit: Option<u32>,
+) -> AppResult<Vec<HistoryEntry>> {
+    let history_repo = QueryHistoryRepository::new(state.db.clone());
+    let limit = limit
+        .unwrap_or(constants::history::DEFAULT_LIMIT)
+        .clamp(1, constants::history::MAX_LIMIT);
+
+    history_repo.list(filter, limit).await.context(AppError::new(
+        "Failed to read query history".to_string(),
+        ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+        ErrorSeverity::Error,
+    ))
+}
+
+/// Command to list the statements run on a connection, most recent first
+///
+/// Leave out `connection_id` to list statements from every connection. Pass the ID of
+/// the last entry received as `before_id` to fetch the next, older page.
+///
+/// # Errors
+/// Returns an error if the history could not be read
+#[tauri::command]
+pub async fn list_query_history(
+    connection_id: Option<i64>,
+    before_id: Option<i64>,
+    limit: Option<u32>,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<HistoryEntry>> {
+    let filter = HistoryFilter {
+        connection_id,
+        before_id,
+        ..HistoryFilter::default()
+    };
+    list_history(&state, &filter, limit).await
+}
+
+/// Command to search the query history, most recent first
+///
+/// Every word in the filter's `text` must appear in a statement for it to match, and the
+/// last may be typed only partly. The search can be narrowed to a connection and to
+/// statements started within a time range.
+///
+/// # Errors
+/// Returns an error if the history could not be read
+#[tauri::command]
+pub async fn search_query_history(
+    filter: HistoryFilter,
+    limit: Option<u32>,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<HistoryEntry>> {
+    list_history(&state, &filter, limit).await
+}
+
+/// Command to run a statement from the query history again
+///
+/// The statement runs on the connection it was first run on, with the same parameters,
+/// and is recorded in the history as a new entry.
+///
+/// # Errors
+/// Returns an error if the entry does not exist, or for the same reasons as `execute_query`
+#[tauri::command]
+pub async fn rerun_query(
+    history_id: i64,
+    query_handle: Option<QueryHandle>,
+    state: State<'_, AppState>,
+) -> AppResult<ResultSet> {
+    info!("Re-running query history entry: {}", history_id);
+
+    let history_repo = QueryHistoryRepository::new(state.db.clone());
+    let entry = history_repo
+        .get(history_id)
+        .await
+        .context(AppError::new(
+            format!("Failed to load query history entry {}", history_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?
+        .ok_or_else(|| {
+            AppError::new(
+                format!("Query history entry {} does not exist", history_id),
+                ErrorCategory::Database(DatabaseSubcategory::NotFound),
+                ErrorSeverity::Error,
+            )
+        })?;
+
+    run_statement(&state, entry.connection_id, entry.statement, entry.params, query_handle).await
+}
+
+/// Command to delete statements from the query history
+///
+/// Only statements run on `connection_id` are deleted when it is set, and only those
+/// started more than `older_than_days` days ago when that is set; with neither, the
+/// whole history is cleared. Returns the number of entries deleted.
+///
+/// # Errors
+/// Returns an error if the history could not be changed
+#[tauri::command]
+pub async fn purge_query_history(
+    connection_id: Option<i64>,
+    older_than_days: Option<u32>,
+    state: State<'_, AppState>,
+) -> AppResult<u64> {
+    info!("Purging query history for connection: {:?}", connection_id);
+
+    let history_repo = QueryHistoryRepository::new(state.db.clone());
+    let started_before = older_than_days.map(|days| Utc::now().timestamp() - i64::from(days) * 24 * 60 * 60);
+
+    history_repo
+        .purge(connection_id, started_before)
+        .await
+        .context(AppError::new(
+            "Failed to purge query history".to_string(),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))
+}
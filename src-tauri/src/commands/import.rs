@@ -0,0 +1,74 @@
+use crate::commands::database::load_connection;
+use crate::error::categories::{ErrorCategory, IoSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::database::cursor::QueryHandle;
+use crate::services::database::read_only;
+use crate::services::import::{
+    self, ColumnMapping, ImportEvent, ImportJob, ImportOptions, ImportPreview, ImportSource, ImportTarget,
+};
+use crate::state::AppState;
+use tauri::ipc::Channel;
+use tauri::State;
+use tracing::{info, warn};
+
+/// Command to read the columns of a CSV or NDJSON file, with inferred types, and its first rows
+///
+/// # Errors
+/// Returns an error if the file could not be opened or read
+#[tauri::command]
+pub async fn preview_import(source: ImportSource) -> AppResult<ImportPreview> {
+    info!("Previewing import of {}", source.path.display());
+
+    tokio::task::spawn_blocking(move || import::preview(&source))
+        .await
+        .map_err(|e| {
+            AppError::new(
+                format!("Preview stopped unexpectedly: {}", e),
+                ErrorCategory::Io(IoSubcategory::ReadFailed),
+                ErrorSeverity::Error,
+            )
+        })?
+}
+
+/// Command to import a CSV or NDJSON file into a table or collection
+///
+/// Each mapping reads a source column as a type and writes it to a target column, or
+/// document field. Rows are loaded in batches, each in its own transaction, and progress
+/// is reported on `on_event` after every batch along with the rows it rejected. A dry run
+/// reads and converts every row without writing. Cancel the import with `cancel_query`
+/// and the returned handle.
+///
+/// # Errors
+/// Returns an error if the connection is read-only or could not be opened, the file
+/// could not be opened, the mapping is invalid, or the target table could not be read
+/// or created
+#[tauri::command]
+pub async fn start_import(
+    connection_id: i64,
+    source: ImportSource,
+    target: ImportTarget,
+    mapping: Vec<ColumnMapping>,
+    options: Option<ImportOptions>,
+    on_event: Channel<ImportEvent>,
+    state: State<'_, AppState>,
+) -> AppResult<QueryHandle> {
+    info!("Importing {} into connection {}", source.path.display(), connection_id);
+
+    let connection = load_connection(&state, connection_id).await?;
+    read_only::check_write(&connection, "imports")?;
+    let pool = state.connection_manager.acquire(&connection).await?;
+
+    let job = ImportJob {
+        connection_id,
+        source,
+        target,
+        mapping,
+        options: options.unwrap_or_default(),
+    };
+    import::start(&state.queries, state.connection_manager.clone(), pool, job, move |event| {
+        if let Err(e) = on_event.send(event) {
+            warn!("Failed to deliver import event: {}", e);
+        }
+    })
+    .await
+}
@@ -0,0 +1,27 @@
+use crate::commands::database::load_connection;
+use crate::error::AppResult;
+use crate::services::introspection::{self, Catalog};
+use crate::state::AppState;
+use tauri::State;
+use tracing::info;
+
+/// Command to read the schema catalog of a saved connection
+///
+/// Returns databases, schemas, tables, views and materialized views with their
+/// columns, indexes, foreign keys, constraints and triggers.
+///
+/// # Errors
+/// Returns an error if the connection could not be opened, does not speak SQL,
+/// or a catalog query failed
+#[tauri::command]
+pub async fn get_catalog(
+    connection_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<Catalog> {
+    info!("Reading schema catalog for connection: {}", connection_id);
+
+    let connection = load_connection(&state, connection_id).await?;
+    let pool = state.connection_manager.acquire(&connection).await?;
+
+    introspection::introspect(&pool).await
+}
@@ -0,0 +1,75 @@
+use crate::commands::database::load_connection;
+use crate::commands::query::statement_timeout;
+use crate::error::AppResult;
+use crate::services::database::keyspace::{self, CommandReply, KeyPage, KeyValue};
+use crate::services::database::read_only;
+use crate::state::AppState;
+use tauri::State;
+use tracing::info;
+
+/// Command to scan a Redis connection's keys
+///
+/// `pattern` is a glob-style `MATCH` pattern and `key_type` limits the scan to one
+/// type. Pass the previous page's `cursor` to continue a scan.
+///
+/// # Errors
+/// Returns an error if the connection could not be opened, is not a Redis connection,
+/// or the scan failed
+#[tauri::command]
+pub async fn scan_keys(
+    connection_id: i64,
+    pattern: Option<String>,
+    key_type: Option<String>,
+    cursor: Option<String>,
+    count: Option<usize>,
+    state: State<'_, AppState>,
+) -> AppResult<KeyPage> {
+    let connection = load_connection(&state, connection_id).await?;
+    let pool = state.connection_manager.acquire(&connection).await?;
+    keyspace::scan_keys(
+        keyspace::pool(&pool)?,
+        pattern.as_deref(),
+        key_type.as_deref(),
+        cursor.as_deref(),
+        count,
+    )
+    .await
+}
+
+/// Command to read a Redis key's value, up to `limit` elements of a collection
+///
+/// # Errors
+/// Returns an error if the connection could not be opened, the key does not exist,
+/// or a read failed
+#[tauri::command]
+pub async fn get_key_value(
+    connection_id: i64,
+    key: String,
+    limit: Option<usize>,
+    state: State<'_, AppState>,
+) -> AppResult<KeyValue> {
+    let connection = load_connection(&state, connection_id).await?;
+    let pool = state.connection_manager.acquire(&connection).await?;
+    keyspace::get_value(keyspace::pool(&pool)?, &key, limit).await
+}
+
+/// Command to run a raw command line from the Redis console
+///
+/// # Errors
+/// Returns an error if the connection could not be opened, the command line is
+/// malformed or refused, including writes on a read-only connection, or the server
+/// replied with an error
+#[tauri::command]
+pub async fn run_redis_command(
+    connection_id: i64,
+    command: String,
+    state: State<'_, AppState>,
+) -> AppResult<CommandReply> {
+    info!("Running console command on connection: {}", connection_id);
+
+    let connection = load_connection(&state, connection_id).await?;
+    read_only::check_command(&connection, &command)?;
+    let pool = state.connection_manager.acquire(&connection).await?;
+    let timeout = statement_timeout(&connection);
+    keyspace::run_command(keyspace::pool(&pool)?, &command, timeout).await
+}
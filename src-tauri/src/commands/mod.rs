@@ -1,4 +1,14 @@
 pub mod projects;
 pub mod database;
 pub mod onboarding;
-pub mod keychain;
\ No newline at end of file
+pub mod keychain;
+pub mod query;
+pub mod introspection;
+pub mod documents;
+pub mod connections;
+pub mod keyspace;
+pub mod history;
+pub mod saved_queries;
+pub mod export;
+pub mod import;
+pub mod edit;
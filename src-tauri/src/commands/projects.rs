@@ -5,10 +5,11 @@ use crate::services::storage::{
         projects::{Project, ProjectRepository},
     },
 };
+use crate::commands::connections::close_sessions;
 use crate::utils;
 use crate::state::AppState;
 use crate::error::{AppError, AppResult, ErrorSeverity};
-use crate::error::categories::{DatabaseSubcategory, IconSubcategory, ErrorCategory};
+use crate::error::categories::{DatabaseSubcategory, IconSubcategory, ErrorCategory, ProjectSubcategory};
 use blake3;
 use tauri::State;
 use tracing::info;
@@ -16,18 +17,21 @@ use snafu::ResultExt;
 
 /// Command to fetch all projects for a user
 ///
+/// Archived projects are left out unless `include_archived` is set.
+///
 /// # Errors
 /// Returns a string error if there was a problem accessing the database or the projects could not be retrieved
 #[tauri::command]
 pub async fn get_user_projects(
     user_id: String,
+    include_archived: Option<bool>,
     state: State<'_, AppState>,
 ) -> AppResult<Vec<Project>> {
     info!("Fetching projects for user: {}", user_id);
 
     let project_repo = ProjectRepository::new(state.db.clone());
 
-    project_repo.get_by_user(&user_id).await
+    project_repo.get_by_user(&user_id, include_archived.unwrap_or(false)).await
         .context(AppError::new(
             format!("Failed to fetch projects for user {}", user_id),
             ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
@@ -125,14 +129,8 @@ pub async fn create_project(
     if let Some(initial_connection) = initial_connection {
         info!("Creating initial connection for project {}", project_id);
         let connection = NewConnection {
-            connection_name: initial_connection.connection_name,
             project_id: Some(project_id),
-            db_type: initial_connection.db_type,
-            host: initial_connection.host,
-            port: initial_connection.port,
-            username: initial_connection.username,
-            password: initial_connection.password,
-            database: initial_connection.database
+            ..initial_connection
         };
 
         if let Err(e) = connection_repo.create(&connection, Some(&mut tx)).await {
@@ -181,4 +179,215 @@ pub async fn get_project_connections(
             ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
             ErrorSeverity::Error,
         ))
-}
\ No newline at end of file
+}
+
+/// Command to rename a project
+///
+/// # Errors
+/// Returns an error if the name is empty, the project does not exist or could not be saved
+#[tauri::command]
+pub async fn rename_project(
+    project_id: i64,
+    name: String,
+    state: State<'_, AppState>,
+) -> AppResult<Project> {
+    info!("Renaming project {} to '{}'", project_id, name);
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::new(
+            "Project name cannot be empty",
+            ErrorCategory::Project(ProjectSubcategory::InvalidName),
+            ErrorSeverity::Error,
+        ));
+    }
+
+    let project_repo = ProjectRepository::new(state.db.clone());
+    let renamed = project_repo.rename(project_id, name).await
+        .context(AppError::new(
+            format!("Failed to rename project {}", project_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?;
+    if !renamed {
+        return Err(project_not_found(project_id));
+    }
+
+    load_project(&project_repo, project_id).await
+}
+
+/// Command to replace a project's icon
+///
+/// Saves `custom_icon_data` as the new icon, or generates a fresh default icon when
+/// it is absent. The previous icon file is removed once the project points at the new one.
+///
+/// # Errors
+/// Returns an error if:
+/// - The project does not exist
+/// - The icon generator fails to initialize
+/// - The new icon fails to generate or save
+/// - There was a problem saving the project
+#[tauri::command]
+pub async fn update_project_icon(
+    project_id: i64,
+    custom_icon_data: Option<String>,
+    state: State<'_, AppState>,
+) -> AppResult<Project> {
+    info!("Replacing icon of project {}", project_id);
+
+    let project_repo = ProjectRepository::new(state.db.clone());
+    let project = load_project(&project_repo, project_id).await?;
+
+    let icon_generator = IconGenerator::new()
+        .context(AppError::new(
+            "Failed to initialize icon generator",
+            ErrorCategory::Icon(IconSubcategory::GenerationFailed),
+            ErrorSeverity::Error,
+        ))?;
+
+    let new_icon_path = if let Some(icon_data) = custom_icon_data {
+        icon_generator
+            .save_custom_icon(&icon_data, &project.name, &project.user_id)
+            .context(AppError::new(
+                "Failed to save custom icon",
+                ErrorCategory::Icon(IconSubcategory::SaveFailed),
+                ErrorSeverity::Error,
+            ))?
+    } else {
+        let hash = blake3::hash(
+            utils::generate_unique_hash(&[&project.name, &project.user_id]).as_bytes(),
+        );
+
+        icon_generator
+            .generate_and_save(hash.as_bytes())
+            .context(AppError::new(
+                "Failed to generate default icon",
+                ErrorCategory::Icon(IconSubcategory::GenerationFailed),
+                ErrorSeverity::Error,
+            ))?
+    };
+
+    match project_repo.set_icon_path(project_id, &new_icon_path).await {
+        Ok(true) => {}
+        result => {
+            remove_icon(&icon_generator, &new_icon_path);
+            return match result {
+                Ok(_) => Err(project_not_found(project_id)),
+                Err(e) => Err(e).context(AppError::new(
+                    format!("Failed to update icon of project {}", project_id),
+                    ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+                    ErrorSeverity::Error,
+                )),
+            };
+        }
+    }
+
+    if let Some(old_icon_path) = project.icon_path.filter(|old| *old != new_icon_path) {
+        remove_icon(&icon_generator, &old_icon_path);
+    }
+
+    load_project(&project_repo, project_id).await
+}
+
+/// Command to archive or restore a project
+///
+/// Archived projects keep their connections but are hidden from `get_user_projects`
+/// unless archived projects are requested.
+///
+/// # Errors
+/// Returns an error if the project does not exist or could not be saved
+#[tauri::command]
+pub async fn set_project_archived(
+    project_id: i64,
+    archived: bool,
+    state: State<'_, AppState>,
+) -> AppResult<Project> {
+    info!("Setting archived = {} for project {}", archived, project_id);
+
+    let project_repo = ProjectRepository::new(state.db.clone());
+    let updated = project_repo.set_archived(project_id, archived).await
+        .context(AppError::new(
+            format!("Failed to archive project {}", project_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?;
+    if !updated {
+        return Err(project_not_found(project_id));
+    }
+
+    load_project(&project_repo, project_id).await
+}
+
+/// Command to permanently delete a project
+///
+/// The project's connections are deleted with it and their live sessions closed,
+/// and its icon file is removed.
+///
+/// # Errors
+/// Returns an error if the project does not exist or could not be deleted
+#[tauri::command]
+pub async fn delete_project(
+    project_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    info!("Deleting project: {}", project_id);
+
+    let project_repo = ProjectRepository::new(state.db.clone());
+    let connection_repo = ConnectionRepository::new(state.db.clone());
+    let project = load_project(&project_repo, project_id).await?;
+    let connections = connection_repo.get_by_project(project_id).await
+        .context(AppError::new(
+            "Failed to get project connections",
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?;
+
+    // The foreign key on connections.project_id removes the connections
+    let deleted = project_repo.delete(project_id).await
+        .context(AppError::new(
+            format!("Failed to delete project {}", project_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?;
+    if !deleted {
+        return Err(project_not_found(project_id));
+    }
+
+    for connection in &connections {
+        close_sessions(&state, connection.id).await;
+    }
+
+    if let Some(icon_path) = project.icon_path {
+        match IconGenerator::new() {
+            Ok(icon_generator) => remove_icon(&icon_generator, &icon_path),
+            Err(e) => info!("Failed to clean up icon file: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+async fn load_project(project_repo: &ProjectRepository, project_id: i64) -> AppResult<Project> {
+    project_repo.get_by_id(project_id).await
+        .context(AppError::new(
+            format!("Failed to load project {}", project_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?
+        .ok_or_else(|| project_not_found(project_id))
+}
+
+fn project_not_found(project_id: i64) -> AppError {
+    AppError::new(
+        format!("Project {} does not exist", project_id),
+        ErrorCategory::Project(ProjectSubcategory::NotFound),
+        ErrorSeverity::Error,
+    )
+}
+
+/// Remove an icon file, logging rather than failing if it cannot be removed
+fn remove_icon(icon_generator: &IconGenerator, icon_path: &str) {
+    if let Err(e) = std::fs::remove_file(icon_generator.get_icon_path(icon_path)) {
+        info!("Failed to clean up icon file: {}", e);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::commands::database::load_connection;
+use crate::commands::history;
+use crate::constants;
+use crate::error::AppResult;
+use crate::services::database::cursor::{CursorOptions, QueryEvent, QueryHandle};
+use crate::services::database::query::{self, DbValue, ResultSet};
+use crate::services::database::read_only;
+use crate::services::storage::repositories::connections::Connection;
+use crate::services::storage::repositories::query_history::NewHistoryEntry;
+use crate::state::AppState;
+use chrono::Utc;
+use std::time::{Duration, Instant};
+use tauri::ipc::Channel;
+use tauri::State;
+use tracing::{info, warn};
+
+/// Command to run a single SQL statement against a saved connection
+///
+/// Parameters are bound positionally (`$1` for Postgres, `?` for MySQL and SQLite).
+/// Pass a handle from `reserve_query_handle` as `query_handle` to be able to
+/// cancel the statement with `cancel_query` while it runs. The statement is recorded
+/// in the connection's query history whether it succeeds or fails.
+///
+/// # Errors
+/// Returns an error if the connection could not be opened, the statement would write
+/// on a read-only connection, failed, was cancelled or exceeded the connection's
+/// statement timeout
+#[tauri::command]
+pub async fn execute_query(
+    connection_id: i64,
+    sql: String,
+    params: Option<Vec<DbValue>>,
+    query_handle: Option<QueryHandle>,
+    state: State<'_, AppState>,
+) -> AppResult<ResultSet> {
+    info!("Executing query on connection: {}", connection_id);
+
+    run_statement(&state, connection_id, sql, params.unwrap_or_default(), query_handle).await
+}
+
+/// Run a statement on a saved connection and record it in the query history
+///
+/// Statements refused before they reach the server are not recorded.
+///
+/// # Errors
+/// Returns the same errors as `execute_query`
+pub(crate) async fn run_statement(
+    state: &AppState,
+    connection_id: i64,
+    sql: String,
+    params: Vec<DbValue>,
+    query_handle: Option<QueryHandle>,
+) -> AppResult<ResultSet> {
+    let connection = load_connection(state, connection_id).await?;
+    read_only::check_statement(&connection, &sql)?;
+    let pool = state.connection_manager.acquire(&connection).await?;
+
+    let (handle, slot) = state.queries.track(connection_id, query_handle).await?;
+    let started_at = Utc::now().timestamp();
+    let started = Instant::now();
+    let result = query::execute(&pool, &sql, &params, &slot, statement_timeout(&connection)).await;
+    state.queries.untrack(handle).await;
+
+    let mut entry = NewHistoryEntry {
+        connection_id,
+        statement: sql,
+        params,
+        started_at,
+        duration_ms: started.elapsed().as_millis() as i64,
+        rows_affected: None,
+        rows_returned: None,
+        error: None,
+    };
+    match &result {
+        Ok(result_set) => {
+            entry.duration_ms = result_set.duration_ms as i64;
+            entry.rows_affected = Some(result_set.rows_affected as i64);
+            entry.rows_returned = Some(result_set.rows.len() as i64);
+        }
+        Err(e) => entry.error = Some((e.category, e.message.clone())),
+    }
+    history::record(state.db.clone(), entry);
+    result
+}
+
+/// Command to reserve a handle for a statement about to be run with `execute_query`
+#[tauri::command]
+pub fn reserve_query_handle(state: State<'_, AppState>) -> QueryHandle {
+    state.queries.reserve_handle()
+}
+
+/// Command to start streaming a statement's results to the frontend in pages
+///
+/// The first page is sent on `on_event` straight away; later pages are only read
+/// from the server after `fetch_next_page` is called for the returned handle.
+/// The statement is recorded in the query history once it finishes or fails.
+///
+/// # Errors
+/// Returns an error if the connection could not be opened or does not speak SQL, or
+/// the statement would write on a read-only connection
+#[tauri::command]
+pub async fn start_query(
+    connection_id: i64,
+    sql: String,
+    params: Option<Vec<DbValue>>,
+    page_size: Option<usize>,
+    on_event: Channel<QueryEvent>,
+    state: State<'_, AppState>,
+) -> AppResult<QueryHandle> {
+    info!("Starting cursor query on connection: {}", connection_id);
+
+    let connection = load_connection(&state, connection_id).await?;
+    read_only::check_statement(&connection, &sql)?;
+    let pool = state.connection_manager.acquire(&connection).await?;
+
+    let params = params.unwrap_or_default();
+    let db = state.db.clone();
+    let entry = NewHistoryEntry {
+        connection_id,
+        statement: sql.clone(),
+        params: params.clone(),
+        started_at: Utc::now().timestamp(),
+        duration_ms: 0,
+        rows_affected: None,
+        rows_returned: None,
+        error: None,
+    };
+    let started = Instant::now();
+
+    state
+        .queries
+        .start(
+            connection_id,
+            pool,
+            sql,
+            params,
+            CursorOptions {
+                page_size: page_size.unwrap_or(constants::queries::DEFAULT_PAGE_SIZE),
+                timeout: statement_timeout(&connection),
+            },
+            move |event| {
+                match &event {
+                    QueryEvent::Done { total_rows, rows_affected, duration_ms, .. } => {
+                        history::record(db.clone(), NewHistoryEntry {
+                            duration_ms: *duration_ms as i64,
+                            rows_affected: Some(*rows_affected as i64),
+                            rows_returned: Some(*total_rows as i64),
+                            ..entry.clone()
+                        });
+                    }
+                    QueryEvent::Error { error, .. } => {
+                        history::record(db.clone(), NewHistoryEntry {
+                            duration_ms: started.elapsed().as_millis() as i64,
+                            error: Some((error.category, error.message.clone())),
+                            ..entry.clone()
+                        });
+                    }
+                    QueryEvent::Page { .. } => {}
+                }
+                if let Err(e) = on_event.send(event) {
+                    warn!("Failed to deliver query event: {}", e);
+                }
+            },
+        )
+        .await
+}
+
+/// Command to request the next page of a streaming query
+///
+/// # Errors
+/// Returns an error if the query has already finished or was cancelled
+#[tauri::command]
+pub async fn fetch_next_page(
+    query_handle: QueryHandle,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    let connection_id = state.queries.request_page(query_handle).await?;
+    state.connection_manager.touch(connection_id).await;
+    Ok(())
+}
+
+/// Command to cancel a running statement on the server
+///
+/// Works for streaming queries and for `execute_query` calls made with a reserved handle.
+///
+/// # Errors
+/// Returns an error if the query has already finished, or the server rejected the cancellation
+#[tauri::command]
+pub async fn cancel_query(
+    query_handle: QueryHandle,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    info!("Cancelling query: {}", query_handle);
+
+    state.queries.cancel(query_handle).await.map(|_| ())
+}
+
+/// The per-statement timeout configured on a connection
+pub(crate) fn statement_timeout(connection: &Connection) -> Option<Duration> {
+    connection
+        .statement_timeout_ms
+        .map(|ms| Duration::from_millis(ms.into()))
+}
@@ -0,0 +1,286 @@
+use crate::commands::database::load_connection;
+use crate::commands::query::run_statement;
+use crate::error::categories::{DatabaseSubcategory, ErrorCategory, ValidationSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::database::cursor::QueryHandle;
+use crate::services::database::query::{DbValue, ResultSet};
+use crate::services::storage::repositories::saved_queries::{
+    NewSavedQuery, QueryParameter, SavedQuery, SavedQueryFilter, SavedQueryRepository, SavedQueryUpdate,
+};
+use crate::state::AppState;
+use snafu::ResultExt;
+use std::collections::BTreeMap;
+use tauri::State;
+use tracing::info;
+
+/// Command to save a query to a project
+///
+/// The folder is a slash-separated path such as `reports/monthly`, created simply by
+/// saving a query into it.
+///
+/// # Errors
+/// Returns an error if the name or statement is empty, a parameter has no name, the
+/// default connection belongs to another project, or the folder already holds a query
+/// with the same name
+#[tauri::command]
+pub async fn create_saved_query(
+    query: NewSavedQuery,
+    state: State<'_, AppState>,
+) -> AppResult<SavedQuery> {
+    info!("Saving query '{}' to project {}", query.name, query.project_id);
+
+    let query = NewSavedQuery {
+        folder: normalize_folder(&query.folder),
+        name: required("Query name", &query.name)?,
+        statement: required("Query statement", &query.statement)?,
+        tags: normalize_tags(&query.tags),
+        ..query
+    };
+    check_parameters(&query.parameters)?;
+    if let Some(connection_id) = query.connection_id {
+        check_connection(&state, query.project_id, connection_id).await?;
+    }
+
+    let saved_query_repo = SavedQueryRepository::new(state.db.clone());
+    let saved_query_id = saved_query_repo
+        .create(&query)
+        .await
+        .map_err(|e| save_failed(e, &query.name))?;
+
+    load_saved_query(&saved_query_repo, saved_query_id).await
+}
+
+/// Command to fetch a single saved query
+///
+/// # Errors
+/// Returns an error if the query could not be read or does not exist
+#[tauri::command]
+pub async fn get_saved_query(
+    saved_query_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<SavedQuery> {
+    load_saved_query(&SavedQueryRepository::new(state.db.clone()), saved_query_id).await
+}
+
+/// Command to list a project's saved queries, by folder and then name
+///
+/// # Errors
+/// Returns an error if the queries could not be read
+#[tauri::command]
+pub async fn list_saved_queries(
+    project_id: i64,
+    filter: Option<SavedQueryFilter>,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<SavedQuery>> {
+    let mut filter = filter.unwrap_or_default();
+    filter.folder = filter.folder.as_deref().map(normalize_folder);
+
+    let saved_query_repo = SavedQueryRepository::new(state.db.clone());
+    saved_query_repo
+        .get_by_project(project_id, &filter)
+        .await
+        .context(AppError::new(
+            format!("Failed to get saved queries of project {}", project_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))
+}
+
+/// Command to change a saved query
+///
+/// Only the fields present in `update` are changed.
+///
+/// # Errors
+/// Returns an error if the query does not exist, a changed field is invalid, or the
+/// query would take the name of another query in its folder
+#[tauri::command]
+pub async fn update_saved_query(
+    saved_query_id: i64,
+    update: SavedQueryUpdate,
+    state: State<'_, AppState>,
+) -> AppResult<SavedQuery> {
+    info!("Updating saved query: {}", saved_query_id);
+
+    let saved_query_repo = SavedQueryRepository::new(state.db.clone());
+    let current = load_saved_query(&saved_query_repo, saved_query_id).await?;
+
+    let update = SavedQueryUpdate {
+        folder: update.folder.as_deref().map(normalize_folder),
+        name: update.name.as_deref().map(|name| required("Query name", name)).transpose()?,
+        statement: update
+            .statement
+            .as_deref()
+            .map(|statement| required("Query statement", statement))
+            .transpose()?,
+        tags: update.tags.as_deref().map(normalize_tags),
+        ..update
+    };
+    if let Some(parameters) = &update.parameters {
+        check_parameters(parameters)?;
+    }
+    if let Some(Some(connection_id)) = update.connection_id {
+        check_connection(&state, current.project_id, connection_id).await?;
+    }
+
+    let updated = saved_query_repo
+        .update(saved_query_id, &update)
+        .await
+        .map_err(|e| save_failed(e, update.name.as_deref().unwrap_or(&current.name)))?;
+    if !updated {
+        return Err(saved_query_not_found(saved_query_id));
+    }
+
+    load_saved_query(&saved_query_repo, saved_query_id).await
+}
+
+/// Command to delete a saved query
+///
+/// # Errors
+/// Returns an error if the query does not exist or could not be deleted
+#[tauri::command]
+pub async fn delete_saved_query(
+    saved_query_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    info!("Deleting saved query: {}", saved_query_id);
+
+    let saved_query_repo = SavedQueryRepository::new(state.db.clone());
+    let deleted = saved_query_repo
+        .delete(saved_query_id)
+        .await
+        .context(AppError::new(
+            format!("Failed to delete saved query {}", saved_query_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?;
+    if deleted {
+        Ok(())
+    } else {
+        Err(saved_query_not_found(saved_query_id))
+    }
+}
+
+/// Command to run a saved query with its parameters bound by name
+///
+/// The query runs on `connection_id` when given, or else on its default connection,
+/// either of which must belong to the query's project. Parameters left out of `params`
+/// take their defaults. The statement is recorded in the query history like any other.
+///
+/// # Errors
+/// Returns an error if there is no connection to run on, a parameter has no value, or for
+/// the same reasons as `execute_query`
+#[tauri::command]
+pub async fn run_saved_query(
+    saved_query_id: i64,
+    connection_id: Option<i64>,
+    params: Option<BTreeMap<String, DbValue>>,
+    query_handle: Option<QueryHandle>,
+    state: State<'_, AppState>,
+) -> AppResult<ResultSet> {
+    info!("Running saved query: {}", saved_query_id);
+
+    let saved_query_repo = SavedQueryRepository::new(state.db.clone());
+    let query = load_saved_query(&saved_query_repo, saved_query_id).await?;
+
+    let connection_id = connection_id.or(query.connection_id).ok_or_else(|| {
+        AppError::new(
+            format!("Choose a connection to run '{}' on", query.name),
+            ErrorCategory::Validation(ValidationSubcategory::MissingRequired),
+            ErrorSeverity::Error,
+        )
+    })?;
+    check_connection(&state, query.project_id, connection_id).await?;
+    let params = query.bind(&params.unwrap_or_default())?;
+
+    run_statement(&state, connection_id, query.statement, params, query_handle).await
+}
+
+async fn load_saved_query(saved_query_repo: &SavedQueryRepository, saved_query_id: i64) -> AppResult<SavedQuery> {
+    saved_query_repo
+        .get_by_id(saved_query_id)
+        .await
+        .context(AppError::new(
+            format!("Failed to load saved query {}", saved_query_id),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ))?
+        .ok_or_else(|| saved_query_not_found(saved_query_id))
+}
+
+fn saved_query_not_found(saved_query_id: i64) -> AppError {
+    AppError::new(
+        format!("Saved query {} does not exist", saved_query_id),
+        ErrorCategory::Database(DatabaseSubcategory::NotFound),
+        ErrorSeverity::Error,
+    )
+}
+
+/// The error for a query that could not be saved, naming a clash with another query
+fn save_failed(category: ErrorCategory, name: &str) -> AppError {
+    let message = match category {
+        ErrorCategory::Database(DatabaseSubcategory::ConstraintViolation) => {
+            format!("A saved query named '{}' already exists in this folder", name)
+        }
+        _ => format!("Failed to save query '{}'", name),
+    };
+    AppError::new(message, category, ErrorSeverity::Error)
+}
+
+/// Check that a connection exists and belongs to the project
+async fn check_connection(state: &AppState, project_id: i64, connection_id: i64) -> AppResult<()> {
+    let connection = load_connection(state, connection_id).await?;
+    if connection.project_id == project_id {
+        Ok(())
+    } else {
+        Err(AppError::new(
+            format!("Connection {} belongs to another project", connection_id),
+            ErrorCategory::Validation(ValidationSubcategory::InvalidRange),
+            ErrorSeverity::Error,
+        ))
+    }
+}
+
+/// `value` with surrounding whitespace removed, which must leave something
+fn required(label: &str, value: &str) -> AppResult<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(AppError::new(
+            format!("{} cannot be empty", label),
+            ErrorCategory::Validation(ValidationSubcategory::MissingRequired),
+            ErrorSeverity::Error,
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// A folder path without empty segments or stray slashes and whitespace
+fn normalize_folder(folder: &str) -> String {
+    folder
+        .split('/')
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Tags without surrounding whitespace, empty tags or repeats, in their original order
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+        if !normalized.iter().any(|existing| existing == tag) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized
+}
+
+fn check_parameters(parameters: &[QueryParameter]) -> AppResult<()> {
+    if parameters.iter().any(|parameter| parameter.name.trim().is_empty()) {
+        return Err(AppError::new(
+            "Query parameters must have a name",
+            ErrorCategory::Validation(ValidationSubcategory::MissingRequired),
+            ErrorSeverity::Error,
+        ));
+    }
+    Ok(())
+}
@@ -36,6 +36,122 @@ pub mod sqlite {
     pub const URI_PREFIX: &str = "sqlite://";
 }
 
+/// Live database sessions opened from saved connections
+pub mod sessions {
+    /// Seconds a session may go unused before it is closed
+    pub const IDLE_TIMEOUT_SECS: u64 = 600;
+    /// How often idle sessions are swept, in seconds
+    pub const REAPER_INTERVAL_SECS: u64 = 60;
+    /// Maximum pooled connections held open per session
+    pub const MAX_POOL_CONNECTIONS: u32 = 5;
+}
+
+/// Streaming query results
+pub mod queries {
+    /// Rows per page when the caller does not choose a size
+    pub const DEFAULT_PAGE_SIZE: usize = 500;
+    /// Largest page the frontend may request
+    pub const MAX_PAGE_SIZE: usize = 10_000;
+    /// Page requests that may queue up before further requests are ignored
+    pub const MAX_PENDING_PAGES: usize = 4;
+}
+
+/// Statements kept in each connection's query history
+pub mod history {
+    /// Days a statement is kept before it is purged
+    pub const RETENTION_DAYS: i64 = 90;
+    /// Statements kept per connection; the oldest are purged first
+    pub const MAX_ENTRIES_PER_CONNECTION: u32 = 10_000;
+    /// Entries listed when the caller does not choose a limit
+    pub const DEFAULT_LIMIT: u32 = 100;
+    /// Most entries listed at once
+    pub const MAX_LIMIT: u32 = 1_000;
+}
+
+/// Exports of query results to files
+pub mod export {
+    /// Rows read from the server per page while exporting
+    pub const PAGE_SIZE: usize = 5_000;
+    /// Rows per `INSERT` statement when the caller does not choose
+    pub const DEFAULT_ROWS_PER_INSERT: usize = 100;
+    /// Most rows per `INSERT` statement; SQL Server refuses more than 1000
+    pub const MAX_ROWS_PER_INSERT: usize = 1_000;
+    /// Rows per worksheet, including the header, before the export continues on another
+    pub const XLSX_MAX_ROWS: u32 = 1_048_576;
+    /// Columns a worksheet can hold
+    pub const XLSX_MAX_COLUMNS: usize = 16_384;
+    /// Characters a worksheet cell can hold; longer text is cut off
+    pub const XLSX_MAX_STRING_CHARS: usize = 32_767;
+}
+
+/// Imports of CSV and NDJSON files into tables and collections
+pub mod import {
+    /// Rows read from the file to infer column types
+    pub const SAMPLE_ROWS: usize = 1_000;
+    /// Rows returned in an import preview
+    pub const PREVIEW_ROWS: usize = 100;
+    /// Rows written per transaction when the caller does not choose
+    pub const DEFAULT_BATCH_SIZE: usize = 1_000;
+    /// Most rows written per transaction
+    pub const MAX_BATCH_SIZE: usize = 50_000;
+    /// Batches read ahead of the one being written
+    pub const READ_AHEAD_BATCHES: usize = 2;
+    /// Most rows per multi-row `INSERT` statement
+    pub const MAX_ROWS_PER_INSERT: usize = 1_000;
+    /// Most parameters bound to one `INSERT`; SQLite refuses more than 32766
+    pub const MAX_PARAMS_PER_INSERT: usize = 32_766;
+}
+
+/// MongoDB document queries
+pub mod documents {
+    /// Documents returned by a MongoDB query when the caller does not choose a limit
+    pub const DEFAULT_LIMIT: usize = 100;
+    /// Most documents a single MongoDB query may return
+    pub const MAX_LIMIT: usize = 10_000;
+    /// Documents sampled to infer a collection's fields
+    pub const SCHEMA_SAMPLE_SIZE: u32 = 100;
+    /// Largest sample the frontend may request
+    pub const MAX_SCHEMA_SAMPLE_SIZE: u32 = 1_000;
+}
+
+/// Redis key browsing and console commands
+pub mod keyspace {
+    /// Keys returned per page of a Redis key scan when the caller does not choose a count
+    pub const DEFAULT_SCAN_COUNT: usize = 200;
+    /// Largest page of keys the frontend may request
+    pub const MAX_SCAN_COUNT: usize = 5_000;
+    /// `SCAN` calls made for one page before returning the keys found so far
+    pub const MAX_SCAN_ROUNDS: usize = 10;
+    /// Elements of a Redis collection read when the caller does not choose a limit
+    pub const DEFAULT_VALUE_LIMIT: usize = 500;
+    /// Most elements of a Redis collection read at once
+    pub const MAX_VALUE_LIMIT: usize = 10_000;
+    /// Bytes of a Redis string read for display
+    pub const MAX_STRING_BYTES: usize = 1024 * 1024;
+}
+
+/// ClickHouse HTTP connections
+pub mod clickhouse {
+    /// Seconds to wait for a ClickHouse server to accept an HTTP connection
+    pub const CONNECT_TIMEOUT_SECS: u64 = 10;
+}
+
+/// SSH tunnels to bastion hosts
+pub mod tunnels {
+    /// Seconds to wait for the bastion to accept a connection or answer a request
+    pub const CONNECT_TIMEOUT_SECS: u64 = 10;
+    /// How long the forwarding thread sleeps when no data is moving, in milliseconds
+    pub const IDLE_POLL_MS: u64 = 2;
+    /// Bytes read from either side of a forwarded connection at a time
+    pub const BUFFER_SIZE: usize = 32 * 1024;
+}
+
+/// Layer-by-layer connection tests
+pub mod diagnostics {
+    /// Seconds each step of a connection test may take before it is reported as timed out
+    pub const STEP_TIMEOUT_SECS: u64 = 10;
+}
+
 /// Logging levels
 pub mod logging {
     use super::Level;
@@ -8,6 +8,8 @@ pub enum DatabaseSubcategory {
     TransactionFailed,
     ConstraintViolation,
     InvalidData,
+    /// A record or database object the request names, such as a saved query or table, does not exist
+    NotFound,
 }
 
 /// Migration-related subcategories
@@ -77,6 +79,7 @@ pub enum ConnectionSubcategory {
     Timeout,
     Refused,
     ProtocolError,
+    NotFound,
 }
 
 /// Validation-related subcategories
@@ -119,6 +119,14 @@ impl From<io::Error> for AppError {
     }
 }
 
+impl From<mongodb::error::Error> for AppError {
+    fn from(error: mongodb::error::Error) -> Self {
+        let message = error.to_string();
+        let category = ErrorCategory::from(error);
+        Self::new(message, category, ErrorSeverity::Error)
+    }
+}
+
 impl From<AppError> for io::Error {
     fn from(error: AppError) -> Self {
         io::Error::new(io::ErrorKind::Other, error.message)
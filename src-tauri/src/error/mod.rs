@@ -12,7 +12,7 @@ pub mod types;
 pub mod categories;
 pub mod conversions;
 
-pub use types::{AppError, ErrorSeverity};
+pub use types::{AppError, DatabaseErrorDetails, ErrorSeverity};
 pub use categories::ErrorCategory;
 
 // Type alias for Result using AppError
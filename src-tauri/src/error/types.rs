@@ -10,12 +10,24 @@ pub enum ErrorSeverity {
     Critical,
 }
 
+/// Server-reported details for a failed database statement
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatabaseErrorDetails {
+    /// Driver or server error code (SQLSTATE for Postgres/MySQL, result code for SQLite)
+    pub code: Option<String>,
+    /// 1-based character offset into the statement where the error was detected
+    pub position: Option<usize>,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+}
+
 /// The main error type for the application
 #[derive(Debug, Snafu)]
 pub struct AppError {
     pub message: String,
     pub category: ErrorCategory,
     pub severity: ErrorSeverity,
+    pub details: Option<DatabaseErrorDetails>,
 }
 
 // Custom serialization for AppError
@@ -44,11 +56,15 @@ impl Serialize for AppError {
             ErrorCategory::Unknown(sub) => ("Unknown", format!("{:?}", sub)),
         };
 
-        let mut state = serializer.serialize_struct("AppError", 4)?;
+        let mut state = serializer.serialize_struct("AppError", 5)?;
         state.serialize_field("message", &self.message)?;
         state.serialize_field("category", category_str)?;
         state.serialize_field("subcategory", &subcategory_str)?;
         state.serialize_field("severity", &self.severity)?;
+        match &self.details {
+            Some(details) => state.serialize_field("details", details)?,
+            None => state.skip_field("details")?,
+        }
         state.end()
     }
 }
@@ -63,9 +79,17 @@ impl AppError {
             message: message.into(),
             category,
             severity,
+            details: None,
         }
     }
 
+    /// Attach server-reported details to the error
+    #[must_use]
+    pub fn with_details(mut self, details: DatabaseErrorDetails) -> Self {
+        self.details = Some(details);
+        self
+    }
+
     pub fn code(&self) -> u32 {
         // Generate a unique code based on category and subcategory
         let category_code = match self.category {
@@ -62,9 +62,73 @@ async fn main() {
             commands::projects::create_project,
             commands::projects::get_user_projects,
             commands::projects::get_project_connections,
+            commands::projects::rename_project,
+            commands::projects::update_project_icon,
+            commands::projects::set_project_archived,
+            commands::projects::delete_project,
+
+            // Connection commands
+            commands::connections::get_connection,
+            commands::connections::parse_connection_uri,
+            commands::connections::get_connection_uri,
+            commands::connections::update_connection,
+            commands::connections::rename_connection,
+            commands::connections::duplicate_connection,
+            commands::connections::delete_connection,
             
             // Database commands
             commands::database::test_connection,
+            commands::database::test_saved_connection,
+            commands::database::open_connection,
+            commands::database::close_connection,
+            commands::database::list_open_connections,
+
+            // Query commands
+            commands::query::execute_query,
+            commands::query::start_query,
+            commands::query::fetch_next_page,
+            commands::query::cancel_query,
+            commands::query::reserve_query_handle,
+
+            // Query history commands
+            commands::history::list_query_history,
+            commands::history::search_query_history,
+            commands::history::rerun_query,
+            commands::history::purge_query_history,
+
+            // Saved query commands
+            commands::saved_queries::create_saved_query,
+            commands::saved_queries::get_saved_query,
+            commands::saved_queries::list_saved_queries,
+            commands::saved_queries::update_saved_query,
+            commands::saved_queries::delete_saved_query,
+            commands::saved_queries::run_saved_query,
+
+            // Export commands
+            commands::export::start_export,
+
+            // Table edit commands
+            commands::edit::preview_table_edits,
+            commands::edit::apply_table_edits,
+
+            // Import commands
+            commands::import::preview_import,
+            commands::import::start_import,
+
+            // Introspection commands
+            commands::introspection::get_catalog,
+
+            // MongoDB commands
+            commands::documents::list_mongo_databases,
+            commands::documents::list_collections,
+            commands::documents::infer_collection_schema,
+            commands::documents::find_documents,
+            commands::documents::aggregate_documents,
+
+            // Redis commands
+            commands::keyspace::scan_keys,
+            commands::keyspace::get_key_value,
+            commands::keyspace::run_redis_command,
 
             // Encryption commands
             commands::keychain::initialize_encryption_key,
@@ -0,0 +1,233 @@
+//! Server-side cancellation and statement timeouts.
+//!
+//! Every tracked statement runs on a dedicated connection whose backend id is
+//! recorded in a [`CancelSlot`] for as long as the statement runs. Cancelling
+//! asks the server to stop that backend's current statement, using a separate
+//! connection so a saturated pool cannot block the request.
+
+use futures::Future;
+use mongodb::Client as MongoClient;
+use sqlx::mysql::MySqlPool;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgPool;
+use sqlx::sqlite::Sqlite;
+use sqlx::{MySql, Postgres};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::error::categories::{ConnectionSubcategory, ErrorCategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::storage::repositories::connections::DbType;
+
+use super::clickhouse::ClickHouseClient;
+use super::driver;
+use super::duckdb::DuckDbInterrupt;
+use super::mssql::{self, MssqlConnection, MssqlPool};
+use super::query::query_error;
+
+/// How long a cancelled statement gets to unwind before its connection is abandoned
+const CANCEL_GRACE: Duration = Duration::from_secs(5);
+
+/// Virtual machine instructions SQLite runs between checks for cancellation
+const SQLITE_PROGRESS_OPS: i32 = 1000;
+
+/// The backend session a statement is running on
+#[derive(Debug, Clone)]
+pub enum CancelTarget {
+    Postgres { pool: PgPool, backend_pid: i32 },
+    MySql { pool: MySqlPool, connection_id: u64 },
+    /// SQLite statements check this flag from a progress handler
+    Sqlite(Arc<AtomicBool>),
+    /// MongoDB operations are found by the comment they were tagged with
+    MongoDb { client: MongoClient, comment: String },
+    MsSql { pool: MssqlPool, session_id: i16 },
+    /// ClickHouse statements are found by the query id they were sent with
+    ClickHouse { client: ClickHouseClient, query_id: String },
+    /// DuckDB statements are interrupted through the connection they run on
+    DuckDb(DuckDbInterrupt),
+}
+
+impl CancelTarget {
+    /// Identify the backend behind a pooled Postgres connection
+    ///
+    /// # Errors
+    /// Returns an error if the backend pid could not be read
+    pub async fn postgres(pool: &PgPool, conn: &mut PoolConnection<Postgres>) -> AppResult<Self> {
+        let backend_pid: i32 = sqlx::query_scalar("SELECT pg_backend_pid()")
+            .fetch_one(&mut **conn)
+            .await
+            .map_err(query_error)?;
+        Ok(Self::Postgres {
+            pool: pool.clone(),
+            backend_pid,
+        })
+    }
+
+    /// Identify the thread behind a pooled MySQL connection
+    ///
+    /// # Errors
+    /// Returns an error if the connection id could not be read
+    pub async fn mysql(pool: &MySqlPool, conn: &mut PoolConnection<MySql>) -> AppResult<Self> {
+        let connection_id: u64 = sqlx::query_scalar("SELECT CONNECTION_ID()")
+            .fetch_one(&mut **conn)
+            .await
+            .map_err(query_error)?;
+        Ok(Self::MySql {
+            pool: pool.clone(),
+            connection_id,
+        })
+    }
+
+    /// Identify the session behind a pooled SQL Server client
+    ///
+    /// # Errors
+    /// Returns an error if the session id could not be read
+    pub async fn mssql(pool: &MssqlPool, conn: &mut MssqlConnection) -> AppResult<Self> {
+        let row = conn
+            .simple_query("SELECT @@SPID")
+            .await
+            .map_err(mssql::mssql_error)?
+            .into_row()
+            .await
+            .map_err(mssql::mssql_error)?;
+        let session_id = row
+            .and_then(|row| row.get::<i16, _>(0))
+            .ok_or_else(|| AppError::new(
+                "SQL Server did not report a session id",
+                ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+                ErrorSeverity::Error,
+            ))?;
+        Ok(Self::MsSql {
+            pool: pool.clone(),
+            session_id,
+        })
+    }
+
+    /// Install a progress handler on a pooled SQLite connection that aborts
+    /// its statements once cancelled
+    ///
+    /// A one-off `sqlite3_interrupt` is not enough: sqlx steps an interrupted
+    /// statement again, which starts it over. The handler keeps aborting until
+    /// the next tracked statement replaces it, or the pool removes it when the
+    /// connection is released.
+    ///
+    /// # Errors
+    /// Returns an error if the connection's worker has shut down
+    pub async fn sqlite(conn: &mut PoolConnection<Sqlite>) -> AppResult<Self> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&cancelled);
+        conn.lock_handle()
+            .await
+            .map_err(query_error)?
+            .set_progress_handler(SQLITE_PROGRESS_OPS, move || !flag.load(Ordering::Relaxed));
+        Ok(Self::Sqlite(cancelled))
+    }
+
+    /// The engine whose server runs the statement
+    fn db_type(&self) -> DbType {
+        match self {
+            Self::Postgres { .. } => DbType::Postgres,
+            Self::MySql { .. } => DbType::MySql,
+            Self::Sqlite(_) => DbType::Sqlite,
+            Self::MongoDb { .. } => DbType::MongoDb,
+            Self::MsSql { .. } => DbType::MsSql,
+            Self::ClickHouse { .. } => DbType::ClickHouse,
+            Self::DuckDb(_) => DbType::DuckDb,
+        }
+    }
+
+    /// Ask the server to stop the statement running on this backend
+    async fn cancel(self) -> AppResult<()> {
+        driver::driver(self.db_type()).cancel(self).await
+    }
+}
+
+/// Shared record of the backend a tracked statement is currently running on
+#[derive(Debug, Clone, Default)]
+pub struct CancelSlot(Arc<Mutex<Option<CancelTarget>>>);
+
+impl CancelSlot {
+    /// Create an empty slot
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the backend a statement is about to run on
+    ///
+    /// The returned guard clears the slot when dropped, so a late cancellation
+    /// cannot reach whatever the connection runs next.
+    #[must_use]
+    pub fn arm(&self, target: CancelTarget) -> CancelGuard {
+        *self.lock() = Some(target);
+        CancelGuard(self.clone())
+    }
+
+    /// Stop the statement currently running in this slot, if any
+    ///
+    /// Returns whether a statement was running.
+    ///
+    /// # Errors
+    /// Returns an error if the server rejected the cancellation
+    pub async fn cancel(&self) -> AppResult<bool> {
+        let Some(target) = self.lock().clone() else {
+            return Ok(false);
+        };
+        debug!("Cancelling statement on {:?}", target);
+        target.cancel().await?;
+        Ok(true)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<CancelTarget>> {
+        self.0.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Clears a [`CancelSlot`] when the statement it was armed for is done
+#[derive(Debug)]
+pub struct CancelGuard(CancelSlot);
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        *self.0.lock() = None;
+    }
+}
+
+/// Run a statement, cancelling it on the server if it outlives `limit`
+///
+/// On timeout the statement is cancelled and given a short grace period to
+/// unwind, so its connection goes back to the pool in a usable state.
+///
+/// # Errors
+/// Returns the statement's own error, or a `Connection(Timeout)` error if it timed out
+pub async fn with_timeout<T, F>(limit: Option<Duration>, slot: &CancelSlot, statement: F) -> AppResult<T>
+where
+    F: Future<Output = AppResult<T>>,
+{
+    let Some(limit) = limit else {
+        return statement.await;
+    };
+
+    tokio::pin!(statement);
+    tokio::select! {
+        result = &mut statement => result,
+        () = tokio::time::sleep(limit) => {
+            if let Err(e) = slot.cancel().await {
+                warn!("Failed to cancel timed out statement: {}", e);
+            }
+            let _ = tokio::time::timeout(CANCEL_GRACE, statement).await;
+            Err(timeout_error(limit))
+        }
+    }
+}
+
+/// The error reported when a statement exceeds its timeout
+pub(crate) fn timeout_error(limit: Duration) -> AppError {
+    AppError::new(
+        format!("Statement exceeded the {} ms timeout", limit.as_millis()),
+        ErrorCategory::Connection(ConnectionSubcategory::Timeout),
+        ErrorSeverity::Error,
+    )
+}
@@ -0,0 +1,598 @@
+//! ClickHouse sessions over its HTTP interface.
+//!
+//! Every statement is a separate HTTP request, so there is no session to hold
+//! on to: [`ClickHouseClient`] keeps one HTTP client, whose own pool keeps
+//! connections alive between requests. Statements are tagged with a query id
+//! so another request can kill them while they run.
+//!
+//! Results are read in ClickHouse's JSON formats, with 64-bit integers and
+//! decimals quoted so they keep their precision, and date times in ISO 8601.
+
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Response, Url};
+use serde::de::{Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::fmt;
+use std::sync::Arc;
+use tracing::debug;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use crate::error::categories::{
+    AuthSubcategory, ConnectionSubcategory, DatabaseSubcategory, ErrorCategory,
+};
+use crate::error::{AppError, AppResult, DatabaseErrorDetails, ErrorSeverity};
+
+use super::query::{ColumnInfo, DbValue};
+
+/// ClickHouse's error numbers for a statement over its time limit, a rejected login,
+/// an unknown user or password, and a user without access
+const TIMEOUT_EXCEEDED: u32 = 159;
+const LOGIN_FAILED: [u32; 3] = [192, 193, 516];
+const ACCESS_DENIED: u32 = 497;
+
+/// Settings sent with every statement so results decode without losing precision
+const OUTPUT_SETTINGS: [(&str, &str); 3] = [
+    ("output_format_json_quote_64bit_integers", "1"),
+    ("output_format_json_quote_decimals", "1"),
+    ("date_time_output_format", "iso"),
+];
+
+struct ClientInner {
+    http: Client,
+    url: Url,
+    username: String,
+    password: String,
+    database: Option<String>,
+    /// Send statements with `readonly=2`, so the server refuses writes
+    read_only: bool,
+}
+
+/// An HTTP client bound to one ClickHouse server, user and database
+#[derive(Clone)]
+pub struct ClickHouseClient(Arc<ClientInner>);
+
+impl fmt::Debug for ClickHouseClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClickHouseClient")
+            .field("url", &self.0.url.as_str())
+            .field("username", &self.0.username)
+            .field("database", &self.0.database)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Columns, rows and rows written by a statement
+pub type Fetched = (Vec<ColumnInfo>, Vec<Vec<DbValue>>, u64);
+
+impl ClickHouseClient {
+    pub(crate) fn new(http: Client, url: Url, username: &str, password: &str, database: &str) -> Self {
+        Self(Arc::new(ClientInner {
+            http,
+            url,
+            username: username.to_string(),
+            password: password.to_string(),
+            database: Some(database.to_string()).filter(|database| !database.is_empty()),
+            read_only: false,
+        }))
+    }
+
+    /// The same client, with the server refusing writes from its statements
+    ///
+    /// Level 2 of `readonly` still lets a statement change its own settings, which the
+    /// output formats rely on, but not `readonly` itself.
+    #[must_use]
+    pub(crate) fn read_only(&self) -> Self {
+        Self(Arc::new(ClientInner {
+            http: self.0.http.clone(),
+            url: self.0.url.clone(),
+            username: self.0.username.clone(),
+            password: self.0.password.clone(),
+            database: self.0.database.clone(),
+            read_only: true,
+        }))
+    }
+
+    /// Whether statements are sent over HTTPS
+    pub fn is_encrypted(&self) -> bool {
+        self.0.url.scheme() == "https"
+    }
+
+    /// Run a statement and read its whole result in the columnar `JSONColumnsWithMetadata` format
+    ///
+    /// The server holds the response back until the statement has finished, so a
+    /// failure is reported as an error rather than a truncated result.
+    ///
+    /// # Errors
+    /// Returns an error if the server could not be reached or rejected the statement
+    pub async fn fetch(&self, sql: &str, params: &[DbValue], query_id: Option<&str>) -> AppResult<Fetched> {
+        let settings =
+            self.statement_settings(&[("default_format", "JSONColumnsWithMetadata"), ("wait_end_of_query", "1")]);
+        let response = self.send(sql, params, query_id, &settings).await?;
+        let rows_written = rows_written(response.headers());
+        let body = response.bytes().await.map_err(transport_error)?;
+        // Statements without a result, such as inserts and DDL, return no body at all
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Ok((Vec::new(), Vec::new(), rows_written));
+        }
+
+        let result: ColumnsResult = serde_json::from_slice(&body).map_err(|e| unexpected_response(&body, e))?;
+        if let Some(exception) = result.exception {
+            return Err(clickhouse_error(None, &exception));
+        }
+        let columns = result.meta.iter().map(Meta::column_info).collect::<Vec<_>>();
+        let row_count = result.data.0.first().map_or(0, Vec::len);
+        let mut rows: Vec<Vec<DbValue>> = (0..row_count).map(|_| Vec::with_capacity(columns.len())).collect();
+        for (meta, column) in result.meta.iter().zip(result.data.0) {
+            for (row, cell) in rows.iter_mut().zip(column) {
+                row.push(value(&meta.type_name, cell));
+            }
+        }
+        Ok((columns, rows, rows_written))
+    }
+
+    /// Start a statement whose rows are read one at a time as they arrive
+    ///
+    /// # Errors
+    /// Returns an error if the server could not be reached or rejected the statement
+    /// before sending its column names and types
+    pub async fn stream(&self, sql: &str, params: &[DbValue], query_id: Option<&str>) -> AppResult<RowStream> {
+        let settings = self.statement_settings(&[("default_format", "JSONCompactEachRowWithNamesAndTypes")]);
+        let response = self.send(sql, params, query_id, &settings).await?;
+        let mut stream = RowStream {
+            response,
+            buffer: Vec::new(),
+            columns: Vec::new(),
+            type_names: Vec::new(),
+        };
+        let names = stream.next_line().await?;
+        let type_names = stream.next_line().await?;
+        if let (Some(JsonValue::Array(names)), Some(JsonValue::Array(type_names))) = (names, type_names) {
+            stream.type_names = type_names.iter().map(|name| name.as_str().unwrap_or_default().to_string()).collect();
+            stream.columns = names
+                .iter()
+                .zip(&stream.type_names)
+                .map(|(name, type_name)| Meta::new(name.as_str().unwrap_or_default(), type_name).column_info())
+                .collect();
+        }
+        Ok(stream)
+    }
+
+    /// Ask the server to stop the statement tagged with `query_id`
+    ///
+    /// # Errors
+    /// Returns an error if the server could not be reached or rejected the request
+    pub async fn kill(&self, query_id: &str) -> AppResult<()> {
+        self.send(
+            "KILL QUERY WHERE query_id = {query_id:String} ASYNC",
+            &[],
+            None,
+            &[("param_query_id", query_id)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// `settings` for a statement, plus `readonly` for a read-only client
+    ///
+    /// Left out of `kill`, which a read-only session must still be able to send.
+    fn statement_settings<'a>(&self, settings: &[(&'a str, &'a str)]) -> Vec<(&'a str, &'a str)> {
+        let mut settings = settings.to_vec();
+        if self.0.read_only {
+            settings.push(("readonly", "2"));
+        }
+        settings
+    }
+
+    /// Post a statement with its parameters bound as `{p1:Type}`, `{p2:Type}`, ...
+    ///
+    /// ClickHouse only binds parameters by name, so the n-th parameter is named `pn`.
+    async fn send(
+        &self,
+        sql: &str,
+        params: &[DbValue],
+        query_id: Option<&str>,
+        settings: &[(&str, &str)],
+    ) -> AppResult<Response> {
+        let mut query: Vec<(String, String)> = OUTPUT_SETTINGS
+            .iter()
+            .chain(settings)
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        if let Some(database) = &self.0.database {
+            query.push(("database".to_string(), database.clone()));
+        }
+        if let Some(query_id) = query_id {
+            query.push(("query_id".to_string(), query_id.to_string()));
+        }
+        for (index, param) in params.iter().enumerate() {
+            query.push((format!("param_p{}", index + 1), param_text(param)));
+        }
+
+        let response = self
+            .0
+            .http
+            .post(self.0.url.clone())
+            .query(&query)
+            .header("X-ClickHouse-User", &self.0.username)
+            .header("X-ClickHouse-Key", &self.0.password)
+            .body(sql.to_string())
+            .send()
+            .await
+            .map_err(transport_error)?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+
+        let code = response
+            .headers()
+            .get("X-ClickHouse-Exception-Code")
+            .and_then(|code| code.to_str().ok())
+            .and_then(|code| code.parse().ok());
+        let status = response.status();
+        let body = response.text().await.map_err(transport_error)?;
+        debug!("ClickHouse answered {} to a statement", status);
+        if body.trim().is_empty() {
+            return Err(clickhouse_error(code, &format!("The server answered {}", status)));
+        }
+        Err(clickhouse_error(code, &body))
+    }
+}
+
+/// Rows of a streamed statement, read as the server sends them
+pub struct RowStream {
+    response: Response,
+    buffer: Vec<u8>,
+    columns: Vec<ColumnInfo>,
+    type_names: Vec<String>,
+}
+
+impl RowStream {
+    /// Column names and types, as the server sent them before the first row
+    pub fn columns(&self) -> &[ColumnInfo] {
+        &self.columns
+    }
+
+    /// Read the next row, `None` once the result is exhausted
+    ///
+    /// # Errors
+    /// Returns an error if the connection dropped or the statement failed part way through
+    pub async fn next_row(&mut self) -> AppResult<Option<Vec<DbValue>>> {
+        let Some(line) = self.next_line().await? else {
+            return Ok(None);
+        };
+        let JsonValue::Array(cells) = line else {
+            return Err(unexpected_response(line.to_string().as_bytes(), "expected a row"));
+        };
+        Ok(Some(
+            cells
+                .into_iter()
+                .zip(&self.type_names)
+                .map(|(cell, type_name)| value(type_name, cell))
+                .collect(),
+        ))
+    }
+
+    async fn next_line(&mut self) -> AppResult<Option<JsonValue>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                return parse_line(&line).map(Some);
+            }
+            match self.response.chunk().await.map_err(transport_error)? {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                None if self.buffer.iter().all(u8::is_ascii_whitespace) => return Ok(None),
+                None => {
+                    let line = std::mem::take(&mut self.buffer);
+                    return parse_line(&line).map(Some);
+                }
+            }
+        }
+    }
+}
+
+/// Parse one line of a row-per-line result
+///
+/// An error that happens after the first rows were sent cannot change the status, so
+/// the server writes it into the body, as an `exception` object or as plain text.
+fn parse_line(line: &[u8]) -> AppResult<JsonValue> {
+    match serde_json::from_slice::<JsonValue>(line) {
+        Ok(JsonValue::Object(object)) if object.contains_key("exception") => Err(clickhouse_error(
+            None,
+            object.get("exception").and_then(JsonValue::as_str).unwrap_or_default(),
+        )),
+        Ok(value) => Ok(value),
+        Err(_) if String::from_utf8_lossy(line).contains("DB::Exception") => {
+            Err(clickhouse_error(None, &String::from_utf8_lossy(line)))
+        }
+        Err(e) => Err(unexpected_response(line, e)),
+    }
+}
+
+/// A new id to tag a statement with, so it can be killed while it runs
+pub fn query_id() -> String {
+    format!("dewey-{:032x}", rand::random::<u128>())
+}
+
+#[derive(Debug, Deserialize)]
+struct Meta {
+    name: String,
+    #[serde(rename = "type")]
+    type_name: String,
+}
+
+impl Meta {
+    fn new(name: &str, type_name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            type_name: type_name.to_string(),
+        }
+    }
+
+    fn column_info(&self) -> ColumnInfo {
+        let type_name = self.type_name.strip_prefix("LowCardinality(").unwrap_or(&self.type_name);
+        ColumnInfo {
+            name: self.name.clone(),
+            type_name: self.type_name.clone(),
+            nullable: Some(type_name.starts_with("Nullable(")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ColumnsResult {
+    #[serde(default)]
+    meta: Vec<Meta>,
+    #[serde(default)]
+    data: Columns,
+    exception: Option<String>,
+}
+
+/// The values of each column in result order
+///
+/// Columns are keyed by name, which a result may repeat, so they are read in
+/// order rather than into a map.
+#[derive(Debug, Default)]
+struct Columns(Vec<Vec<JsonValue>>);
+
+impl<'de> Deserialize<'de> for Columns {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ColumnsVisitor;
+
+        impl<'de> Visitor<'de> for ColumnsVisitor {
+            type Value = Columns;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("an object of column values")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Columns, A::Error> {
+                let mut columns = Vec::new();
+                while let Some((IgnoredAny, values)) = map.next_entry::<IgnoredAny, Vec<JsonValue>>()? {
+                    columns.push(values);
+                }
+                Ok(Columns(columns))
+            }
+        }
+
+        deserializer.deserialize_map(ColumnsVisitor)
+    }
+}
+
+/// Decode a JSON cell by the ClickHouse type of its column
+fn value(type_name: &str, cell: JsonValue) -> DbValue {
+    let mut base = type_name;
+    for wrapper in ["LowCardinality(", "Nullable("] {
+        if let Some(inner) = base.strip_prefix(wrapper).and_then(|inner| inner.strip_suffix(')')) {
+            base = inner;
+        }
+    }
+    let text = match &cell {
+        JsonValue::Null => return DbValue::Null,
+        JsonValue::String(text) => Some(text.as_str()),
+        _ => None,
+    };
+    let type_prefix = base.split('(').next().unwrap_or(base);
+
+    match (type_prefix, text) {
+        ("Bool", _) => cell.as_bool().map_or_else(|| plain(cell), DbValue::Bool),
+        ("Int64", Some(text)) => text.parse().map_or_else(|_| plain(cell.clone()), DbValue::Int),
+        ("UInt64", Some(text)) => text.parse().map_or_else(|_| plain(cell.clone()), DbValue::UInt),
+        ("Int128" | "Int256" | "UInt128" | "UInt256" | "Decimal" | "Decimal32" | "Decimal64"
+        | "Decimal128" | "Decimal256", _) => match cell {
+            JsonValue::String(text) => DbValue::Decimal(text),
+            JsonValue::Number(number) => DbValue::Decimal(number.to_string()),
+            other => plain(other),
+        },
+        ("UUID", Some(text)) => DbValue::Uuid(text.to_string()),
+        ("Date" | "Date32", Some(text)) => DbValue::Date(text.to_string()),
+        ("DateTime" | "DateTime64", Some(text)) => DbValue::DateTime(text.to_string()),
+        ("String" | "FixedString", Some(text)) => DbValue::Text(text.to_string()),
+        _ => plain(cell),
+    }
+}
+
+/// Decode a cell by its JSON type alone
+fn plain(cell: JsonValue) -> DbValue {
+    match cell {
+        JsonValue::Null => DbValue::Null,
+        JsonValue::Bool(value) => DbValue::Bool(value),
+        JsonValue::String(text) => DbValue::Text(text),
+        JsonValue::Number(number) => {
+            if let Some(value) = number.as_i64() {
+                DbValue::Int(value)
+            } else if let Some(value) = number.as_u64() {
+                DbValue::UInt(value)
+            } else {
+                number.as_f64().map_or(DbValue::Null, DbValue::Float)
+            }
+        }
+        other => DbValue::Json(other),
+    }
+}
+
+/// A parameter as ClickHouse parses query parameters, in its escaped text format
+fn param_text(param: &DbValue) -> String {
+    let escape = |text: &str| {
+        text.replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\'', "\\'")
+    };
+    match param {
+        DbValue::Null => "\\N".to_string(),
+        DbValue::Bool(value) => value.to_string(),
+        DbValue::Int(value) => value.to_string(),
+        DbValue::UInt(value) => value.to_string(),
+        DbValue::Float(value) => value.to_string(),
+        DbValue::Bytes(value) => match BASE64.decode(value) {
+            Ok(bytes) => escape(&String::from_utf8_lossy(&bytes)),
+            Err(_) => escape(value),
+        },
+        DbValue::Json(value) => escape(&value.to_string()),
+        DbValue::Decimal(value)
+        | DbValue::Text(value)
+        | DbValue::Uuid(value)
+        | DbValue::Date(value)
+        | DbValue::Time(value)
+        | DbValue::DateTime(value) => escape(value),
+    }
+}
+
+/// Rows written by an insert, from the summary the server sends as a header
+fn rows_written(headers: &HeaderMap) -> u64 {
+    #[derive(Deserialize)]
+    struct Summary {
+        written_rows: Option<String>,
+    }
+
+    headers
+        .get("X-ClickHouse-Summary")
+        .and_then(|summary| serde_json::from_slice::<Summary>(summary.as_bytes()).ok())
+        .and_then(|summary| summary.written_rows?.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Convert a ClickHouse exception into an `AppError`, keeping the server's error number
+///
+/// The number is read from the message when the server did not send it as a header.
+pub(crate) fn clickhouse_error(code: Option<u32>, message: &str) -> AppError {
+    let message = message.trim();
+    let code = code.or_else(|| {
+        let rest = message.strip_prefix("Code: ")?;
+        rest[..rest.find(|c: char| !c.is_ascii_digit())?].parse().ok()
+    });
+    // Drop the repeated error number and the server version around the message itself
+    let text = message
+        .split_once("DB::Exception: ")
+        .map_or(message, |(_, text)| text);
+    let text = text.rsplit_once(" (version ").map_or(text, |(text, _)| text);
+
+    let category = match code {
+        Some(TIMEOUT_EXCEEDED) => ErrorCategory::Connection(ConnectionSubcategory::Timeout),
+        Some(code) if LOGIN_FAILED.contains(&code) => ErrorCategory::Auth(AuthSubcategory::InvalidCredentials),
+        Some(ACCESS_DENIED) => ErrorCategory::Auth(AuthSubcategory::PermissionDenied),
+        _ => ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+    };
+    let error = AppError::new(text.to_string(), category, ErrorSeverity::Error);
+    match code {
+        Some(code) => error.with_details(DatabaseErrorDetails {
+            code: Some(code.to_string()),
+            detail: Some(format!("ClickHouse error {}", code)),
+            ..DatabaseErrorDetails::default()
+        }),
+        None => error,
+    }
+}
+
+/// Convert an HTTP client error into an `AppError`, naming its underlying cause
+pub(crate) fn transport_error(error: reqwest::Error) -> AppError {
+    let category = if error.is_timeout() {
+        ErrorCategory::Connection(ConnectionSubcategory::Timeout)
+    } else if error.is_connect() || error.is_request() {
+        ErrorCategory::Connection(ConnectionSubcategory::ConnectionFailed)
+    } else {
+        ErrorCategory::Connection(ConnectionSubcategory::ProtocolError)
+    };
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    AppError::new(message, category, ErrorSeverity::Error)
+}
+
+fn unexpected_response(body: &[u8], error: impl fmt::Display) -> AppError {
+    let preview: String = String::from_utf8_lossy(body).chars().take(200).collect();
+    AppError::new(
+        format!("Unexpected response from ClickHouse ({}): {}", error, preview),
+        ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+        ErrorSeverity::Error,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columnar_results_decode_by_type() {
+        let body = r#"{
+            "meta": [
+                {"name": "n", "type": "UInt64"},
+                {"name": "n", "type": "Nullable(Decimal(38, 2))"},
+                {"name": "at", "type": "DateTime('UTC')"},
+                {"name": "tags", "type": "Array(LowCardinality(String))"}
+            ],
+            "data": {
+                "n": ["18446744073709551615", "2"],
+                "n": ["1.50", null],
+                "at": ["2024-05-01T12:00:00Z", "2024-05-02T12:00:00Z"],
+                "tags": [["a"], []]
+            },
+            "rows": 2
+        }"#;
+        let result: ColumnsResult = serde_json::from_str(body).unwrap();
+        assert_eq!(result.data.0.len(), 4);
+        assert_eq!(result.meta[1].column_info().nullable, Some(true));
+
+        let decoded: Vec<DbValue> = result
+            .meta
+            .iter()
+            .zip(&result.data.0)
+            .map(|(meta, column)| value(&meta.type_name, column[0].clone()))
+            .collect();
+        assert_eq!(
+            decoded,
+            vec![
+                DbValue::UInt(u64::MAX),
+                DbValue::Decimal("1.50".to_string()),
+                DbValue::DateTime("2024-05-01T12:00:00Z".to_string()),
+                DbValue::Json(serde_json::json!(["a"])),
+            ]
+        );
+        assert_eq!(value("Nullable(Decimal(38, 2))", JsonValue::Null), DbValue::Null);
+    }
+
+    #[test]
+    fn test_exceptions_keep_their_error_number() {
+        let error = clickhouse_error(
+            None,
+            "Code: 60. DB::Exception: Table default.missing does not exist. (UNKNOWN_TABLE) (version 24.3.1.1)\n",
+        );
+        assert_eq!(error.message, "Table default.missing does not exist. (UNKNOWN_TABLE)");
+        assert_eq!(error.details.and_then(|details| details.code).as_deref(), Some("60"));
+
+        let error = clickhouse_error(Some(516), "Code: 516. DB::Exception: default: Authentication failed");
+        assert_eq!(error.category, ErrorCategory::Auth(AuthSubcategory::InvalidCredentials));
+
+        assert_eq!(param_text(&DbValue::Text("a\tb\\".to_string())), "a\\tb\\\\");
+        assert_eq!(param_text(&DbValue::Null), "\\N");
+    }
+}
@@ -0,0 +1,767 @@
+//! Tracking of running queries and streaming, paginated result delivery.
+//!
+//! Every statement started from the frontend is registered under a
+//! [`QueryHandle`] so it can be cancelled on the server while it runs.
+//!
+//! A cursor query additionally runs in its own task and reads rows from the
+//! driver's `fetch_many` stream one page at a time. A page is only read once
+//! the frontend has asked for it, so a slow consumer holds the server back
+//! instead of filling memory with rows nobody has looked at yet.
+
+use ::duckdb::Connection as DuckDbConnection;
+use futures::stream::Peekable;
+use futures::{Stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+use sqlx::{Either, Executor, Row};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tiberius::QueryItem;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::{AbortHandle, JoinHandle};
+use tracing::{debug, info, warn};
+
+use crate::constants;
+use crate::error::categories::{ErrorCategory, ValidationSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+
+use super::cancel::{with_timeout, CancelSlot, CancelTarget};
+use super::clickhouse::{self, ClickHouseClient};
+use super::duckdb::{DuckDbInterrupt, PageReader};
+use super::mssql::{self, MssqlConnection};
+use super::pool::DatabasePool;
+use super::query::{
+    bind_all, describe_columns, mysql_row_values, not_sql, pg_row_values, query_error,
+    row_columns, sqlite_columns, sqlite_row_values, ColumnInfo, DbValue, RowsAffected,
+};
+
+/// Identifies a running query
+pub type QueryHandle = u64;
+
+/// Messages pushed to the frontend while a cursor query runs
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum QueryEvent {
+    /// A page of rows; `columns` is only set on the first page
+    Page {
+        handle: QueryHandle,
+        page: usize,
+        columns: Option<Vec<ColumnInfo>>,
+        rows: Vec<Vec<DbValue>>,
+    },
+    /// The statement finished and no more pages will follow
+    Done {
+        handle: QueryHandle,
+        total_rows: u64,
+        rows_affected: u64,
+        duration_ms: u64,
+    },
+    /// The statement failed; no more pages will follow
+    Error {
+        handle: QueryHandle,
+        error: AppError,
+    },
+}
+
+type EventSink = Arc<dyn Fn(QueryEvent) + Send + Sync>;
+
+/// How a cursor query delivers its results
+#[derive(Debug, Clone, Copy)]
+pub struct CursorOptions {
+    /// Rows per page, clamped to `1..=MAX_PAGE_SIZE`
+    pub page_size: usize,
+    /// Longest the server may spend producing a single page
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct PageStream {
+    page_requests: mpsc::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Debug)]
+struct RunningQuery {
+    connection_id: i64,
+    slot: CancelSlot,
+    /// Set for cursor queries, `None` for one-shot statements
+    stream: Option<PageStream>,
+    /// Set for background jobs, such as imports, attached with `QueryRegistry::attach`
+    job: Option<AbortHandle>,
+}
+
+impl RunningQuery {
+    /// Cancel the statement on the server, then stop delivering its pages
+    async fn stop(self) -> AppResult<i64> {
+        let cancelled = self.slot.cancel().await;
+        if let Some(stream) = self.stream {
+            stream.task.abort();
+        }
+        if let Some(job) = self.job {
+            job.abort();
+        }
+        cancelled.map(|_| self.connection_id)
+    }
+}
+
+/// Registry of running queries keyed by handle
+#[derive(Debug, Default)]
+pub struct QueryRegistry {
+    next_handle: AtomicU64,
+    queries: Mutex<HashMap<QueryHandle, RunningQuery>>,
+}
+
+impl QueryRegistry {
+    /// Create an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hand out a handle the frontend can cancel a statement by before it starts
+    pub fn reserve_handle(&self) -> QueryHandle {
+        self.next_handle.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Register a one-shot statement so it can be cancelled while it runs
+    ///
+    /// Uses `handle` if one was reserved, otherwise allocates a new one.
+    /// The statement must be deregistered with [`QueryRegistry::untrack`].
+    ///
+    /// # Errors
+    /// Returns an error if the handle is already in use
+    pub async fn track(
+        &self,
+        connection_id: i64,
+        handle: Option<QueryHandle>,
+    ) -> AppResult<(QueryHandle, CancelSlot)> {
+        let handle = handle.unwrap_or_else(|| self.reserve_handle());
+        let mut queries = self.queries.lock().await;
+        if queries.contains_key(&handle) {
+            return Err(AppError::new(
+                format!("Query {} is already running", handle),
+                ErrorCategory::Validation(ValidationSubcategory::InvalidRange),
+                ErrorSeverity::Error,
+            ));
+        }
+
+        let slot = CancelSlot::new();
+        queries.insert(
+            handle,
+            RunningQuery {
+                connection_id,
+                slot: slot.clone(),
+                stream: None,
+                job: None,
+            },
+        );
+        Ok((handle, slot))
+    }
+
+    /// Attach the task running a tracked background job, so cancelling it also stops the task
+    ///
+    /// A job cancelled before its task was attached is stopped straight away.
+    pub async fn attach(&self, handle: QueryHandle, job: AbortHandle) {
+        match self.queries.lock().await.get_mut(&handle) {
+            Some(query) => query.job = Some(job),
+            None => job.abort(),
+        }
+    }
+
+    /// Deregister a one-shot statement once it has finished
+    pub async fn untrack(&self, handle: QueryHandle) {
+        self.queries.lock().await.remove(&handle);
+    }
+
+    /// Start streaming a statement's results in pages
+    ///
+    /// The first page is delivered without being requested; each later page
+    /// is read only after [`QueryRegistry::request_page`] is called.
+    ///
+    /// # Errors
+    /// Returns an error if the pool does not speak SQL
+    pub async fn start<F>(
+        self: &Arc<Self>,
+        connection_id: i64,
+        pool: DatabasePool,
+        sql: String,
+        params: Vec<DbValue>,
+        options: CursorOptions,
+        sink: F,
+    ) -> AppResult<QueryHandle>
+    where
+        F: Fn(QueryEvent) + Send + Sync + 'static,
+    {
+        if !pool.driver().capabilities().sql {
+            return Err(not_sql());
+        }
+
+        let options = CursorOptions {
+            page_size: options.page_size.clamp(1, constants::queries::MAX_PAGE_SIZE),
+            ..options
+        };
+        let handle = self.reserve_handle();
+        let (page_requests, mut pending) = mpsc::channel(constants::queries::MAX_PENDING_PAGES);
+        // The first page is sent without waiting for the frontend to ask
+        let _ = page_requests.try_send(());
+
+        let sink: EventSink = Arc::new(sink);
+        let slot = CancelSlot::new();
+        let task_slot = slot.clone();
+        let registry: Weak<Self> = Arc::downgrade(self);
+
+        // Hold the lock while spawning so a fast query cannot finish and
+        // deregister itself before it has been registered
+        let mut queries = self.queries.lock().await;
+        let task = tokio::spawn(async move {
+            let started = Instant::now();
+            let cursor = Cursor {
+                handle,
+                options,
+                slot: &task_slot,
+                sink: &sink,
+            };
+            let outcome = cursor.run(&pool, &sql, &params, &mut pending).await;
+
+            match outcome {
+                Ok(Some((total_rows, rows_affected))) => sink(QueryEvent::Done {
+                    handle,
+                    total_rows,
+                    rows_affected,
+                    duration_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+                }),
+                Ok(None) => debug!("Cursor query {} stopped before completion", handle),
+                Err(error) => sink(QueryEvent::Error { handle, error }),
+            }
+
+            if let Some(registry) = registry.upgrade() {
+                registry.queries.lock().await.remove(&handle);
+            }
+        });
+
+        queries.insert(
+            handle,
+            RunningQuery {
+                connection_id,
+                slot,
+                stream: Some(PageStream {
+                    page_requests,
+                    task,
+                }),
+                job: None,
+            },
+        );
+        info!("Started cursor query {} on connection {}", handle, connection_id);
+        Ok(handle)
+    }
+
+    /// Ask a cursor query for its next page
+    ///
+    /// Requests beyond the pending-page limit are ignored until the query catches up.
+    /// Returns the id of the connection the query is running on.
+    ///
+    /// # Errors
+    /// Returns an error if the query has already finished, was cancelled or is not a cursor query
+    pub async fn request_page(&self, handle: QueryHandle) -> AppResult<i64> {
+        let queries = self.queries.lock().await;
+        let query = queries.get(&handle).ok_or_else(|| not_running(handle))?;
+        let stream = query.stream.as_ref().ok_or_else(|| not_running(handle))?;
+        match stream.page_requests.try_send(()) {
+            Ok(()) | Err(mpsc::error::TrySendError::Full(())) => Ok(query.connection_id),
+            Err(mpsc::error::TrySendError::Closed(())) => Err(not_running(handle)),
+        }
+    }
+
+    /// Cancel a running query on the server and release its connection
+    ///
+    /// Returns the id of the connection the query was running on.
+    ///
+    /// # Errors
+    /// Returns an error if the query has already finished, or the server
+    /// rejected the cancellation
+    pub async fn cancel(&self, handle: QueryHandle) -> AppResult<i64> {
+        let query = self
+            .queries
+            .lock()
+            .await
+            .remove(&handle)
+            .ok_or_else(|| not_running(handle))?;
+        info!("Cancelling query {}", handle);
+        query.stop().await
+    }
+
+    /// Cancel every query running on a connection
+    pub async fn cancel_for_connection(&self, connection_id: i64) {
+        let stopped: Vec<(QueryHandle, RunningQuery)> = {
+            let mut queries = self.queries.lock().await;
+            let handles: Vec<QueryHandle> = queries
+                .iter()
+                .filter(|(_, query)| query.connection_id == connection_id)
+                .map(|(handle, _)| *handle)
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| queries.remove(&handle).map(|query| (handle, query)))
+                .collect()
+        };
+
+        for (handle, query) in stopped {
+            debug!("Cancelling query {} for closed connection", handle);
+            if let Err(e) = query.stop().await {
+                warn!("Failed to cancel query {}: {}", handle, e);
+            }
+        }
+    }
+}
+
+/// A cursor query's task-local state
+struct Cursor<'a> {
+    handle: QueryHandle,
+    options: CursorOptions,
+    slot: &'a CancelSlot,
+    sink: &'a EventSink,
+}
+
+impl Cursor<'_> {
+    /// Run the statement on a dedicated connection until it finishes or is stopped
+    async fn run(
+        &self,
+        pool: &DatabasePool,
+        sql: &str,
+        params: &[DbValue],
+        pending: &mut mpsc::Receiver<()>,
+    ) -> AppResult<Option<(u64, u64)>> {
+        match pool {
+            DatabasePool::Postgres(pool) => {
+                let mut conn = pool.acquire().await.map_err(query_error)?;
+                let _guard = self.slot.arm(CancelTarget::postgres(pool, &mut conn).await?);
+                let columns = describe_columns(conn.describe(sql).await);
+                let query = bind_all(sqlx::query(sql), params)?;
+                self.stream_pages(conn.fetch_many(query), pg_row_values, columns, pending)
+                    .await
+            }
+            DatabasePool::MySql(pool) => {
+                let mut conn = pool.acquire().await.map_err(query_error)?;
+                let _guard = self.slot.arm(CancelTarget::mysql(pool, &mut conn).await?);
+                let columns = describe_columns(conn.describe(sql).await);
+                let query = bind_all(sqlx::query(sql), params)?;
+                self.stream_pages(conn.fetch_many(query), mysql_row_values, columns, pending)
+                    .await
+            }
+            DatabasePool::Sqlite(pool) => {
+                let mut conn = pool.acquire().await.map_err(query_error)?;
+                let _guard = self.slot.arm(CancelTarget::sqlite(&mut conn).await?);
+                let columns = sqlite_columns(conn.prepare(sql).await);
+                let query = bind_all(sqlx::query(sql), params)?;
+                self.stream_pages(conn.fetch_many(query), sqlite_row_values, columns, pending)
+                    .await
+            }
+            DatabasePool::MsSql(pool) => {
+                let mut conn = pool.acquire().await?;
+                let _guard = self.slot.arm(CancelTarget::mssql(pool, &mut conn).await?);
+                let outcome = self.stream_mssql(&mut conn, sql, params, pending).await?;
+                if outcome.is_some() {
+                    conn.release();
+                }
+                Ok(outcome)
+            }
+            DatabasePool::ClickHouse(client) => {
+                let query_id = clickhouse::query_id();
+                let _guard = self.slot.arm(CancelTarget::ClickHouse {
+                    client: client.clone(),
+                    query_id: query_id.clone(),
+                });
+                self.stream_clickhouse(client, sql, params, &query_id, pending).await
+            }
+            DatabasePool::DuckDb(pool) => {
+                let conn = pool.connect()?;
+                let _guard = self.slot.arm(CancelTarget::DuckDb(DuckDbInterrupt::new(&conn)));
+                self.stream_duckdb(conn, sql, params, pending).await
+            }
+            DatabasePool::MongoDb(_) | DatabasePool::Redis(_) => Err(not_sql()),
+        }
+    }
+
+    /// Read pages from a SQL Server result stream, one page per request
+    ///
+    /// Row counts are not reported by the TDS client, so rows affected is always 0.
+    async fn stream_mssql(
+        &self,
+        conn: &mut MssqlConnection,
+        sql: &str,
+        params: &[DbValue],
+        pending: &mut mpsc::Receiver<()>,
+    ) -> AppResult<Option<(u64, u64)>> {
+        if pending.recv().await.is_none() {
+            return Ok(None);
+        }
+        let stream = with_timeout(self.options.timeout, self.slot, mssql::query(conn, sql, params)).await?;
+        let stream = stream.peekable();
+        futures::pin_mut!(stream);
+
+        let mut columns = None;
+        let mut page = 0;
+        let mut total_rows = 0;
+        let mut finished = false;
+
+        while !finished {
+            if page > 0 && pending.recv().await.is_none() {
+                return Ok(None);
+            }
+
+            let read = read_mssql_page(stream.as_mut(), self.options.page_size, &mut columns);
+            let (rows, done) = with_timeout(self.options.timeout, self.slot, read).await?;
+            finished = done;
+
+            total_rows += rows.len() as u64;
+            (self.sink)(QueryEvent::Page {
+                handle: self.handle,
+                page,
+                columns: if page == 0 { Some(columns.take().unwrap_or_default()) } else { None },
+                rows,
+            });
+            page += 1;
+        }
+
+        Ok(Some((total_rows, 0)))
+    }
+
+    /// Read pages from a ClickHouse response as its rows arrive, one page per request
+    ///
+    /// The response is read no further than the pages asked for, so the server is held
+    /// back by the connection. Rows written are not known until the statement has
+    /// finished, so rows affected is always 0.
+    async fn stream_clickhouse(
+        &self,
+        client: &ClickHouseClient,
+        sql: &str,
+        params: &[DbValue],
+        query_id: &str,
+        pending: &mut mpsc::Receiver<()>,
+    ) -> AppResult<Option<(u64, u64)>> {
+        if pending.recv().await.is_none() {
+            return Ok(None);
+        }
+        let mut stream =
+            with_timeout(self.options.timeout, self.slot, client.stream(sql, params, Some(query_id))).await?;
+
+        let mut page = 0;
+        let mut total_rows = 0;
+        let mut finished = false;
+
+        while !finished {
+            if page > 0 && pending.recv().await.is_none() {
+                return Ok(None);
+            }
+
+            let read = async {
+                let mut rows = Vec::new();
+                while rows.len() < self.options.page_size {
+                    match stream.next_row().await? {
+                        Some(row) => rows.push(row),
+                        None => return Ok((rows, true)),
+                    }
+                }
+                Ok((rows, false))
+            };
+            let (rows, done) = with_timeout(self.options.timeout, self.slot, read).await?;
+            finished = done;
+
+            total_rows += rows.len() as u64;
+            (self.sink)(QueryEvent::Page {
+                handle: self.handle,
+                page,
+                columns: if page == 0 { Some(stream.columns().to_vec()) } else { None },
+                rows,
+            });
+            page += 1;
+        }
+
+        Ok(Some((total_rows, 0)))
+    }
+
+    /// Read pages from a DuckDB statement on its blocking thread, one page per request
+    ///
+    /// DuckDB reports changed rows as a `Count` result, so rows affected is always 0.
+    async fn stream_duckdb(
+        &self,
+        conn: DuckDbConnection,
+        sql: &str,
+        params: &[DbValue],
+        pending: &mut mpsc::Receiver<()>,
+    ) -> AppResult<Option<(u64, u64)>> {
+        if pending.recv().await.is_none() {
+            return Ok(None);
+        }
+        let mut reader = PageReader::start(conn, sql, params);
+
+        let mut page = 0;
+        let mut total_rows = 0;
+        let mut finished = false;
+
+        while !finished {
+            if page > 0 && pending.recv().await.is_none() {
+                return Ok(None);
+            }
+
+            let read = reader.next_page(self.options.page_size);
+            let next = with_timeout(self.options.timeout, self.slot, read).await?;
+            finished = next.done;
+
+            total_rows += next.rows.len() as u64;
+            (self.sink)(QueryEvent::Page {
+                handle: self.handle,
+                page,
+                columns: if page == 0 { Some(next.columns.unwrap_or_default()) } else { None },
+                rows: next.rows,
+            });
+            page += 1;
+        }
+
+        Ok(Some((total_rows, 0)))
+    }
+
+    /// Read pages from a `fetch_many` stream, one page per request
+    ///
+    /// Returns `(total_rows, rows_affected)` once the stream is exhausted, or
+    /// `None` if the request channel closed before that.
+    async fn stream_pages<S, Q, R>(
+        &self,
+        stream: S,
+        decode: fn(&R) -> Result<Vec<DbValue>, sqlx::Error>,
+        mut columns: Option<Vec<ColumnInfo>>,
+        pending: &mut mpsc::Receiver<()>,
+    ) -> AppResult<Option<(u64, u64)>>
+    where
+        S: Stream<Item = Result<Either<Q, R>, sqlx::Error>>,
+        Q: RowsAffected,
+        R: Row,
+    {
+        let stream = stream.peekable();
+        futures::pin_mut!(stream);
+
+        let mut page = 0;
+        let mut total_rows = 0;
+        let mut rows_affected = 0;
+        let mut finished = false;
+
+        while !finished {
+            if pending.recv().await.is_none() {
+                return Ok(None);
+            }
+
+            // Only time spent waiting on the server counts towards the timeout,
+            // not time spent waiting for the frontend to ask for the next page
+            let read = read_page(stream.as_mut(), self.options.page_size);
+            let (rows, affected, done) = with_timeout(self.options.timeout, self.slot, read).await?;
+            rows_affected += affected;
+            finished = done;
+
+            if page == 0 && columns.is_none() {
+                columns = rows.first().map(|row| row_columns(Some(row)));
+            }
+            let rows = rows
+                .iter()
+                .map(decode)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(query_error)?;
+
+            total_rows += rows.len() as u64;
+            (self.sink)(QueryEvent::Page {
+                handle: self.handle,
+                page,
+                columns: if page == 0 { columns.take() } else { None },
+                rows,
+            });
+            page += 1;
+        }
+
+        Ok(Some((total_rows, rows_affected)))
+    }
+}
+
+/// Read up to `page_size` rows
+///
+/// Returns the rows, the rows affected by statements passed along the way,
+/// and whether the stream is exhausted.
+async fn read_page<S, Q, R>(
+    mut stream: Pin<&mut Peekable<S>>,
+    page_size: usize,
+) -> AppResult<(Vec<R>, u64, bool)>
+where
+    S: Stream<Item = Result<Either<Q, R>, sqlx::Error>>,
+    Q: RowsAffected,
+{
+    let mut rows = Vec::with_capacity(page_size);
+    let mut rows_affected = 0;
+
+    while rows.len() < page_size {
+        match stream.try_next().await.map_err(query_error)? {
+            Some(Either::Left(result)) => rows_affected += result.rows_affected(),
+            Some(Either::Right(row)) => rows.push(row),
+            None => return Ok((rows, rows_affected, true)),
+        }
+    }
+
+    // Consume trailing statement results so the final page is not followed by an empty one
+    loop {
+        match stream.as_mut().peek().await {
+            Some(Ok(Either::Left(_))) => {
+                if let Some(Ok(Either::Left(result))) = stream.next().await {
+                    rows_affected += result.rows_affected();
+                }
+            }
+            None => return Ok((rows, rows_affected, true)),
+            Some(_) => return Ok((rows, rows_affected, false)),
+        }
+    }
+}
+
+/// Read up to `page_size` rows from a SQL Server result stream
+///
+/// Records the columns of the first result set in `columns` and returns the
+/// rows along with whether the stream is exhausted.
+async fn read_mssql_page<S>(
+    mut stream: Pin<&mut Peekable<S>>,
+    page_size: usize,
+    columns: &mut Option<Vec<ColumnInfo>>,
+) -> AppResult<(Vec<Vec<DbValue>>, bool)>
+where
+    S: Stream<Item = tiberius::Result<QueryItem>>,
+{
+    let mut rows = Vec::with_capacity(page_size);
+
+    while rows.len() < page_size {
+        match stream.try_next().await.map_err(mssql::mssql_error)? {
+            Some(QueryItem::Metadata(metadata)) => {
+                columns.get_or_insert_with(|| mssql::column_info(metadata.columns()));
+            }
+            Some(QueryItem::Row(row)) => rows.push(mssql::row_values(row)),
+            None => return Ok((rows, true)),
+        }
+    }
+
+    // Skip the metadata of trailing empty result sets so the final page is not followed by an empty one
+    loop {
+        match stream.as_mut().peek().await {
+            Some(Ok(QueryItem::Metadata(_))) => {
+                let _ = stream.next().await;
+            }
+            None => return Ok((rows, true)),
+            Some(_) => return Ok((rows, false)),
+        }
+    }
+}
+
+fn not_running(handle: QueryHandle) -> AppError {
+    AppError::new(
+        format!("Query {} is not running", handle),
+        ErrorCategory::Validation(ValidationSubcategory::InvalidRange),
+        ErrorSeverity::Error,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::time::Duration;
+
+    async fn next_event(events: &mut mpsc::UnboundedReceiver<QueryEvent>) -> QueryEvent {
+        tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pages_are_only_read_on_request() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE numbers (n INTEGER)").execute(&pool).await.unwrap();
+        for n in 0..5 {
+            sqlx::query("INSERT INTO numbers VALUES (?)").bind(n).execute(&pool).await.unwrap();
+        }
+
+        let registry = Arc::new(QueryRegistry::new());
+        let (tx, mut events) = mpsc::unbounded_channel();
+        let handle = registry
+            .start(
+                1,
+                DatabasePool::Sqlite(pool),
+                "SELECT n FROM numbers ORDER BY n".to_string(),
+                Vec::new(),
+                CursorOptions {
+                    page_size: 2,
+                    timeout: None,
+                },
+                move |event| {
+                    let _ = tx.send(event);
+                },
+            )
+            .await
+            .unwrap();
+
+        match next_event(&mut events).await {
+            QueryEvent::Page { page, columns, rows, .. } => {
+                assert_eq!(page, 0);
+                assert_eq!(columns.unwrap()[0].name, "n");
+                assert_eq!(rows, vec![vec![DbValue::Int(0)], vec![DbValue::Int(1)]]);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(events.try_recv().is_err(), "second page was read without a request");
+
+        registry.request_page(handle).await.unwrap();
+        assert!(matches!(next_event(&mut events).await, QueryEvent::Page { page: 1, .. }));
+        registry.request_page(handle).await.unwrap();
+        match next_event(&mut events).await {
+            QueryEvent::Page { page, columns, rows, .. } => {
+                assert_eq!(page, 2);
+                assert!(columns.is_none());
+                assert_eq!(rows, vec![vec![DbValue::Int(4)]]);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(matches!(
+            next_event(&mut events).await,
+            QueryEvent::Done { total_rows: 5, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_queries_stop_streaming() {
+        let pool = SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let registry = Arc::new(QueryRegistry::new());
+        let (tx, mut events) = mpsc::unbounded_channel();
+        let handle = registry
+            .start(
+                7,
+                DatabasePool::Sqlite(pool),
+                "SELECT 1 UNION ALL SELECT 2".to_string(),
+                Vec::new(),
+                CursorOptions {
+                    page_size: 1,
+                    timeout: None,
+                },
+                move |event| {
+                    let _ = tx.send(event);
+                },
+            )
+            .await
+            .unwrap();
+        next_event(&mut events).await;
+
+        assert_eq!(registry.cancel(handle).await.unwrap(), 7);
+        assert!(registry.request_page(handle).await.is_err());
+    }
+}
@@ -0,0 +1,1609 @@
+//! Layer-by-layer connection tests.
+//!
+//! A test walks the same path a session would: SSH tunnel, DNS, TCP, TLS, login,
+//! server and database. Each layer is reported as a step classified into an error
+//! category, so a failure names the layer it happened in without the driver's text.
+
+use mongodb::bson::{doc, Document};
+use mongodb::error::ErrorKind as MongoErrorKind;
+use mongodb::options::{ListDatabasesOptions, Tls};
+use mongodb::Client as MongoClient;
+use redis::aio::MultiplexedConnection;
+use redis::ConnectionAddr;
+use serde::Serialize;
+use sqlx::mysql::{MySqlConnection, MySqlDatabaseError};
+use sqlx::postgres::PgConnection;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{ConnectOptions, Connection};
+use std::collections::BTreeMap;
+use std::fs;
+use std::future::Future;
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::net::{lookup_host, TcpStream};
+use tracing::debug;
+
+use crate::constants::diagnostics::STEP_TIMEOUT_SECS;
+use crate::error::categories::{
+    AuthSubcategory, ConnectionSubcategory, DatabaseSubcategory, ErrorCategory, IoSubcategory,
+    ValidationSubcategory,
+};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::storage::repositories::connections::{
+    DbType, NewConnection, ServerInfo, TlsMode, TlsSettings,
+};
+
+use super::clickhouse::ClickHouseClient;
+use super::driver::{self, Endpoint};
+use super::duckdb::{DuckDbPool, FileKind};
+use super::mssql::{self, column, fetch_all, MssqlClient};
+use super::query::DbValue;
+use super::{
+    clickhouse_client, disable_tls_for_loopback, is_local_file, mongodb_options, mongodb_tls,
+    mssql_config, mysql_options, postgres_options, redis_client, sqlite_options,
+    tcp_host_for_local_connect, tunnel, uri, SqliteMode,
+};
+
+/// A layer of the path from Dewey to the selected database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticStage {
+    Tunnel,
+    Dns,
+    Tcp,
+    Tls,
+    Authentication,
+    Server,
+    Database,
+}
+
+/// Outcome of a single step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Passed,
+    /// The layer works but something about it deserves attention
+    Warning,
+    /// The layer does not apply to this connection
+    Skipped,
+    Failed,
+}
+
+/// What a connection test learned about one layer
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticStep {
+    pub stage: DiagnosticStage,
+    pub status: StepStatus,
+    pub detail: String,
+    /// Time the step took, for steps that were timed
+    pub duration_ms: Option<u64>,
+    /// Classification of a failed or suspicious step
+    pub category: Option<ErrorCategory>,
+}
+
+/// Result of testing a connection, step by step
+///
+/// Steps after the first failure are not attempted, so a failed report ends with the
+/// failing step and carries its error.
+#[derive(Debug, Serialize)]
+pub struct ConnectionReport {
+    pub success: bool,
+    pub steps: Vec<DiagnosticStep>,
+    /// What the server reported about itself, once logged in
+    pub server: Option<ServerInfo>,
+    pub error: Option<AppError>,
+}
+
+type PgServerRow = (String, String, String, Option<String>, String, String, bool);
+type MySqlServerRow = (String, String, String, Option<String>, String, String, i64);
+
+/// SQL Server's error numbers for a rejected login, an unopenable database and
+/// a login without a user in the selected database
+const MSSQL_LOGIN_FAILED: [u32; 2] = [18456, 18452];
+const MSSQL_DATABASE_UNAVAILABLE: u32 = 4060;
+const MSSQL_DATABASE_DENIED: u32 = 916;
+
+/// Marks that a failing step has been recorded and the test must stop
+pub struct StepFailed;
+
+pub type StepResult<T> = Result<T, StepFailed>;
+
+/// Collects the steps of a report as the test runs
+#[derive(Default)]
+pub struct Recorder {
+    steps: Vec<DiagnosticStep>,
+    server: Option<ServerInfo>,
+    error: Option<AppError>,
+}
+
+impl Recorder {
+    fn push(
+        &mut self,
+        stage: DiagnosticStage,
+        status: StepStatus,
+        detail: impl Into<String>,
+        started: Option<Instant>,
+        category: Option<ErrorCategory>,
+    ) {
+        self.steps.push(DiagnosticStep {
+            stage,
+            status,
+            detail: detail.into(),
+            duration_ms: started.map(|started| started.elapsed().as_millis() as u64),
+            category,
+        });
+    }
+
+    fn pass(&mut self, stage: DiagnosticStage, detail: impl Into<String>, started: Option<Instant>) {
+        self.push(stage, StepStatus::Passed, detail, started, None);
+    }
+
+    fn warn(&mut self, stage: DiagnosticStage, detail: impl Into<String>, category: ErrorCategory) {
+        self.push(stage, StepStatus::Warning, detail, None, Some(category));
+    }
+
+    fn skip(&mut self, stage: DiagnosticStage, detail: impl Into<String>) {
+        self.push(stage, StepStatus::Skipped, detail, None, None);
+    }
+
+    /// Record a failed step along with the error the test ends with
+    fn fail(
+        &mut self,
+        stage: DiagnosticStage,
+        detail: impl Into<String>,
+        category: ErrorCategory,
+        started: Option<Instant>,
+    ) -> StepFailed {
+        let detail = detail.into();
+        self.error = Some(AppError::new(detail.clone(), category, ErrorSeverity::Error));
+        self.push(stage, StepStatus::Failed, detail, started, Some(category));
+        StepFailed
+    }
+
+    fn fail_with(&mut self, stage: DiagnosticStage, error: AppError, started: Option<Instant>) -> StepFailed {
+        self.push(stage, StepStatus::Failed, error.message.clone(), started, Some(error.category));
+        self.error = Some(error);
+        StepFailed
+    }
+
+    fn finish(self, success: bool) -> ConnectionReport {
+        ConnectionReport {
+            success,
+            steps: self.steps,
+            server: self.server,
+            error: self.error,
+        }
+    }
+}
+
+/// Test the settings of a connection that may not be saved yet
+///
+/// Connects through the SSH tunnel and with the TLS settings the connection is configured
+/// with. Nothing is written to the server or, for SQLite, to the database file.
+pub async fn diagnose(connection: &NewConnection) -> ConnectionReport {
+    let mut recorder = Recorder::default();
+    let result = run(connection, &mut recorder).await;
+    recorder.finish(result.is_ok())
+}
+
+async fn run(connection: &NewConnection, recorder: &mut Recorder) -> StepResult<()> {
+    let db_type = connection.db_type;
+    let driver = driver::driver(db_type);
+    let host = connection.host.trim();
+    let port = connection.port.trim();
+
+    debug!("Diagnosing {} connection to {}:{}", db_type, host, port);
+
+    if driver.capabilities().local_file {
+        let endpoint = Endpoint {
+            host,
+            port,
+            tunnelled: false,
+        };
+        return driver.test(connection, endpoint, recorder).await;
+    }
+
+    let started = Instant::now();
+    let tunnel = tunnel::open_for(db_type, host, port, connection.ssh_tunnel.as_ref())
+        .await
+        .map_err(|e| recorder.fail_with(DiagnosticStage::Tunnel, e, Some(started)))?;
+    let tunnel_port = tunnel.as_ref().map(|tunnel| tunnel.local_port().to_string());
+    let (host, port) = match (&tunnel_port, &connection.ssh_tunnel) {
+        (Some(local_port), Some(ssh)) => {
+            recorder.pass(
+                DiagnosticStage::Tunnel,
+                format!("Forwarding through {} on local port {}", ssh.host.trim(), local_port),
+                Some(started),
+            );
+            recorder.skip(DiagnosticStage::Dns, "Resolved by the SSH host");
+            recorder.skip(DiagnosticStage::Tcp, "Connected from the SSH host");
+            ("127.0.0.1", local_port.as_str())
+        }
+        _ => {
+            if db_type == DbType::MongoDb && port.is_empty() {
+                recorder.skip(DiagnosticStage::Dns, "Seed list resolved by the driver from DNS SRV records");
+                recorder.skip(DiagnosticStage::Tcp, "Seed list resolved by the driver from DNS SRV records");
+            } else if db_type == DbType::MsSql && port.is_empty() {
+                recorder.skip(DiagnosticStage::Dns, "Instance port looked up through the SQL Server Browser");
+                recorder.skip(DiagnosticStage::Tcp, "Instance port looked up through the SQL Server Browser");
+            } else if db_type == DbType::MsSql {
+                let (probe_host, _) = host.split_once('\\').unwrap_or((host, ""));
+                probe_network(probe_host, port, recorder).await?;
+            } else {
+                // Only the first member of a MongoDB seed list is probed
+                let first = host.split(',').next().unwrap_or_default().trim();
+                let (probe_host, probe_port) = uri::split_host_port(first);
+                probe_network(probe_host, probe_port.unwrap_or(port), recorder).await?;
+            }
+            (host, port)
+        }
+    };
+
+    let endpoint = Endpoint {
+        host,
+        port,
+        tunnelled: tunnel.is_some(),
+    };
+    let result = driver.test(connection, endpoint, recorder).await;
+    drop(tunnel);
+    result
+}
+
+/// Resolve the host and open a plain TCP connection to it
+async fn probe_network(host: &str, port: &str, recorder: &mut Recorder) -> StepResult<()> {
+    let port: u16 = port.parse().map_err(|_| {
+        recorder.fail(
+            DiagnosticStage::Tcp,
+            format!("Invalid port number: {}", port),
+            ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+            None,
+        )
+    })?;
+    let connect_host = tcp_host_for_local_connect(host);
+
+    let started = Instant::now();
+    let addresses: Vec<SocketAddr> = match with_timeout(lookup_host((connect_host, port))).await {
+        None => {
+            return Err(recorder.fail(
+                DiagnosticStage::Dns,
+                format!("Timed out resolving {}", host),
+                ErrorCategory::Connection(ConnectionSubcategory::Timeout),
+                Some(started),
+            ))
+        }
+        Some(Err(e)) => {
+            return Err(recorder.fail(
+                DiagnosticStage::Dns,
+                format!("Could not resolve {}: {}", host, e),
+                ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+                Some(started),
+            ))
+        }
+        Some(Ok(addresses)) => addresses.collect(),
+    };
+    if addresses.is_empty() {
+        return Err(recorder.fail(
+            DiagnosticStage::Dns,
+            format!("{} has no addresses", host),
+            ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+            Some(started),
+        ));
+    }
+    let listed: Vec<String> = addresses.iter().map(|address| address.ip().to_string()).collect();
+    recorder.pass(
+        DiagnosticStage::Dns,
+        format!("{} resolved to {}", host, listed.join(", ")),
+        Some(started),
+    );
+
+    let started = Instant::now();
+    match with_timeout(TcpStream::connect(&addresses[..])).await {
+        None => Err(recorder.fail(
+            DiagnosticStage::Tcp,
+            format!("No answer from {}:{} within {}s", host, port, STEP_TIMEOUT_SECS),
+            ErrorCategory::Connection(ConnectionSubcategory::Timeout),
+            Some(started),
+        )),
+        Some(Err(e)) => Err(recorder.fail(
+            DiagnosticStage::Tcp,
+            format!("Could not connect to {}:{}: {}", host, port, e),
+            io_category(&e),
+            Some(started),
+        )),
+        Some(Ok(stream)) => {
+            let peer = stream
+                .peer_addr()
+                .map(|address| address.to_string())
+                .unwrap_or_else(|_| format!("{}:{}", host, port));
+            recorder.pass(DiagnosticStage::Tcp, format!("Connected to {}", peer), Some(started));
+            Ok(())
+        }
+    }
+}
+
+pub(crate) async fn postgres(
+    connection: &NewConnection,
+    host: &str,
+    port: &str,
+    recorder: &mut Recorder,
+) -> StepResult<()> {
+    let tls = connection.tls.as_ref();
+    check_tls(tls, recorder)?;
+    let opts = postgres_options(
+        host,
+        port,
+        connection.username.trim(),
+        &connection.password,
+        connection.database.trim(),
+        tls,
+    )
+    .map_err(|e| invalid_settings(e, recorder))?;
+
+    let started = Instant::now();
+    let mut conn = match with_timeout(PgConnection::connect_with(&opts)).await {
+        None => return Err(handshake_timed_out(recorder, started)),
+        Some(Err(e)) => return Err(sqlx_failure(e, recorder, started)),
+        Some(Ok(conn)) => conn,
+    };
+    let connected = started.elapsed();
+
+    let encryption: Result<Option<(bool, Option<String>)>, sqlx::Error> =
+        sqlx::query_as("SELECT ssl, version FROM pg_stat_ssl WHERE pid = pg_backend_pid()")
+            .fetch_optional(&mut conn)
+            .await;
+    match encryption {
+        Ok(Some((true, version))) => report_encryption(Some(version), tls, host, recorder),
+        Ok(_) => report_encryption(None, tls, host, recorder),
+        Err(e) => recorder.skip(DiagnosticStage::Tls, format!("Could not read the TLS state: {}", e)),
+    }
+    report_login(connection, connected, recorder);
+
+    let server: Result<PgServerRow, sqlx::Error> = sqlx::query_as(
+        "SELECT current_setting('server_version'), version(), current_user::text, \
+                current_schema()::text, current_setting('TimeZone'), current_setting('server_encoding'), \
+                current_setting('transaction_read_only') = 'on' OR pg_is_in_recovery()",
+    )
+    .fetch_one(&mut conn)
+    .await;
+    report_server(
+        server.map(|(version, banner, user, schema, timezone, encoding, read_only)| ServerInfo {
+            product: "PostgreSQL".to_string(),
+            // server_version may carry the packager's suffix, e.g. "16.2 (Debian 16.2-1)"
+            version: version.split_whitespace().next().unwrap_or_default().to_string(),
+            version_string: Some(banner),
+            current_user: Some(user),
+            default_schema: schema,
+            timezone: Some(timezone),
+            encoding: Some(encoding),
+            read_only: Some(read_only),
+        }),
+        recorder,
+    );
+    let database = sqlx::query_scalar("SELECT current_database()").fetch_one(&mut conn).await;
+    report_database(database.map(Some), recorder);
+
+    let _ = conn.close().await;
+    Ok(())
+}
+
+pub(crate) async fn mysql(
+    connection: &NewConnection,
+    host: &str,
+    port: &str,
+    recorder: &mut Recorder,
+) -> StepResult<()> {
+    let tls = connection.tls.as_ref();
+    check_tls(tls, recorder)?;
+    let opts = mysql_options(
+        host,
+        port,
+        connection.username.trim(),
+        &connection.password,
+        connection.database.trim(),
+        tls,
+    )
+    .map_err(|e| invalid_settings(e, recorder))?;
+
+    let started = Instant::now();
+    let mut conn = match with_timeout(MySqlConnection::connect_with(&opts)).await {
+        None => return Err(handshake_timed_out(recorder, started)),
+        Some(Err(e)) => return Err(sqlx_failure(e, recorder, started)),
+        Some(Ok(conn)) => conn,
+    };
+    let connected = started.elapsed();
+
+    let encryption: Result<Option<(String, String)>, sqlx::Error> =
+        sqlx::query_as("SHOW SESSION STATUS LIKE 'Ssl_version'")
+            .fetch_optional(&mut conn)
+            .await;
+    match encryption {
+        Ok(Some((_, version))) if !version.is_empty() => {
+            report_encryption(Some(Some(version)), tls, host, recorder)
+        }
+        Ok(_) => report_encryption(None, tls, host, recorder),
+        Err(e) => recorder.skip(DiagnosticStage::Tls, format!("Could not read the TLS state: {}", e)),
+    }
+    report_login(connection, connected, recorder);
+
+    // System variables are read as CHAR since some servers return them as binary strings
+    let server: Result<MySqlServerRow, sqlx::Error> = sqlx::query_as(
+        "SELECT CAST(@@version AS CHAR), CAST(@@version_comment AS CHAR), CURRENT_USER(), DATABASE(), \
+                CAST(IF(@@session.time_zone = 'SYSTEM', @@system_time_zone, @@session.time_zone) AS CHAR), \
+                CAST(@@character_set_database AS CHAR), CAST(@@read_only AS SIGNED)",
+    )
+    .fetch_one(&mut conn)
+    .await;
+    report_server(
+        server.map(|(version, comment, user, database, timezone, encoding, read_only)| ServerInfo {
+            product: if version.contains("MariaDB") { "MariaDB" } else { "MySQL" }.to_string(),
+            // MariaDB reports e.g. "10.11.6-MariaDB-1:10.11.6+maria~ubu2204"
+            version: version.split('-').next().unwrap_or_default().to_string(),
+            version_string: Some(format!("{} ({})", version, comment)),
+            current_user: Some(user),
+            default_schema: database,
+            timezone: Some(timezone),
+            encoding: Some(encoding),
+            read_only: Some(read_only != 0),
+        }),
+        recorder,
+    );
+    let database = sqlx::query_scalar("SELECT DATABASE()").fetch_one(&mut conn).await;
+    report_database(database, recorder);
+
+    let _ = conn.close().await;
+    Ok(())
+}
+
+pub(crate) async fn mongodb(
+    connection: &NewConnection,
+    host: &str,
+    port: &str,
+    tunneled: bool,
+    recorder: &mut Recorder,
+) -> StepResult<()> {
+    let tls = connection.tls.as_ref();
+    mongodb_tls(tls).map_err(|e| {
+        recorder.fail(
+            DiagnosticStage::Tls,
+            e,
+            ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+            None,
+        )
+    })?;
+
+    let started = Instant::now();
+    let mut client_options = mongodb_options(
+        host,
+        port,
+        connection.username.trim(),
+        &connection.password,
+        tls,
+        &connection.options,
+    )
+    .await
+    .map_err(|e| {
+        // Parsing a mongodb+srv:// seed list looks up its SRV records
+        if port.is_empty() {
+            recorder.fail(
+                DiagnosticStage::Dns,
+                e,
+                ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+                Some(started),
+            )
+        } else {
+            invalid_settings(e, recorder)
+        }
+    })?;
+    let timeout = Duration::from_secs(STEP_TIMEOUT_SECS);
+    client_options.server_selection_timeout = Some(timeout);
+    client_options.connect_timeout = Some(timeout);
+    if tunneled {
+        client_options.direct_connection = Some(true);
+    }
+    let encrypted = matches!(client_options.tls, Some(Tls::Enabled(_)));
+    let client = MongoClient::with_options(client_options).map_err(|e| invalid_settings(e.to_string(), recorder))?;
+
+    // The first command selects a server, which connects and logs in
+    let started = Instant::now();
+    let build_info = client
+        .database("admin")
+        .run_command(doc! { "buildInfo": 1 }, None)
+        .await
+        .map_err(|e| {
+            let (stage, category) = mongodb_classify(&e);
+            recorder.fail(stage, e.to_string(), category, Some(started))
+        })?;
+    let connected = started.elapsed();
+
+    report_encryption(encrypted.then_some(None), tls, host, recorder);
+    report_login(connection, connected, recorder);
+    let database = connection.database.trim();
+    report_server(mongodb_server_info(&client, &build_info, database).await, recorder);
+
+    if database.is_empty() {
+        recorder.skip(DiagnosticStage::Database, "No default database selected");
+    } else {
+        let options = ListDatabasesOptions::builder().authorized_databases(true).build();
+        match client.list_database_names(None, options).await {
+            Ok(names) if names.iter().any(|name| name == database) => {
+                recorder.pass(DiagnosticStage::Database, format!("Database {} exists", database), None)
+            }
+            Ok(_) => recorder.warn(
+                DiagnosticStage::Database,
+                format!("Database {} does not exist yet; MongoDB creates it on first write", database),
+                ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+            ),
+            Err(e) => recorder.warn(
+                DiagnosticStage::Database,
+                format!("Could not list databases: {}", e),
+                mongodb_classify(&e).1,
+            ),
+        }
+    }
+
+    client.shutdown().await;
+    Ok(())
+}
+
+/// Read the version from `buildInfo`, the logged in user and whether this member takes writes
+async fn mongodb_server_info(
+    client: &MongoClient,
+    build_info: &Document,
+    database: &str,
+) -> Result<ServerInfo, String> {
+    let admin = client.database("admin");
+    let version = build_info.get_str("version").map_err(|e| e.to_string())?;
+    let status = admin
+        .run_command(doc! { "connectionStatus": 1 }, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    let current_user = status
+        .get_document("authInfo")
+        .and_then(|auth| auth.get_array("authenticatedUsers"))
+        .ok()
+        .and_then(|users| users.first())
+        .and_then(|user| user.as_document())
+        .and_then(|user| user.get_str("user").ok())
+        .map(str::to_string);
+    // `isMaster` rather than `hello`, which servers before 4.4.2 lack
+    let writable = admin
+        .run_command(doc! { "isMaster": 1 }, None)
+        .await
+        .ok()
+        .and_then(|reply| reply.get_bool("ismaster").ok());
+
+    Ok(ServerInfo {
+        product: "MongoDB".to_string(),
+        version: version.to_string(),
+        version_string: None,
+        current_user,
+        default_schema: (!database.is_empty()).then(|| database.to_string()),
+        // BSON dates are UTC and strings UTF-8
+        timezone: Some("UTC".to_string()),
+        encoding: Some("UTF-8".to_string()),
+        read_only: writable.map(|writable| !writable),
+    })
+}
+
+pub(crate) async fn sqlite(connection: &NewConnection, recorder: &mut Recorder) -> StepResult<()> {
+    if !is_local_file(&connection.host, &connection.port) {
+        return Err(recorder.fail(
+            DiagnosticStage::Database,
+            "SQLite connections must point at a local database file",
+            ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+            None,
+        ));
+    }
+    let database = connection.database.trim();
+    let mode = SqliteMode::from_options(&connection.options).map_err(|e| invalid_sqlite_settings(e, recorder))?;
+    if mode != SqliteMode::Memory && database.is_empty() {
+        return Err(recorder.fail(
+            DiagnosticStage::Database,
+            "SQLite database path cannot be empty",
+            ErrorCategory::Validation(ValidationSubcategory::MissingRequired),
+            None,
+        ));
+    }
+    let opts = sqlite_options(database, &connection.password, &connection.options)
+        .map_err(|e| invalid_sqlite_settings(e, recorder))?;
+
+    // The test never writes, so it never leaves a file or directory behind whatever the mode
+    let started = Instant::now();
+    let mut notes = Vec::new();
+    let (name, opts) = if mode == SqliteMode::Memory {
+        ("an in-memory database".to_string(), opts)
+    } else {
+        let path = Path::new(database);
+        if !path.is_file() {
+            return sqlite_missing(database, mode, recorder, started).await;
+        }
+        check_sqlite_header(path, recorder, started)?;
+        let sidecars: Vec<&str> = ["-wal", "-shm"]
+            .into_iter()
+            .filter(|suffix| Path::new(&format!("{}{}", database, suffix)).is_file())
+            .collect();
+        if !sidecars.is_empty() {
+            notes.push(format!("{} sidecar files present", sidecars.join(" and ")));
+        }
+        (database.to_string(), opts.read_only(true).create_if_missing(false))
+    };
+
+    let mut conn = opts.connect().await.map_err(|e| {
+        recorder.fail(
+            DiagnosticStage::Database,
+            format!("Could not open {}: {}", name, e),
+            ErrorCategory::Database(DatabaseSubcategory::ConnectionFailed),
+            Some(started),
+        )
+    })?;
+
+    let journal_mode: Result<String, sqlx::Error> =
+        sqlx::query_scalar("PRAGMA journal_mode").fetch_one(&mut conn).await;
+    let page_size: Result<i64, sqlx::Error> = sqlx::query_scalar("PRAGMA page_size").fetch_one(&mut conn).await;
+    match (journal_mode, page_size) {
+        (Ok(journal_mode), Ok(page_size)) => {
+            notes.insert(0, format!("journal mode {}, {}-byte pages", journal_mode, page_size));
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            return Err(recorder.fail(
+                DiagnosticStage::Database,
+                format!("Could not read {}: {}", name, e),
+                ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+                Some(started),
+            ))
+        }
+    }
+    recorder.pass(
+        DiagnosticStage::Database,
+        format!("Opened {} ({})", name, notes.join("; ")),
+        Some(started),
+    );
+
+    let read_only = match mode {
+        SqliteMode::ReadOnly => Some(true),
+        SqliteMode::Memory => Some(false),
+        // The test opens the file read-only, so ask the file system whether sessions could write
+        _ if connection.options.get("immutable").is_some_and(|flag| flag == "1" || flag == "true") => Some(true),
+        _ => fs::metadata(database).map(|metadata| metadata.permissions().readonly()).ok(),
+    };
+    report_sqlite_server(&mut conn, read_only, recorder).await;
+
+    let _ = conn.close().await;
+    Ok(())
+}
+
+/// Check a missing file can be created in `rwc` mode, without creating it
+async fn sqlite_missing(
+    database: &str,
+    mode: SqliteMode,
+    recorder: &mut Recorder,
+    started: Instant,
+) -> StepResult<()> {
+    if mode != SqliteMode::ReadWriteCreate {
+        return Err(recorder.fail(
+            DiagnosticStage::Database,
+            format!("Database file not found: {}", database),
+            ErrorCategory::Io(IoSubcategory::PathNotFound),
+            Some(started),
+        ));
+    }
+    let parent = Path::new(database)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    if !parent.is_dir() {
+        return Err(recorder.fail(
+            DiagnosticStage::Database,
+            format!("Directory not found: {}", parent.display()),
+            ErrorCategory::Io(IoSubcategory::PathNotFound),
+            Some(started),
+        ));
+    }
+    recorder.pass(
+        DiagnosticStage::Database,
+        format!("{} does not exist yet and will be created on connect", database),
+        Some(started),
+    );
+
+    // Report the library version from a throwaway in-memory database
+    let opts = SqliteConnectOptions::from_str("sqlite::memory:")
+        .map_err(|e| invalid_sqlite_settings(e.to_string(), recorder))?;
+    match opts.connect().await {
+        Ok(mut conn) => {
+            report_sqlite_server(&mut conn, Some(false), recorder).await;
+            let _ = conn.close().await;
+        }
+        Err(e) => recorder.warn(
+            DiagnosticStage::Server,
+            format!("Could not read the server details: {}", e),
+            ErrorCategory::Database(DatabaseSubcategory::ConnectionFailed),
+        ),
+    }
+    Ok(())
+}
+
+fn check_sqlite_header(path: &Path, recorder: &mut Recorder, started: Instant) -> StepResult<()> {
+    let mut header = [0u8; 16];
+    let read = fs::File::open(path).and_then(|mut file| file.read_exact(&mut header));
+    match read {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            // An empty file is a database with no tables yet
+            Ok(())
+        }
+        Err(e) => Err(recorder.fail(
+            DiagnosticStage::Database,
+            format!("Failed to read database file header: {}", e),
+            ErrorCategory::Io(IoSubcategory::ReadFailed),
+            Some(started),
+        )),
+        Ok(()) if &header[0..15] != b"SQLite format 3" => Err(recorder.fail(
+            DiagnosticStage::Database,
+            "File exists but is not a valid SQLite database, or is encrypted (SQLCipher databases are not supported)",
+            ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+            Some(started),
+        )),
+        Ok(()) => Ok(()),
+    }
+}
+
+async fn report_sqlite_server(conn: &mut SqliteConnection, read_only: Option<bool>, recorder: &mut Recorder) {
+    let server: Result<(String, String), sqlx::Error> =
+        sqlx::query_as("SELECT sqlite_version(), encoding FROM pragma_encoding")
+            .fetch_one(conn)
+            .await;
+    report_server(
+        server.map(|(version, encoding)| ServerInfo {
+            product: "SQLite".to_string(),
+            version,
+            version_string: None,
+            current_user: None,
+            default_schema: Some("main".to_string()),
+            timezone: None,
+            encoding: Some(encoding),
+            read_only,
+        }),
+        recorder,
+    );
+}
+
+fn invalid_sqlite_settings(message: String, recorder: &mut Recorder) -> StepFailed {
+    recorder.fail(
+        DiagnosticStage::Database,
+        message,
+        ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+        None,
+    )
+}
+
+pub(crate) async fn duckdb(connection: &NewConnection, recorder: &mut Recorder) -> StepResult<()> {
+    if !is_local_file(&connection.host, &connection.port) {
+        return Err(recorder.fail(
+            DiagnosticStage::Database,
+            "DuckDB connections must point at a local file",
+            ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+            None,
+        ));
+    }
+    let database = connection.database.trim();
+    if database.is_empty() {
+        return Err(recorder.fail(
+            DiagnosticStage::Database,
+            "DuckDB file path cannot be empty",
+            ErrorCategory::Validation(ValidationSubcategory::MissingRequired),
+            None,
+        ));
+    }
+
+    let started = Instant::now();
+    let path = Path::new(database);
+    if !path.is_file() {
+        return Err(recorder.fail(
+            DiagnosticStage::Database,
+            format!("File not found: {}", database),
+            ErrorCategory::Io(IoSubcategory::PathNotFound),
+            Some(started),
+        ));
+    }
+    // Data files are read by DuckDB itself; database files carry a magic number
+    if FileKind::of(database) == FileKind::Database {
+        let mut header = [0u8; 12];
+        let read = fs::File::open(path).and_then(|mut file| file.read_exact(&mut header));
+        match read {
+            Err(e) if e.kind() != io::ErrorKind::UnexpectedEof => {
+                return Err(recorder.fail(
+                    DiagnosticStage::Database,
+                    format!("Failed to read database file header: {}", e),
+                    ErrorCategory::Io(IoSubcategory::ReadFailed),
+                    Some(started),
+                ))
+            }
+            Ok(()) if &header[8..12] == b"DUCK" => {}
+            _ => {
+                return Err(recorder.fail(
+                    DiagnosticStage::Database,
+                    "File exists but is not a valid DuckDB database",
+                    ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+                    Some(started),
+                ))
+            }
+        }
+    }
+
+    let pool = DuckDbPool::open(database, true).await.map_err(|e| {
+        recorder.fail(
+            DiagnosticStage::Database,
+            format!("Could not open {}: {}", database, e.message),
+            ErrorCategory::Database(DatabaseSubcategory::ConnectionFailed),
+            Some(started),
+        )
+    })?;
+    recorder.pass(DiagnosticStage::Database, format!("Opened {}", database), Some(started));
+
+    let version = pool.fetch("SELECT version()").await.and_then(|(_, rows)| {
+        match rows.into_iter().next().and_then(|row| row.into_iter().next()) {
+            Some(DbValue::Text(version)) => Ok(version),
+            _ => Err(AppError::new(
+                "DuckDB did not report its version",
+                ErrorCategory::Database(DatabaseSubcategory::InvalidData),
+                ErrorSeverity::Error,
+            )),
+        }
+    });
+    // The test opens the file read-only, so ask the file system whether sessions could write
+    let read_only = fs::metadata(path).map(|metadata| metadata.permissions().readonly()).ok();
+    report_server(
+        version
+            .map(|version| ServerInfo {
+                product: "DuckDB".to_string(),
+                version: version.trim_start_matches('v').to_string(),
+                version_string: None,
+                current_user: None,
+                default_schema: Some("main".to_string()),
+                timezone: None,
+                encoding: Some("UTF-8".to_string()),
+                read_only,
+            })
+            .map_err(|e| e.message),
+        recorder,
+    );
+    Ok(())
+}
+
+pub(crate) async fn mssql_server(
+    connection: &NewConnection,
+    host: &str,
+    port: &str,
+    recorder: &mut Recorder,
+) -> StepResult<()> {
+    let tls = connection.tls.as_ref();
+    check_tls(tls, recorder)?;
+    let config = mssql_config(
+        host,
+        port,
+        connection.username.trim(),
+        &connection.password,
+        connection.database.trim(),
+        tls,
+        &connection.options,
+    )
+    .map_err(|e| invalid_settings(e, recorder))?;
+
+    let started = Instant::now();
+    let mut client = match with_timeout(mssql::connect(&config)).await {
+        None => return Err(handshake_timed_out(recorder, started)),
+        Some(Err(e)) => return Err(mssql_failure(e, recorder, started)),
+        Some(Ok(client)) => client,
+    };
+    let connected = started.elapsed();
+
+    // Reading other sessions needs VIEW SERVER STATE, but a session can always see itself
+    let encryption = match fetch_all(
+        &mut client,
+        "SELECT encrypt_option FROM sys.dm_exec_connections WHERE session_id = @@SPID",
+    )
+    .await
+    {
+        Ok(rows) => rows
+            .first()
+            .map_or(Ok(false), |row| column::<&str>(row, 0).map(|option| option == Some("TRUE"))),
+        Err(e) => Err(e),
+    };
+    match encryption {
+        Ok(true) => report_encryption(Some(None), tls, host, recorder),
+        Ok(false) => report_encryption(None, tls, host, recorder),
+        Err(e) => recorder.skip(
+            DiagnosticStage::Tls,
+            format!("Could not read the TLS state: {}", e.message),
+        ),
+    }
+    report_login(connection, connected, recorder);
+
+    report_server(mssql_server_info(&mut client).await.map_err(|e| e.message), recorder);
+    let database = match fetch_all(&mut client, "SELECT DB_NAME()").await {
+        Ok(rows) => rows
+            .first()
+            .map(|row| column::<&str>(row, 0).map(|name| name.map(str::to_string)))
+            .transpose()
+            .map(Option::flatten),
+        Err(e) => Err(e),
+    };
+    report_database(database.map_err(|e| e.message), recorder);
+
+    let _ = client.close().await;
+    Ok(())
+}
+
+async fn mssql_server_info(client: &mut MssqlClient) -> AppResult<ServerInfo> {
+    let rows = fetch_all(
+        client,
+        "SELECT CAST(SERVERPROPERTY('ProductVersion') AS NVARCHAR(128)), @@VERSION, SUSER_SNAME(), \
+                SCHEMA_NAME(), DATENAME(TZOFFSET, SYSDATETIMEOFFSET()), \
+                CAST(DATABASEPROPERTYEX(DB_NAME(), 'Collation') AS NVARCHAR(128)), \
+                CAST(DATABASEPROPERTYEX(DB_NAME(), 'Updateability') AS NVARCHAR(128)), \
+                CAST(SERVERPROPERTY('EngineEdition') AS INT)",
+    )
+    .await?;
+    let row = rows.first().ok_or_else(|| {
+        AppError::new(
+            "The server returned no details",
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        )
+    })?;
+    let text = |index| column::<&str>(row, index).map(|value| value.map(str::to_string));
+    // Engine edition 5 is Azure SQL Database, 8 Azure SQL Managed Instance
+    let product = match column::<i32>(row, 7)? {
+        Some(5) => "Azure SQL Database",
+        Some(8) => "Azure SQL Managed Instance",
+        _ => "SQL Server",
+    };
+    Ok(ServerInfo {
+        product: product.to_string(),
+        version: text(0)?.unwrap_or_default(),
+        // @@VERSION spans several lines, the first names the release
+        version_string: text(1)?.map(|banner| banner.lines().next().unwrap_or_default().trim().to_string()),
+        current_user: text(2)?,
+        default_schema: text(3)?,
+        timezone: text(4)?,
+        encoding: text(5)?,
+        read_only: text(6)?.map(|updateability| updateability == "READ_ONLY"),
+    })
+}
+
+pub(crate) async fn redis_server(
+    connection: &NewConnection,
+    host: &str,
+    port: &str,
+    recorder: &mut Recorder,
+) -> StepResult<()> {
+    let tls = connection.tls.as_ref();
+    check_tls(tls, recorder)?;
+    let client = redis_client(
+        host,
+        port,
+        connection.username.trim(),
+        &connection.password,
+        connection.database.trim(),
+        tls,
+    )
+    .map_err(|e| invalid_settings(e, recorder))?;
+    let encrypted = matches!(client.get_connection_info().addr, ConnectionAddr::TcpTls { .. });
+    let db = client.get_connection_info().redis.db;
+
+    // The client logs in and selects the database while connecting
+    let started = Instant::now();
+    let mut conn = match with_timeout(client.get_multiplexed_tokio_connection()).await {
+        None => return Err(handshake_timed_out(recorder, started)),
+        Some(Err(e)) => return Err(redis_failure(e, recorder, started)),
+        Some(Ok(conn)) => conn,
+    };
+    let connected = started.elapsed();
+
+    report_encryption(encrypted.then_some(None), tls, host, recorder);
+    report_login(connection, connected, recorder);
+    report_server(redis_server_info(&mut conn, connection).await, recorder);
+    report_database(Ok::<_, String>(Some(db.to_string())), recorder);
+    Ok(())
+}
+
+async fn redis_server_info(
+    conn: &mut MultiplexedConnection,
+    connection: &NewConnection,
+) -> Result<ServerInfo, redis::RedisError> {
+    let (server, replication): (String, String) = redis::pipe()
+        .cmd("INFO")
+        .arg("server")
+        .cmd("INFO")
+        .arg("replication")
+        .query_async(conn)
+        .await?;
+    let fields: BTreeMap<&str, &str> = server
+        .lines()
+        .chain(replication.lines())
+        .filter_map(|line| line.trim().split_once(':'))
+        .collect();
+
+    // ACL WHOAMI needs Redis 6; older servers only know the default user
+    let user: Option<String> = redis::cmd("ACL").arg("WHOAMI").query_async(conn).await.ok();
+    let user = user.or_else(|| Some(connection.username.trim()).filter(|user| !user.is_empty()).map(str::to_string));
+
+    // Valkey also reports a Redis-compatible redis_version
+    let (product, version) = match fields.get("valkey_version") {
+        Some(version) => ("Valkey", *version),
+        None => ("Redis", fields.get("redis_version").copied().unwrap_or_default()),
+    };
+    let mode = fields.get("redis_mode").copied().unwrap_or("standalone");
+    let os = fields.get("os").copied().unwrap_or_default();
+    Ok(ServerInfo {
+        product: product.to_string(),
+        version: version.to_string(),
+        version_string: Some(format!("{} {} ({}, {})", product, version, mode, os)),
+        current_user: Some(user.unwrap_or_else(|| "default".to_string())),
+        default_schema: None,
+        timezone: None,
+        encoding: None,
+        read_only: Some(
+            fields.get("role") == Some(&"slave") && fields.get("slave_read_only") != Some(&"0"),
+        ),
+    })
+}
+
+pub(crate) async fn clickhouse_server(
+    connection: &NewConnection,
+    host: &str,
+    port: &str,
+    recorder: &mut Recorder,
+) -> StepResult<()> {
+    let tls = connection.tls.as_ref();
+    check_tls(tls, recorder)?;
+    let client = clickhouse_client(
+        host,
+        port,
+        connection.username.trim(),
+        &connection.password,
+        connection.database.trim(),
+        tls,
+    )
+    .map_err(|e| invalid_settings(e, recorder))?;
+
+    // Every request logs in and selects the database, so the first statement checks both
+    let started = Instant::now();
+    let version = match with_timeout(client.fetch("SELECT version()", &[], None)).await {
+        None => return Err(handshake_timed_out(recorder, started)),
+        Some(Err(e)) => return Err(clickhouse_failure(e, recorder, started)),
+        Some(Ok((_, rows, _))) => rows.into_iter().next().and_then(|row| row.into_iter().next()),
+    };
+    let connected = started.elapsed();
+
+    report_encryption(client.is_encrypted().then_some(None), tls, host, recorder);
+    report_login(connection, connected, recorder);
+    let server = clickhouse_server_info(&client, version).await.map_err(|e| e.message);
+    let database = server.as_ref().map(|server| server.default_schema.clone()).map_err(Clone::clone);
+    report_server(server, recorder);
+    report_database(database, recorder);
+    Ok(())
+}
+
+async fn clickhouse_server_info(client: &ClickHouseClient, version: Option<DbValue>) -> AppResult<ServerInfo> {
+    let (_, rows, _) = client
+        .fetch(
+            "SELECT currentUser(), currentDatabase(), timezone(), toUInt8(getSetting('readonly'))",
+            &[],
+            None,
+        )
+        .await?;
+    let row = rows.into_iter().next().unwrap_or_default();
+    let text = |index: usize| match row.get(index) {
+        Some(DbValue::Text(text)) => Some(text.clone()),
+        _ => None,
+    };
+    let version = match version {
+        Some(DbValue::Text(version)) => version,
+        _ => String::new(),
+    };
+    Ok(ServerInfo {
+        product: "ClickHouse".to_string(),
+        version_string: Some(format!("ClickHouse server version {}", version)),
+        version,
+        current_user: text(0),
+        default_schema: text(1),
+        timezone: text(2),
+        encoding: Some("UTF-8".to_string()),
+        // `readonly` is 1 or 2 for a user who may not write
+        read_only: row.get(3).map(|readonly| !matches!(readonly, DbValue::Int(0))),
+    })
+}
+
+/// Run a step under the step timeout, `None` if it ran out
+async fn with_timeout<F: Future>(future: F) -> Option<F::Output> {
+    tokio::time::timeout(Duration::from_secs(STEP_TIMEOUT_SECS), future)
+        .await
+        .ok()
+}
+
+/// Fail on missing certificate files before any handshake is attempted
+fn check_tls(tls: Option<&TlsSettings>, recorder: &mut Recorder) -> StepResult<()> {
+    match tls.map(super::check_tls_files) {
+        Some(Err(e)) => Err(recorder.fail(
+            DiagnosticStage::Tls,
+            e,
+            ErrorCategory::Io(IoSubcategory::PathNotFound),
+            None,
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn invalid_settings(message: String, recorder: &mut Recorder) -> StepFailed {
+    recorder.fail(
+        DiagnosticStage::Server,
+        message,
+        ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+        None,
+    )
+}
+
+fn handshake_timed_out(recorder: &mut Recorder, started: Instant) -> StepFailed {
+    recorder.fail(
+        DiagnosticStage::Server,
+        format!("The server did not complete the handshake within {}s", STEP_TIMEOUT_SECS),
+        ErrorCategory::Connection(ConnectionSubcategory::Timeout),
+        Some(started),
+    )
+}
+
+/// Record a failed sqlx connect at the layer it happened in
+///
+/// Servers check the database only after the login succeeded, so that step is recorded too.
+fn sqlx_failure(error: sqlx::Error, recorder: &mut Recorder, started: Instant) -> StepFailed {
+    let (stage, category) = sqlx_classify(&error);
+    if stage == DiagnosticStage::Database {
+        recorder.pass(DiagnosticStage::Authentication, "Logged in", None);
+    }
+    recorder.fail(stage, error.to_string(), category, Some(started))
+}
+
+/// The layer and category of an error raised while connecting with sqlx
+fn sqlx_classify(error: &sqlx::Error) -> (DiagnosticStage, ErrorCategory) {
+    let invalid_credentials = (
+        DiagnosticStage::Authentication,
+        ErrorCategory::Auth(AuthSubcategory::InvalidCredentials),
+    );
+    let database_missing = (
+        DiagnosticStage::Database,
+        ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+    );
+    let database_denied = (
+        DiagnosticStage::Database,
+        ErrorCategory::Auth(AuthSubcategory::PermissionDenied),
+    );
+    let rejected = (
+        DiagnosticStage::Server,
+        ErrorCategory::Database(DatabaseSubcategory::ConnectionFailed),
+    );
+
+    match error {
+        sqlx::Error::Tls(_) => (
+            DiagnosticStage::Tls,
+            ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+        ),
+        sqlx::Error::Io(e) => (DiagnosticStage::Tcp, io_category(e)),
+        sqlx::Error::Protocol(_) => (
+            DiagnosticStage::Server,
+            ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+        ),
+        sqlx::Error::Database(db_error) => {
+            if let Some(mysql) = db_error.try_downcast_ref::<MySqlDatabaseError>() {
+                return match mysql.number() {
+                    // ER_ACCESS_DENIED_ERROR
+                    1045 => invalid_credentials,
+                    // ER_BAD_DB_ERROR
+                    1049 => database_missing,
+                    // ER_DBACCESS_DENIED_ERROR
+                    1044 => database_denied,
+                    _ => rejected,
+                };
+            }
+            match db_error.code().as_deref() {
+                // invalid_password, invalid_authorization_specification
+                Some("28P01") | Some("28000") => invalid_credentials,
+                // invalid_catalog_name
+                Some("3D000") => database_missing,
+                // insufficient_privilege, as for a database without CONNECT
+                Some("42501") => database_denied,
+                _ => rejected,
+            }
+        }
+        _ => rejected,
+    }
+}
+
+/// Record a failed SQL Server login at the layer it happened in
+fn mssql_failure(error: tiberius::error::Error, recorder: &mut Recorder, started: Instant) -> StepFailed {
+    let (stage, category) = mssql_classify(&error);
+    if stage == DiagnosticStage::Database {
+        recorder.pass(DiagnosticStage::Authentication, "Logged in", None);
+    }
+    recorder.fail(stage, error.to_string(), category, Some(started))
+}
+
+/// The layer and category of an error raised while connecting to SQL Server
+fn mssql_classify(error: &tiberius::error::Error) -> (DiagnosticStage, ErrorCategory) {
+    use tiberius::error::Error;
+
+    match error {
+        Error::Server(token) if MSSQL_LOGIN_FAILED.contains(&token.code()) => (
+            DiagnosticStage::Authentication,
+            ErrorCategory::Auth(AuthSubcategory::InvalidCredentials),
+        ),
+        Error::Server(token) if token.code() == MSSQL_DATABASE_UNAVAILABLE => (
+            DiagnosticStage::Database,
+            ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+        ),
+        Error::Server(token) if token.code() == MSSQL_DATABASE_DENIED => (
+            DiagnosticStage::Database,
+            ErrorCategory::Auth(AuthSubcategory::PermissionDenied),
+        ),
+        Error::Io { kind, .. } => (DiagnosticStage::Tcp, io_category(&io::Error::from(*kind))),
+        Error::Tls(_) => (
+            DiagnosticStage::Tls,
+            ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+        ),
+        Error::Protocol(_) => (
+            DiagnosticStage::Server,
+            ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+        ),
+        _ => (
+            DiagnosticStage::Server,
+            ErrorCategory::Database(DatabaseSubcategory::ConnectionFailed),
+        ),
+    }
+}
+
+/// Record a failed Redis connect at the layer it happened in
+fn redis_failure(error: redis::RedisError, recorder: &mut Recorder, started: Instant) -> StepFailed {
+    let (stage, category) = redis_classify(&error);
+    if stage == DiagnosticStage::Database {
+        recorder.pass(DiagnosticStage::Authentication, "Logged in", None);
+    }
+    recorder.fail(stage, error.to_string(), category, Some(started))
+}
+
+/// The layer and category of an error raised while connecting to Redis
+///
+/// TLS failures surface as I/O errors, so they are told apart by message.
+fn redis_classify(error: &redis::RedisError) -> (DiagnosticStage, ErrorCategory) {
+    let message = error.to_string().to_lowercase();
+    if error.kind() == redis::ErrorKind::AuthenticationFailed || message.contains("wrongpass") {
+        (
+            DiagnosticStage::Authentication,
+            ErrorCategory::Auth(AuthSubcategory::InvalidCredentials),
+        )
+    } else if error.code() == Some("NOPERM") {
+        (
+            DiagnosticStage::Authentication,
+            ErrorCategory::Auth(AuthSubcategory::PermissionDenied),
+        )
+    } else if message.contains("db index") {
+        (
+            DiagnosticStage::Database,
+            ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+        )
+    } else if message.contains("tls") || message.contains("certificate") {
+        (
+            DiagnosticStage::Tls,
+            ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+        )
+    } else if error.is_connection_refusal() {
+        (
+            DiagnosticStage::Tcp,
+            ErrorCategory::Connection(ConnectionSubcategory::Refused),
+        )
+    } else if error.is_timeout() {
+        (
+            DiagnosticStage::Tcp,
+            ErrorCategory::Connection(ConnectionSubcategory::Timeout),
+        )
+    } else if error.is_io_error() {
+        (
+            DiagnosticStage::Tcp,
+            ErrorCategory::Connection(ConnectionSubcategory::ConnectionFailed),
+        )
+    } else {
+        (
+            DiagnosticStage::Server,
+            ErrorCategory::Database(DatabaseSubcategory::ConnectionFailed),
+        )
+    }
+}
+
+/// Record a failed first ClickHouse request at the layer it happened in
+fn clickhouse_failure(error: AppError, recorder: &mut Recorder, started: Instant) -> StepFailed {
+    let stage = clickhouse_stage(&error);
+    if stage == DiagnosticStage::Database {
+        recorder.pass(DiagnosticStage::Authentication, "Logged in", None);
+    }
+    recorder.push(stage, StepStatus::Failed, error.message.clone(), Some(started), Some(error.category));
+    recorder.error = Some(error);
+    StepFailed
+}
+
+/// The layer a ClickHouse request failed in
+///
+/// HTTP client errors do not say which layer failed, so TLS is told apart by message.
+fn clickhouse_stage(error: &AppError) -> DiagnosticStage {
+    let code = error.details.as_ref().and_then(|details| details.code.as_deref());
+    let message = error.message.to_lowercase();
+    match error.category {
+        ErrorCategory::Auth(_) => DiagnosticStage::Authentication,
+        // UNKNOWN_DATABASE
+        _ if code == Some("81") => DiagnosticStage::Database,
+        ErrorCategory::Connection(_)
+            if message.contains("tls") || message.contains("ssl") || message.contains("certificate") =>
+        {
+            DiagnosticStage::Tls
+        }
+        ErrorCategory::Connection(ConnectionSubcategory::ConnectionFailed) => DiagnosticStage::Tcp,
+        _ => DiagnosticStage::Server,
+    }
+}
+
+/// The layer and category of a MongoDB driver error
+///
+/// Server selection reports connection problems as text, so it is classified by message.
+fn mongodb_classify(error: &mongodb::error::Error) -> (DiagnosticStage, ErrorCategory) {
+    match error.kind.as_ref() {
+        MongoErrorKind::Authentication { .. } => (
+            DiagnosticStage::Authentication,
+            ErrorCategory::Auth(AuthSubcategory::InvalidCredentials),
+        ),
+        MongoErrorKind::DnsResolve { .. } => (
+            DiagnosticStage::Dns,
+            ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+        ),
+        MongoErrorKind::InvalidTlsConfig { .. } => (
+            DiagnosticStage::Tls,
+            ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+        ),
+        MongoErrorKind::Io(e) => (DiagnosticStage::Tcp, io_category(e)),
+        // AuthenticationFailed
+        MongoErrorKind::Command(e) if e.code == 18 => (
+            DiagnosticStage::Authentication,
+            ErrorCategory::Auth(AuthSubcategory::InvalidCredentials),
+        ),
+        // Unauthorized
+        MongoErrorKind::Command(e) if e.code == 13 => (
+            DiagnosticStage::Authentication,
+            ErrorCategory::Auth(AuthSubcategory::PermissionDenied),
+        ),
+        MongoErrorKind::ServerSelection { message, .. } => {
+            let message = message.to_lowercase();
+            if message.contains("tls") || message.contains("ssl") || message.contains("certificate") {
+                (
+                    DiagnosticStage::Tls,
+                    ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+                )
+            } else if message.contains("auth") {
+                (
+                    DiagnosticStage::Authentication,
+                    ErrorCategory::Auth(AuthSubcategory::InvalidCredentials),
+                )
+            } else if message.contains("refused") {
+                (
+                    DiagnosticStage::Tcp,
+                    ErrorCategory::Connection(ConnectionSubcategory::Refused),
+                )
+            } else {
+                (
+                    DiagnosticStage::Server,
+                    ErrorCategory::Connection(ConnectionSubcategory::Timeout),
+                )
+            }
+        }
+        _ => (
+            DiagnosticStage::Server,
+            ErrorCategory::Database(DatabaseSubcategory::ConnectionFailed),
+        ),
+    }
+}
+
+fn io_category(error: &io::Error) -> ErrorCategory {
+    match error.kind() {
+        io::ErrorKind::ConnectionRefused => ErrorCategory::Connection(ConnectionSubcategory::Refused),
+        io::ErrorKind::TimedOut => ErrorCategory::Connection(ConnectionSubcategory::Timeout),
+        _ => ErrorCategory::Connection(ConnectionSubcategory::ConnectionFailed),
+    }
+}
+
+/// Report whether the session is encrypted, given the protocol version if it is
+///
+/// An unencrypted session is only worth a warning when TLS was expected, which it is
+/// unless disabled explicitly or, without TLS settings, for loopback hosts.
+fn report_encryption(
+    encryption: Option<Option<String>>,
+    tls: Option<&TlsSettings>,
+    host: &str,
+    recorder: &mut Recorder,
+) {
+    match encryption {
+        Some(Some(version)) => recorder.pass(DiagnosticStage::Tls, format!("Encrypted with {}", version), None),
+        Some(None) => recorder.pass(DiagnosticStage::Tls, "Encrypted", None),
+        None => {
+            let expected = match tls {
+                Some(tls) => tls.mode != TlsMode::Disable,
+                None => !disable_tls_for_loopback(host, tcp_host_for_local_connect(host)),
+            };
+            if expected {
+                recorder.warn(
+                    DiagnosticStage::Tls,
+                    "The connection is not encrypted",
+                    ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+                );
+            } else {
+                recorder.skip(DiagnosticStage::Tls, "TLS is disabled for this connection");
+            }
+        }
+    }
+}
+
+fn report_login(connection: &NewConnection, took: Duration, recorder: &mut Recorder) {
+    let username = connection.username.trim();
+    let detail = if username.is_empty() {
+        "Connected without credentials".to_string()
+    } else {
+        format!("Logged in as {}", username)
+    };
+    recorder.steps.push(DiagnosticStep {
+        stage: DiagnosticStage::Authentication,
+        status: StepStatus::Passed,
+        detail,
+        duration_ms: Some(took.as_millis() as u64),
+        category: None,
+    });
+}
+
+/// Details the server would not report do not fail the test
+fn report_server<E: ToString>(server: Result<ServerInfo, E>, recorder: &mut Recorder) {
+    match server {
+        Ok(server) => {
+            let mut detail = format!("{} {}", server.product, server.version);
+            if server.read_only == Some(true) {
+                detail.push_str(", read-only");
+            }
+            recorder.pass(DiagnosticStage::Server, detail, None);
+            recorder.server = Some(server);
+        }
+        Err(e) => recorder.warn(
+            DiagnosticStage::Server,
+            format!("Could not read the server details: {}", e.to_string()),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+        ),
+    }
+}
+
+fn report_database<E: ToString>(database: Result<Option<String>, E>, recorder: &mut Recorder) {
+    match database {
+        Ok(Some(database)) => recorder.pass(DiagnosticStage::Database, format!("Using database {}", database), None),
+        Ok(None) => recorder.skip(DiagnosticStage::Database, "No default database selected"),
+        Err(e) => recorder.warn(
+            DiagnosticStage::Database,
+            format!("Could not read the selected database: {}", e.to_string()),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::net::TcpListener;
+
+    fn connection(db_type: DbType, host: &str, port: &str, database: &str) -> NewConnection {
+        NewConnection {
+            connection_name: String::new(),
+            project_id: None,
+            db_type,
+            host: host.to_string(),
+            port: port.to_string(),
+            username: "dewey".to_string(),
+            password: "secret".to_string(),
+            database: database.to_string(),
+            statement_timeout_ms: None,
+            read_only: false,
+            ssh_tunnel: None,
+            tls: None,
+            options: BTreeMap::new(),
+        }
+    }
+
+    fn stages(report: &ConnectionReport) -> Vec<(DiagnosticStage, StepStatus)> {
+        report.steps.iter().map(|step| (step.stage, step.status)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_refused_connection_fails_at_tcp() {
+        // Bind and release a port so nothing is listening on it
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+        let report = diagnose(&connection(DbType::Postgres, "127.0.0.1", &port.to_string(), "db")).await;
+        assert!(!report.success);
+        assert_eq!(
+            stages(&report),
+            vec![
+                (DiagnosticStage::Dns, StepStatus::Passed),
+                (DiagnosticStage::Tcp, StepStatus::Failed),
+            ]
+        );
+        assert_eq!(
+            report.error.unwrap().category,
+            ErrorCategory::Connection(ConnectionSubcategory::Refused)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_report() {
+        let dir = std::env::temp_dir().join(format!("dewey-diagnostics-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.db");
+        let path = path.to_str().unwrap();
+
+        let mut settings = connection(DbType::Sqlite, "localhost", "0", path);
+        settings.password.clear();
+        let report = diagnose(&settings).await;
+        assert_eq!(report.error.unwrap().category, ErrorCategory::Io(IoSubcategory::PathNotFound));
+
+        // Create-if-missing only checks the directory, and the test never creates anything
+        settings.options.insert("mode".to_string(), "rwc".to_string());
+        assert!(diagnose(&settings).await.success);
+        assert!(!Path::new(path).exists());
+        let nested = dir.join("missing").join("test.db");
+        settings.database = nested.to_str().unwrap().to_string();
+        let report = diagnose(&settings).await;
+        assert_eq!(report.error.unwrap().category, ErrorCategory::Io(IoSubcategory::PathNotFound));
+        assert!(!dir.join("missing").exists());
+        settings.database = path.to_string();
+        settings.options.clear();
+
+        fs::write(path, b"not a database at all").unwrap();
+        let report = diagnose(&settings).await;
+        assert_eq!(
+            report.error.unwrap().category,
+            ErrorCategory::Validation(ValidationSubcategory::InvalidFormat)
+        );
+
+        fs::remove_file(path).unwrap();
+        SqliteConnectOptions::from_str(&format!("sqlite://{}", path))
+            .unwrap()
+            .create_if_missing(true)
+            .connect()
+            .await
+            .unwrap()
+            .close()
+            .await
+            .unwrap();
+        let report = diagnose(&settings).await;
+        assert!(report.success);
+        assert_eq!(
+            stages(&report),
+            vec![
+                (DiagnosticStage::Database, StepStatus::Passed),
+                (DiagnosticStage::Server, StepStatus::Passed),
+            ]
+        );
+        let server = report.server.unwrap();
+        assert_eq!(server.product, "SQLite");
+        assert!(server.version.starts_with("3."));
+        assert_eq!(server.encoding.as_deref(), Some("UTF-8"));
+        assert_eq!(server.read_only, Some(false));
+        assert!(report.steps[0].detail.contains("journal mode delete, 4096-byte pages"));
+
+        settings.options.insert("mode".to_string(), "ro".to_string());
+        assert_eq!(diagnose(&settings).await.server.unwrap().read_only, Some(true));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,413 @@
+//! MongoDB browsing and document queries.
+//!
+//! Filters, projections, sorts and pipelines arrive as Extended JSON (either
+//! the canonical or relaxed form) and documents are returned as canonical
+//! Extended JSON, so types such as `ObjectId`, `Date` and `Decimal128` survive
+//! the round trip through the frontend unchanged.
+
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::{AggregateOptions, FindOptions};
+use mongodb::results::CollectionType;
+use mongodb::Client as MongoClient;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::constants;
+use crate::error::categories::{ErrorCategory, ValidationSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+
+use super::cancel::{with_timeout, CancelSlot, CancelTarget};
+use super::pool::DatabasePool;
+
+/// A collection or view in a database
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectionInfo {
+    pub name: String,
+    /// `collection`, `view` or `timeseries`
+    pub kind: String,
+}
+
+/// A field seen while sampling a collection, addressed in dot notation
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldInfo {
+    pub path: String,
+    /// BSON type aliases as used by `$type`, e.g. `objectId`, `string`, `date`
+    pub types: Vec<String>,
+    /// Sampled documents containing the field
+    pub occurrences: u64,
+}
+
+/// The field schema inferred from a sample of documents
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaSample {
+    pub sampled: u64,
+    pub fields: Vec<FieldInfo>,
+}
+
+/// Options for a `find` query, each given as Extended JSON
+#[derive(Debug, Clone, Default)]
+pub struct FindRequest {
+    pub filter: Option<JsonValue>,
+    pub projection: Option<JsonValue>,
+    pub sort: Option<JsonValue>,
+    pub skip: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+/// Documents returned by a query, as canonical Extended JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentSet {
+    pub documents: Vec<JsonValue>,
+    /// Whether more documents were available than the limit allowed
+    pub truncated: bool,
+    pub duration_ms: u64,
+}
+
+/// The MongoDB client behind a pool
+///
+/// # Errors
+/// Returns an error if the pool belongs to a SQL connection
+pub fn client(pool: &DatabasePool) -> AppResult<&MongoClient> {
+    match pool {
+        DatabasePool::MongoDb(client) => Ok(client),
+        _ => Err(AppError::new(
+            "Document queries can only be run against a MongoDB connection",
+            ErrorCategory::Validation(ValidationSubcategory::InvalidType),
+            ErrorSeverity::Error,
+        )),
+    }
+}
+
+/// List the databases on the server
+///
+/// # Errors
+/// Returns an error if the server rejected the request
+pub async fn list_databases(client: &MongoClient) -> AppResult<Vec<String>> {
+    let mut names = client.list_database_names(None, None).await?;
+    names.sort();
+    Ok(names)
+}
+
+/// List the collections and views in a database
+///
+/// # Errors
+/// Returns an error if the server rejected the request
+pub async fn list_collections(client: &MongoClient, database: &str) -> AppResult<Vec<CollectionInfo>> {
+    let mut collections: Vec<CollectionInfo> = client
+        .database(database)
+        .list_collections(None, None)
+        .await?
+        .map_ok(|spec| CollectionInfo {
+            name: spec.name,
+            kind: match spec.collection_type {
+                CollectionType::View => "view",
+                CollectionType::Timeseries => "timeseries",
+                _ => "collection",
+            }
+            .to_string(),
+        })
+        .try_collect()
+        .await?;
+    collections.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(collections)
+}
+
+/// Infer a collection's fields from a random sample of its documents
+///
+/// # Errors
+/// Returns an error if the server rejected the sampling pipeline
+pub async fn infer_schema(
+    client: &MongoClient,
+    database: &str,
+    collection: &str,
+    sample_size: Option<u32>,
+) -> AppResult<SchemaSample> {
+    let sample_size = sample_size
+        .unwrap_or(constants::documents::SCHEMA_SAMPLE_SIZE)
+        .clamp(1, constants::documents::MAX_SCHEMA_SAMPLE_SIZE);
+    let documents: Vec<Document> = client
+        .database(database)
+        .collection::<Document>(collection)
+        .aggregate([doc! { "$sample": { "size": sample_size } }], None)
+        .await?
+        .try_collect()
+        .await?;
+
+    Ok(schema_of(&documents))
+}
+
+/// Run a `find` query
+///
+/// The query is tagged with `comment` and recorded in `slot`, so it can be killed
+/// on the server while it runs or once it exceeds `timeout`.
+///
+/// # Errors
+/// Returns a validation error for malformed Extended JSON, a `Connection(Timeout)`
+/// error if the query timed out, or the server's error
+pub async fn find(
+    client: &MongoClient,
+    database: &str,
+    collection: &str,
+    request: FindRequest,
+    comment: String,
+    slot: &CancelSlot,
+    timeout: Option<Duration>,
+) -> AppResult<DocumentSet> {
+    let filter = request.filter.map(|filter| to_document(filter, "filter")).transpose()?;
+    let limit = clamp_limit(request.limit);
+
+    let mut options = FindOptions::default();
+    options.projection = request
+        .projection
+        .map(|projection| to_document(projection, "projection"))
+        .transpose()?;
+    options.sort = request.sort.map(|sort| to_document(sort, "sort")).transpose()?;
+    options.skip = request.skip;
+    // Ask for one extra document to learn whether the result was truncated
+    options.limit = i64::try_from(limit + 1).ok();
+    options.comment_bson = Some(Bson::String(comment.clone()));
+
+    let collection = client.database(database).collection::<Document>(collection);
+    let _guard = slot.arm(CancelTarget::MongoDb {
+        client: client.clone(),
+        comment,
+    });
+    let started = Instant::now();
+    let documents = with_timeout(timeout, slot, async {
+        let cursor = collection.find(filter, options).await?;
+        take_documents(cursor, limit).await
+    })
+    .await?;
+
+    Ok(document_set(documents, limit, started))
+}
+
+/// Run an aggregation pipeline
+///
+/// Tagging, cancellation and timeouts work as for [`find`]. At most `limit`
+/// documents are returned.
+///
+/// # Errors
+/// Returns a validation error for malformed Extended JSON, a `Connection(Timeout)`
+/// error if the pipeline timed out, or the server's error
+#[allow(clippy::too_many_arguments)]
+pub async fn aggregate(
+    client: &MongoClient,
+    database: &str,
+    collection: &str,
+    pipeline: Vec<JsonValue>,
+    limit: Option<usize>,
+    comment: String,
+    slot: &CancelSlot,
+    timeout: Option<Duration>,
+) -> AppResult<DocumentSet> {
+    let pipeline = pipeline
+        .into_iter()
+        .map(|stage| to_document(stage, "pipeline stage"))
+        .collect::<AppResult<Vec<_>>>()?;
+    let limit = clamp_limit(limit);
+
+    let mut options = AggregateOptions::default();
+    options.allow_disk_use = Some(true);
+    options.comment_bson = Some(Bson::String(comment.clone()));
+
+    let collection = client.database(database).collection::<Document>(collection);
+    let _guard = slot.arm(CancelTarget::MongoDb {
+        client: client.clone(),
+        comment,
+    });
+    let started = Instant::now();
+    let documents = with_timeout(timeout, slot, async {
+        let cursor = collection.aggregate(pipeline, options).await?;
+        take_documents(cursor, limit).await
+    })
+    .await?;
+
+    Ok(document_set(documents, limit, started))
+}
+
+/// Read up to `limit + 1` documents, the extra one only signalling truncation
+async fn take_documents(
+    mut cursor: mongodb::Cursor<Document>,
+    limit: usize,
+) -> AppResult<Vec<Document>> {
+    let mut documents = Vec::new();
+    while documents.len() <= limit {
+        match cursor.try_next().await? {
+            Some(document) => documents.push(document),
+            None => break,
+        }
+    }
+    Ok(documents)
+}
+
+fn document_set(mut documents: Vec<Document>, limit: usize, started: Instant) -> DocumentSet {
+    let truncated = documents.len() > limit;
+    documents.truncate(limit);
+    let duration_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+    debug!("Query returned {} documents in {}ms", documents.len(), duration_ms);
+
+    DocumentSet {
+        documents: documents
+            .into_iter()
+            .map(|document| Bson::Document(document).into_canonical_extjson())
+            .collect(),
+        truncated,
+        duration_ms,
+    }
+}
+
+fn clamp_limit(limit: Option<usize>) -> usize {
+    limit
+        .unwrap_or(constants::documents::DEFAULT_LIMIT)
+        .clamp(1, constants::documents::MAX_LIMIT)
+}
+
+/// Parse an Extended JSON object into a BSON document
+fn to_document(value: JsonValue, what: &str) -> AppResult<Document> {
+    match Bson::try_from(value) {
+        Ok(Bson::Document(document)) => Ok(document),
+        Ok(_) => Err(invalid_json(what, "expected an object")),
+        Err(e) => Err(invalid_json(what, &e.to_string())),
+    }
+}
+
+fn invalid_json(what: &str, reason: &str) -> AppError {
+    AppError::new(
+        format!("Invalid {}: {}", what, reason),
+        ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+        ErrorSeverity::Error,
+    )
+}
+
+/// Collect every field path and the types seen for it
+fn schema_of(documents: &[Document]) -> SchemaSample {
+    let mut fields: BTreeMap<String, (BTreeSet<&'static str>, u64)> = BTreeMap::new();
+    for document in documents {
+        let mut seen = BTreeSet::new();
+        visit_fields(document, "", &mut fields, &mut seen);
+    }
+
+    SchemaSample {
+        sampled: documents.len() as u64,
+        fields: fields
+            .into_iter()
+            .map(|(path, (types, occurrences))| FieldInfo {
+                path,
+                types: types.into_iter().map(str::to_string).collect(),
+                occurrences,
+            })
+            .collect(),
+    }
+}
+
+/// Record the fields of one (possibly nested) document
+///
+/// `seen` holds the paths already counted for the current top-level document,
+/// so a field inside an array of documents counts that document only once.
+fn visit_fields(
+    document: &Document,
+    prefix: &str,
+    fields: &mut BTreeMap<String, (BTreeSet<&'static str>, u64)>,
+    seen: &mut BTreeSet<String>,
+) {
+    for (key, value) in document {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        let entry = fields.entry(path.clone()).or_default();
+        entry.0.insert(type_name(value));
+        if seen.insert(path.clone()) {
+            entry.1 += 1;
+        }
+
+        match value {
+            Bson::Document(nested) => visit_fields(nested, &path, fields, seen),
+            Bson::Array(items) => {
+                for item in items {
+                    if let Bson::Document(nested) = item {
+                        visit_fields(nested, &path, fields, seen);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The `$type` alias for a value
+fn type_name(value: &Bson) -> &'static str {
+    match value {
+        Bson::Double(_) => "double",
+        Bson::String(_) => "string",
+        Bson::Array(_) => "array",
+        Bson::Document(_) => "object",
+        Bson::Boolean(_) => "bool",
+        Bson::Null => "null",
+        Bson::RegularExpression(_) => "regex",
+        Bson::JavaScriptCode(_) => "javascript",
+        Bson::JavaScriptCodeWithScope(_) => "javascriptWithScope",
+        Bson::Int32(_) => "int",
+        Bson::Int64(_) => "long",
+        Bson::Timestamp(_) => "timestamp",
+        Bson::Binary(_) => "binData",
+        Bson::ObjectId(_) => "objectId",
+        Bson::DateTime(_) => "date",
+        Bson::Symbol(_) => "symbol",
+        Bson::Decimal128(_) => "decimal",
+        Bson::Undefined => "undefined",
+        Bson::MaxKey => "maxKey",
+        Bson::MinKey => "minKey",
+        Bson::DbPointer(_) => "dbPointer",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::oid::ObjectId;
+    use serde_json::json;
+
+    #[test]
+    fn test_extended_json_round_trips() {
+        let filter = to_document(
+            json!({ "_id": { "$oid": "65f1a2b3c4d5e6f708091a2b" }, "n": { "$gt": 5 } }),
+            "filter",
+        )
+        .unwrap();
+        assert!(matches!(filter.get("_id"), Some(Bson::ObjectId(_))));
+        assert!(to_document(json!([1, 2]), "filter").is_err());
+
+        let set = document_set(
+            vec![doc! { "_id": ObjectId::parse_str("65f1a2b3c4d5e6f708091a2b").unwrap(), "n": 7 }],
+            1,
+            Instant::now(),
+        );
+        assert_eq!(
+            set.documents[0],
+            json!({ "_id": { "$oid": "65f1a2b3c4d5e6f708091a2b" }, "n": { "$numberInt": "7" } })
+        );
+        assert!(!set.truncated);
+    }
+
+    #[test]
+    fn test_schema_inference_counts_fields_per_document() {
+        let sample = schema_of(&[
+            doc! { "name": "a", "tags": [{ "k": 1 }, { "k": 2 }] },
+            doc! { "name": 3_i64, "address": { "city": "Oslo" } },
+        ]);
+
+        let field = |path: &str| sample.fields.iter().find(|field| field.path == path).unwrap();
+        assert_eq!(sample.sampled, 2);
+        assert_eq!(field("name").types, vec!["long", "string"]);
+        assert_eq!(field("name").occurrences, 2);
+        assert_eq!(field("tags.k").occurrences, 1);
+        assert_eq!(field("address.city").types, vec!["string"]);
+    }
+}
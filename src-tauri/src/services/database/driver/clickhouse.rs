@@ -0,0 +1,84 @@
+//! ClickHouse, over its HTTP interface.
+
+use async_trait::async_trait;
+
+use crate::error::AppResult;
+use crate::services::database::cancel::{CancelSlot, CancelTarget};
+use crate::services::database::clickhouse;
+use crate::services::database::clickhouse_client;
+use crate::services::database::diagnostics::{self, Recorder, StepResult};
+use crate::services::database::pool::DatabasePool;
+use crate::services::database::query::DbValue;
+use crate::services::introspection::{self, Catalog};
+use crate::services::storage::repositories::connections::{Connection, DbType, NewConnection};
+
+use super::{mismatched, quote_with, Capabilities, DatabaseDriver, Endpoint, StatementResult};
+
+pub(super) struct ClickHouseDriver;
+
+#[async_trait]
+impl DatabaseDriver for ClickHouseDriver {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            sql: true,
+            introspection: true,
+            cancel: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn quote_identifier(&self, name: &str) -> String {
+        quote_with(name, '`', '`')
+    }
+
+    async fn connect(&self, connection: &Connection, endpoint: Endpoint<'_>) -> Result<DatabasePool, String> {
+        let client = clickhouse_client(
+            endpoint.host,
+            endpoint.port,
+            connection.username.trim(),
+            &connection.password,
+            connection.database.trim(),
+            connection.tls.as_ref(),
+        )?;
+        let client = if connection.read_only { client.read_only() } else { client };
+        // Each statement is its own request, so check the login once up front
+        client.fetch("SELECT 1", &[], None).await.map_err(|e| e.message)?;
+        Ok(DatabasePool::ClickHouse(client))
+    }
+
+    async fn test(&self, connection: &NewConnection, endpoint: Endpoint<'_>, recorder: &mut Recorder) -> StepResult<()> {
+        diagnostics::clickhouse_server(connection, endpoint.host, endpoint.port, recorder).await
+    }
+
+    async fn execute(
+        &self,
+        pool: &DatabasePool,
+        sql: &str,
+        params: &[DbValue],
+        slot: &CancelSlot,
+    ) -> AppResult<StatementResult> {
+        let DatabasePool::ClickHouse(client) = pool else {
+            return Err(mismatched(DbType::ClickHouse));
+        };
+        let query_id = clickhouse::query_id();
+        let _guard = slot.arm(CancelTarget::ClickHouse {
+            client: client.clone(),
+            query_id: query_id.clone(),
+        });
+        client.fetch(sql, params, Some(&query_id)).await
+    }
+
+    async fn introspect(&self, pool: &DatabasePool) -> AppResult<Catalog> {
+        let DatabasePool::ClickHouse(client) = pool else {
+            return Err(mismatched(DbType::ClickHouse));
+        };
+        introspection::clickhouse::introspect(client).await
+    }
+
+    async fn cancel(&self, target: CancelTarget) -> AppResult<()> {
+        let CancelTarget::ClickHouse { client, query_id } = target else {
+            return Err(mismatched(DbType::ClickHouse));
+        };
+        client.kill(&query_id).await
+    }
+}
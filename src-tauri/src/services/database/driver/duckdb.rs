@@ -0,0 +1,82 @@
+//! DuckDB database files and Parquet, CSV and JSON files, through embedded DuckDB.
+
+use async_trait::async_trait;
+
+use crate::error::AppResult;
+use crate::services::database::cancel::{CancelSlot, CancelTarget};
+use crate::services::database::diagnostics::{self, Recorder, StepResult};
+use crate::services::database::duckdb::{self, DuckDbInterrupt, DuckDbPool};
+use crate::services::database::is_local_file;
+use crate::services::database::pool::DatabasePool;
+use crate::services::database::query::DbValue;
+use crate::services::introspection::{self, Catalog};
+use crate::services::storage::repositories::connections::{Connection, DbType, NewConnection};
+
+use super::{mismatched, quote_with, Capabilities, DatabaseDriver, Endpoint, StatementResult};
+
+pub(super) struct DuckDbDriver;
+
+#[async_trait]
+impl DatabaseDriver for DuckDbDriver {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            sql: true,
+            introspection: true,
+            cancel: true,
+            local_file: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn quote_identifier(&self, name: &str) -> String {
+        quote_with(name, '"', '"')
+    }
+
+    async fn connect(&self, connection: &Connection, endpoint: Endpoint<'_>) -> Result<DatabasePool, String> {
+        if !is_local_file(endpoint.host, endpoint.port) {
+            return Err("DuckDB connections must point at a local file".to_string());
+        }
+        let database = connection.database.trim();
+        if database.is_empty() {
+            return Err("DuckDB file path cannot be empty".to_string());
+        }
+        let pool = DuckDbPool::open(database, connection.read_only).await.map_err(|e| e.message)?;
+        Ok(DatabasePool::DuckDb(pool))
+    }
+
+    async fn test(&self, connection: &NewConnection, _endpoint: Endpoint<'_>, recorder: &mut Recorder) -> StepResult<()> {
+        diagnostics::duckdb(connection, recorder).await
+    }
+
+    async fn execute(
+        &self,
+        pool: &DatabasePool,
+        sql: &str,
+        params: &[DbValue],
+        slot: &CancelSlot,
+    ) -> AppResult<StatementResult> {
+        let DatabasePool::DuckDb(pool) = pool else {
+            return Err(mismatched(DbType::DuckDb));
+        };
+        let conn = pool.connect()?;
+        let _guard = slot.arm(CancelTarget::DuckDb(DuckDbInterrupt::new(&conn)));
+        let (columns, rows) = duckdb::run(conn, sql, params).await?;
+        // Changed rows are reported as a `Count` result rather than a row count
+        Ok((columns, rows, 0))
+    }
+
+    async fn introspect(&self, pool: &DatabasePool) -> AppResult<Catalog> {
+        let DatabasePool::DuckDb(pool) = pool else {
+            return Err(mismatched(DbType::DuckDb));
+        };
+        introspection::duckdb::introspect(pool).await
+    }
+
+    async fn cancel(&self, target: CancelTarget) -> AppResult<()> {
+        let CancelTarget::DuckDb(interrupt) = target else {
+            return Err(mismatched(DbType::DuckDb));
+        };
+        interrupt.interrupt();
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+//! One driver per database engine.
+//!
+//! Everything that differs between engines goes through [`DatabaseDriver`]:
+//! opening a session, testing settings, running statements, reading the
+//! catalog, cancelling and quoting. Callers look the driver up from a
+//! connection's [`DbType`] or from an open [`DatabasePool`] instead of
+//! matching on the engine themselves.
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::error::categories::{ErrorCategory, ValidationSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::introspection::{Catalog, TableInfo};
+use crate::services::storage::repositories::connections::{Connection, DbType, NewConnection};
+
+use super::cancel::{CancelSlot, CancelTarget};
+use super::diagnostics::{Recorder, StepResult};
+use super::pool::DatabasePool;
+use super::query::{ColumnInfo, DbValue};
+
+mod clickhouse;
+mod duckdb;
+mod mongodb;
+mod mssql;
+mod mysql;
+mod postgres;
+mod redis;
+mod sqlite;
+
+/// Columns, rows and rows affected of an executed statement
+pub type StatementResult = (Vec<ColumnInfo>, Vec<Vec<DbValue>>, u64);
+
+/// What an engine supports beyond opening a session
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Capabilities {
+    /// Runs SQL statements, directly or through a cursor
+    pub sql: bool,
+    /// Reports its tables and columns through introspection
+    pub introspection: bool,
+    /// Running statements can be stopped on the server
+    pub cancel: bool,
+    /// Opens a local file instead of reaching a server, so there is no network, TLS or tunnel
+    pub local_file: bool,
+    /// Holds collections of documents
+    pub documents: bool,
+    /// Holds a keyspace of typed values
+    pub keyspace: bool,
+}
+
+/// Where a session or test reaches the server
+#[derive(Debug, Clone, Copy)]
+pub struct Endpoint<'a> {
+    pub host: &'a str,
+    pub port: &'a str,
+    /// Whether this is the loopback end of an SSH tunnel rather than the server itself
+    pub tunnelled: bool,
+}
+
+/// The engine-specific half of every database operation
+#[async_trait]
+pub trait DatabaseDriver: Send + Sync {
+    fn capabilities(&self) -> Capabilities;
+
+    /// Quote a table, column or schema name for use in a statement
+    fn quote_identifier(&self, name: &str) -> String;
+
+    /// Open a pool for a saved connection
+    ///
+    /// Sessions of a read-only connection are opened so the server refuses writes,
+    /// where the engine has a switch for it.
+    ///
+    /// # Errors
+    /// Returns a string error if the settings are invalid or the server could not be reached
+    async fn connect(&self, connection: &Connection, endpoint: Endpoint<'_>) -> Result<DatabasePool, String>;
+
+    /// Test the layers past the network: TLS, login, server and database
+    async fn test(&self, connection: &NewConnection, endpoint: Endpoint<'_>, recorder: &mut Recorder) -> StepResult<()>;
+
+    /// Run one statement and read its whole result, recording its backend in `slot`
+    ///
+    /// # Errors
+    /// Returns an error if the statement failed, or the engine does not speak SQL
+    async fn execute(
+        &self,
+        pool: &DatabasePool,
+        sql: &str,
+        params: &[DbValue],
+        slot: &CancelSlot,
+    ) -> AppResult<StatementResult>;
+
+    /// Read the catalog of everything the connection can see
+    ///
+    /// # Errors
+    /// Returns an error if a catalog query failed, or the engine has no catalog
+    async fn introspect(&self, pool: &DatabasePool) -> AppResult<Catalog>;
+
+    /// Read one table, view or materialized view, or `None` if there is no such object
+    ///
+    /// Engines that can narrow their catalog queries to the table override this; the
+    /// default reads the whole catalog.
+    ///
+    /// # Errors
+    /// Returns an error if a catalog query failed, or the engine has no catalog
+    async fn introspect_table(&self, pool: &DatabasePool, schema: &str, name: &str) -> AppResult<Option<TableInfo>> {
+        Ok(self.introspect(pool).await?.table(schema, name).cloned())
+    }
+
+    /// Ask the server to stop the statement running on `target`
+    ///
+    /// # Errors
+    /// Returns an error if the server could not be asked
+    async fn cancel(&self, target: CancelTarget) -> AppResult<()>;
+}
+
+/// The driver for an engine
+#[must_use]
+pub fn driver(db_type: DbType) -> &'static dyn DatabaseDriver {
+    match db_type {
+        DbType::Postgres => &postgres::PostgresDriver,
+        DbType::MySql => &mysql::MySqlDriver,
+        DbType::Sqlite => &sqlite::SqliteDriver,
+        DbType::MongoDb => &mongodb::MongoDbDriver,
+        DbType::MsSql => &mssql::MsSqlDriver,
+        DbType::Redis => &redis::RedisDriver,
+        DbType::ClickHouse => &clickhouse::ClickHouseDriver,
+        DbType::DuckDb => &duckdb::DuckDbDriver,
+    }
+}
+
+/// The error reported when a driver is handed a session of another engine
+fn mismatched(expected: DbType) -> AppError {
+    AppError::new(
+        format!("Expected a {} session", expected),
+        ErrorCategory::Validation(ValidationSubcategory::InvalidType),
+        ErrorSeverity::Error,
+    )
+}
+
+/// Quote a name between `open` and `close`, doubling any `close` inside it
+fn quote_with(name: &str, open: char, close: char) -> String {
+    let mut quoted = String::with_capacity(name.len() + 2);
+    quoted.push(open);
+    for c in name.chars() {
+        if c == close {
+            quoted.push(close);
+        }
+        quoted.push(c);
+    }
+    quoted.push(close);
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identifiers_are_quoted_per_engine() {
+        assert_eq!(driver(DbType::Postgres).quote_identifier("order \"items\""), "\"order \"\"items\"\"\"");
+        assert_eq!(driver(DbType::MySql).quote_identifier("a`b"), "`a``b`");
+        assert_eq!(driver(DbType::MsSql).quote_identifier("a]b"), "[a]]b]");
+        assert_eq!(driver(DbType::ClickHouse).quote_identifier("a`b"), "`a``b`");
+    }
+}
@@ -0,0 +1,91 @@
+//! MongoDB, through the official driver.
+
+use async_trait::async_trait;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::Client as MongoClient;
+
+use crate::constants;
+use crate::error::AppResult;
+use crate::services::database::cancel::{CancelSlot, CancelTarget};
+use crate::services::database::diagnostics::{self, Recorder, StepResult};
+use crate::services::database::mongodb_options;
+use crate::services::database::pool::DatabasePool;
+use crate::services::database::query::{not_sql, DbValue};
+use crate::services::introspection::{self, Catalog};
+use crate::services::storage::repositories::connections::{Connection, DbType, NewConnection};
+
+use super::{mismatched, Capabilities, DatabaseDriver, Endpoint, StatementResult};
+
+pub(super) struct MongoDbDriver;
+
+#[async_trait]
+impl DatabaseDriver for MongoDbDriver {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            cancel: true,
+            documents: true,
+            ..Capabilities::default()
+        }
+    }
+
+    /// Collection and field names are passed as values, never spliced into a query
+    fn quote_identifier(&self, name: &str) -> String {
+        name.to_string()
+    }
+
+    async fn connect(&self, connection: &Connection, endpoint: Endpoint<'_>) -> Result<DatabasePool, String> {
+        let mut opts = mongodb_options(
+            endpoint.host,
+            endpoint.port,
+            connection.username.trim(),
+            &connection.password,
+            connection.tls.as_ref(),
+            &connection.options,
+        )
+        .await?;
+        opts.max_pool_size = Some(constants::sessions::MAX_POOL_CONNECTIONS);
+        if endpoint.tunnelled {
+            // Replica set members advertise addresses the tunnel does not cover
+            opts.direct_connection = Some(true);
+        }
+        let client = MongoClient::with_options(opts).map_err(|e| e.to_string())?;
+        Ok(DatabasePool::MongoDb(client))
+    }
+
+    async fn test(&self, connection: &NewConnection, endpoint: Endpoint<'_>, recorder: &mut Recorder) -> StepResult<()> {
+        diagnostics::mongodb(connection, endpoint.host, endpoint.port, endpoint.tunnelled, recorder).await
+    }
+
+    async fn execute(
+        &self,
+        _pool: &DatabasePool,
+        _sql: &str,
+        _params: &[DbValue],
+        _slot: &CancelSlot,
+    ) -> AppResult<StatementResult> {
+        Err(not_sql())
+    }
+
+    async fn introspect(&self, _pool: &DatabasePool) -> AppResult<Catalog> {
+        Err(introspection::not_sql())
+    }
+
+    async fn cancel(&self, target: CancelTarget) -> AppResult<()> {
+        let CancelTarget::MongoDb { client, comment } = target else {
+            return Err(mismatched(DbType::MongoDb));
+        };
+        let admin = client.database("admin");
+        let current = admin
+            .run_command(doc! { "currentOp": 1, "command.comment": &comment }, None)
+            .await?;
+        let ops = current.get_array("inprog").cloned().unwrap_or_default();
+        for op in ops {
+            if let Some(opid) = op.as_document().and_then(|op: &Document| op.get("opid")) {
+                admin
+                    .run_command(doc! { "killOp": 1, "op": Bson::clone(opid) }, None)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
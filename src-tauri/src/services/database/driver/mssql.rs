@@ -0,0 +1,95 @@
+//! Microsoft SQL Server and Azure SQL, through tiberius.
+
+use async_trait::async_trait;
+
+use crate::constants;
+use crate::error::AppResult;
+use crate::services::database::cancel::{CancelSlot, CancelTarget};
+use crate::services::database::diagnostics::{self, Recorder, StepResult};
+use crate::services::database::mssql::{self, MssqlPool};
+use crate::services::database::mssql_config;
+use crate::services::database::pool::DatabasePool;
+use crate::services::database::query::DbValue;
+use crate::services::introspection::{self, Catalog};
+use crate::services::storage::repositories::connections::{Connection, DbType, NewConnection};
+
+use super::{mismatched, quote_with, Capabilities, DatabaseDriver, Endpoint, StatementResult};
+
+pub(super) struct MsSqlDriver;
+
+#[async_trait]
+impl DatabaseDriver for MsSqlDriver {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            sql: true,
+            introspection: true,
+            cancel: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn quote_identifier(&self, name: &str) -> String {
+        quote_with(name, '[', ']')
+    }
+
+    async fn connect(&self, connection: &Connection, endpoint: Endpoint<'_>) -> Result<DatabasePool, String> {
+        let config = mssql_config(
+            endpoint.host,
+            endpoint.port,
+            connection.username.trim(),
+            &connection.password,
+            connection.database.trim(),
+            connection.tls.as_ref(),
+            &connection.options,
+        )?;
+        let pool = MssqlPool::connect(config, constants::sessions::MAX_POOL_CONNECTIONS)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(DatabasePool::MsSql(pool))
+    }
+
+    async fn test(&self, connection: &NewConnection, endpoint: Endpoint<'_>, recorder: &mut Recorder) -> StepResult<()> {
+        diagnostics::mssql_server(connection, endpoint.host, endpoint.port, recorder).await
+    }
+
+    async fn execute(
+        &self,
+        pool: &DatabasePool,
+        sql: &str,
+        params: &[DbValue],
+        slot: &CancelSlot,
+    ) -> AppResult<StatementResult> {
+        let DatabasePool::MsSql(pool) = pool else {
+            return Err(mismatched(DbType::MsSql));
+        };
+        let mut conn = pool.acquire().await?;
+        let _guard = slot.arm(CancelTarget::mssql(pool, &mut conn).await?);
+        let stream = mssql::query(&mut conn, sql, params).await?;
+        let (columns, rows) = mssql::collect(stream).await?;
+        conn.release();
+        // The TDS client does not report row counts for queried statements
+        Ok((columns.unwrap_or_default(), rows, 0))
+    }
+
+    async fn introspect(&self, pool: &DatabasePool) -> AppResult<Catalog> {
+        let DatabasePool::MsSql(pool) = pool else {
+            return Err(mismatched(DbType::MsSql));
+        };
+        introspection::mssql::introspect(pool).await
+    }
+
+    async fn cancel(&self, target: CancelTarget) -> AppResult<()> {
+        let CancelTarget::MsSql { pool, session_id } = target else {
+            return Err(mismatched(DbType::MsSql));
+        };
+        let mut client = pool.connect_detached().await?;
+        // SQL Server has no per-statement cancel from another session, so the
+        // session is killed; its client is closed rather than returned to the pool
+        client
+            .execute(format!("KILL {}", session_id), &[])
+            .await
+            .map_err(mssql::mssql_error)?;
+        let _ = client.close().await;
+        Ok(())
+    }
+}
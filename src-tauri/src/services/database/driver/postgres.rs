@@ -0,0 +1,119 @@
+//! PostgreSQL, through sqlx.
+
+use async_trait::async_trait;
+use sqlx::postgres::{PgConnection, PgPoolOptions};
+use sqlx::{Connection as _, Executor};
+
+use crate::constants;
+use crate::error::AppResult;
+use crate::services::database::cancel::{CancelSlot, CancelTarget};
+use crate::services::database::diagnostics::{self, Recorder, StepResult};
+use crate::services::database::pool::DatabasePool;
+use crate::services::database::postgres_options;
+use crate::services::database::query::{
+    bind_all, collect, describe_columns, pg_row_values, query_error, row_columns, DbValue,
+};
+use crate::services::introspection::{self, Catalog, TableInfo};
+use crate::services::storage::repositories::connections::{Connection, DbType, NewConnection};
+
+use super::{mismatched, quote_with, Capabilities, DatabaseDriver, Endpoint, StatementResult};
+
+pub(super) struct PostgresDriver;
+
+#[async_trait]
+impl DatabaseDriver for PostgresDriver {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            sql: true,
+            introspection: true,
+            cancel: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn quote_identifier(&self, name: &str) -> String {
+        quote_with(name, '"', '"')
+    }
+
+    async fn connect(&self, connection: &Connection, endpoint: Endpoint<'_>) -> Result<DatabasePool, String> {
+        let opts = postgres_options(
+            endpoint.host,
+            endpoint.port,
+            connection.username.trim(),
+            &connection.password,
+            connection.database.trim(),
+            connection.tls.as_ref(),
+        )?;
+        let mut pool_options = PgPoolOptions::new().max_connections(constants::sessions::MAX_POOL_CONNECTIONS);
+        if connection.read_only {
+            pool_options = pool_options.after_connect(|conn, _| {
+                Box::pin(async move {
+                    conn.execute("SET SESSION CHARACTERISTICS AS TRANSACTION READ ONLY").await?;
+                    Ok(())
+                })
+            });
+        }
+        let pool = pool_options
+            .connect_with(opts)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(DatabasePool::Postgres(pool))
+    }
+
+    async fn test(&self, connection: &NewConnection, endpoint: Endpoint<'_>, recorder: &mut Recorder) -> StepResult<()> {
+        diagnostics::postgres(connection, endpoint.host, endpoint.port, recorder).await
+    }
+
+    async fn execute(
+        &self,
+        pool: &DatabasePool,
+        sql: &str,
+        params: &[DbValue],
+        slot: &CancelSlot,
+    ) -> AppResult<StatementResult> {
+        let DatabasePool::Postgres(pool) = pool else {
+            return Err(mismatched(DbType::Postgres));
+        };
+        let mut conn = pool.acquire().await.map_err(query_error)?;
+        let _guard = slot.arm(CancelTarget::postgres(pool, &mut conn).await?);
+        let columns = describe_columns(conn.describe(sql).await);
+        let query = bind_all(sqlx::query(sql), params)?;
+        let (rows, rows_affected) = collect(conn.fetch_many(query)).await?;
+        let values = rows
+            .iter()
+            .map(pg_row_values)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(query_error)?;
+        Ok((columns.unwrap_or_else(|| row_columns(rows.first())), values, rows_affected))
+    }
+
+    async fn introspect(&self, pool: &DatabasePool) -> AppResult<Catalog> {
+        let DatabasePool::Postgres(pool) = pool else {
+            return Err(mismatched(DbType::Postgres));
+        };
+        introspection::postgres::introspect(pool).await
+    }
+
+    async fn introspect_table(&self, pool: &DatabasePool, schema: &str, name: &str) -> AppResult<Option<TableInfo>> {
+        let DatabasePool::Postgres(pool) = pool else {
+            return Err(mismatched(DbType::Postgres));
+        };
+        introspection::postgres::table(pool, schema, name).await
+    }
+
+    async fn cancel(&self, target: CancelTarget) -> AppResult<()> {
+        let CancelTarget::Postgres { pool, backend_pid } = target else {
+            return Err(mismatched(DbType::Postgres));
+        };
+        let mut conn = PgConnection::connect_with(&pool.connect_options())
+            .await
+            .map_err(query_error)?;
+        sqlx::query("SELECT pg_cancel_backend($1)")
+            .bind(backend_pid)
+            .execute(&mut conn)
+            .await
+            .map_err(query_error)?;
+        let _ = conn.close().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+//! Redis and Valkey, through redis-rs.
+
+use async_trait::async_trait;
+
+use crate::error::AppResult;
+use crate::services::database::cancel::{CancelSlot, CancelTarget};
+use crate::services::database::diagnostics::{self, Recorder, StepResult};
+use crate::services::database::keyspace::RedisPool;
+use crate::services::database::pool::DatabasePool;
+use crate::services::database::query::{not_sql, DbValue};
+use crate::services::database::redis_client;
+use crate::services::introspection::{self, Catalog};
+use crate::services::storage::repositories::connections::{Connection, DbType, NewConnection};
+
+use super::{mismatched, Capabilities, DatabaseDriver, Endpoint, StatementResult};
+
+pub(super) struct RedisDriver;
+
+#[async_trait]
+impl DatabaseDriver for RedisDriver {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            keyspace: true,
+            ..Capabilities::default()
+        }
+    }
+
+    /// Keys are passed as command arguments, never spliced into a command
+    fn quote_identifier(&self, name: &str) -> String {
+        name.to_string()
+    }
+
+    async fn connect(&self, connection: &Connection, endpoint: Endpoint<'_>) -> Result<DatabasePool, String> {
+        let client = redis_client(
+            endpoint.host,
+            endpoint.port,
+            connection.username.trim(),
+            &connection.password,
+            connection.database.trim(),
+            connection.tls.as_ref(),
+        )?;
+        let pool = RedisPool::connect(client).await.map_err(|e| e.to_string())?;
+        Ok(DatabasePool::Redis(pool))
+    }
+
+    async fn test(&self, connection: &NewConnection, endpoint: Endpoint<'_>, recorder: &mut Recorder) -> StepResult<()> {
+        diagnostics::redis_server(connection, endpoint.host, endpoint.port, recorder).await
+    }
+
+    async fn execute(
+        &self,
+        _pool: &DatabasePool,
+        _sql: &str,
+        _params: &[DbValue],
+        _slot: &CancelSlot,
+    ) -> AppResult<StatementResult> {
+        Err(not_sql())
+    }
+
+    async fn introspect(&self, _pool: &DatabasePool) -> AppResult<Catalog> {
+        Err(introspection::not_sql())
+    }
+
+    /// Console commands are not tracked, so there is never anything to cancel
+    async fn cancel(&self, _target: CancelTarget) -> AppResult<()> {
+        Err(mismatched(DbType::Redis))
+    }
+}
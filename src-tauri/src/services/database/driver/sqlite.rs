@@ -0,0 +1,115 @@
+//! SQLite database files, through sqlx.
+
+use async_trait::async_trait;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::Executor;
+use std::sync::atomic::Ordering;
+
+use crate::constants;
+use crate::error::AppResult;
+use crate::services::database::cancel::{CancelSlot, CancelTarget};
+use crate::services::database::diagnostics::{self, Recorder, StepResult};
+use crate::services::database::{is_local_file, sqlite_options, SqliteMode};
+use crate::services::database::pool::DatabasePool;
+use crate::services::database::query::{
+    bind_all, collect, query_error, row_columns, sqlite_columns, sqlite_row_values, DbValue,
+};
+use crate::services::introspection::{self, Catalog, TableInfo};
+use crate::services::storage::repositories::connections::{Connection, DbType, NewConnection};
+
+use super::{mismatched, quote_with, Capabilities, DatabaseDriver, Endpoint, StatementResult};
+
+pub(super) struct SqliteDriver;
+
+#[async_trait]
+impl DatabaseDriver for SqliteDriver {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            sql: true,
+            introspection: true,
+            cancel: true,
+            local_file: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn quote_identifier(&self, name: &str) -> String {
+        quote_with(name, '"', '"')
+    }
+
+    async fn connect(&self, connection: &Connection, endpoint: Endpoint<'_>) -> Result<DatabasePool, String> {
+        if !is_local_file(endpoint.host, endpoint.port) {
+            return Err("SQLite connections must point at a local database file".to_string());
+        }
+        let mut opts = sqlite_options(&connection.database, &connection.password, &connection.options)?;
+        if connection.read_only {
+            opts = opts.read_only(true).create_if_missing(false);
+        }
+        let mut pool_options = SqlitePoolOptions::new().max_connections(constants::sessions::MAX_POOL_CONNECTIONS);
+        if SqliteMode::from_options(&connection.options)? == SqliteMode::Memory {
+            // An in-memory database lives only as long as one of its connections stays open
+            pool_options = pool_options.min_connections(1).idle_timeout(None).max_lifetime(None);
+        }
+        let pool = pool_options
+            // Drop the cancellation handler a tracked statement left behind
+            .after_release(|conn, _| {
+                Box::pin(async move {
+                    conn.lock_handle().await?.remove_progress_handler();
+                    Ok(true)
+                })
+            })
+            .connect_with(opts)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(DatabasePool::Sqlite(pool))
+    }
+
+    async fn test(&self, connection: &NewConnection, _endpoint: Endpoint<'_>, recorder: &mut Recorder) -> StepResult<()> {
+        diagnostics::sqlite(connection, recorder).await
+    }
+
+    async fn execute(
+        &self,
+        pool: &DatabasePool,
+        sql: &str,
+        params: &[DbValue],
+        slot: &CancelSlot,
+    ) -> AppResult<StatementResult> {
+        let DatabasePool::Sqlite(pool) = pool else {
+            return Err(mismatched(DbType::Sqlite));
+        };
+        let mut conn = pool.acquire().await.map_err(query_error)?;
+        let _guard = slot.arm(CancelTarget::sqlite(&mut conn).await?);
+        let columns = sqlite_columns(conn.prepare(sql).await);
+        let query = bind_all(sqlx::query(sql), params)?;
+        let (rows, rows_affected) = collect(conn.fetch_many(query)).await?;
+        let values = rows
+            .iter()
+            .map(sqlite_row_values)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(query_error)?;
+        Ok((columns.unwrap_or_else(|| row_columns(rows.first())), values, rows_affected))
+    }
+
+    async fn introspect(&self, pool: &DatabasePool) -> AppResult<Catalog> {
+        let DatabasePool::Sqlite(pool) = pool else {
+            return Err(mismatched(DbType::Sqlite));
+        };
+        introspection::sqlite::introspect(pool).await
+    }
+
+    async fn introspect_table(&self, pool: &DatabasePool, schema: &str, name: &str) -> AppResult<Option<TableInfo>> {
+        let DatabasePool::Sqlite(pool) = pool else {
+            return Err(mismatched(DbType::Sqlite));
+        };
+        introspection::sqlite::table(pool, schema, name).await
+    }
+
+    async fn cancel(&self, target: CancelTarget) -> AppResult<()> {
+        let CancelTarget::Sqlite(cancelled) = target else {
+            return Err(mismatched(DbType::Sqlite));
+        };
+        cancelled.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
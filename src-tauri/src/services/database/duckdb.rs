@@ -0,0 +1,500 @@
+//! Embedded DuckDB sessions for local database and data files.
+//!
+//! A connection's path either names a DuckDB database file, which is opened as
+//! is, or a Parquet, CSV or JSON file, which is exposed as a view named after
+//! the file in an in-memory database. Either way any other local file can be
+//! queried with DuckDB's own `read_parquet`, `read_csv` and `read_json`.
+//!
+//! DuckDB runs statements on the calling thread, so every statement runs on a
+//! blocking thread with a connection of its own, and is cancelled through that
+//! connection's interrupt handle.
+
+use ::duckdb::arrow::datatypes::{DataType, TimeUnit as ArrowTimeUnit};
+use ::duckdb::types::{TimeUnit, Value, ValueRef};
+use ::duckdb::{params_from_iter, AccessMode, Config, Connection, InterruptHandle, Row, Statement};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use serde_json::Value as JsonValue;
+use std::fmt;
+use std::path::Path;
+use std::sync::{mpsc as std_mpsc, Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::error::categories::{ConnectionSubcategory, DatabaseSubcategory, ErrorCategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+
+use super::query::{ColumnInfo, DbValue};
+
+/// What a DuckDB connection's path points at, told apart by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Database,
+    Parquet,
+    Csv,
+    Json,
+}
+
+impl FileKind {
+    #[must_use]
+    pub fn of(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("parquet") => Self::Parquet,
+            Some("csv" | "tsv") => Self::Csv,
+            Some("json" | "ndjson" | "jsonl") => Self::Json,
+            _ => Self::Database,
+        }
+    }
+
+    /// The table function that reads this kind of data file
+    fn reader(self) -> Option<&'static str> {
+        match self {
+            Self::Database => None,
+            Self::Parquet => Some("read_parquet"),
+            Self::Csv => Some("read_csv"),
+            Self::Json => Some("read_json"),
+        }
+    }
+}
+
+/// Open a DuckDB database file, or an in-memory database with a view over a data file
+///
+/// `read_only` applies to database files; views over data files never write to them.
+///
+/// # Errors
+/// Returns an error if the file could not be opened or read as its extension says
+pub fn open(path: &str, read_only: bool) -> Result<Connection, ::duckdb::Error> {
+    let Some(reader) = FileKind::of(path).reader() else {
+        return if read_only {
+            Connection::open_with_flags(path, Config::default().access_mode(AccessMode::ReadOnly)?)
+        } else {
+            Connection::open(path)
+        };
+    };
+    let conn = Connection::open_in_memory()?;
+    conn.execute_batch(&format!(
+        "CREATE VIEW {} AS SELECT * FROM {}({})",
+        quote_identifier(&view_name(path)),
+        reader,
+        quote_literal(path)
+    ))?;
+    Ok(conn)
+}
+
+/// The view a data file is exposed as: its file name without the extension
+#[must_use]
+pub fn view_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map_or_else(|| "data".to_string(), |stem| stem.to_string_lossy().into_owned())
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+struct PoolInner {
+    path: String,
+    /// Every statement runs on a clone of this connection, sharing its database
+    connection: Mutex<Connection>,
+}
+
+/// An open DuckDB database for one saved connection
+#[derive(Clone)]
+pub struct DuckDbPool(Arc<PoolInner>);
+
+impl fmt::Debug for DuckDbPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DuckDbPool")
+            .field("path", &self.0.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DuckDbPool {
+    /// Open the file at `path`, which must already exist
+    ///
+    /// # Errors
+    /// Returns an error if the file is missing or DuckDB could not open it
+    pub async fn open(path: &str, read_only: bool) -> AppResult<Self> {
+        if !Path::new(path).is_file() {
+            return Err(AppError::new(
+                format!("File not found: {}", path),
+                ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+                ErrorSeverity::Error,
+            ));
+        }
+        let owned = path.to_string();
+        let connection = tokio::task::spawn_blocking(move || open(&owned, read_only))
+            .await
+            .map_err(worker_failed)?
+            .map_err(duckdb_error)?;
+        Ok(Self(Arc::new(PoolInner {
+            path: path.to_string(),
+            connection: Mutex::new(connection),
+        })))
+    }
+
+    /// A new connection to the same database, for one statement
+    ///
+    /// # Errors
+    /// Returns an error if DuckDB could not open another connection
+    pub fn connect(&self) -> AppResult<Connection> {
+        self.0
+            .connection
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .try_clone()
+            .map_err(duckdb_error)
+    }
+
+    /// Run a statement without parameters and read its whole result
+    ///
+    /// # Errors
+    /// Returns an error if the statement failed
+    pub async fn fetch(&self, sql: &str) -> AppResult<(Vec<ColumnInfo>, Vec<Vec<DbValue>>)> {
+        run(self.connect()?, sql, &[]).await
+    }
+}
+
+/// Stops the statement running on a DuckDB connection
+#[derive(Clone)]
+pub struct DuckDbInterrupt(Arc<InterruptHandle>);
+
+impl DuckDbInterrupt {
+    #[must_use]
+    pub fn new(conn: &Connection) -> Self {
+        Self(conn.interrupt_handle())
+    }
+
+    pub fn interrupt(&self) {
+        self.0.interrupt();
+    }
+}
+
+impl fmt::Debug for DuckDbInterrupt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DuckDbInterrupt")
+    }
+}
+
+/// A page of rows read on the statement's blocking thread
+#[derive(Debug)]
+pub struct Page {
+    /// Only set on the first page
+    pub columns: Option<Vec<ColumnInfo>>,
+    pub rows: Vec<Vec<DbValue>>,
+    pub done: bool,
+}
+
+/// A statement running on a blocking thread, read a page at a time
+///
+/// Rows are only read once a page has been asked for, so an abandoned reader
+/// leaves the rest of the result unread.
+pub struct PageReader {
+    requests: std_mpsc::Sender<usize>,
+    pages: mpsc::Receiver<AppResult<Page>>,
+}
+
+impl PageReader {
+    /// Start a statement on a connection of its own
+    #[must_use]
+    pub fn start(conn: Connection, sql: &str, params: &[DbValue]) -> Self {
+        let (requests, pending) = std_mpsc::channel::<usize>();
+        let (pages, received) = mpsc::channel(1);
+        let sql = sql.to_string();
+        let params: Vec<Value> = params.iter().map(param).collect();
+
+        tokio::task::spawn_blocking(move || {
+            let read = || -> AppResult<()> {
+                let mut statement = conn.prepare(&sql).map_err(duckdb_error)?;
+                let mut rows = statement.query(params_from_iter(params)).map_err(duckdb_error)?;
+                let mut columns = rows.as_ref().map(statement_columns);
+                while let Ok(size) = pending.recv() {
+                    let mut page = Vec::new();
+                    let mut done = false;
+                    while page.len() < size {
+                        match rows.next().map_err(duckdb_error)? {
+                            Some(row) => page.push(row_values(row)?),
+                            None => {
+                                done = true;
+                                break;
+                            }
+                        }
+                    }
+                    let page = Page {
+                        columns: columns.take(),
+                        rows: page,
+                        done,
+                    };
+                    if pages.blocking_send(Ok(page)).is_err() || done {
+                        break;
+                    }
+                }
+                Ok(())
+            };
+            if let Err(error) = read() {
+                let _ = pages.blocking_send(Err(error));
+            }
+        });
+
+        Self {
+            requests,
+            pages: received,
+        }
+    }
+
+    /// Read up to `size` more rows
+    ///
+    /// # Errors
+    /// Returns the statement's error, or an error if its thread has already finished
+    pub async fn next_page(&mut self, size: usize) -> AppResult<Page> {
+        let finished = || {
+            AppError::new(
+                "The DuckDB statement has already finished",
+                ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+                ErrorSeverity::Error,
+            )
+        };
+        self.requests.send(size).map_err(|_| finished())?;
+        self.pages.recv().await.ok_or_else(finished)?
+    }
+}
+
+/// Run a statement with its parameters bound as `?` or `$1` and read its whole result
+///
+/// DuckDB reports changed rows as a `Count` result rather than a row count.
+///
+/// # Errors
+/// Returns an error if the statement failed or was interrupted
+pub async fn run(conn: Connection, sql: &str, params: &[DbValue]) -> AppResult<(Vec<ColumnInfo>, Vec<Vec<DbValue>>)> {
+    let page = PageReader::start(conn, sql, params).next_page(usize::MAX).await?;
+    Ok((page.columns.unwrap_or_default(), page.rows))
+}
+
+/// Column names and types of an executed statement
+fn statement_columns(statement: &Statement<'_>) -> Vec<ColumnInfo> {
+    (0..statement.column_count())
+        .map(|index| ColumnInfo {
+            name: statement
+                .column_name(index)
+                .map_or_else(|_| format!("column{}", index), Clone::clone),
+            type_name: type_name(&statement.column_type(index)),
+            nullable: None,
+        })
+        .collect()
+}
+
+/// DuckDB's own name for the type a column is read as
+fn type_name(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Boolean => "BOOLEAN".to_string(),
+        DataType::Int8 => "TINYINT".to_string(),
+        DataType::Int16 => "SMALLINT".to_string(),
+        DataType::Int32 => "INTEGER".to_string(),
+        DataType::Int64 => "BIGINT".to_string(),
+        DataType::UInt8 => "UTINYINT".to_string(),
+        DataType::UInt16 => "USMALLINT".to_string(),
+        DataType::UInt32 => "UINTEGER".to_string(),
+        DataType::UInt64 => "UBIGINT".to_string(),
+        DataType::Float32 => "FLOAT".to_string(),
+        DataType::Float64 => "DOUBLE".to_string(),
+        DataType::Decimal128(precision, scale) => format!("DECIMAL({},{})", precision, scale),
+        DataType::Utf8 | DataType::LargeUtf8 => "VARCHAR".to_string(),
+        DataType::Binary | DataType::LargeBinary => "BLOB".to_string(),
+        DataType::Date32 => "DATE".to_string(),
+        DataType::Time64(_) => "TIME".to_string(),
+        DataType::Timestamp(ArrowTimeUnit::Second, None) => "TIMESTAMP_S".to_string(),
+        DataType::Timestamp(ArrowTimeUnit::Millisecond, None) => "TIMESTAMP_MS".to_string(),
+        DataType::Timestamp(ArrowTimeUnit::Nanosecond, None) => "TIMESTAMP_NS".to_string(),
+        DataType::Timestamp(_, None) => "TIMESTAMP".to_string(),
+        DataType::Timestamp(_, Some(_)) => "TIMESTAMP WITH TIME ZONE".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Decode a DuckDB row into driver-neutral values
+fn row_values(row: &Row<'_>) -> AppResult<Vec<DbValue>> {
+    let mut values = Vec::new();
+    let mut index = 0;
+    // Rows do not report their width, so read until the first invalid index
+    while let Ok(value) = row.get_ref(index) {
+        values.push(value_of(value));
+        index += 1;
+    }
+    Ok(values)
+}
+
+fn value_of(value: ValueRef<'_>) -> DbValue {
+    match value {
+        ValueRef::Null => DbValue::Null,
+        ValueRef::Boolean(value) => DbValue::Bool(value),
+        ValueRef::TinyInt(value) => DbValue::Int(value.into()),
+        ValueRef::SmallInt(value) => DbValue::Int(value.into()),
+        ValueRef::Int(value) => DbValue::Int(value.into()),
+        ValueRef::BigInt(value) => DbValue::Int(value),
+        ValueRef::UTinyInt(value) => DbValue::Int(value.into()),
+        ValueRef::USmallInt(value) => DbValue::Int(value.into()),
+        ValueRef::UInt(value) => DbValue::Int(value.into()),
+        ValueRef::UBigInt(value) => DbValue::UInt(value),
+        ValueRef::HugeInt(value) => DbValue::Decimal(value.to_string()),
+        ValueRef::Float(value) => DbValue::Float(value.into()),
+        ValueRef::Double(value) => DbValue::Float(value),
+        ValueRef::Decimal(value) => DbValue::Decimal(value.to_string()),
+        ValueRef::Text(bytes) => DbValue::Text(String::from_utf8_lossy(bytes).into_owned()),
+        ValueRef::Blob(bytes) => DbValue::Bytes(BASE64.encode(bytes)),
+        ValueRef::Date32(days) => date(days).map_or(DbValue::Null, DbValue::Date),
+        ValueRef::Time64(unit, value) => time(unit, value).map_or(DbValue::Null, DbValue::Time),
+        ValueRef::Timestamp(unit, value) => timestamp(unit, value).map_or(DbValue::Null, DbValue::DateTime),
+        ValueRef::Interval { months, days, nanos } => DbValue::Text(interval(months, days, nanos)),
+        // Lists, structs, maps, enums and unions
+        other => match json(other.to_owned()) {
+            JsonValue::String(text) => DbValue::Text(text),
+            value => DbValue::Json(value),
+        },
+    }
+}
+
+/// Nested values as JSON, with scalars spelled as they decode on their own
+fn json(value: Value) -> JsonValue {
+    match value {
+        Value::List(items) | Value::Array(items) => JsonValue::Array(items.into_iter().map(json).collect()),
+        Value::Struct(fields) => JsonValue::Object(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), json(value.clone())))
+                .collect(),
+        ),
+        Value::Map(entries) => JsonValue::Array(
+            entries
+                .iter()
+                .map(|(key, value)| JsonValue::Array(vec![json(key.clone()), json(value.clone())]))
+                .collect(),
+        ),
+        Value::Enum(label) => JsonValue::String(label),
+        Value::Union(value) => json(*value),
+        Value::Null => JsonValue::Null,
+        Value::Boolean(value) => JsonValue::Bool(value),
+        Value::TinyInt(value) => value.into(),
+        Value::SmallInt(value) => value.into(),
+        Value::Int(value) => value.into(),
+        Value::BigInt(value) => value.into(),
+        Value::UTinyInt(value) => value.into(),
+        Value::USmallInt(value) => value.into(),
+        Value::UInt(value) => value.into(),
+        Value::UBigInt(value) => value.into(),
+        Value::Float(value) => f64::from(value).into(),
+        Value::Double(value) => value.into(),
+        Value::HugeInt(value) => JsonValue::String(value.to_string()),
+        Value::Decimal(value) => JsonValue::String(value.to_string()),
+        Value::Text(text) => JsonValue::String(text),
+        Value::Blob(bytes) => JsonValue::String(BASE64.encode(bytes)),
+        Value::Date32(days) => date(days).map_or(JsonValue::Null, JsonValue::String),
+        Value::Time64(unit, value) => time(unit, value).map_or(JsonValue::Null, JsonValue::String),
+        Value::Timestamp(unit, value) => timestamp(unit, value).map_or(JsonValue::Null, JsonValue::String),
+        Value::Interval { months, days, nanos } => JsonValue::String(interval(months, days, nanos)),
+    }
+}
+
+fn micros(unit: TimeUnit, value: i64) -> i64 {
+    match unit {
+        TimeUnit::Second => value.saturating_mul(1_000_000),
+        TimeUnit::Millisecond => value.saturating_mul(1_000),
+        TimeUnit::Microsecond => value,
+        TimeUnit::Nanosecond => value / 1_000,
+    }
+}
+
+fn date(days: i32) -> Option<String> {
+    NaiveDate::from_ymd_opt(1970, 1, 1)?
+        .checked_add_signed(chrono::Duration::days(days.into()))
+        .map(|date| date.to_string())
+}
+
+fn time(unit: TimeUnit, value: i64) -> Option<String> {
+    let micros = micros(unit, value);
+    let seconds = u32::try_from(micros / 1_000_000).ok()?;
+    let nanos = u32::try_from(micros % 1_000_000).ok()? * 1_000;
+    NaiveTime::from_num_seconds_from_midnight_opt(seconds, nanos).map(|time| time.to_string())
+}
+
+fn timestamp(unit: TimeUnit, value: i64) -> Option<String> {
+    DateTime::from_timestamp_micros(micros(unit, value))
+        .map(|datetime| datetime.naive_utc().format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+}
+
+/// An interval as an ISO 8601 duration
+fn interval(months: i32, days: i32, nanos: i64) -> String {
+    let seconds = nanos as f64 / 1e9;
+    format!("P{}M{}DT{}S", months, days, seconds)
+}
+
+fn param(value: &DbValue) -> Value {
+    match value.clone() {
+        DbValue::Null => Value::Null,
+        DbValue::Bool(value) => Value::Boolean(value),
+        DbValue::Int(value) => Value::BigInt(value),
+        DbValue::UInt(value) => Value::UBigInt(value),
+        DbValue::Float(value) => Value::Double(value),
+        DbValue::Bytes(value) => match BASE64.decode(&value) {
+            Ok(bytes) => Value::Blob(bytes),
+            Err(_) => Value::Text(value),
+        },
+        DbValue::Json(value) => Value::Text(value.to_string()),
+        DbValue::Decimal(value)
+        | DbValue::Text(value)
+        | DbValue::Uuid(value)
+        | DbValue::Date(value)
+        | DbValue::Time(value)
+        | DbValue::DateTime(value) => Value::Text(value),
+    }
+}
+
+/// Convert a DuckDB error into an `AppError`
+pub(crate) fn duckdb_error(error: ::duckdb::Error) -> AppError {
+    AppError::new(
+        error.to_string(),
+        ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+        ErrorSeverity::Error,
+    )
+}
+
+fn worker_failed(error: tokio::task::JoinError) -> AppError {
+    AppError::new(
+        format!("The DuckDB worker stopped: {}", error),
+        ErrorCategory::Database(DatabaseSubcategory::ConnectionFailed),
+        ErrorSeverity::Error,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_files_are_told_apart_by_extension() {
+        assert_eq!(FileKind::of("/exports/orders.parquet"), FileKind::Parquet);
+        assert_eq!(FileKind::of("/exports/Orders.CSV"), FileKind::Csv);
+        assert_eq!(FileKind::of("/exports/events.ndjson"), FileKind::Json);
+        assert_eq!(FileKind::of("/data/warehouse.duckdb"), FileKind::Database);
+        assert_eq!(view_name("/exports/orders 2024.parquet"), "orders 2024");
+        assert_eq!(quote_identifier("a\"b"), "\"a\"\"b\"");
+        assert_eq!(quote_literal("/it's.csv"), "'/it''s.csv'");
+    }
+
+    #[test]
+    fn test_temporal_values_are_iso_8601() {
+        assert_eq!(date(19_723).as_deref(), Some("2024-01-01"));
+        assert_eq!(time(TimeUnit::Microsecond, 3_723_000_500).as_deref(), Some("01:02:03.000500"));
+        assert_eq!(
+            timestamp(TimeUnit::Second, 1_704_067_200).as_deref(),
+            Some("2024-01-01T00:00:00")
+        );
+    }
+}
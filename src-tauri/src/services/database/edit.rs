@@ -0,0 +1,476 @@
+//! Inline edits of table rows.
+//!
+//! A batch of cell edits, inserts and deletes made in the data grid is turned
+//! into one parameterized `UPDATE`, `INSERT` or `DELETE` per row, which can be
+//! previewed before anything is sent. Rows are found by their primary key, as
+//! reported by introspection, and every original value sent with an edit is
+//! checked in the statement's `WHERE` clause: if another session changed or
+//! deleted the row since it was read, the statement matches no row and the
+//! whole batch is rolled back. Postgres values of types that cannot be compared
+//! for equality, such as `json` or `point`, are left out of that check.
+
+use serde::{Deserialize, Serialize};
+use sqlx::database::HasArguments;
+use sqlx::pool::PoolConnection;
+use sqlx::{Connection as _, Executor, IntoArguments};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::error::categories::{DatabaseSubcategory, ErrorCategory, ValidationSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::export::qualified_name;
+use crate::services::introspection::{TableColumn, TableInfo, TableKind};
+use crate::services::storage::repositories::connections::DbType;
+
+use super::cancel::{with_timeout, CancelSlot, CancelTarget};
+use super::pool::DatabasePool;
+use super::query::{bind_all, query_error, BindValues, DbValue, RowsAffected};
+
+/// Postgres types with no equality operator, whose values a `WHERE` clause cannot check
+const INCOMPARABLE_TYPES: &[&str] = &["json", "xml", "point", "polygon", "jsonpath", "pg_snapshot", "txid_snapshot"];
+
+/// A change to one row of a table, with values keyed by column name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RowEdit {
+    /// Set some cells of a row
+    Update {
+        /// Values of the row as it was read: every primary key column, and any other
+        /// column that must not have changed since
+        original: BTreeMap<String, DbValue>,
+        changes: BTreeMap<String, DbValue>,
+    },
+    /// Add a row; columns left out take their default
+    Insert { values: BTreeMap<String, DbValue> },
+    /// Remove a row, found and checked as for an update
+    Delete { original: BTreeMap<String, DbValue> },
+}
+
+/// A statement generated for one edit
+#[derive(Debug, Clone, Serialize)]
+pub struct EditStatement {
+    pub sql: String,
+    pub params: Vec<DbValue>,
+    /// The row the statement changes, as its primary key, for messages about it
+    pub row: String,
+}
+
+/// Edits applied in one transaction
+#[derive(Debug, Clone, Serialize)]
+pub struct EditResult {
+    pub statements: Vec<EditStatement>,
+    pub rows_affected: u64,
+    /// How long each statement took, in the order of `statements`
+    pub statement_durations_ms: Vec<u64>,
+    pub duration_ms: u64,
+}
+
+/// Generate the statements for a batch of edits to `table`, in the order of the edits
+///
+/// # Errors
+/// Returns a validation error if the table has no primary key, an edit names a column
+/// the table does not have, or an update or delete does not give the full primary key
+pub fn statements(
+    db_type: DbType,
+    schema: &str,
+    table: &TableInfo,
+    edits: &[RowEdit],
+) -> AppResult<Vec<EditStatement>> {
+    if table.kind != TableKind::Table {
+        return Err(invalid(format!("{} is a view, so its rows cannot be edited", table.name)));
+    }
+    let key: Vec<&TableColumn> = table.columns.iter().filter(|column| column.primary_key).collect();
+    if key.is_empty() {
+        return Err(invalid(format!("{} has no primary key, so its rows cannot be edited", table.name)));
+    }
+    let name = qualified_name(db_type, Some(schema), &table.name);
+
+    edits
+        .iter()
+        .enumerate()
+        .map(|(index, edit)| {
+            let mut builder = Builder {
+                db_type,
+                table,
+                params: Vec::new(),
+            };
+            let (sql, row) = match edit {
+                RowEdit::Update { original, changes } => {
+                    let row = builder.row(&key, original)?;
+                    let changes = builder.columns(changes)?;
+                    if changes.is_empty() {
+                        return Err(invalid(format!("The update of {} changes no columns", row)));
+                    }
+                    let sets: Vec<String> = changes
+                        .into_iter()
+                        .map(|(column, value)| format!("{} = {}", builder.quote(column), builder.param(column, value)))
+                        .collect();
+                    let filter = builder.filter(original)?;
+                    (format!("UPDATE {} SET {} WHERE {}", name, sets.join(", "), filter), row)
+                }
+                RowEdit::Insert { values } => {
+                    let values = builder.columns(values)?;
+                    let sql = if values.is_empty() && db_type == DbType::MySql {
+                        format!("INSERT INTO {} () VALUES ()", name)
+                    } else if values.is_empty() {
+                        format!("INSERT INTO {} DEFAULT VALUES", name)
+                    } else {
+                        let columns: Vec<String> = values.iter().map(|(column, _)| builder.quote(column)).collect();
+                        let params: Vec<String> =
+                            values.into_iter().map(|(column, value)| builder.param(column, value)).collect();
+                        format!("INSERT INTO {} ({}) VALUES ({})", name, columns.join(", "), params.join(", "))
+                    };
+                    (sql, format!("new row {}", index + 1))
+                }
+                RowEdit::Delete { original } => {
+                    let row = builder.row(&key, original)?;
+                    let filter = builder.filter(original)?;
+                    (format!("DELETE FROM {} WHERE {}", name, filter), row)
+                }
+            };
+            Ok(EditStatement {
+                sql,
+                params: builder.params,
+                row,
+            })
+        })
+        .collect()
+}
+
+/// Run the statements in one transaction, rolling every one back unless each changes exactly one row
+///
+/// The transaction runs on a dedicated connection recorded in `slot`, so it can be
+/// cancelled on the server while it runs or once it exceeds `timeout`.
+///
+/// # Errors
+/// Returns a `Database(TransactionFailed)` error naming the row if a statement matched
+/// no row or several, the server's error if a statement failed, or a validation error
+/// for engines whose rows cannot be edited
+pub async fn apply(
+    pool: &DatabasePool,
+    statements: Vec<EditStatement>,
+    slot: &CancelSlot,
+    timeout: Option<Duration>,
+) -> AppResult<EditResult> {
+    let started = Instant::now();
+    let (rows_affected, statement_durations_ms) = with_timeout(timeout, slot, async {
+        match pool {
+            DatabasePool::Postgres(pool) => {
+                let mut conn = pool.acquire().await.map_err(query_error)?;
+                let _guard = slot.arm(CancelTarget::postgres(pool, &mut conn).await?);
+                run_in_transaction(&mut conn, &statements).await
+            }
+            DatabasePool::MySql(pool) => {
+                let mut conn = pool.acquire().await.map_err(query_error)?;
+                let _guard = slot.arm(CancelTarget::mysql(pool, &mut conn).await?);
+                run_in_transaction(&mut conn, &statements).await
+            }
+            DatabasePool::Sqlite(pool) => {
+                let mut conn = pool.acquire().await.map_err(query_error)?;
+                let _guard = slot.arm(CancelTarget::sqlite(&mut conn).await?);
+                run_in_transaction(&mut conn, &statements).await
+            }
+            _ => Err(invalid(format!("Rows of {} tables cannot be edited", pool.db_type()))),
+        }
+    })
+    .await?;
+
+    Ok(EditResult {
+        statements,
+        rows_affected,
+        statement_durations_ms,
+        duration_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+    })
+}
+
+/// Run the statements in a transaction on `conn`, committing only if each changed exactly one row
+///
+/// Returns the number of rows changed and how long each statement took.
+async fn run_in_transaction<DB>(
+    conn: &mut PoolConnection<DB>,
+    statements: &[EditStatement],
+) -> AppResult<(u64, Vec<u64>)>
+where
+    DB: BindValues,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    DB::QueryResult: RowsAffected,
+{
+    let mut rows_affected = 0;
+    let mut durations_ms = Vec::with_capacity(statements.len());
+    let mut tx = conn.begin().await.map_err(query_error)?;
+    for statement in statements {
+        let started = Instant::now();
+        let result = bind_all(sqlx::query(&statement.sql), &statement.params)?
+            .execute(&mut *tx)
+            .await
+            .map_err(query_error)?;
+        durations_ms.push(u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX));
+        rows_affected += check_one_row(statement, result.rows_affected())?;
+    }
+    tx.commit().await.map_err(query_error)?;
+    Ok((rows_affected, durations_ms))
+}
+
+/// Refuse a statement that did not change exactly one row, which rolls back the transaction
+fn check_one_row(statement: &EditStatement, rows_affected: u64) -> AppResult<u64> {
+    let reason = match rows_affected {
+        1 => return Ok(1),
+        0 => "was changed or deleted since it was read".to_string(),
+        n => format!("matched {} rows", n),
+    };
+    Err(AppError::new(
+        format!("No edits were applied: the row where {} {}", statement.row, reason),
+        ErrorCategory::Database(DatabaseSubcategory::TransactionFailed),
+        ErrorSeverity::Error,
+    ))
+}
+
+/// Builds the clauses and parameters of one statement
+struct Builder<'a> {
+    db_type: DbType,
+    table: &'a TableInfo,
+    params: Vec<DbValue>,
+}
+
+impl<'a> Builder<'a> {
+    /// Look up the table's columns for the values, in table order
+    fn columns<'v>(&self, values: &'v BTreeMap<String, DbValue>) -> AppResult<Vec<(&'a TableColumn, &'v DbValue)>> {
+        let known = |name: &&String| self.table.columns.iter().any(|column| column.name == **name);
+        if let Some(unknown) = values.keys().find(|name| !known(name)) {
+            return Err(invalid(format!("{} has no column {}", self.table.name, unknown)));
+        }
+        Ok(self
+            .table
+            .columns
+            .iter()
+            .filter_map(|column| values.get(&column.name).map(|value| (column, value)))
+            .collect())
+    }
+
+    /// Describe the row by its primary key, which `original` must hold in full
+    fn row(&self, key: &[&TableColumn], original: &BTreeMap<String, DbValue>) -> AppResult<String> {
+        let parts = key
+            .iter()
+            .map(|column| match original.get(&column.name) {
+                Some(value) => Ok(format!("{} = {}", column.name, describe(value))),
+                None => Err(AppError::new(
+                    format!("Give the original value of primary key column {} to find the row", column.name),
+                    ErrorCategory::Validation(ValidationSubcategory::MissingRequired),
+                    ErrorSeverity::Error,
+                )),
+            })
+            .collect::<AppResult<Vec<_>>>()?;
+        Ok(parts.join(", "))
+    }
+
+    /// A `WHERE` clause matching every original value that can be compared
+    fn filter(&mut self, original: &BTreeMap<String, DbValue>) -> AppResult<String> {
+        let db_type = self.db_type;
+        let conditions: Vec<String> = self
+            .columns(original)?
+            .into_iter()
+            .filter(|(column, _)| comparable(db_type, column))
+            .map(|(column, value)| match value {
+                DbValue::Null => format!("{} IS NULL", self.quote(column)),
+                value => format!("{} = {}", self.quote(column), self.param(column, value)),
+            })
+            .collect();
+        Ok(conditions.join(" AND "))
+    }
+
+    fn quote(&self, column: &TableColumn) -> String {
+        super::driver::driver(self.db_type).quote_identifier(&column.name)
+    }
+
+    /// Bind a value and return its placeholder
+    ///
+    /// Postgres parameters are cast to the column's type, as text values such as enum
+    /// labels and network addresses are not converted implicitly. The cast leaves out
+    /// the type's modifier: casting to `varchar(10)` would cut a longer value short,
+    /// where storing it in the column refuses it.
+    fn param(&mut self, column: &TableColumn, value: &DbValue) -> String {
+        self.params.push(value.clone());
+        match self.db_type {
+            DbType::Postgres => {
+                let base_type = column.base_type.as_deref().unwrap_or(&column.data_type);
+                format!("CAST(${} AS {})", self.params.len(), base_type)
+            }
+            _ => "?".to_string(),
+        }
+    }
+}
+
+/// Whether the engine can compare values of the column for equality
+fn comparable(db_type: DbType, column: &TableColumn) -> bool {
+    let base_type = column.base_type.as_deref().unwrap_or(&column.data_type);
+    db_type != DbType::Postgres || !INCOMPARABLE_TYPES.contains(&base_type.trim_end_matches("[]"))
+}
+
+/// A value as shown in messages
+fn describe(value: &DbValue) -> String {
+    match value {
+        DbValue::Null => "NULL".to_string(),
+        DbValue::Bool(value) => value.to_string(),
+        DbValue::Int(value) => value.to_string(),
+        DbValue::UInt(value) => value.to_string(),
+        DbValue::Float(value) => value.to_string(),
+        DbValue::Decimal(value) => value.clone(),
+        DbValue::Json(value) => value.to_string(),
+        DbValue::Text(value)
+        | DbValue::Bytes(value)
+        | DbValue::Uuid(value)
+        | DbValue::Date(value)
+        | DbValue::Time(value)
+        | DbValue::DateTime(value) => format!("'{}'", value),
+    }
+}
+
+fn invalid(message: String) -> AppError {
+    AppError::new(
+        message,
+        ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+        ErrorSeverity::Error,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::introspection::Catalog;
+
+    fn values(pairs: &[(&str, DbValue)]) -> BTreeMap<String, DbValue> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+    }
+
+    async fn settings() -> (DatabasePool, Catalog) {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        for statement in [
+            "CREATE TABLE settings (name TEXT PRIMARY KEY, value TEXT, note TEXT DEFAULT 'x')",
+            "INSERT INTO settings (name, value) VALUES ('theme', 'dark'), ('lang', 'en')",
+            "CREATE TABLE log (line TEXT)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        let catalog = crate::services::introspection::sqlite::introspect(&pool).await.unwrap();
+        (DatabasePool::Sqlite(pool), catalog)
+    }
+
+    #[tokio::test]
+    async fn test_edits_become_statements() {
+        let (_, catalog) = settings().await;
+        let table = catalog.table("main", "settings").unwrap();
+        let edits = vec![
+            RowEdit::Update {
+                original: values(&[("name", DbValue::Text("theme".to_string())), ("value", DbValue::Null)]),
+                changes: values(&[("value", DbValue::Text("light".to_string()))]),
+            },
+            RowEdit::Insert {
+                values: values(&[
+                    ("value", DbValue::Text("utc".to_string())),
+                    ("name", DbValue::Text("tz".to_string())),
+                ]),
+            },
+        ];
+
+        let statements = statements(DbType::Sqlite, "main", table, &edits).unwrap();
+        assert_eq!(
+            statements[0].sql,
+            "UPDATE \"main\".\"settings\" SET \"value\" = ? WHERE \"name\" = ? AND \"value\" IS NULL"
+        );
+        assert_eq!(statements[0].row, "name = 'theme'");
+        assert_eq!(statements[1].sql, "INSERT INTO \"main\".\"settings\" (\"name\", \"value\") VALUES (?, ?)");
+        assert_eq!(statements[1].params[0], DbValue::Text("tz".to_string()));
+
+        let postgres = super::statements(DbType::Postgres, "main", table, &edits[..1]).unwrap();
+        assert_eq!(
+            postgres[0].sql,
+            "UPDATE \"main\".\"settings\" SET \"value\" = CAST($1 AS TEXT) WHERE \"name\" = CAST($2 AS TEXT) \
+             AND \"value\" IS NULL"
+        );
+
+        let no_key = RowEdit::Delete {
+            original: values(&[("value", DbValue::Text("dark".to_string()))]),
+        };
+        assert!(super::statements(DbType::Sqlite, "main", table, &[no_key]).is_err());
+        let log = catalog.table("main", "log").unwrap();
+        assert!(super::statements(DbType::Sqlite, "main", log, &[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_edit_rolls_back_the_batch() {
+        let (pool, catalog) = settings().await;
+        let table = catalog.table("main", "settings").unwrap();
+        let update = |original: &str, value: &str| RowEdit::Update {
+            original: values(&[
+                ("name", DbValue::Text("theme".to_string())),
+                ("value", DbValue::Text(original.to_string())),
+            ]),
+            changes: values(&[("value", DbValue::Text(value.to_string()))]),
+        };
+        let delete = RowEdit::Delete {
+            original: values(&[("name", DbValue::Text("lang".to_string()))]),
+        };
+
+        // The second update expects the value the first one replaced
+        let edits = vec![delete.clone(), update("dark", "light"), update("dark", "blue")];
+        let statements = statements(DbType::Sqlite, "main", table, &edits).unwrap();
+        let error = apply(&pool, statements, &CancelSlot::new(), None).await.unwrap_err();
+        assert!(error.message.contains("name = 'theme' was changed or deleted"));
+
+        let DatabasePool::Sqlite(sqlite) = &pool else { unreachable!() };
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM settings").fetch_one(sqlite).await.unwrap();
+        assert_eq!(count, 2);
+
+        let edits = vec![delete, update("dark", "light")];
+        let statements = super::statements(DbType::Sqlite, "main", table, &edits).unwrap();
+        let result = apply(&pool, statements, &CancelSlot::new(), None).await.unwrap();
+        assert_eq!(result.rows_affected, 2);
+        assert_eq!(result.statement_durations_ms.len(), 2);
+        let value: String = sqlx::query_scalar("SELECT value FROM settings").fetch_one(sqlite).await.unwrap();
+        assert_eq!(value, "light");
+    }
+
+    /// Set `DEWEY_TEST_POSTGRES` to a connection URL, e.g. `postgres://postgres@127.0.0.1/postgres`
+    #[tokio::test]
+    #[ignore = "needs a Postgres server, see DEWEY_TEST_POSTGRES"]
+    async fn test_postgres_edits_keep_column_types() {
+        let url = std::env::var("DEWEY_TEST_POSTGRES").expect("DEWEY_TEST_POSTGRES is not set");
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+        for statement in [
+            "DROP SCHEMA IF EXISTS dewey_edit CASCADE",
+            "CREATE SCHEMA dewey_edit",
+            "CREATE TABLE dewey_edit.items (id int PRIMARY KEY, code varchar(5), spec json, at point)",
+            "INSERT INTO dewey_edit.items VALUES (1, 'abc', '{\"a\": 1}', '(1,2)')",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        let catalog = crate::services::introspection::postgres::introspect(&pool).await.unwrap();
+        let table = catalog.table("dewey_edit", "items").unwrap();
+        let update = |code: &str| RowEdit::Update {
+            original: values(&[
+                ("id", DbValue::Int(1)),
+                ("spec", DbValue::Json(serde_json::json!({"a": 1}))),
+                ("at", DbValue::Text("(1,2)".to_string())),
+            ]),
+            changes: values(&[("code", DbValue::Text(code.to_string()))]),
+        };
+
+        let pool = DatabasePool::Postgres(pool);
+        let too_long = statements(DbType::Postgres, "dewey_edit", table, &[update("abcdefgh")]).unwrap();
+        let refused = apply(&pool, too_long, &CancelSlot::new(), None).await;
+        let fits = statements(DbType::Postgres, "dewey_edit", table, &[update("xyz")]).unwrap();
+        let applied = apply(&pool, fits, &CancelSlot::new(), None).await;
+        let DatabasePool::Postgres(postgres) = &pool else { unreachable!() };
+        let code: String = sqlx::query_scalar("SELECT code FROM dewey_edit.items").fetch_one(postgres).await.unwrap();
+        sqlx::query("DROP SCHEMA dewey_edit CASCADE").execute(postgres).await.unwrap();
+
+        assert!(refused.unwrap_err().message.contains("too long"));
+        assert_eq!(applied.unwrap().rows_affected, 1);
+        assert_eq!(code, "xyz");
+    }
+}
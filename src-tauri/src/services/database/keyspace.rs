@@ -0,0 +1,637 @@
+//! Redis key browsing, value viewers and the command console.
+//!
+//! A session shares one multiplexed connection, so console commands that
+//! change the state of the connection itself (`SELECT`, `SUBSCRIBE`,
+//! `MULTI`, ...) are refused rather than leaking into key browsing. Keys are
+//! handled as UTF-8 text; values that are not valid UTF-8 are returned as
+//! base64 [`DbValue::Bytes`].
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use redis::aio::ConnectionManager;
+use redis::{from_redis_value, RedisError, Value};
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::constants;
+use crate::error::categories::{
+    AuthSubcategory, ConnectionSubcategory, DatabaseSubcategory, ErrorCategory,
+    ValidationSubcategory,
+};
+use crate::error::{AppError, AppResult, DatabaseErrorDetails, ErrorSeverity};
+
+use super::cancel::timeout_error;
+use super::pool::DatabasePool;
+use super::query::DbValue;
+
+/// Console commands that would change the state of the shared connection
+const CONNECTION_COMMANDS: &[&str] = &[
+    "AUTH", "DISCARD", "EXEC", "HELLO", "MONITOR", "MULTI", "PSUBSCRIBE", "PSYNC", "QUIT", "RESET",
+    "SELECT", "SSUBSCRIBE", "SUBSCRIBE", "SYNC", "UNWATCH", "WATCH",
+];
+
+/// Entries of an `XRANGE` reply: ids with their fields and values interleaved
+type StreamRange = Vec<(String, Vec<Vec<u8>>)>;
+
+/// The shared, reconnecting Redis connection for one saved connection
+#[derive(Clone)]
+pub struct RedisPool {
+    manager: Arc<ConnectionManager>,
+    db: i64,
+}
+
+impl fmt::Debug for RedisPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisPool").field("db", &self.db).finish_non_exhaustive()
+    }
+}
+
+impl RedisPool {
+    /// Connect, log in and select the client's database
+    ///
+    /// # Errors
+    /// Returns an error if the server could not be reached or rejected the login
+    pub async fn connect(client: redis::Client) -> Result<Self, RedisError> {
+        let db = client.get_connection_info().redis.db;
+        let manager = Arc::new(ConnectionManager::new(client).await?);
+        Ok(Self { manager, db })
+    }
+
+    fn connection(&self) -> ConnectionManager {
+        ConnectionManager::clone(&self.manager)
+    }
+}
+
+/// A key found by a scan
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyInfo {
+    pub key: String,
+    /// `string`, `list`, `set`, `zset`, `hash`, `stream` or a module type name
+    pub key_type: String,
+    /// Time to live in milliseconds, `None` for keys that do not expire
+    pub ttl_ms: Option<i64>,
+}
+
+/// One page of a key scan
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyPage {
+    pub keys: Vec<KeyInfo>,
+    /// Cursor to continue the scan from, as text since it may not fit a JavaScript number
+    pub cursor: String,
+    /// Whether the scan has covered the whole keyspace
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HashField {
+    pub field: DbValue,
+    pub value: DbValue,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ZSetMember {
+    pub member: DbValue,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamEntry {
+    pub id: String,
+    pub fields: Vec<HashField>,
+}
+
+/// The contents of a key, by type
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum RedisValue {
+    String(DbValue),
+    Hash(Vec<HashField>),
+    List(Vec<DbValue>),
+    Set(Vec<DbValue>),
+    #[serde(rename = "zset")]
+    ZSet(Vec<ZSetMember>),
+    Stream(Vec<StreamEntry>),
+    /// Types without a viewer, such as those added by modules
+    Unsupported,
+}
+
+/// A key's value along with its metadata
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyValue {
+    pub key: String,
+    pub key_type: String,
+    pub ttl_ms: Option<i64>,
+    /// Length in bytes for strings, elements for everything else
+    pub length: u64,
+    pub value: RedisValue,
+    /// Whether only part of the value was read
+    pub truncated: bool,
+}
+
+/// A console reply, mirroring the RESP types
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Reply {
+    Nil,
+    Integer(i64),
+    Status(String),
+    Text(String),
+    /// Binary data, base64 encoded
+    Bytes(String),
+    Array(Vec<Reply>),
+}
+
+/// The reply to a console command
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandReply {
+    pub reply: Reply,
+    pub duration_ms: u64,
+}
+
+/// The Redis connection behind a pool
+///
+/// # Errors
+/// Returns an error if the pool belongs to another kind of connection
+pub fn pool(pool: &DatabasePool) -> AppResult<&RedisPool> {
+    match pool {
+        DatabasePool::Redis(pool) => Ok(pool),
+        _ => Err(AppError::new(
+            "Keys can only be browsed on a Redis connection",
+            ErrorCategory::Validation(ValidationSubcategory::InvalidType),
+            ErrorSeverity::Error,
+        )),
+    }
+}
+
+/// Scan for keys matching a glob-style `pattern`, optionally of one type only
+///
+/// Pass the previous page's cursor to continue a scan. A page holds at least `count`
+/// keys unless the scan finished, though `SCAN` may return a few more, and repeats
+/// keys that were changed while the scan ran.
+///
+/// # Errors
+/// Returns an error if the cursor is malformed or the server rejected the scan
+pub async fn scan_keys(
+    pool: &RedisPool,
+    pattern: Option<&str>,
+    key_type: Option<&str>,
+    cursor: Option<&str>,
+    count: Option<usize>,
+) -> AppResult<KeyPage> {
+    let count = count
+        .unwrap_or(constants::keyspace::DEFAULT_SCAN_COUNT)
+        .clamp(1, constants::keyspace::MAX_SCAN_COUNT);
+    let mut cursor: u64 = match cursor.map(str::trim) {
+        None | Some("") => 0,
+        Some(cursor) => cursor.parse().map_err(|_| {
+            AppError::new(
+                format!("Invalid scan cursor: {}", cursor),
+                ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+                ErrorSeverity::Error,
+            )
+        })?,
+    };
+    let mut conn = pool.connection();
+
+    let mut names: Vec<Vec<u8>> = Vec::new();
+    for _ in 0..constants::keyspace::MAX_SCAN_ROUNDS {
+        let mut scan = redis::cmd("SCAN");
+        scan.arg(cursor)
+            .arg("MATCH")
+            .arg(pattern.filter(|pattern| !pattern.is_empty()).unwrap_or("*"))
+            .arg("COUNT")
+            .arg(count);
+        if let Some(key_type) = key_type {
+            scan.arg("TYPE").arg(key_type);
+        }
+        let (next, batch): (u64, Vec<Vec<u8>>) = scan.query_async(&mut conn).await.map_err(redis_error)?;
+        names.extend(batch);
+        cursor = next;
+        if cursor == 0 || names.len() >= count {
+            break;
+        }
+    }
+
+    let mut details = redis::pipe();
+    for name in &names {
+        details.cmd("TYPE").arg(name).cmd("PTTL").arg(name);
+    }
+    let details: Vec<Value> = if names.is_empty() {
+        Vec::new()
+    } else {
+        details.query_async(&mut conn).await.map_err(redis_error)?
+    };
+
+    let mut keys = Vec::with_capacity(names.len());
+    for (name, detail) in names.iter().zip(details.chunks(2)) {
+        let [key_type, ttl] = detail else { continue };
+        let key_type: String = from_redis_value(key_type).map_err(redis_error)?;
+        // Keys deleted since the scan saw them
+        if key_type == "none" {
+            continue;
+        }
+        keys.push(KeyInfo {
+            key: String::from_utf8_lossy(name).into_owned(),
+            key_type,
+            ttl_ms: ttl_ms(from_redis_value(ttl).map_err(redis_error)?),
+        });
+    }
+    debug!("Scan found {} keys, next cursor {}", keys.len(), cursor);
+
+    Ok(KeyPage {
+        keys,
+        cursor: cursor.to_string(),
+        done: cursor == 0,
+    })
+}
+
+/// Read a key's value, up to `limit` elements of a collection
+///
+/// Lists, sorted sets and streams are read from the start in order. Hashes and
+/// sets have no order, so the first elements a scan returns are read.
+///
+/// # Errors
+/// Returns an error if the key does not exist or the server rejected a read
+pub async fn get_value(pool: &RedisPool, key: &str, limit: Option<usize>) -> AppResult<KeyValue> {
+    let limit = limit
+        .unwrap_or(constants::keyspace::DEFAULT_VALUE_LIMIT)
+        .clamp(1, constants::keyspace::MAX_VALUE_LIMIT);
+    let mut conn = pool.connection();
+
+    let (key_type, ttl): (String, i64) = redis::pipe()
+        .cmd("TYPE")
+        .arg(key)
+        .cmd("PTTL")
+        .arg(key)
+        .query_async(&mut conn)
+        .await
+        .map_err(redis_error)?;
+    if key_type == "none" {
+        return Err(AppError::new(
+            format!("Key not found: {}", key),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ));
+    }
+
+    let last = limit as isize - 1;
+    let (length, value, read) = match key_type.as_str() {
+        "string" => {
+            let (length, bytes): (u64, Vec<u8>) = redis::pipe()
+                .cmd("STRLEN")
+                .arg(key)
+                .cmd("GETRANGE")
+                .arg(key)
+                .arg(0)
+                .arg(constants::keyspace::MAX_STRING_BYTES as isize - 1)
+                .query_async(&mut conn)
+                .await
+                .map_err(redis_error)?;
+            let read = bytes.len() as u64;
+            (length, RedisValue::String(text(bytes)), read)
+        }
+        "list" => {
+            let (length, items): (u64, Vec<Vec<u8>>) = redis::pipe()
+                .cmd("LLEN")
+                .arg(key)
+                .cmd("LRANGE")
+                .arg(key)
+                .arg(0)
+                .arg(last)
+                .query_async(&mut conn)
+                .await
+                .map_err(redis_error)?;
+            let read = items.len() as u64;
+            (length, RedisValue::List(items.into_iter().map(text).collect()), read)
+        }
+        "set" => {
+            let length: u64 = redis::cmd("SCARD").arg(key).query_async(&mut conn).await.map_err(redis_error)?;
+            let members = scan_collection(&mut conn, "SSCAN", key, limit).await?;
+            let read = members.len() as u64;
+            (length, RedisValue::Set(members.into_iter().map(text).collect()), read)
+        }
+        "hash" => {
+            let length: u64 = redis::cmd("HLEN").arg(key).query_async(&mut conn).await.map_err(redis_error)?;
+            // HSCAN returns fields and values interleaved
+            let flat = scan_collection(&mut conn, "HSCAN", key, limit * 2).await?;
+            let fields: Vec<HashField> = pairs(flat)
+                .map(|(field, value)| HashField {
+                    field: text(field),
+                    value: text(value),
+                })
+                .collect();
+            let read = fields.len() as u64;
+            (length, RedisValue::Hash(fields), read)
+        }
+        "zset" => {
+            let (length, flat): (u64, Vec<Vec<u8>>) = redis::pipe()
+                .cmd("ZCARD")
+                .arg(key)
+                .cmd("ZRANGE")
+                .arg(key)
+                .arg(0)
+                .arg(last)
+                .arg("WITHSCORES")
+                .query_async(&mut conn)
+                .await
+                .map_err(redis_error)?;
+            let members: Vec<ZSetMember> = pairs(flat)
+                .map(|(member, score)| ZSetMember {
+                    member: text(member),
+                    score: String::from_utf8_lossy(&score).parse().unwrap_or(f64::NAN),
+                })
+                .collect();
+            let read = members.len() as u64;
+            (length, RedisValue::ZSet(members), read)
+        }
+        "stream" => {
+            let (length, entries): (u64, StreamRange) = redis::pipe()
+                .cmd("XLEN")
+                .arg(key)
+                .cmd("XRANGE")
+                .arg(key)
+                .arg("-")
+                .arg("+")
+                .arg("COUNT")
+                .arg(limit)
+                .query_async(&mut conn)
+                .await
+                .map_err(redis_error)?;
+            let entries: Vec<StreamEntry> = entries
+                .into_iter()
+                .map(|(id, flat)| StreamEntry {
+                    id,
+                    fields: pairs(flat)
+                        .map(|(field, value)| HashField {
+                            field: text(field),
+                            value: text(value),
+                        })
+                        .collect(),
+                })
+                .collect();
+            let read = entries.len() as u64;
+            (length, RedisValue::Stream(entries), read)
+        }
+        _ => (0, RedisValue::Unsupported, 0),
+    };
+
+    Ok(KeyValue {
+        key: key.to_string(),
+        key_type,
+        ttl_ms: ttl_ms(ttl),
+        length,
+        value,
+        truncated: read < length,
+    })
+}
+
+/// Run a console command line, split into arguments as `redis-cli` does
+///
+/// Commands that change the state of the shared connection are refused. The command
+/// is abandoned once it exceeds `timeout`, though the server may still complete it.
+///
+/// # Errors
+/// Returns a validation error for an empty, malformed or refused command line, a
+/// `Connection(Timeout)` error if it timed out, or the server's error reply
+pub async fn run_command(pool: &RedisPool, line: &str, timeout: Option<Duration>) -> AppResult<CommandReply> {
+    let args = split_command(line)?;
+    let Some(name) = args.first().map(|name| name.to_ascii_uppercase()) else {
+        return Err(AppError::new(
+            "Enter a command to run",
+            ErrorCategory::Validation(ValidationSubcategory::MissingRequired),
+            ErrorSeverity::Error,
+        ));
+    };
+    if CONNECTION_COMMANDS.contains(&name.as_str()) {
+        return Err(AppError::new(
+            format!("{} cannot be run from the console, as it would change the shared connection", name),
+            ErrorCategory::Validation(ValidationSubcategory::InvalidType),
+            ErrorSeverity::Error,
+        ));
+    }
+
+    let mut command = redis::cmd(&args[0]);
+    for arg in &args[1..] {
+        command.arg(arg);
+    }
+    let mut conn = pool.connection();
+    let started = Instant::now();
+    let reply = command.query_async::<_, Value>(&mut conn);
+    let reply = match timeout {
+        Some(limit) => tokio::time::timeout(limit, reply)
+            .await
+            .map_err(|_| timeout_error(limit))?,
+        None => reply.await,
+    }
+    .map_err(redis_error)?;
+
+    Ok(CommandReply {
+        reply: to_reply(reply),
+        duration_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+    })
+}
+
+/// Read up to `limit` items of a set or hash with `SSCAN`/`HSCAN`
+async fn scan_collection(
+    conn: &mut ConnectionManager,
+    command: &str,
+    key: &str,
+    limit: usize,
+) -> AppResult<Vec<Vec<u8>>> {
+    let mut items = Vec::new();
+    let mut cursor: u64 = 0;
+    loop {
+        let (next, batch): (u64, Vec<Vec<u8>>) = redis::cmd(command)
+            .arg(key)
+            .arg(cursor)
+            .arg("COUNT")
+            .arg(limit.min(constants::keyspace::DEFAULT_VALUE_LIMIT))
+            .query_async(conn)
+            .await
+            .map_err(redis_error)?;
+        items.extend(batch);
+        cursor = next;
+        if cursor == 0 || items.len() >= limit {
+            break;
+        }
+    }
+    items.truncate(limit);
+    Ok(items)
+}
+
+/// Split a command line into arguments
+///
+/// Arguments are separated by whitespace. Double-quoted arguments understand the
+/// escapes `\n`, `\r`, `\t`, `\b`, `\a`, `\\`, `\"` and `\xHH`; single-quoted
+/// arguments only `\'`.
+///
+/// # Errors
+/// Returns a validation error for unbalanced quotes
+pub fn split_command(line: &str) -> AppResult<Vec<String>> {
+    let unbalanced = || {
+        AppError::new(
+            "Unbalanced quotes in command",
+            ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+            ErrorSeverity::Error,
+        )
+    };
+
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return Ok(args);
+        };
+
+        let mut arg = String::new();
+        match first {
+            '"' => {
+                chars.next();
+                loop {
+                    match chars.next().ok_or_else(unbalanced)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(unbalanced)? {
+                            'n' => arg.push('\n'),
+                            'r' => arg.push('\r'),
+                            't' => arg.push('\t'),
+                            'b' => arg.push('\u{8}'),
+                            'a' => arg.push('\u{7}'),
+                            'x' => {
+                                let hex: String = chars.by_ref().take(2).collect();
+                                match u8::from_str_radix(&hex, 16) {
+                                    Ok(byte) => arg.push(char::from(byte)),
+                                    Err(_) => {
+                                        arg.push('x');
+                                        arg.push_str(&hex);
+                                    }
+                                }
+                            }
+                            other => arg.push(other),
+                        },
+                        c => arg.push(c),
+                    }
+                }
+            }
+            '\'' => {
+                chars.next();
+                loop {
+                    match chars.next().ok_or_else(unbalanced)? {
+                        '\'' => break,
+                        '\\' if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            arg.push('\'');
+                        }
+                        c => arg.push(c),
+                    }
+                }
+            }
+            _ => {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    arg.push(c);
+                }
+            }
+        }
+        // A closing quote must end the argument
+        if matches!(first, '"' | '\'') && chars.peek().is_some_and(|c| !c.is_whitespace()) {
+            return Err(unbalanced());
+        }
+        args.push(arg);
+    }
+}
+
+fn to_reply(value: Value) -> Reply {
+    match value {
+        Value::Nil => Reply::Nil,
+        Value::Int(value) => Reply::Integer(value),
+        Value::Okay => Reply::Status("OK".to_string()),
+        Value::Status(status) => Reply::Status(status),
+        Value::Data(bytes) => match String::from_utf8(bytes) {
+            Ok(text) => Reply::Text(text),
+            Err(e) => Reply::Bytes(BASE64.encode(e.as_bytes())),
+        },
+        Value::Bulk(items) => Reply::Array(items.into_iter().map(to_reply).collect()),
+    }
+}
+
+fn text(bytes: Vec<u8>) -> DbValue {
+    match String::from_utf8(bytes) {
+        Ok(text) => DbValue::Text(text),
+        Err(e) => DbValue::Bytes(BASE64.encode(e.as_bytes())),
+    }
+}
+
+/// Pair up a flat list of alternating items, as returned by `HSCAN` and `ZRANGE ... WITHSCORES`
+fn pairs<T>(flat: Vec<T>) -> impl Iterator<Item = (T, T)> {
+    let mut items = flat.into_iter();
+    std::iter::from_fn(move || Some((items.next()?, items.next()?)))
+}
+
+/// `PTTL` reports -1 for keys without an expiry and -2 for missing keys
+fn ttl_ms(pttl: i64) -> Option<i64> {
+    (pttl >= 0).then_some(pttl)
+}
+
+/// Convert a Redis client error into an `AppError`, keeping the server's error code
+pub(crate) fn redis_error(error: RedisError) -> AppError {
+    let category = if error.is_timeout() {
+        ErrorCategory::Connection(ConnectionSubcategory::Timeout)
+    } else if error.is_connection_refusal() {
+        ErrorCategory::Connection(ConnectionSubcategory::Refused)
+    } else if error.is_io_error() || error.is_connection_dropped() {
+        ErrorCategory::Connection(ConnectionSubcategory::ConnectionFailed)
+    } else if error.kind() == redis::ErrorKind::AuthenticationFailed {
+        ErrorCategory::Auth(AuthSubcategory::InvalidCredentials)
+    } else if error.code() == Some("NOPERM") {
+        ErrorCategory::Auth(AuthSubcategory::PermissionDenied)
+    } else {
+        ErrorCategory::Database(DatabaseSubcategory::QueryFailed)
+    };
+    let details = error.code().map(|code| DatabaseErrorDetails {
+        code: Some(code.to_string()),
+        detail: error.detail().map(str::to_string),
+        ..DatabaseErrorDetails::default()
+    });
+    let app_error = AppError::new(error.to_string(), category, ErrorSeverity::Error);
+    match details {
+        Some(details) => app_error.with_details(details),
+        None => app_error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_command_handles_quotes_and_escapes() {
+        assert_eq!(
+            split_command(r#"  SET "user:1 name" 'it\'s' "a\tb\x41"  "#).unwrap(),
+            vec!["SET", "user:1 name", "it's", "a\tbA"]
+        );
+        assert_eq!(split_command("   ").unwrap(), Vec::<String>::new());
+        assert!(split_command(r#"GET "open"#).is_err());
+        assert!(split_command(r#"GET "a"b"#).is_err());
+    }
+
+    #[test]
+    fn test_replies_keep_binary_data() {
+        let reply = to_reply(Value::Bulk(vec![
+            Value::Okay,
+            Value::Data(b"text".to_vec()),
+            Value::Data(vec![0xff, 0x00]),
+            Value::Nil,
+        ]));
+        assert_eq!(
+            reply,
+            Reply::Array(vec![
+                Reply::Status("OK".to_string()),
+                Reply::Text("text".to_string()),
+                Reply::Bytes("/wA=".to_string()),
+                Reply::Nil,
+            ])
+        );
+    }
+}
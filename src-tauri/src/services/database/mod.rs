@@ -1,11 +1,35 @@
-use mongodb::{Client as MongoClient, options::ClientOptions};
-use sqlx::mysql::{MySqlConnectOptions, MySqlPool, MySqlSslMode};
-use sqlx::postgres::{PgConnectOptions, PgPool, PgSslMode};
-use sqlx::sqlite;
-use std::fs;
-use std::io::Read;
-use std::path::Path;
-use tracing::debug;
+use mongodb::options::{ClientOptions, Tls, TlsOptions};
+use redis::{ClientTlsConfig, ConnectionAddr, ConnectionInfo, RedisConnectionInfo, TlsCertificates};
+use reqwest::{Certificate, Identity, Url};
+use sqlx::mysql::{MySqlConnectOptions, MySqlSslMode};
+use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use sqlx::sqlite::SqliteConnectOptions;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tiberius::{AuthMethod, Config as MssqlConfig, EncryptionLevel};
+
+use crate::constants;
+use crate::services::storage::repositories::connections::{TlsMode, TlsSettings};
+
+use clickhouse::ClickHouseClient;
+
+pub mod cancel;
+pub mod clickhouse;
+pub mod cursor;
+pub mod diagnostics;
+pub mod driver;
+pub mod documents;
+pub mod duckdb;
+pub mod edit;
+pub mod keyspace;
+pub mod mssql;
+pub mod pool;
+pub mod query;
+pub mod read_only;
+pub mod tunnel;
+pub mod uri;
 
 /// `localhost` / `::1` often resolve to IPv6 first on macOS; Docker Desktop Postgres typically
 /// listens on IPv4 only. Force IPv4 loopback so behavior matches many GUI clients.
@@ -26,139 +50,529 @@ fn disable_tls_for_loopback(original_host: &str, resolved_host: &str) -> bool {
         || resolved_host == "127.0.0.1"
 }
 
-/// Tests a database connection based on the connection parameters
-pub async fn test_connection(
-    db_type: &str,
+/// Builds Postgres connect options, forcing IPv4 loopback
+///
+/// Without explicit `tls` settings TLS is disabled for local hosts and left to sqlx otherwise.
+pub(crate) fn postgres_options(
     host: &str,
     port: &str,
     username: &str,
     password: &str,
     database: &str,
-) -> Result<(), String> {
-    let db_type = db_type.trim();
-    let host = host.trim();
-    let port = port.trim();
-    let username = username.trim();
-    let database = database.trim();
-
-    debug!("Testing {} connection to {}:{}", db_type, host, port);
-
-    match db_type.to_lowercase().as_str() {
-        "postgres" => {
-            let port_num: u16 = port
-                .parse()
-                .map_err(|_| "Invalid port number".to_string())?;
-            let connect_host = tcp_host_for_local_connect(host);
-            let mut opts = PgConnectOptions::new_without_pgpass()
-                .host(connect_host)
-                .port(port_num)
-                .username(username)
-                .password(password)
-                .database(database);
-            if disable_tls_for_loopback(host, connect_host) {
-                opts = opts.ssl_mode(PgSslMode::Disable);
-            }
-            PgPool::connect_with(opts)
-                .await
-                .map_err(|e| e.to_string())?;
-            debug!("PostgreSQL connection test successful");
+    tls: Option<&TlsSettings>,
+) -> Result<PgConnectOptions, String> {
+    let port_num: u16 = port
+        .parse()
+        .map_err(|_| "Invalid port number".to_string())?;
+    let connect_host = tcp_host_for_local_connect(host);
+    let mut opts = PgConnectOptions::new_without_pgpass()
+        .host(connect_host)
+        .port(port_num)
+        .username(username)
+        .password(password)
+        .database(database);
+    if let Some(tls) = tls {
+        check_tls_files(tls)?;
+        opts = opts.ssl_mode(match tls.mode {
+            TlsMode::Disable => PgSslMode::Disable,
+            TlsMode::Prefer => PgSslMode::Prefer,
+            TlsMode::Require => PgSslMode::Require,
+            TlsMode::VerifyCa => PgSslMode::VerifyCa,
+            TlsMode::VerifyFull => PgSslMode::VerifyFull,
+        });
+        if let Some(path) = &tls.ca_cert_path {
+            opts = opts.ssl_root_cert(path);
         }
-        "mysql" => {
-            let port_num: u16 = port
-                .parse()
-                .map_err(|_| "Invalid port number".to_string())?;
-            let connect_host = tcp_host_for_local_connect(host);
-            let mut opts = MySqlConnectOptions::new()
-                .host(connect_host)
-                .port(port_num)
-                .username(username)
-                .password(password)
-                .database(database);
-            if disable_tls_for_loopback(host, connect_host) {
-                // `Preferred` can still fail against servers with no TLS on some native-tls builds.
-                opts = opts.ssl_mode(MySqlSslMode::Disabled);
-            }
-            MySqlPool::connect_with(opts)
-                .await
-                .map_err(|e| e.to_string())?;
-            debug!("MySQL connection test successful");
+        if let Some(path) = &tls.client_cert_path {
+            opts = opts.ssl_client_cert(path);
         }
-        "mongodb" => {
-            let connection_string = if username.is_empty() && password.is_empty() {
-                format!(
-                    "mongodb://{}:{}",
-                    tcp_host_for_local_connect(host),
-                    port
-                )
-            } else {
-                format!(
-                    "mongodb://{}:{}@{}:{}",
-                    username,
-                    password,
-                    tcp_host_for_local_connect(host),
-                    port
-                )
-            };
-
-            let mut client_options = ClientOptions::parse(&connection_string)
-                .await
-                .map_err(|e| e.to_string())?;
-            client_options.app_name = Some("Dewey".to_string());
-
-            let client = MongoClient::with_options(client_options).map_err(|e| e.to_string())?;
-            client
-                .list_database_names(None, None)
-                .await
-                .map_err(|e| e.to_string())?;
-            debug!("MongoDB connection test successful");
+        if let Some(path) = &tls.client_key_path {
+            opts = opts.ssl_client_key(path);
         }
-        "sqlite" => {
-            if host == "localhost" && port == "0" {
-                if database.is_empty() {
-                    return Err("SQLite database path cannot be empty".to_string());
-                }
+    } else if disable_tls_for_loopback(host, connect_host) {
+        opts = opts.ssl_mode(PgSslMode::Disable);
+    }
+    Ok(opts)
+}
+
+/// Builds MySQL connect options, forcing IPv4 loopback
+///
+/// Without explicit `tls` settings TLS is disabled for local hosts and left to sqlx otherwise.
+pub(crate) fn mysql_options(
+    host: &str,
+    port: &str,
+    username: &str,
+    password: &str,
+    database: &str,
+    tls: Option<&TlsSettings>,
+) -> Result<MySqlConnectOptions, String> {
+    let port_num: u16 = port
+        .parse()
+        .map_err(|_| "Invalid port number".to_string())?;
+    let connect_host = tcp_host_for_local_connect(host);
+    let mut opts = MySqlConnectOptions::new()
+        .host(connect_host)
+        .port(port_num)
+        .username(username)
+        .password(password)
+        .database(database);
+    if let Some(tls) = tls {
+        check_tls_files(tls)?;
+        opts = opts.ssl_mode(match tls.mode {
+            TlsMode::Disable => MySqlSslMode::Disabled,
+            TlsMode::Prefer => MySqlSslMode::Preferred,
+            TlsMode::Require => MySqlSslMode::Required,
+            TlsMode::VerifyCa => MySqlSslMode::VerifyCa,
+            TlsMode::VerifyFull => MySqlSslMode::VerifyIdentity,
+        });
+        if let Some(path) = &tls.ca_cert_path {
+            opts = opts.ssl_ca(path);
+        }
+        if let Some(path) = &tls.client_cert_path {
+            opts = opts.ssl_client_cert(path);
+        }
+        if let Some(path) = &tls.client_key_path {
+            opts = opts.ssl_client_key(path);
+        }
+    } else if disable_tls_for_loopback(host, connect_host) {
+        // `Preferred` can still fail against servers with no TLS on some native-tls builds.
+        opts = opts.ssl_mode(MySqlSslMode::Disabled);
+    }
+    Ok(opts)
+}
+
+/// Builds a SQL Server client config, forcing IPv4 loopback
+///
+/// `host` may name an instance as `host\INSTANCE`, whose port is looked up through the
+/// SQL Server Browser when `port` is empty. The `authentication` option picks the login:
+/// `sql_server` (the default), `windows` or `integrated` on Windows, or `aad_token` with
+/// the token as the password. `applicationIntent=ReadOnly` routes to readable secondaries.
+/// Without explicit `tls` settings the server certificate is trusted for local hosts, as
+/// the official container images use a self-signed one. TDS has no client certificates,
+/// and the driver cannot skip only the host name check, so `verify-ca` is rejected.
+pub(crate) fn mssql_config(
+    host: &str,
+    port: &str,
+    username: &str,
+    password: &str,
+    database: &str,
+    tls: Option<&TlsSettings>,
+    options: &BTreeMap<String, String>,
+) -> Result<MssqlConfig, String> {
+    let (host, instance) = match host.split_once('\\') {
+        Some((host, instance)) => (host, Some(instance)),
+        None => (host, None),
+    };
+    let connect_host = tcp_host_for_local_connect(host);
 
-                let path = Path::new(database);
-
-                if path.exists() {
-                    let mut file =
-                        fs::File::open(path).map_err(|e| e.to_string())?;
-                    let mut header = [0u8; 16];
-                    if file.read_exact(&mut header).is_ok() {
-                        if &header[0..15] != b"SQLite format 3" {
-                            return Err("File exists but is not a valid SQLite database".to_string());
-                        }
-                    } else {
-                        return Err("Failed to read database file header".to_string());
+    let mut config = MssqlConfig::new();
+    config.host(connect_host);
+    match (port.trim(), instance) {
+        ("", Some(_)) => {}
+        (port, _) => config.port(port.parse().map_err(|_| "Invalid port number".to_string())?),
+    }
+    if let Some(instance) = instance {
+        config.instance_name(instance);
+    }
+    if !database.is_empty() {
+        config.database(database);
+    }
+    config.application_name("Dewey");
+
+    let authentication = options.get("authentication").map(String::as_str).unwrap_or("sql_server");
+    config.authentication(match authentication {
+        "sql_server" => AuthMethod::sql_server(username, password),
+        "aad_token" => AuthMethod::AADToken(password.to_string()),
+        #[cfg(windows)]
+        "windows" => AuthMethod::windows(username, password),
+        #[cfg(windows)]
+        "integrated" => AuthMethod::Integrated,
+        "windows" | "integrated" => {
+            return Err(format!("{} authentication is only available on Windows", authentication))
+        }
+        other => return Err(format!("Unknown SQL Server authentication: {}", other)),
+    });
+    if options
+        .get("applicationIntent")
+        .is_some_and(|intent| intent.eq_ignore_ascii_case("readonly"))
+    {
+        config.readonly(true);
+    }
+
+    match tls {
+        Some(tls) => {
+            check_tls_files(tls)?;
+            if tls.client_cert_path.is_some() || tls.client_key_path.is_some() {
+                return Err("SQL Server connections do not support TLS client certificates".to_string());
+            }
+            match tls.mode {
+                TlsMode::Disable => config.encryption(EncryptionLevel::NotSupported),
+                TlsMode::Prefer => {
+                    config.encryption(EncryptionLevel::On);
+                    config.trust_cert();
+                }
+                TlsMode::Require => {
+                    config.encryption(EncryptionLevel::Required);
+                    config.trust_cert();
+                }
+                TlsMode::VerifyCa => {
+                    return Err("SQL Server connections do not support verify-ca; use verify-full".to_string())
+                }
+                TlsMode::VerifyFull => {
+                    config.encryption(EncryptionLevel::Required);
+                    if let Some(path) = &tls.ca_cert_path {
+                        config.trust_cert_ca(path);
                     }
-                } else if let Some(parent) = path.parent() {
-                    std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
-                    fs::File::create(path)
-                        .and_then(|f| f.sync_all())
-                        .map_err(|e| e.to_string())?;
-                    fs::remove_file(path).map_err(|e| e.to_string())?;
                 }
-
-                let connection_string = format!("sqlite://{}", database);
-                sqlite::SqlitePool::connect(&connection_string)
-                    .await
-                    .map_err(|e| e.to_string())?;
-                debug!("SQLite file connection test successful");
-            } else {
-                let connection_string = format!(
-                    "sqlite://{}:{}@{}:{}/{}",
-                    username, password, host, port, database
-                );
-                sqlite::SqlitePool::connect(&connection_string)
-                    .await
-                    .map_err(|e| e.to_string())?;
-                debug!("SQLite hosted connection test successful");
             }
         }
-        _ => {
-            return Err(format!("Unsupported database type: {}", db_type));
+        None if disable_tls_for_loopback(host, connect_host) => config.trust_cert(),
+        None => {}
+    }
+    Ok(config)
+}
+
+/// Builds MongoDB client options tagged with the Dewey app name
+///
+/// `host` may be a comma-separated seed list, and an empty `port` makes it a
+/// `mongodb+srv://` name; `options` are passed through as URI query parameters.
+/// MongoDB has no opportunistic TLS, so `prefer` keeps the driver default, and the
+/// driver cannot skip only the host name check, so `verify-ca` is rejected. The client
+/// certificate and key must be in one PEM file.
+pub(crate) async fn mongodb_options(
+    host: &str,
+    port: &str,
+    username: &str,
+    password: &str,
+    tls: Option<&TlsSettings>,
+    options: &BTreeMap<String, String>,
+) -> Result<ClientOptions, String> {
+    let parameters: Vec<(String, String)> = options
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let connection_string = uri::mongodb_connection_string(
+        &uri::userinfo(&uri::encode(username), &uri::encode(password)),
+        tcp_host_for_local_connect(host),
+        port,
+        "",
+        &parameters,
+    );
+
+    let mut client_options = ClientOptions::parse(&connection_string)
+        .await
+        .map_err(|e| e.to_string())?;
+    client_options.app_name = Some("Dewey".to_string());
+    if let Some(tls) = mongodb_tls(tls)? {
+        client_options.tls = Some(tls);
+    }
+    Ok(client_options)
+}
+
+/// The MongoDB TLS setting for a connection's TLS settings, `None` to keep the driver default
+fn mongodb_tls(tls: Option<&TlsSettings>) -> Result<Option<Tls>, String> {
+    let Some(tls) = tls else {
+        return Ok(None);
+    };
+    check_tls_files(tls)?;
+    if tls.client_key_path.is_some() && tls.client_key_path != tls.client_cert_path {
+        return Err("MongoDB needs the client certificate and key in a single PEM file".to_string());
+    }
+
+    let options = TlsOptions::builder()
+        .ca_file_path(tls.ca_cert_path.as_ref().map(PathBuf::from))
+        .cert_key_file_path(tls.client_cert_path.as_ref().map(PathBuf::from))
+        .allow_invalid_certificates((tls.mode == TlsMode::Require).then_some(true))
+        .build();
+    match tls.mode {
+        TlsMode::Disable => Ok(Some(Tls::Disabled)),
+        TlsMode::Prefer => Ok(None),
+        TlsMode::Require | TlsMode::VerifyFull => Ok(Some(Tls::Enabled(options))),
+        TlsMode::VerifyCa => {
+            Err("MongoDB connections do not support verify-ca; use verify-full".to_string())
         }
     }
+}
 
+/// Builds a Redis client, forcing IPv4 loopback
+///
+/// `database` holds the logical database index, `0` when empty. An empty username logs in
+/// with the password alone, as `requirepass` expects. Redis cannot negotiate TLS on a plain
+/// port, so `prefer` connects without it, as does the default without `tls` settings;
+/// `require` skips certificate checks entirely, so `verify-ca` is rejected.
+pub(crate) fn redis_client(
+    host: &str,
+    port: &str,
+    username: &str,
+    password: &str,
+    database: &str,
+    tls: Option<&TlsSettings>,
+) -> Result<redis::Client, String> {
+    let host = tcp_host_for_local_connect(host).to_string();
+    let port: u16 = port.parse().map_err(|_| "Invalid port number".to_string())?;
+    let db = match database.trim() {
+        "" => 0,
+        index => index
+            .parse()
+            .map_err(|_| format!("Redis databases are numbered, not named: {}", index))?,
+    };
+    let redis = RedisConnectionInfo {
+        db,
+        username: Some(username.to_string()).filter(|username| !username.is_empty()),
+        password: Some(password.to_string()).filter(|password| !password.is_empty()),
+    };
+
+    let mode = tls.map_or(TlsMode::Prefer, |tls| tls.mode);
+    let addr = match mode {
+        TlsMode::Disable | TlsMode::Prefer => ConnectionAddr::Tcp(host, port),
+        TlsMode::Require | TlsMode::VerifyFull => ConnectionAddr::TcpTls {
+            host,
+            port,
+            insecure: mode == TlsMode::Require,
+            tls_params: None,
+        },
+        TlsMode::VerifyCa => {
+            return Err("Redis connections do not support verify-ca; use verify-full".to_string())
+        }
+    };
+    let info = ConnectionInfo { addr, redis };
+
+    let Some(tls) = tls.filter(|tls| {
+        matches!(info.addr, ConnectionAddr::TcpTls { .. })
+            && (tls.ca_cert_path.is_some() || tls.client_cert_path.is_some() || tls.client_key_path.is_some())
+    }) else {
+        return redis::Client::open(info).map_err(|e| e.to_string());
+    };
+    check_tls_files(tls)?;
+    let read = |path: &String| std::fs::read(path).map_err(|e| format!("Could not read {}: {}", path, e));
+    let client_tls = match (&tls.client_cert_path, &tls.client_key_path) {
+        (Some(cert), Some(key)) => Some(ClientTlsConfig {
+            client_cert: read(cert)?,
+            client_key: read(key)?,
+        }),
+        (None, None) => None,
+        _ => return Err("Redis needs both a client certificate and a client key".to_string()),
+    };
+    let certificates = TlsCertificates {
+        client_tls,
+        root_cert: tls.ca_cert_path.as_ref().map(read).transpose()?,
+    };
+    redis::Client::build_with_tls(info, certificates).map_err(|e| e.to_string())
+}
+
+/// Builds a ClickHouse HTTP client, forcing IPv4 loopback
+///
+/// An HTTP port cannot be upgraded to TLS, so `prefer` connects over plain HTTP, as does
+/// the default without `tls` settings; the HTTPS interface listens on its own port (8443
+/// by default). `require` skips certificate checks and `verify-ca` skips only the host
+/// name check. The client key must be in PKCS#8 PEM format.
+pub(crate) fn clickhouse_client(
+    host: &str,
+    port: &str,
+    username: &str,
+    password: &str,
+    database: &str,
+    tls: Option<&TlsSettings>,
+) -> Result<ClickHouseClient, String> {
+    let port: u16 = port.parse().map_err(|_| "Invalid port number".to_string())?;
+    let host = tcp_host_for_local_connect(host);
+    let mode = tls.map_or(TlsMode::Prefer, |tls| tls.mode);
+    let scheme = match mode {
+        TlsMode::Disable | TlsMode::Prefer => "http",
+        TlsMode::Require | TlsMode::VerifyCa | TlsMode::VerifyFull => "https",
+    };
+    let authority = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let url = Url::parse(&format!("{}://{}/", scheme, authority)).map_err(|e| e.to_string())?;
+
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(constants::clickhouse::CONNECT_TIMEOUT_SECS))
+        .user_agent("Dewey");
+    if let Some(tls) = tls.filter(|_| scheme == "https") {
+        check_tls_files(tls)?;
+        let read = |path: &String| std::fs::read(path).map_err(|e| format!("Could not read {}: {}", path, e));
+        builder = builder
+            .danger_accept_invalid_certs(mode == TlsMode::Require)
+            .danger_accept_invalid_hostnames(mode == TlsMode::VerifyCa);
+        if let Some(path) = &tls.ca_cert_path {
+            let certificate = Certificate::from_pem(&read(path)?).map_err(|e| e.to_string())?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        match (&tls.client_cert_path, &tls.client_key_path) {
+            (Some(cert), Some(key)) => {
+                let identity = Identity::from_pkcs8_pem(&read(cert)?, &read(key)?).map_err(|e| e.to_string())?;
+                builder = builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => return Err("ClickHouse needs both a client certificate and a client key".to_string()),
+        }
+    }
+    let http = builder.build().map_err(|e| e.to_string())?;
+
+    // ClickHouse logs in as `default` when no user is given
+    let username = if username.is_empty() { "default" } else { username };
+    Ok(ClickHouseClient::new(http, url, username, password, database))
+}
+
+/// Fail early, with the offending setting named, when a configured PEM file is missing
+fn check_tls_files(tls: &TlsSettings) -> Result<(), String> {
+    for (label, path) in [
+        ("CA certificate", &tls.ca_cert_path),
+        ("client certificate", &tls.client_cert_path),
+        ("client key", &tls.client_key_path),
+    ] {
+        if let Some(path) = path {
+            if !Path::new(path).is_file() {
+                return Err(format!("TLS {} not found: {}", label, path));
+            }
+        }
+    }
     Ok(())
 }
+
+/// How a SQLite database is opened, named after SQLite's `mode` URI parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SqliteMode {
+    ReadOnly,
+    ReadWrite,
+    ReadWriteCreate,
+    Memory,
+}
+
+impl SqliteMode {
+    /// The mode named by the `mode` option, read-write when unset
+    pub(crate) fn from_options(options: &BTreeMap<String, String>) -> Result<Self, String> {
+        match options.get("mode").map(|mode| mode.trim()) {
+            None | Some("" | "rw") => Ok(Self::ReadWrite),
+            Some("ro") => Ok(Self::ReadOnly),
+            Some("rwc") => Ok(Self::ReadWriteCreate),
+            Some("memory") => Ok(Self::Memory),
+            Some(other) => Err(format!("Unknown SQLite mode: {}", other)),
+        }
+    }
+}
+
+/// Builds SQLite connect options for a database file
+///
+/// The `mode` option follows SQLite's URI parameter: `rw` (the default) opens an existing
+/// file for reading and writing, `ro` opens it read-only, `rwc` also creates a missing file
+/// and `memory` opens a private in-memory database, ignoring `path`. `immutable`, `cache`
+/// and `vfs` are passed on as the URI flags of the same names. SQLite files take no
+/// password: this build cannot open SQLCipher databases, so a non-empty `password` is
+/// refused rather than ignored. Missing parent directories are never created.
+pub(crate) fn sqlite_options(
+    path: &str,
+    password: &str,
+    options: &BTreeMap<String, String>,
+) -> Result<SqliteConnectOptions, String> {
+    if !password.is_empty() {
+        return Err(
+            "This build cannot open encrypted (SQLCipher) SQLite databases, leave the password empty".to_string(),
+        );
+    }
+    let mode = SqliteMode::from_options(options)?;
+    let mut opts = match mode {
+        SqliteMode::Memory => SqliteConnectOptions::from_str("sqlite::memory:").map_err(|e| e.to_string())?,
+        _ if path.trim().is_empty() => return Err("SQLite database path cannot be empty".to_string()),
+        _ => SqliteConnectOptions::new().filename(path.trim()),
+    };
+    opts = opts
+        .read_only(mode == SqliteMode::ReadOnly)
+        .create_if_missing(mode == SqliteMode::ReadWriteCreate);
+
+    for (name, value) in options {
+        let value = value.trim();
+        opts = match name.as_str() {
+            "mode" => opts,
+            "immutable" => opts.immutable(match value {
+                "1" | "true" => true,
+                "0" | "false" => false,
+                other => return Err(format!("Invalid SQLite immutable flag: {}", other)),
+            }),
+            "cache" => opts.shared_cache(match value {
+                "shared" => true,
+                "private" => false,
+                other => return Err(format!("Invalid SQLite cache mode: {}", other)),
+            }),
+            "vfs" => opts.vfs(value.to_string()),
+            other => return Err(format!("Unknown SQLite option: {}", other)),
+        };
+    }
+    Ok(opts)
+}
+
+/// Returns true when the connection fields describe a local SQLite or DuckDB file rather than a hosted database
+pub(crate) fn is_local_file(host: &str, port: &str) -> bool {
+    host.trim() == "localhost" && port.trim() == "0"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tls(mode: TlsMode) -> TlsSettings {
+        TlsSettings {
+            mode,
+            ca_cert_path: None,
+            client_cert_path: None,
+            client_key_path: None,
+        }
+    }
+
+    #[test]
+    fn test_explicit_tls_mode_overrides_loopback_default() {
+        let opts = postgres_options("localhost", "5432", "u", "p", "db", None).unwrap();
+        assert!(matches!(opts.get_ssl_mode(), PgSslMode::Disable));
+
+        let verify_full = tls(TlsMode::VerifyFull);
+        let opts = postgres_options("localhost", "5432", "u", "p", "db", Some(&verify_full)).unwrap();
+        assert!(matches!(opts.get_ssl_mode(), PgSslMode::VerifyFull));
+        let opts = mysql_options("localhost", "3306", "u", "p", "db", Some(&verify_full)).unwrap();
+        assert!(matches!(opts.get_ssl_mode(), MySqlSslMode::VerifyIdentity));
+    }
+
+    #[tokio::test]
+    async fn test_unusable_tls_settings_are_rejected() {
+        let missing_ca = TlsSettings {
+            ca_cert_path: Some("/nonexistent/ca.pem".to_string()),
+            ..tls(TlsMode::VerifyCa)
+        };
+        let e = postgres_options("db.example.com", "5432", "u", "p", "db", Some(&missing_ca)).unwrap_err();
+        assert!(e.contains("CA certificate"));
+
+        let verify_ca = tls(TlsMode::VerifyCa);
+        assert!(mssql_config("db.example.com", "1433", "u", "p", "db", Some(&verify_ca), &BTreeMap::new()).is_err());
+        assert!(mongodb_options("db.example.com", "27017", "", "", Some(&verify_ca), &BTreeMap::new())
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_mssql_config_reads_instances_and_logins() {
+        let config = mssql_config("localhost", "1433", "sa", "p", "", None, &BTreeMap::new()).unwrap();
+        assert_eq!(config.get_addr(), "127.0.0.1:1433");
+
+        assert!(mssql_config("db.example.com\\SQLEXPRESS", "", "sa", "p", "", None, &BTreeMap::new()).is_ok());
+        assert!(mssql_config("db.example.com", "", "sa", "p", "", None, &BTreeMap::new()).is_err());
+
+        let unknown = BTreeMap::from([("authentication".to_string(), "kerberos".to_string())]);
+        let e = mssql_config("db.example.com", "1433", "", "", "", None, &unknown).unwrap_err();
+        assert!(e.contains("kerberos"));
+    }
+
+    #[test]
+    fn test_sqlite_options_are_checked() {
+        let options = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        assert!(sqlite_options("", "", &options(&[("mode", "memory")])).is_ok());
+        assert!(sqlite_options("", "", &BTreeMap::new()).unwrap_err().contains("empty"));
+        assert!(sqlite_options("app.db", "", &options(&[("mode", "ro"), ("immutable", "1")])).is_ok());
+        assert!(sqlite_options("app.db", "", &options(&[("mode", "rwx")])).unwrap_err().contains("rwx"));
+        assert!(sqlite_options("app.db", "", &options(&[("nolock", "1")])).unwrap_err().contains("nolock"));
+        assert!(sqlite_options("app.db", "secret", &BTreeMap::new()).unwrap_err().contains("SQLCipher"));
+    }
+}
@@ -0,0 +1,394 @@
+//! Microsoft SQL Server sessions over TDS.
+//!
+//! The TDS client has no pool of its own, so [`MssqlPool`] keeps idle clients
+//! for reuse and limits how many are open at once. A client is only returned
+//! to the pool once the statement it ran has been read to the end; a client
+//! dropped mid-statement or after an error is closed instead, since its
+//! session may be left mid-response or killed by a cancellation.
+
+use futures::TryStreamExt;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use tiberius::error::Error as MssqlError;
+use tiberius::{
+    Client, ColumnData, ColumnType, Config, FromSql, Query, QueryItem, QueryStream, Row,
+    SqlBrowser,
+};
+use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
+use tracing::debug;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use sqlx::types::chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::error::categories::{ConnectionSubcategory, DatabaseSubcategory, ErrorCategory};
+use crate::error::{AppError, AppResult, DatabaseErrorDetails, ErrorSeverity};
+
+use super::query::{ColumnInfo, DbValue};
+
+/// A TDS client connected over TCP
+pub type MssqlClient = Client<Compat<TcpStream>>;
+
+/// SQL Server's error number for a session stopped with `KILL`
+const SESSION_KILLED: u32 = 596;
+
+struct PoolInner {
+    config: Config,
+    idle: Mutex<Vec<MssqlClient>>,
+    permits: Arc<Semaphore>,
+}
+
+/// A bounded set of reusable SQL Server clients for one saved connection
+#[derive(Clone)]
+pub struct MssqlPool(Arc<PoolInner>);
+
+impl fmt::Debug for MssqlPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MssqlPool")
+            .field("addr", &self.0.config.get_addr())
+            .finish_non_exhaustive()
+    }
+}
+
+impl MssqlPool {
+    /// Connect once to check the settings, keeping that client for reuse
+    ///
+    /// # Errors
+    /// Returns an error if the server could not be reached or rejected the login
+    pub async fn connect(config: Config, max_connections: u32) -> Result<Self, MssqlError> {
+        let client = connect(&config).await?;
+        let pool = Self(Arc::new(PoolInner {
+            config,
+            idle: Mutex::new(vec![client]),
+            permits: Arc::new(Semaphore::new(max_connections.max(1) as usize)),
+        }));
+        Ok(pool)
+    }
+
+    /// Take an idle client, or open a new one once fewer than the maximum are in use
+    ///
+    /// # Errors
+    /// Returns an error if a new client could not connect
+    pub async fn acquire(&self) -> AppResult<MssqlConnection> {
+        let permit = Arc::clone(&self.0.permits)
+            .acquire_owned()
+            .await
+            .map_err(|_| pool_closed())?;
+        let idle = self.lock_idle().pop();
+        let client = match idle {
+            Some(client) => client,
+            None => connect(&self.0.config).await.map_err(mssql_error)?,
+        };
+        Ok(MssqlConnection {
+            client: Some(client),
+            pool: Arc::clone(&self.0),
+            _permit: permit,
+        })
+    }
+
+    /// Open a client outside the pool, e.g. to cancel a statement while the pool is busy
+    ///
+    /// # Errors
+    /// Returns an error if the server could not be reached or rejected the login
+    pub async fn connect_detached(&self) -> AppResult<MssqlClient> {
+        connect(&self.0.config).await.map_err(mssql_error)
+    }
+
+    /// Stop handing out clients and close the idle ones
+    ///
+    /// Clients in use are closed as their statements finish.
+    pub async fn close(&self) {
+        self.0.permits.close();
+        let idle: Vec<MssqlClient> = self.lock_idle().drain(..).collect();
+        for client in idle {
+            let _ = client.close().await;
+        }
+    }
+
+    fn lock_idle(&self) -> std::sync::MutexGuard<'_, Vec<MssqlClient>> {
+        self.0.idle.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// A client checked out of an [`MssqlPool`]
+///
+/// Dropped clients are closed; call [`MssqlConnection::release`] once a statement
+/// has been read to the end to return the client for reuse.
+pub struct MssqlConnection {
+    client: Option<MssqlClient>,
+    pool: Arc<PoolInner>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl MssqlConnection {
+    /// Return the client to its pool
+    pub fn release(mut self) {
+        if self.pool.permits.is_closed() {
+            return;
+        }
+        if let Some(client) = self.client.take() {
+            self.pool
+                .idle
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .push(client);
+        }
+    }
+}
+
+impl Deref for MssqlConnection {
+    type Target = MssqlClient;
+
+    fn deref(&self) -> &MssqlClient {
+        self.client.as_ref().expect("client is only taken on release")
+    }
+}
+
+impl DerefMut for MssqlConnection {
+    fn deref_mut(&mut self) -> &mut MssqlClient {
+        self.client.as_mut().expect("client is only taken on release")
+    }
+}
+
+/// Connect and log in, following Azure SQL's redirect to another gateway
+///
+/// # Errors
+/// Returns an error if the server could not be reached or rejected the login
+pub(crate) async fn connect(config: &Config) -> Result<MssqlClient, MssqlError> {
+    let tcp = TcpStream::connect_named(config).await?;
+    tcp.set_nodelay(true)?;
+    match Client::connect(config.clone(), tcp.compat_write()).await {
+        Err(MssqlError::Routing { host, port }) => {
+            debug!("SQL Server redirected the login to {}:{}", host, port);
+            let mut config = config.clone();
+            config.host(&host);
+            config.port(port);
+            let tcp = TcpStream::connect(config.get_addr()).await?;
+            tcp.set_nodelay(true)?;
+            Client::connect(config, tcp.compat_write()).await
+        }
+        result => result,
+    }
+}
+
+/// Send a statement with its parameters bound as `@P1`, `@P2`, ...
+///
+/// # Errors
+/// Returns an error if the server rejected the statement
+pub(crate) async fn query<'a>(
+    client: &'a mut MssqlClient,
+    sql: &str,
+    params: &[DbValue],
+) -> AppResult<QueryStream<'a>> {
+    let mut query = Query::new(sql.to_string());
+    for param in params {
+        match param.clone() {
+            DbValue::Null => query.bind(None::<String>),
+            DbValue::Bool(value) => query.bind(value),
+            DbValue::Int(value) => query.bind(value),
+            DbValue::UInt(value) => match i64::try_from(value) {
+                Ok(value) => query.bind(value),
+                Err(_) => query.bind(value.to_string()),
+            },
+            DbValue::Float(value) => query.bind(value),
+            DbValue::Bytes(value) => match BASE64.decode(&value) {
+                Ok(bytes) => query.bind(bytes),
+                Err(_) => query.bind(value),
+            },
+            DbValue::Json(value) => query.bind(value.to_string()),
+            DbValue::Decimal(value)
+            | DbValue::Text(value)
+            | DbValue::Uuid(value)
+            | DbValue::Date(value)
+            | DbValue::Time(value)
+            | DbValue::DateTime(value) => query.bind(value),
+        }
+    }
+    query.query(client).await.map_err(mssql_error)
+}
+
+/// Read a statement's results to the end
+///
+/// Columns are those of the first result set; rows of every result set are
+/// collected in order. The TDS client does not report row counts, so unlike the
+/// other engines no rows affected are returned.
+///
+/// # Errors
+/// Returns an error if a later statement in the batch failed
+pub(crate) async fn collect(
+    mut stream: QueryStream<'_>,
+) -> AppResult<(Option<Vec<ColumnInfo>>, Vec<Vec<DbValue>>)> {
+    let mut columns = None;
+    let mut rows = Vec::new();
+    while let Some(item) = stream.try_next().await.map_err(mssql_error)? {
+        match item {
+            QueryItem::Metadata(metadata) => {
+                columns.get_or_insert_with(|| column_info(metadata.columns()));
+            }
+            QueryItem::Row(row) => rows.push(row_values(row)),
+        }
+    }
+    Ok((columns, rows))
+}
+
+/// Read one column of a row, treating NULL as `None`
+///
+/// # Errors
+/// Returns an `InvalidData` error if the column has a different type
+pub(crate) fn column<'a, T: FromSql<'a>>(row: &'a Row, index: usize) -> AppResult<Option<T>> {
+    row.try_get(index).map_err(|e| {
+        AppError::new(
+            format!("Unexpected value in column {}: {}", index, e),
+            ErrorCategory::Database(DatabaseSubcategory::InvalidData),
+            ErrorSeverity::Error,
+        )
+    })
+}
+
+/// Run a statement without parameters and read its first result set
+///
+/// # Errors
+/// Returns an error if the server rejected the statement
+pub(crate) async fn fetch_all(client: &mut MssqlClient, sql: &str) -> AppResult<Vec<Row>> {
+    client
+        .simple_query(sql)
+        .await
+        .map_err(mssql_error)?
+        .into_first_result()
+        .await
+        .map_err(mssql_error)
+}
+
+/// Column metadata as SQL Server names the types
+pub(crate) fn column_info(columns: &[tiberius::Column]) -> Vec<ColumnInfo> {
+    columns
+        .iter()
+        .map(|column| ColumnInfo {
+            name: column.name().to_string(),
+            type_name: type_name(column.column_type()).to_string(),
+            nullable: None,
+        })
+        .collect()
+}
+
+fn type_name(column_type: ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Null => "NULL",
+        ColumnType::Bit | ColumnType::Bitn => "BIT",
+        ColumnType::Int1 => "TINYINT",
+        ColumnType::Int2 => "SMALLINT",
+        ColumnType::Int4 | ColumnType::Intn => "INT",
+        ColumnType::Int8 => "BIGINT",
+        ColumnType::Float4 => "REAL",
+        ColumnType::Float8 | ColumnType::Floatn => "FLOAT",
+        ColumnType::Money | ColumnType::Money4 => "MONEY",
+        ColumnType::Datetime4 => "SMALLDATETIME",
+        ColumnType::Datetime | ColumnType::Datetimen => "DATETIME",
+        ColumnType::Guid => "UNIQUEIDENTIFIER",
+        ColumnType::Decimaln => "DECIMAL",
+        ColumnType::Numericn => "NUMERIC",
+        ColumnType::Daten => "DATE",
+        ColumnType::Timen => "TIME",
+        ColumnType::Datetime2 => "DATETIME2",
+        ColumnType::DatetimeOffsetn => "DATETIMEOFFSET",
+        ColumnType::BigVarBin => "VARBINARY",
+        ColumnType::BigVarChar => "VARCHAR",
+        ColumnType::BigBinary => "BINARY",
+        ColumnType::BigChar => "CHAR",
+        ColumnType::NVarchar => "NVARCHAR",
+        ColumnType::NChar => "NCHAR",
+        ColumnType::Xml => "XML",
+        ColumnType::Udt => "UDT",
+        ColumnType::Text => "TEXT",
+        ColumnType::Image => "IMAGE",
+        ColumnType::NText => "NTEXT",
+        ColumnType::SSVariant => "SQL_VARIANT",
+    }
+}
+
+/// Decode a SQL Server row into driver-neutral values
+pub(crate) fn row_values(row: Row) -> Vec<DbValue> {
+    row.into_iter().map(|data| value(&data)).collect()
+}
+
+fn value(data: &ColumnData<'static>) -> DbValue {
+    fn or_null<T>(value: Option<T>, convert: impl FnOnce(T) -> DbValue) -> DbValue {
+        value.map_or(DbValue::Null, convert)
+    }
+    fn convert<'a, T: FromSql<'a>>(data: &'a ColumnData<'static>, convert: impl FnOnce(T) -> DbValue) -> DbValue {
+        match T::from_sql(data) {
+            Ok(value) => or_null(value, convert),
+            Err(e) => DbValue::Text(format!("<{}>", e)),
+        }
+    }
+
+    match data {
+        ColumnData::U8(value) => or_null(*value, |v| DbValue::Int(v.into())),
+        ColumnData::I16(value) => or_null(*value, |v| DbValue::Int(v.into())),
+        ColumnData::I32(value) => or_null(*value, |v| DbValue::Int(v.into())),
+        ColumnData::I64(value) => or_null(*value, DbValue::Int),
+        ColumnData::F32(value) => or_null(*value, |v| DbValue::Float(v.into())),
+        ColumnData::F64(value) => or_null(*value, DbValue::Float),
+        ColumnData::Bit(value) => or_null(*value, DbValue::Bool),
+        ColumnData::String(value) => or_null(value.as_ref(), |v| DbValue::Text(v.to_string())),
+        ColumnData::Guid(value) => or_null(*value, |v| DbValue::Uuid(v.to_string())),
+        ColumnData::Binary(value) => or_null(value.as_ref(), |v| DbValue::Bytes(BASE64.encode(v))),
+        ColumnData::Numeric(value) => or_null(*value, |v| DbValue::Decimal(v.to_string())),
+        ColumnData::Xml(value) => or_null(value.as_ref(), |v| DbValue::Text(v.to_string())),
+        ColumnData::DateTime(_) | ColumnData::SmallDateTime(_) | ColumnData::DateTime2(_) => {
+            convert(data, |v: NaiveDateTime| {
+                DbValue::DateTime(v.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+            })
+        }
+        ColumnData::Date(_) => convert(data, |v: NaiveDate| DbValue::Date(v.to_string())),
+        ColumnData::Time(_) => convert(data, |v: NaiveTime| DbValue::Time(v.to_string())),
+        ColumnData::DateTimeOffset(_) => {
+            convert(data, |v: DateTime<FixedOffset>| DbValue::DateTime(v.to_rfc3339()))
+        }
+    }
+}
+
+/// Convert a TDS client error into an `AppError`, keeping the server's error number
+pub(crate) fn mssql_error(error: MssqlError) -> AppError {
+    match &error {
+        MssqlError::Server(token) => {
+            let category = if token.code() == SESSION_KILLED {
+                ErrorCategory::Connection(ConnectionSubcategory::ConnectionFailed)
+            } else {
+                ErrorCategory::Database(DatabaseSubcategory::QueryFailed)
+            };
+            AppError::new(token.message().to_string(), category, ErrorSeverity::Error).with_details(
+                DatabaseErrorDetails {
+                    code: Some(token.code().to_string()),
+                    detail: Some(format!("SQL Server error {}, line {}", token.code(), token.line())),
+                    ..DatabaseErrorDetails::default()
+                },
+            )
+        }
+        MssqlError::Io { .. } | MssqlError::Tls(_) | MssqlError::Routing { .. } => AppError::new(
+            error.to_string(),
+            ErrorCategory::Connection(ConnectionSubcategory::ConnectionFailed),
+            ErrorSeverity::Error,
+        ),
+        MssqlError::Protocol(_) => AppError::new(
+            error.to_string(),
+            ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+            ErrorSeverity::Error,
+        ),
+        _ => AppError::new(
+            error.to_string(),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ),
+    }
+}
+
+fn pool_closed() -> AppError {
+    AppError::new(
+        "The SQL Server session has been closed",
+        ErrorCategory::Connection(ConnectionSubcategory::ConnectionFailed),
+        ErrorSeverity::Error,
+    )
+}
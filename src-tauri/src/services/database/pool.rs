@@ -0,0 +1,435 @@
+//! Live database sessions for saved connections.
+//!
+//! `ConnectionManager` opens one pool per saved `Connection.id`, hands out
+//! clones of it to command handlers and closes it again once every caller
+//! has released it or it has sat idle for too long.
+
+use chrono::Utc;
+use mongodb::Client as MongoClient;
+use serde::Serialize;
+use sqlx::mysql::MySqlPool;
+use sqlx::postgres::PgPool;
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::constants;
+use crate::error::categories::{ConnectionSubcategory, DatabaseSubcategory, ErrorCategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::storage::repositories::connections::{Connection, DbType};
+
+use super::clickhouse::ClickHouseClient;
+use super::driver::{self, DatabaseDriver, Endpoint};
+use super::duckdb::DuckDbPool;
+use super::keyspace::RedisPool;
+use super::mssql::MssqlPool;
+use super::tunnel::{self, Tunnel};
+
+/// A driver-specific pool (or client) for one saved connection
+#[derive(Debug, Clone)]
+pub enum DatabasePool {
+    Postgres(PgPool),
+    MySql(MySqlPool),
+    Sqlite(SqlitePool),
+    MongoDb(MongoClient),
+    MsSql(MssqlPool),
+    Redis(RedisPool),
+    ClickHouse(ClickHouseClient),
+    DuckDb(DuckDbPool),
+}
+
+impl DatabasePool {
+    /// Open a pool for the given saved connection
+    ///
+    /// When `tunnel` is given the pool connects through its loopback port instead
+    /// of the connection's own host and port.
+    ///
+    /// # Errors
+    /// Returns a string error if the connection settings are invalid or the server could not be reached
+    pub async fn connect(connection: &Connection, tunnel: Option<&Tunnel>) -> Result<Self, String> {
+        let tunnel_port = tunnel.map(|tunnel| tunnel.local_port().to_string());
+        let (host, port) = match &tunnel_port {
+            Some(port) => ("127.0.0.1", port.as_str()),
+            None => (connection.host.trim(), connection.port.trim()),
+        };
+        let endpoint = Endpoint {
+            host,
+            port,
+            tunnelled: tunnel.is_some(),
+        };
+        driver::driver(connection.db_type).connect(connection, endpoint).await
+    }
+
+    /// The engine this pool belongs to
+    #[must_use]
+    pub fn db_type(&self) -> DbType {
+        match self {
+            Self::Postgres(_) => DbType::Postgres,
+            Self::MySql(_) => DbType::MySql,
+            Self::Sqlite(_) => DbType::Sqlite,
+            Self::MongoDb(_) => DbType::MongoDb,
+            Self::MsSql(_) => DbType::MsSql,
+            Self::Redis(_) => DbType::Redis,
+            Self::ClickHouse(_) => DbType::ClickHouse,
+            Self::DuckDb(_) => DbType::DuckDb,
+        }
+    }
+
+    /// The driver for this pool's engine
+    #[must_use]
+    pub fn driver(&self) -> &'static dyn DatabaseDriver {
+        driver::driver(self.db_type())
+    }
+
+    /// Close every connection held by the pool
+    ///
+    /// Waits until connections checked out by running queries are returned.
+    pub async fn close(self) {
+        match self {
+            Self::Postgres(pool) => pool.close().await,
+            Self::MySql(pool) => pool.close().await,
+            Self::Sqlite(pool) => pool.close().await,
+            Self::MongoDb(client) => client.shutdown().await,
+            Self::MsSql(pool) => pool.close().await,
+            // Connections close once the last clone is dropped
+            Self::Redis(_) | Self::ClickHouse(_) | Self::DuckDb(_) => {}
+        }
+    }
+
+    /// Close the pool without waiting for connections still held by running queries
+    pub fn close_in_background(self) {
+        tokio::spawn(self.close());
+    }
+}
+
+/// Summary of an open session, as reported to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub connection_id: i64,
+    pub connection_name: String,
+    pub db_type: DbType,
+    pub ref_count: usize,
+    pub opened_at: i64,
+    pub last_used_at: i64,
+}
+
+#[derive(Debug)]
+struct Session {
+    pool: DatabasePool,
+    /// Kept open for as long as the pool connects through it
+    tunnel: Option<Tunnel>,
+    connection_name: String,
+    db_type: DbType,
+    ref_count: usize,
+    opened_at: i64,
+    last_used_at: i64,
+}
+
+impl Session {
+    /// Close the pool without waiting, then the tunnel it connects through
+    fn close_in_background(self) {
+        tokio::spawn(async move {
+            self.pool.close().await;
+            drop(self.tunnel);
+        });
+    }
+
+    fn info(&self, connection_id: i64) -> SessionInfo {
+        SessionInfo {
+            connection_id,
+            connection_name: self.connection_name.clone(),
+            db_type: self.db_type,
+            ref_count: self.ref_count,
+            opened_at: self.opened_at,
+            last_used_at: self.last_used_at,
+        }
+    }
+}
+
+/// Registry of open database sessions keyed by saved connection id
+#[derive(Debug)]
+pub struct ConnectionManager {
+    sessions: Mutex<HashMap<i64, Session>>,
+    idle_timeout: Duration,
+}
+
+impl ConnectionManager {
+    /// Create an empty manager that closes sessions unused for `idle_timeout`
+    #[must_use]
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            idle_timeout,
+        }
+    }
+
+    /// Open (or reuse) the session for a connection and take a reference to it
+    ///
+    /// Every successful call must eventually be paired with [`ConnectionManager::close`].
+    ///
+    /// # Errors
+    /// Returns an error if a new pool had to be opened and the connection failed
+    pub async fn open(&self, connection: &Connection) -> AppResult<SessionInfo> {
+        self.ensure_session(connection).await?;
+
+        let mut sessions = self.sessions.lock().await;
+        let session = sessions.get_mut(&connection.id).ok_or_else(|| not_open(connection.id))?;
+        session.ref_count += 1;
+        session.last_used_at = Utc::now().timestamp();
+        debug!("Session {} now has {} references", connection.id, session.ref_count);
+        Ok(session.info(connection.id))
+    }
+
+    /// Get the pool for a connection, opening a session without taking a reference if needed
+    ///
+    /// Sessions opened this way are closed by the idle reaper.
+    ///
+    /// # Errors
+    /// Returns an error if a new pool had to be opened and the connection failed
+    pub async fn acquire(&self, connection: &Connection) -> AppResult<DatabasePool> {
+        self.ensure_session(connection).await?;
+
+        let mut sessions = self.sessions.lock().await;
+        let session = sessions.get_mut(&connection.id).ok_or_else(|| not_open(connection.id))?;
+        session.last_used_at = Utc::now().timestamp();
+        Ok(session.pool.clone())
+    }
+
+    /// Mark a session as in use so the idle reaper leaves it alone
+    pub async fn touch(&self, connection_id: i64) {
+        if let Some(session) = self.sessions.lock().await.get_mut(&connection_id) {
+            session.last_used_at = Utc::now().timestamp();
+        }
+    }
+
+    /// Get the pool for an already open session
+    pub async fn get(&self, connection_id: i64) -> Option<DatabasePool> {
+        let mut sessions = self.sessions.lock().await;
+        sessions.get_mut(&connection_id).map(|session| {
+            session.last_used_at = Utc::now().timestamp();
+            session.pool.clone()
+        })
+    }
+
+    /// Release a reference to a session, closing it once nothing holds it
+    ///
+    /// Returns the remaining session, or `None` if it was closed.
+    ///
+    /// # Errors
+    /// Returns an error if the connection has no open session
+    pub async fn close(&self, connection_id: i64) -> AppResult<Option<SessionInfo>> {
+        let mut sessions = self.sessions.lock().await;
+        let session = sessions.get_mut(&connection_id).ok_or_else(|| not_open(connection_id))?;
+        session.ref_count = session.ref_count.saturating_sub(1);
+
+        if session.ref_count > 0 {
+            return Ok(Some(session.info(connection_id)));
+        }
+
+        let session = sessions.remove(&connection_id).ok_or_else(|| not_open(connection_id))?;
+        drop(sessions);
+        info!("Closing session for connection {}", connection_id);
+        session.close_in_background();
+        Ok(None)
+    }
+
+    /// Close a session regardless of how many references it has
+    pub async fn close_all_for(&self, connection_id: i64) {
+        let removed = self.sessions.lock().await.remove(&connection_id);
+        if let Some(session) = removed {
+            info!("Force closing session for connection {}", connection_id);
+            session.close_in_background();
+        }
+    }
+
+    /// List all open sessions, oldest first
+    pub async fn list(&self) -> Vec<SessionInfo> {
+        let sessions = self.sessions.lock().await;
+        let mut infos: Vec<SessionInfo> = sessions
+            .iter()
+            .map(|(id, session)| session.info(*id))
+            .collect();
+        infos.sort_by_key(|info| info.opened_at);
+        infos
+    }
+
+    /// Close every unreferenced session that has not been used within the idle timeout
+    ///
+    /// Sessions taken with [`ConnectionManager::open`] stay open until they are closed.
+    /// Returns the number of sessions closed.
+    pub async fn close_idle(&self) -> usize {
+        let cutoff = Utc::now().timestamp()
+            - i64::try_from(self.idle_timeout.as_secs()).unwrap_or(i64::MAX);
+
+        let expired: Vec<(i64, Session)> = {
+            let mut sessions = self.sessions.lock().await;
+            let ids: Vec<i64> = sessions
+                .iter()
+                .filter(|(_, session)| session.ref_count == 0 && session.last_used_at <= cutoff)
+                .map(|(id, _)| *id)
+                .collect();
+            ids.into_iter()
+                .filter_map(|id| sessions.remove(&id).map(|session| (id, session)))
+                .collect()
+        };
+
+        let count = expired.len();
+        for (id, session) in expired {
+            info!("Closing idle session for connection {}", id);
+            session.close_in_background();
+        }
+        count
+    }
+
+    /// Spawn a background task that periodically closes idle sessions
+    ///
+    /// The task stops on its own once the manager is dropped.
+    pub fn spawn_reaper(self: &Arc<Self>, interval: Duration) {
+        let manager: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                let closed = manager.close_idle().await;
+                if closed > 0 {
+                    debug!("Reaped {} idle sessions", closed);
+                }
+            }
+        });
+    }
+
+    /// Make sure a session exists for the connection, opening a pool outside the lock if needed
+    async fn ensure_session(&self, connection: &Connection) -> AppResult<()> {
+        if self.sessions.lock().await.contains_key(&connection.id) {
+            return Ok(());
+        }
+
+        info!("Opening session for connection {}", connection.id);
+        let tunnel = tunnel::open_for(
+            connection.db_type,
+            &connection.host,
+            &connection.port,
+            connection.ssh_tunnel.as_ref(),
+        )
+        .await?;
+        let pool = DatabasePool::connect(connection, tunnel.as_ref()).await.map_err(|msg| {
+            AppError::new(
+                msg,
+                ErrorCategory::Database(DatabaseSubcategory::ConnectionFailed),
+                ErrorSeverity::Error,
+            )
+        })?;
+
+        let mut sessions = self.sessions.lock().await;
+        if sessions.contains_key(&connection.id) {
+            // Another caller opened the same session while we were connecting
+            drop(sessions);
+            pool.close().await;
+            return Ok(());
+        }
+
+        let now = Utc::now().timestamp();
+        sessions.insert(
+            connection.id,
+            Session {
+                pool,
+                tunnel,
+                connection_name: connection.connection_name.clone(),
+                db_type: connection.db_type,
+                ref_count: 0,
+                opened_at: now,
+                last_used_at: now,
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(constants::sessions::IDLE_TIMEOUT_SECS))
+    }
+}
+
+fn not_open(connection_id: i64) -> AppError {
+    AppError::new(
+        format!("Connection {} has no open session", connection_id),
+        ErrorCategory::Connection(ConnectionSubcategory::NotFound),
+        ErrorSeverity::Error,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqlite_connection(id: i64, path: &str) -> Connection {
+        Connection {
+            id,
+            connection_name: "scratch".to_string(),
+            project_id: 1,
+            db_type: DbType::Sqlite,
+            host: "localhost".to_string(),
+            port: "0".to_string(),
+            username: String::new(),
+            password: String::new(),
+            database: path.to_string(),
+            statement_timeout_ms: None,
+            read_only: false,
+            ssh_tunnel: None,
+            tls: None,
+            options: Default::default(),
+            server_info: None,
+            server_checked_at: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_reference_counted() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let connection = sqlite_connection(1, file.path().to_str().unwrap());
+        let manager = ConnectionManager::default();
+
+        assert_eq!(manager.open(&connection).await.unwrap().ref_count, 1);
+        assert_eq!(manager.open(&connection).await.unwrap().ref_count, 2);
+        assert_eq!(manager.list().await.len(), 1);
+
+        assert_eq!(manager.close(1).await.unwrap().unwrap().ref_count, 1);
+        assert!(manager.close(1).await.unwrap().is_none());
+        assert!(manager.list().await.is_empty());
+        assert!(manager.close(1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_idle_sessions_are_closed() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let connection = sqlite_connection(2, file.path().to_str().unwrap());
+        let manager = ConnectionManager::new(Duration::ZERO);
+
+        manager.acquire(&connection).await.unwrap();
+        assert_eq!(manager.close_idle().await, 1);
+        assert!(manager.get(2).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_referenced_sessions_outlive_the_idle_timeout() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let connection = sqlite_connection(3, file.path().to_str().unwrap());
+        let manager = ConnectionManager::new(Duration::ZERO);
+
+        manager.open(&connection).await.unwrap();
+        assert_eq!(manager.close_idle().await, 0);
+        assert!(manager.get(3).await.is_some());
+
+        assert!(manager.close(3).await.unwrap().is_none());
+        assert!(manager.get(3).await.is_none());
+    }
+}
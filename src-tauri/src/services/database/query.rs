@@ -0,0 +1,889 @@
+//! Ad-hoc statement execution against live SQL sessions.
+//!
+//! Rows come back as [`DbValue`]s so the frontend can render results from any
+//! engine without knowing the driver's native types.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use sqlx::database::HasArguments;
+use sqlx::encode::IsNull;
+use sqlx::mysql::{MySqlArguments, MySqlDatabaseError, MySqlRow};
+use sqlx::postgres::types::{Oid, PgInterval, PgMoney, PgTimeTz};
+use sqlx::postgres::{
+    PgArgumentBuffer, PgArguments, PgDatabaseError, PgErrorPosition, PgRow, PgTypeInfo, PgTypeKind,
+};
+use sqlx::query::Query;
+use sqlx::sqlite::{SqliteArguments, SqliteRow, SqliteStatement};
+use sqlx::types::chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use sqlx::types::{Decimal, Json, JsonValue, Uuid};
+use sqlx::{
+    Column, Database, Describe, Either, Encode, MySql, Postgres, Row, Sqlite, Statement, Type,
+    TypeInfo, ValueRef,
+};
+use futures::TryStreamExt;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::error::categories::{
+    ConnectionSubcategory, DatabaseSubcategory, ErrorCategory, ValidationSubcategory,
+};
+use crate::error::{AppError, AppResult, DatabaseErrorDetails, ErrorSeverity};
+
+use super::cancel::{with_timeout, CancelSlot};
+use super::pool::DatabasePool;
+
+/// A single cell value, independent of the database driver
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum DbValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    /// Arbitrary-precision numbers, kept as text to avoid rounding
+    Decimal(String),
+    Text(String),
+    /// Binary data, base64 encoded
+    Bytes(String),
+    Uuid(String),
+    Json(serde_json::Value),
+    /// ISO 8601 date (`YYYY-MM-DD`)
+    Date(String),
+    /// ISO 8601 time of day
+    Time(String),
+    /// ISO 8601 date and time, with an offset when the column has one
+    DateTime(String),
+}
+
+/// Metadata for one result column
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    /// The database's own name for the column type (e.g. `INT8`, `VARCHAR`)
+    pub type_name: String,
+    /// Whether the column can contain NULL, if the driver can tell
+    pub nullable: Option<bool>,
+}
+
+/// Columns and rows returned by a statement
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultSet {
+    pub columns: Vec<ColumnInfo>,
+    pub rows: Vec<Vec<DbValue>>,
+    pub rows_affected: u64,
+    pub duration_ms: u64,
+}
+
+/// Run a single statement with bound parameters and collect its results
+///
+/// The statement runs on a dedicated connection recorded in `slot`, so it can be
+/// cancelled on the server while it runs or once it exceeds `timeout`.
+///
+/// # Errors
+/// Returns a `Database(QueryFailed)` error carrying the server's error code and position
+/// if the statement fails, a `Connection(Timeout)` error if it timed out, or a validation
+/// error for pools that do not speak SQL
+pub async fn execute(
+    pool: &DatabasePool,
+    sql: &str,
+    params: &[DbValue],
+    slot: &CancelSlot,
+    timeout: Option<Duration>,
+) -> AppResult<ResultSet> {
+    debug!("Executing statement with {} parameters", params.len());
+    let started = Instant::now();
+
+    let (columns, rows, rows_affected) =
+        with_timeout(timeout, slot, pool.driver().execute(pool, sql, params, slot)).await?;
+
+    let duration_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+    debug!("Statement returned {} rows in {}ms", rows.len(), duration_ms);
+
+    Ok(ResultSet {
+        columns,
+        rows,
+        rows_affected,
+        duration_ms,
+    })
+}
+
+/// The error reported when a SQL statement is sent to a MongoDB or Redis connection
+pub(crate) fn not_sql() -> AppError {
+    AppError::new(
+        "SQL statements can only be run against SQL connections",
+        ErrorCategory::Validation(ValidationSubcategory::InvalidType),
+        ErrorSeverity::Error,
+    )
+}
+
+/// Convert a driver error into an `AppError`, keeping the server's code and position
+///
+/// Pool and statement timeouts reported by the driver or server are classified
+/// as `Connection(Timeout)` so the frontend can tell them apart from bad SQL.
+pub(crate) fn query_error(error: sqlx::Error) -> AppError {
+    let timed_out = match &error {
+        sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(db_error) => {
+            if let Some(pg) = db_error.try_downcast_ref::<PgDatabaseError>() {
+                // 57014 is also used for user cancellation, so check which one it was
+                pg.code() == "57014" && pg.message().contains("statement timeout")
+            } else if let Some(mysql) = db_error.try_downcast_ref::<MySqlDatabaseError>() {
+                // ER_QUERY_TIMEOUT: maximum statement execution time exceeded
+                mysql.number() == 3024
+            } else {
+                false
+            }
+        }
+        _ => false,
+    };
+
+    let details = match &error {
+        sqlx::Error::Database(db_error) => {
+            let mut details = DatabaseErrorDetails {
+                code: db_error.code().map(|code| code.into_owned()),
+                ..DatabaseErrorDetails::default()
+            };
+            if let Some(pg) = db_error.try_downcast_ref::<PgDatabaseError>() {
+                details.position = match pg.position() {
+                    Some(PgErrorPosition::Original(position)) => Some(position),
+                    _ => None,
+                };
+                details.detail = pg.detail().map(str::to_string);
+                details.hint = pg.hint().map(str::to_string);
+            } else if let Some(mysql) = db_error.try_downcast_ref::<MySqlDatabaseError>() {
+                details.detail = Some(format!("MySQL error {}", mysql.number()));
+            }
+            Some(details)
+        }
+        _ => None,
+    };
+
+    let category = if timed_out {
+        ErrorCategory::Connection(ConnectionSubcategory::Timeout)
+    } else {
+        ErrorCategory::Database(DatabaseSubcategory::QueryFailed)
+    };
+    let app_error = AppError::new(error.to_string(), category, ErrorSeverity::Error);
+    match details {
+        Some(details) => app_error.with_details(details),
+        None => app_error,
+    }
+}
+
+/// Bind every parameter onto a query in order, as the closest type the engine has
+///
+/// # Errors
+/// Returns a validation error if a value does not parse as its type, such as a UUID,
+/// date or base64 that is malformed
+pub(crate) fn bind_all<'q, DB: BindValues>(
+    mut query: Query<'q, DB, <DB as HasArguments<'q>>::Arguments>,
+    params: &[DbValue],
+) -> AppResult<Query<'q, DB, <DB as HasArguments<'q>>::Arguments>> {
+    for param in params {
+        query = DB::bind_value(query, param.clone())?;
+    }
+    Ok(query)
+}
+
+/// How each engine binds a driver-neutral value
+pub(crate) trait BindValues: Database {
+    fn bind_value<'q>(
+        query: Query<'q, Self, <Self as HasArguments<'q>>::Arguments>,
+        value: DbValue,
+    ) -> AppResult<Query<'q, Self, <Self as HasArguments<'q>>::Arguments>>;
+}
+
+/// Postgres binds every value as its own type, since it does not compare or assign
+/// text to typed columns implicitly
+impl BindValues for Postgres {
+    fn bind_value<'q>(
+        query: Query<'q, Self, PgArguments>,
+        value: DbValue,
+    ) -> AppResult<Query<'q, Self, PgArguments>> {
+        Ok(match value {
+            DbValue::Null => query.bind(UntypedNull),
+            DbValue::Bool(value) => query.bind(value),
+            DbValue::Int(value) => query.bind(value),
+            DbValue::UInt(value) => match i64::try_from(value) {
+                Ok(value) => query.bind(value),
+                Err(_) => query.bind(Decimal::from(value)),
+            },
+            DbValue::Float(value) => query.bind(value),
+            DbValue::Decimal(value) => query.bind(parse_decimal(&value)?),
+            DbValue::Text(value) => query.bind(value),
+            DbValue::Bytes(value) => query.bind(decode_bytes(&value)?),
+            DbValue::Uuid(value) => query.bind(parse_uuid(&value)?),
+            DbValue::Json(value) => query.bind(Json(value)),
+            DbValue::Date(value) => query.bind(parse_date(&value)?),
+            DbValue::Time(value) => query.bind(parse_time(&value)?),
+            DbValue::DateTime(value) => match parse_datetime(&value)? {
+                Either::Left(value) => query.bind(value),
+                Either::Right(value) => query.bind(value),
+            },
+        })
+    }
+}
+
+/// MySQL converts text to most column types, but dates, decimals and JSON are bound
+/// natively; UUIDs stay text as they are usually stored in `CHAR(36)` columns
+impl BindValues for MySql {
+    fn bind_value<'q>(
+        query: Query<'q, Self, MySqlArguments>,
+        value: DbValue,
+    ) -> AppResult<Query<'q, Self, MySqlArguments>> {
+        Ok(match value {
+            DbValue::Null => query.bind(None::<String>),
+            DbValue::Bool(value) => query.bind(value),
+            DbValue::Int(value) => query.bind(value),
+            DbValue::UInt(value) => query.bind(value),
+            DbValue::Float(value) => query.bind(value),
+            DbValue::Decimal(value) => query.bind(parse_decimal(&value)?),
+            DbValue::Text(value) => query.bind(value),
+            DbValue::Bytes(value) => query.bind(decode_bytes(&value)?),
+            DbValue::Uuid(value) => query.bind(parse_uuid(&value)?.to_string()),
+            DbValue::Json(value) => query.bind(Json(value)),
+            DbValue::Date(value) => query.bind(parse_date(&value)?),
+            DbValue::Time(value) => query.bind(parse_time(&value)?),
+            DbValue::DateTime(value) => match parse_datetime(&value)? {
+                Either::Left(value) => query.bind(value),
+                Either::Right(value) => query.bind(value),
+            },
+        })
+    }
+}
+
+/// SQLite has no date, UUID or decimal types, so those are bound as the text it stores them as
+impl BindValues for Sqlite {
+    fn bind_value<'q>(
+        query: Query<'q, Self, SqliteArguments<'q>>,
+        value: DbValue,
+    ) -> AppResult<Query<'q, Self, SqliteArguments<'q>>> {
+        Ok(match value {
+            DbValue::Null => query.bind(None::<String>),
+            DbValue::Bool(value) => query.bind(value),
+            DbValue::Int(value) => query.bind(value),
+            DbValue::UInt(value) => match i64::try_from(value) {
+                Ok(value) => query.bind(value),
+                Err(_) => query.bind(value.to_string()),
+            },
+            DbValue::Float(value) => query.bind(value),
+            DbValue::Bytes(value) => query.bind(decode_bytes(&value)?),
+            DbValue::Json(value) => query.bind(value.to_string()),
+            DbValue::Decimal(value)
+            | DbValue::Text(value)
+            | DbValue::Uuid(value)
+            | DbValue::Date(value)
+            | DbValue::Time(value)
+            | DbValue::DateTime(value) => query.bind(value),
+        })
+    }
+}
+
+/// A Postgres NULL whose type the server infers from where it is used, as it would
+/// for a literal `NULL`, rather than `text`, which typed columns refuse
+struct UntypedNull;
+
+impl Type<Postgres> for UntypedNull {
+    fn type_info() -> PgTypeInfo {
+        // OID 0 leaves the parameter type unspecified
+        PgTypeInfo::with_oid(Oid(0))
+    }
+}
+
+impl Encode<'_, Postgres> for UntypedNull {
+    fn encode_by_ref(&self, _buf: &mut PgArgumentBuffer) -> IsNull {
+        IsNull::Yes
+    }
+}
+
+fn parse_decimal(value: &str) -> AppResult<Decimal> {
+    Decimal::from_str_exact(value.trim())
+        .or_else(|_| Decimal::from_scientific(value.trim()))
+        .map_err(|_| invalid_value(value, "decimal number"))
+}
+
+fn decode_bytes(value: &str) -> AppResult<Vec<u8>> {
+    BASE64.decode(value).map_err(|_| invalid_value(value, "base64 binary value"))
+}
+
+fn parse_uuid(value: &str) -> AppResult<Uuid> {
+    Uuid::parse_str(value.trim()).map_err(|_| invalid_value(value, "UUID"))
+}
+
+fn parse_date(value: &str) -> AppResult<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").map_err(|_| invalid_value(value, "date"))
+}
+
+fn parse_time(value: &str) -> AppResult<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M:%S%.f")
+        .or_else(|_| NaiveTime::parse_from_str(value.trim(), "%H:%M"))
+        .map_err(|_| invalid_value(value, "time of day"))
+}
+
+/// A date and time with an offset as UTC, or one without as it is
+fn parse_datetime(value: &str) -> AppResult<Either<DateTime<Utc>, NaiveDateTime>> {
+    let trimmed = value.trim();
+    if let Ok(instant) = DateTime::parse_from_rfc3339(trimmed) {
+        return Ok(Either::Left(instant.with_timezone(&Utc)));
+    }
+    NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(trimmed, "%Y-%m-%d %H:%M:%S%.f"))
+        .map(Either::Right)
+        .map_err(|_| invalid_value(value, "date and time"))
+}
+
+fn invalid_value(value: &str, expected: &str) -> AppError {
+    AppError::new(
+        format!("{:?} is not a valid {}", value, expected),
+        ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+        ErrorSeverity::Error,
+    )
+}
+
+/// Drain a `fetch_many` stream into rows and the total rows affected
+pub(crate) async fn collect<'e, R, Q, S>(stream: S) -> AppResult<(Vec<R>, u64)>
+where
+    S: futures::Stream<Item = Result<Either<Q, R>, sqlx::Error>> + 'e,
+    Q: RowsAffected,
+{
+    let mut rows = Vec::new();
+    let mut rows_affected = 0;
+    futures::pin_mut!(stream);
+    while let Some(item) = stream.try_next().await.map_err(query_error)? {
+        match item {
+            Either::Left(result) => rows_affected += result.rows_affected(),
+            Either::Right(row) => rows.push(row),
+        }
+    }
+    Ok((rows, rows_affected))
+}
+
+/// Common access to each driver's `QueryResult::rows_affected`
+pub(crate) trait RowsAffected {
+    fn rows_affected(&self) -> u64;
+}
+
+impl RowsAffected for sqlx::postgres::PgQueryResult {
+    fn rows_affected(&self) -> u64 {
+        self.rows_affected()
+    }
+}
+
+impl RowsAffected for sqlx::mysql::MySqlQueryResult {
+    fn rows_affected(&self) -> u64 {
+        self.rows_affected()
+    }
+}
+
+impl RowsAffected for sqlx::sqlite::SqliteQueryResult {
+    fn rows_affected(&self) -> u64 {
+        self.rows_affected()
+    }
+}
+
+/// Column types and nullability from describing a statement without running it
+///
+/// Returns `None` when the driver could not describe the statement, in which case
+/// column metadata falls back to what the first row reports. Takes the result of
+/// `describe` rather than an executor so callers stay `Send` inside spawned tasks.
+pub(crate) fn describe_columns<DB: Database>(
+    describe: Result<Describe<DB>, sqlx::Error>,
+) -> Option<Vec<ColumnInfo>> {
+    let describe = describe.ok()?;
+    Some(
+        describe
+            .columns()
+            .iter()
+            .enumerate()
+            .map(|(index, column)| ColumnInfo {
+                name: column.name().to_string(),
+                type_name: column.type_info().name().to_string(),
+                nullable: describe.nullable(index),
+            })
+            .collect(),
+    )
+}
+
+/// Column names and declared types of a prepared SQLite statement
+///
+/// SQLite's `describe` steps the statement to infer expression types, which runs
+/// aggregates to completion before the statement itself starts and out of reach
+/// of cancellation. Preparing only reads declared column types, so this returns
+/// `None` when a column has none and metadata falls back to the first row.
+pub(crate) fn sqlite_columns(
+    statement: Result<SqliteStatement<'_>, sqlx::Error>,
+) -> Option<Vec<ColumnInfo>> {
+    let statement = statement.ok()?;
+    statement
+        .columns()
+        .iter()
+        .map(|column| {
+            let type_name = column.type_info();
+            (!type_name.is_null()).then(|| ColumnInfo {
+                name: column.name().to_string(),
+                type_name: type_name.name().to_string(),
+                nullable: None,
+            })
+        })
+        .collect()
+}
+
+/// Column metadata read from a result row, without nullability
+pub(crate) fn row_columns<R: Row>(row: Option<&R>) -> Vec<ColumnInfo> {
+    row.map(|row| {
+        row.columns()
+            .iter()
+            .map(|column| ColumnInfo {
+                name: column.name().to_string(),
+                type_name: column.type_info().name().to_string(),
+                nullable: None,
+            })
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+fn or_null<T>(value: Option<T>, convert: impl FnOnce(T) -> DbValue) -> DbValue {
+    value.map_or(DbValue::Null, convert)
+}
+
+fn bytes_value(bytes: &[u8]) -> DbValue {
+    DbValue::Bytes(BASE64.encode(bytes))
+}
+
+/// Decode a Postgres row into driver-neutral values
+pub(crate) fn pg_row_values(row: &PgRow) -> Result<Vec<DbValue>, sqlx::Error> {
+    (0..row.len()).map(|index| pg_value(row, index)).collect()
+}
+
+fn pg_value(row: &PgRow, index: usize) -> Result<DbValue, sqlx::Error> {
+    let raw = row.try_get_raw(index)?;
+    if raw.is_null() {
+        return Ok(DbValue::Null);
+    }
+    let type_name = raw.type_info().name().to_string();
+
+    Ok(match type_name.as_str() {
+        "BOOL" => or_null(row.try_get::<Option<bool>, _>(index)?, DbValue::Bool),
+        "INT2" => or_null(row.try_get::<Option<i16>, _>(index)?, |v| DbValue::Int(v.into())),
+        "INT4" => or_null(row.try_get::<Option<i32>, _>(index)?, |v| DbValue::Int(v.into())),
+        "INT8" => or_null(row.try_get::<Option<i64>, _>(index)?, DbValue::Int),
+        "FLOAT4" => or_null(row.try_get::<Option<f32>, _>(index)?, |v| DbValue::Float(v.into())),
+        "FLOAT8" => or_null(row.try_get::<Option<f64>, _>(index)?, DbValue::Float),
+        "NUMERIC" => or_null(row.try_get::<Option<Decimal>, _>(index)?, |v| {
+            DbValue::Decimal(v.to_string())
+        }),
+        "UUID" => or_null(row.try_get::<Option<Uuid>, _>(index)?, |v| DbValue::Uuid(v.to_string())),
+        "JSON" | "JSONB" => or_null(row.try_get::<Option<JsonValue>, _>(index)?, DbValue::Json),
+        "BYTEA" => or_null(row.try_get::<Option<Vec<u8>>, _>(index)?, |v| bytes_value(&v)),
+        "DATE" => or_null(row.try_get::<Option<NaiveDate>, _>(index)?, |v| DbValue::Date(v.to_string())),
+        "TIME" => or_null(row.try_get::<Option<NaiveTime>, _>(index)?, |v| DbValue::Time(v.to_string())),
+        "TIMESTAMP" => or_null(row.try_get::<Option<NaiveDateTime>, _>(index)?, |v| {
+            DbValue::DateTime(v.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+        }),
+        "TIMESTAMPTZ" => or_null(row.try_get::<Option<DateTime<Utc>>, _>(index)?, |v| {
+            DbValue::DateTime(v.to_rfc3339())
+        }),
+        "TIMETZ" => or_null(row.try_get::<Option<PgTimeTz<NaiveTime, FixedOffset>>, _>(index)?, |v| {
+            DbValue::Time(format!("{}{}", v.time, v.offset))
+        }),
+        "INTERVAL" => or_null(row.try_get::<Option<PgInterval>, _>(index)?, |v| DbValue::Text(interval_text(&v))),
+        // The server's lc_monetary decides the fraction digits; two covers nearly every currency
+        "MONEY" => or_null(row.try_get::<Option<PgMoney>, _>(index)?, |v| {
+            DbValue::Decimal(v.to_decimal(2).to_string())
+        }),
+        "OID" => or_null(row.try_get::<Option<Oid>, _>(index)?, |v| DbValue::Int(v.0.into())),
+        "\"CHAR\"" => or_null(row.try_get::<Option<i8>, _>(index)?, |v| {
+            DbValue::Text(char::from(v.to_ne_bytes()[0]).to_string())
+        }),
+        "INET" | "CIDR" | "MACADDR" | "MACADDR8" => {
+            let Some(bytes) = row.try_get_unchecked::<Option<Vec<u8>>, _>(index)? else {
+                return Ok(DbValue::Null);
+            };
+            let text = match type_name.as_str() {
+                "INET" | "CIDR" => inet_text(&bytes, type_name == "CIDR"),
+                _ => Some(bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":")),
+            };
+            DbValue::Text(text.ok_or_else(|| undecodable(&type_name))?)
+        }
+        "BOOL[]" => pg_array::<bool>(row, index, JsonValue::from)?,
+        "INT2[]" => pg_array::<i16>(row, index, JsonValue::from)?,
+        "INT4[]" => pg_array::<i32>(row, index, JsonValue::from)?,
+        "INT8[]" => pg_array::<i64>(row, index, JsonValue::from)?,
+        "FLOAT4[]" => pg_array::<f32>(row, index, JsonValue::from)?,
+        "FLOAT8[]" => pg_array::<f64>(row, index, JsonValue::from)?,
+        "NUMERIC[]" => pg_array::<Decimal>(row, index, |v| JsonValue::from(v.to_string()))?,
+        "TEXT[]" | "VARCHAR[]" | "CHAR[]" | "NAME[]" => pg_array::<String>(row, index, JsonValue::from)?,
+        "UUID[]" => pg_array::<Uuid>(row, index, |v| JsonValue::from(v.to_string()))?,
+        "DATE[]" => pg_array::<NaiveDate>(row, index, |v| JsonValue::from(v.to_string()))?,
+        "TIMESTAMP[]" => pg_array::<NaiveDateTime>(row, index, |v| {
+            JsonValue::from(v.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+        })?,
+        "TIMESTAMPTZ[]" => pg_array::<DateTime<Utc>>(row, index, |v| JsonValue::from(v.to_rfc3339()))?,
+        "INTERVAL[]" => pg_array::<PgInterval>(row, index, |v| JsonValue::from(interval_text(&v)))?,
+        "JSON[]" | "JSONB[]" => pg_array::<JsonValue>(row, index, |v| v)?,
+        // Binary results of these types, and of enums, are the text itself
+        "TEXT" | "VARCHAR" | "CHAR" | "NAME" | "XML" | "UNKNOWN" | "citext" => {
+            or_null(row.try_get_unchecked::<Option<String>, _>(index)?, DbValue::Text)
+        }
+        _ if matches!(raw.type_info().kind(), PgTypeKind::Enum(_)) => {
+            or_null(row.try_get_unchecked::<Option<String>, _>(index)?, DbValue::Text)
+        }
+        // Rows come back in binary form, which would be shown as garbage if read as text
+        _ => return Err(undecodable(&type_name)),
+    })
+}
+
+/// Read a one-dimensional array as a JSON array, keeping its NULL elements
+fn pg_array<T>(row: &PgRow, index: usize, element: impl Fn(T) -> JsonValue) -> Result<DbValue, sqlx::Error>
+where
+    T: for<'r> sqlx::Decode<'r, Postgres> + Type<Postgres>,
+{
+    let values = row.try_get_unchecked::<Option<Vec<Option<T>>>, _>(index)?;
+    Ok(or_null(values, |values| {
+        DbValue::Json(values.into_iter().map(|value| value.map_or(JsonValue::Null, &element)).collect())
+    }))
+}
+
+/// Spell an interval as Postgres prints it by default, e.g. `1 year 2 mons 3 days 04:05:06.5`
+fn interval_text(interval: &PgInterval) -> String {
+    let mut parts = Vec::new();
+    // A positive part after a negative one is signed, as in `-1 days +01:00:00`
+    let mut after_negative = false;
+    let mut sign = |negative: bool| {
+        let sign = if negative { "-" } else if after_negative { "+" } else { "" };
+        after_negative = negative;
+        sign
+    };
+    for (value, unit) in [(interval.months / 12, "year"), (interval.months % 12, "mon"), (interval.days, "day")] {
+        if value != 0 {
+            let plural = if value == 1 { "" } else { "s" };
+            parts.push(format!("{}{} {}{}", sign(value < 0), value.unsigned_abs(), unit, plural));
+        }
+    }
+    if interval.microseconds != 0 || parts.is_empty() {
+        let micros = interval.microseconds.unsigned_abs();
+        let seconds = micros / 1_000_000;
+        let mut time = format!(
+            "{}{:02}:{:02}:{:02}",
+            sign(interval.microseconds < 0),
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        );
+        let fraction = micros % 1_000_000;
+        if fraction > 0 {
+            time.push_str(format!(".{:06}", fraction).trim_end_matches('0'));
+        }
+        parts.push(time);
+    }
+    parts.join(" ")
+}
+
+/// Spell an `inet` or `cidr` value from its binary form, leaving out a full-length netmask of `inet`
+fn inet_text(bytes: &[u8], cidr: bool) -> Option<String> {
+    let [family, bits, _, _, address @ ..] = bytes else {
+        return None;
+    };
+    let address = match family {
+        2 => IpAddr::from(<[u8; 4]>::try_from(address).ok()?),
+        3 => IpAddr::from(<[u8; 16]>::try_from(address).ok()?),
+        _ => return None,
+    };
+    let full_length = if address.is_ipv4() { 32 } else { 128 };
+    Some(if cidr || *bits != full_length {
+        format!("{}/{}", address, bits)
+    } else {
+        address.to_string()
+    })
+}
+
+/// The error for a column whose type cannot be shown
+fn undecodable(type_name: &str) -> sqlx::Error {
+    sqlx::Error::Decode(format!("{} values cannot be shown yet, cast the column to text", type_name).into())
+}
+
+/// Decode a MySQL row into driver-neutral values
+pub(crate) fn mysql_row_values(row: &MySqlRow) -> Result<Vec<DbValue>, sqlx::Error> {
+    (0..row.len()).map(|index| mysql_value(row, index)).collect()
+}
+
+fn mysql_value(row: &MySqlRow, index: usize) -> Result<DbValue, sqlx::Error> {
+    let raw = row.try_get_raw(index)?;
+    if raw.is_null() {
+        return Ok(DbValue::Null);
+    }
+    let type_name = raw.type_info().name().to_string();
+
+    Ok(match type_name.as_str() {
+        "BOOLEAN" => or_null(row.try_get::<Option<bool>, _>(index)?, DbValue::Bool),
+        "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "BIGINT" | "YEAR" => {
+            or_null(row.try_get_unchecked::<Option<i64>, _>(index)?, DbValue::Int)
+        }
+        "TINYINT UNSIGNED" | "SMALLINT UNSIGNED" | "MEDIUMINT UNSIGNED" | "INT UNSIGNED"
+        | "BIGINT UNSIGNED" => {
+            or_null(row.try_get_unchecked::<Option<u64>, _>(index)?, DbValue::UInt)
+        }
+        "FLOAT" => or_null(row.try_get::<Option<f32>, _>(index)?, |v| DbValue::Float(v.into())),
+        "DOUBLE" => or_null(row.try_get::<Option<f64>, _>(index)?, DbValue::Float),
+        "DECIMAL" => or_null(row.try_get::<Option<Decimal>, _>(index)?, |v| {
+            DbValue::Decimal(v.to_string())
+        }),
+        "JSON" => or_null(row.try_get::<Option<JsonValue>, _>(index)?, DbValue::Json),
+        "DATE" => or_null(row.try_get::<Option<NaiveDate>, _>(index)?, |v| DbValue::Date(v.to_string())),
+        "TIME" => or_null(row.try_get::<Option<NaiveTime>, _>(index)?, |v| DbValue::Time(v.to_string())),
+        "DATETIME" => or_null(row.try_get::<Option<NaiveDateTime>, _>(index)?, |v| {
+            DbValue::DateTime(v.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+        }),
+        "TIMESTAMP" => or_null(row.try_get::<Option<DateTime<Utc>>, _>(index)?, |v| {
+            DbValue::DateTime(v.to_rfc3339())
+        }),
+        "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB" | "BIT"
+        | "GEOMETRY" => {
+            or_null(row.try_get_unchecked::<Option<Vec<u8>>, _>(index)?, |v| bytes_value(&v))
+        }
+        _ => match row.try_get_unchecked::<Option<String>, _>(index) {
+            Ok(value) => or_null(value, DbValue::Text),
+            Err(_) => or_null(row.try_get_unchecked::<Option<Vec<u8>>, _>(index)?, |v| bytes_value(&v)),
+        },
+    })
+}
+
+/// Decode a SQLite row into driver-neutral values
+///
+/// SQLite is dynamically typed, so each value is decoded by its storage class
+/// rather than the column's declared type.
+pub(crate) fn sqlite_row_values(row: &SqliteRow) -> Result<Vec<DbValue>, sqlx::Error> {
+    (0..row.len()).map(|index| sqlite_value(row, index)).collect()
+}
+
+fn sqlite_value(row: &SqliteRow, index: usize) -> Result<DbValue, sqlx::Error> {
+    let raw = row.try_get_raw(index)?;
+    if raw.is_null() {
+        return Ok(DbValue::Null);
+    }
+    let storage_class = raw.type_info().name().to_string();
+
+    Ok(match storage_class.as_str() {
+        "INTEGER" | "BOOLEAN" => or_null(row.try_get_unchecked::<Option<i64>, _>(index)?, DbValue::Int),
+        "REAL" | "NUMERIC" => or_null(row.try_get_unchecked::<Option<f64>, _>(index)?, DbValue::Float),
+        "BLOB" => or_null(row.try_get_unchecked::<Option<Vec<u8>>, _>(index)?, |v| bytes_value(&v)),
+        _ => or_null(row.try_get_unchecked::<Option<String>, _>(index)?, DbValue::Text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_execute_binds_params_and_decodes_rows() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let pool = DatabasePool::Sqlite(pool);
+        let slot = CancelSlot::new();
+
+        execute(&pool, "CREATE TABLE items (id INTEGER NOT NULL, name TEXT, price REAL)", &[], &slot, None)
+            .await
+            .unwrap();
+        let inserted = execute(
+            &pool,
+            "INSERT INTO items (id, name, price) VALUES (?, ?, ?)",
+            &[DbValue::Int(1), DbValue::Text("widget".to_string()), DbValue::Null],
+            &slot,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(inserted.rows_affected, 1);
+
+        let result = execute(&pool, "SELECT id, name, price FROM items", &[], &slot, None)
+            .await
+            .unwrap();
+        let names: Vec<&str> = result.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["id", "name", "price"]);
+        assert_eq!(
+            result.rows,
+            vec![vec![DbValue::Int(1), DbValue::Text("widget".to_string()), DbValue::Null]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_values_are_refused() {
+        let pool = SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let error = execute(
+            &DatabasePool::Sqlite(pool),
+            "SELECT ?",
+            &[DbValue::Bytes("not base64!".to_string())],
+            &CancelSlot::new(),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.category, ErrorCategory::Validation(ValidationSubcategory::InvalidFormat));
+
+        assert!(parse_uuid("8f6e").is_err());
+        assert!(parse_date("2024-02-30").is_err());
+        assert!(parse_decimal("12.5.1").is_err());
+        assert_eq!(
+            parse_datetime("2024-05-01T12:00:00+02:00").unwrap(),
+            Either::Left("2024-05-01T10:00:00Z".parse::<DateTime<Utc>>().unwrap())
+        );
+    }
+
+    /// Set `DEWEY_TEST_POSTGRES` to a connection URL, e.g. `postgres://postgres@127.0.0.1/postgres`
+    #[tokio::test]
+    #[ignore = "needs a Postgres server, see DEWEY_TEST_POSTGRES"]
+    async fn test_postgres_binds_typed_values() {
+        let url = std::env::var("DEWEY_TEST_POSTGRES").expect("DEWEY_TEST_POSTGRES is not set");
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&url)
+            .await
+            .unwrap();
+        let pool = DatabasePool::Postgres(pool);
+        let slot = CancelSlot::new();
+
+        execute(
+            &pool,
+            "CREATE TEMPORARY TABLE typed (id uuid PRIMARY KEY, n int4, price numeric(10, 2), day date, \
+             at time, local timestamp, instant timestamptz, doc jsonb, data bytea)",
+            &[],
+            &slot,
+            None,
+        )
+        .await
+        .unwrap();
+        let id = DbValue::Uuid("0b7e2c1a-52a4-4c52-9d1e-2f1b2a3c4d5e".to_string());
+        let row = vec![
+            id.clone(),
+            DbValue::Null,
+            DbValue::Decimal("12.50".to_string()),
+            DbValue::Date("2024-05-01".to_string()),
+            DbValue::Time("08:30:00".to_string()),
+            DbValue::DateTime("2024-05-01T08:30:00".to_string()),
+            DbValue::DateTime("2024-05-01T10:30:00+02:00".to_string()),
+            DbValue::Json(serde_json::json!({"tags": ["a"]})),
+            DbValue::Bytes(BASE64.encode([0u8, 255])),
+        ];
+        execute(&pool, "INSERT INTO typed VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)", &row, &slot, None)
+            .await
+            .unwrap();
+
+        let result = execute(&pool, "SELECT * FROM typed WHERE id = $1 AND at = $2", &[id, row[4].clone()], &slot, None)
+            .await
+            .unwrap();
+        assert_eq!(result.rows[0][..6], row[..6]);
+        assert_eq!(result.rows[0][6], DbValue::DateTime("2024-05-01T08:30:00+00:00".to_string()));
+        assert_eq!(result.rows[0][7..], row[7..]);
+
+        let error = execute(&pool, "SELECT $1::uuid", &[DbValue::Uuid("nope".to_string())], &slot, None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.category, ErrorCategory::Validation(ValidationSubcategory::InvalidFormat));
+    }
+
+    #[test]
+    fn test_intervals_and_addresses_are_spelled_like_postgres() {
+        let interval = |months, days, microseconds| PgInterval {
+            months,
+            days,
+            microseconds,
+        };
+        assert_eq!(interval_text(&interval(14, 3, 14_706_500_000)), "1 year 2 mons 3 days 04:05:06.5");
+        assert_eq!(interval_text(&interval(0, -1, 3_600_000_000)), "-1 days +01:00:00");
+        assert_eq!(interval_text(&interval(0, 0, 0)), "00:00:00");
+
+        assert_eq!(inet_text(&[2, 32, 0, 4, 10, 0, 0, 1], false).as_deref(), Some("10.0.0.1"));
+        assert_eq!(inet_text(&[2, 24, 1, 4, 10, 0, 0, 0], true).as_deref(), Some("10.0.0.0/24"));
+        assert_eq!(inet_text(&[2, 24, 0, 4, 10, 0], false), None);
+    }
+
+    /// Set `DEWEY_TEST_POSTGRES` to a connection URL, e.g. `postgres://postgres@127.0.0.1/postgres`
+    #[tokio::test]
+    #[ignore = "needs a Postgres server, see DEWEY_TEST_POSTGRES"]
+    async fn test_postgres_reads_arrays_and_intervals() {
+        let url = std::env::var("DEWEY_TEST_POSTGRES").expect("DEWEY_TEST_POSTGRES is not set");
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&url)
+            .await
+            .unwrap();
+        let pool = DatabasePool::Postgres(pool);
+        let slot = CancelSlot::new();
+
+        let result = execute(
+            &pool,
+            "SELECT ARRAY[1, NULL, 3]::int4[], interval '1 year 2 months 3 days 04:05:06.5', \
+                    '192.168.0.1/24'::inet, 12.5::money",
+            &[],
+            &slot,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.rows[0][0], DbValue::Json(serde_json::json!([1, null, 3])));
+        assert_eq!(result.rows[0][1], DbValue::Text("1 year 2 mons 3 days 04:05:06.5".to_string()));
+        assert_eq!(result.rows[0][2], DbValue::Text("192.168.0.1/24".to_string()));
+        assert_eq!(result.rows[0][3], DbValue::Decimal("12.50".to_string()));
+
+        execute(&pool, "CREATE TYPE pg_temp.mood AS ENUM ('calm')", &[], &slot, None)
+            .await
+            .unwrap();
+        let result = execute(&pool, "SELECT 'calm'::pg_temp.mood", &[], &slot, None).await.unwrap();
+        assert_eq!(result.rows[0][0], DbValue::Text("calm".to_string()));
+
+        let error = execute(&pool, "SELECT to_tsvector('simple', 'a b')", &[], &slot, None)
+            .await
+            .unwrap_err();
+        assert!(error.message.contains("cast the column to text"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn test_execute_reports_query_failures() {
+        let pool = SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let error = execute(&DatabasePool::Sqlite(pool), "SELECT * FROM missing", &[], &CancelSlot::new(), None)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.category, ErrorCategory::Database(DatabaseSubcategory::QueryFailed));
+        assert!(error.details.and_then(|d| d.code).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_execute_times_out_and_interrupts_sqlite() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE t (n INTEGER)").execute(&pool).await.unwrap();
+        for n in 0..1000 {
+            sqlx::query("INSERT INTO t VALUES (?)").bind(n).execute(&pool).await.unwrap();
+        }
+        let pool = DatabasePool::Sqlite(pool);
+        let slot = CancelSlot::new();
+        let endless = "SELECT count(*) FROM t a, t b, t c, t d";
+
+        let error = execute(&pool, endless, &[], &slot, Some(Duration::from_millis(100)))
+            .await
+            .unwrap_err();
+        assert_eq!(error.category, ErrorCategory::Connection(ConnectionSubcategory::Timeout));
+
+        // The interrupted connection is usable again
+        let result = execute(&pool, "SELECT 1", &[], &slot, None).await.unwrap();
+        assert_eq!(result.rows, vec![vec![DbValue::Int(1)]]);
+    }
+}
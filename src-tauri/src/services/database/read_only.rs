@@ -0,0 +1,376 @@
+//! Read-only connections.
+//!
+//! A connection marked `read_only` is protected twice. Its sessions are opened
+//! so the server itself refuses writes where the engine has a switch for it
+//! (see each driver's `connect`). On top of that, every statement, pipeline and
+//! console command is checked here before it is sent, so a write is refused up
+//! front with a `Validation` error, also on engines without such a switch.
+//!
+//! The statement check works on keywords, not a full parse: it accepts what
+//! starts like a query and contains no keyword that writes, and refuses
+//! everything else.
+
+use serde_json::Value as JsonValue;
+
+use crate::error::categories::{ErrorCategory, ValidationSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::storage::repositories::connections::{Connection, DbType};
+
+use super::keyspace::split_command;
+
+/// Statements that only read, by their first keyword
+const READ_STATEMENTS: &[&str] = &[
+    "SELECT", "WITH", "VALUES", "TABLE", "SHOW", "DESCRIBE", "DESC", "EXPLAIN", "EXISTS", "FROM",
+    "SUMMARIZE", "USE", "DECLARE",
+];
+
+/// Statements that control the session or transaction, refused only if they would allow writes
+const SESSION_STATEMENTS: &[&str] = &["BEGIN", "START", "COMMIT", "ROLLBACK", "END", "SAVEPOINT", "RELEASE", "SET"];
+
+/// Keywords that make any statement write, wherever they appear
+///
+/// Dynamic SQL counts as a write: the statement it runs is a string the check cannot see.
+const WRITE_KEYWORDS: &[&str] = &[
+    "INSERT", "UPDATE", "DELETE", "MERGE", "UPSERT", "CREATE", "DROP", "ALTER", "TRUNCATE", "GRANT",
+    "REVOKE", "INTO", "COPY", "ATTACH", "DETACH", "SET_CONFIG", "EXEC", "EXECUTE", "SP_EXECUTESQL",
+];
+
+/// Redis commands that only read
+const READ_COMMANDS: &[&str] = &[
+    "GET", "MGET", "GETRANGE", "SUBSTR", "STRLEN", "LCS", "GETBIT", "BITCOUNT", "BITPOS", "BITFIELD_RO",
+    "EXISTS", "TYPE", "TTL", "PTTL", "EXPIRETIME", "PEXPIRETIME", "KEYS", "SCAN", "RANDOMKEY", "DBSIZE",
+    "DUMP", "SORT_RO", "HGET", "HMGET", "HGETALL", "HKEYS", "HVALS", "HLEN", "HEXISTS", "HSTRLEN",
+    "HSCAN", "HRANDFIELD", "LRANGE", "LINDEX", "LLEN", "LPOS", "SMEMBERS", "SISMEMBER", "SMISMEMBER",
+    "SCARD", "SSCAN", "SRANDMEMBER", "SINTER", "SINTERCARD", "SUNION", "SDIFF", "ZRANGE",
+    "ZRANGEBYSCORE", "ZRANGEBYLEX", "ZREVRANGE", "ZREVRANGEBYSCORE", "ZREVRANGEBYLEX", "ZSCORE",
+    "ZMSCORE", "ZCARD", "ZCOUNT", "ZLEXCOUNT", "ZRANK", "ZREVRANK", "ZSCAN", "ZRANDMEMBER", "ZINTER",
+    "ZINTERCARD", "ZUNION", "ZDIFF", "XRANGE", "XREVRANGE", "XLEN", "XREAD", "XPENDING", "XINFO",
+    "PFCOUNT", "GEOPOS", "GEODIST", "GEOHASH", "GEOSEARCH", "GEORADIUS_RO", "GEORADIUSBYMEMBER_RO",
+    "EVAL_RO", "EVALSHA_RO", "FCALL_RO", "OBJECT", "MEMORY", "COMMAND", "LATENCY", "PING", "ECHO",
+    "INFO", "TIME", "LASTSAVE", "ROLE",
+];
+
+/// Redis commands whose subcommands are checked instead, with the subcommands that only read
+const READ_SUBCOMMANDS: &[(&str, &[&str])] = &[
+    ("CONFIG", &["GET"]),
+    ("CLIENT", &["LIST", "INFO", "GETNAME", "ID"]),
+    ("SLOWLOG", &["GET", "LEN"]),
+    ("ACL", &["WHOAMI", "LIST", "USERS", "CAT", "GETUSER"]),
+    ("SCRIPT", &["EXISTS"]),
+    ("FUNCTION", &["LIST", "STATS"]),
+    ("CLUSTER", &["INFO", "NODES", "SLOTS", "SHARDS", "MYID", "KEYSLOT", "COUNTKEYSINSLOT", "GETKEYSINSLOT"]),
+];
+
+/// Aggregation stages that write their results to a collection
+const WRITE_STAGES: &[&str] = &["$out", "$merge"];
+
+/// Refuse SQL that could write, if the connection is read-only
+///
+/// # Errors
+/// Returns a `Validation` error naming the first statement that could write
+pub fn check_statement(connection: &Connection, sql: &str) -> AppResult<()> {
+    if !connection.read_only {
+        return Ok(());
+    }
+    for statement in statements(connection.db_type, sql) {
+        if let Some(refusal) = refusal(&statement) {
+            return Err(refused(refusal));
+        }
+    }
+    Ok(())
+}
+
+/// Refuse an aggregation pipeline that writes, if the connection is read-only
+///
+/// # Errors
+/// Returns a `Validation` error naming the stage that writes
+pub fn check_pipeline(connection: &Connection, pipeline: &[JsonValue]) -> AppResult<()> {
+    if !connection.read_only {
+        return Ok(());
+    }
+    let stage = pipeline
+        .iter()
+        .filter_map(JsonValue::as_object)
+        .flat_map(|stage| stage.keys())
+        .find(|name| WRITE_STAGES.contains(&name.as_str()));
+    match stage {
+        Some(stage) => Err(refused(format!("{} stages are not allowed", stage))),
+        None => Ok(()),
+    }
+}
+
+/// Refuse a Redis console command line that is not known to only read, if the connection is read-only
+///
+/// # Errors
+/// Returns a `Validation` error naming the refused command, or for a malformed command line
+pub fn check_command(connection: &Connection, line: &str) -> AppResult<()> {
+    if !connection.read_only {
+        return Ok(());
+    }
+    let args = split_command(line)?;
+    let Some(name) = args.first().map(|name| name.to_ascii_uppercase()) else {
+        return Ok(());
+    };
+    if READ_COMMANDS.contains(&name.as_str()) {
+        return Ok(());
+    }
+    let subcommand = args.get(1).map(|name| name.to_ascii_uppercase()).unwrap_or_default();
+    let reads = READ_SUBCOMMANDS
+        .iter()
+        .any(|(command, reads)| *command == name && reads.contains(&subcommand.as_str()));
+    if reads {
+        return Ok(());
+    }
+    Err(refused(format!("{} commands are not allowed", name)))
+}
+
+/// Refuse an operation that always writes, such as an import, if the connection is read-only
+///
+/// # Errors
+/// Returns a `Validation` error naming the operation
+pub fn check_write(connection: &Connection, operation: &str) -> AppResult<()> {
+    if !connection.read_only {
+        return Ok(());
+    }
+    Err(refused(format!("{} are not allowed", operation)))
+}
+
+fn refused(reason: String) -> AppError {
+    AppError::new(
+        format!("This connection is read-only: {}", reason),
+        ErrorCategory::Validation(ValidationSubcategory::InvalidType),
+        ErrorSeverity::Error,
+    )
+}
+
+/// Why a statement is refused, or `None` if it only reads
+fn refusal(statement: &[Token]) -> Option<String> {
+    let words = || {
+        statement.iter().filter_map(|token| match token {
+            Token::Word(word) => Some(word.as_str()),
+            Token::Symbol(_) => None,
+        })
+    };
+    let first = match statement.iter().find(|token| **token != Token::Symbol('('))? {
+        Token::Word(word) => word.as_str(),
+        Token::Symbol(symbol) => return Some(format!("statements starting with `{}` are not allowed", symbol)),
+    };
+
+    if let Some(keyword) = words().find(|word| WRITE_KEYWORDS.contains(word)) {
+        return Some(format!("statements using {} are not allowed", keyword));
+    }
+    if READ_STATEMENTS.contains(&first) {
+        return None;
+    }
+    if SESSION_STATEMENTS.contains(&first) {
+        // `READ WRITE` and the read-only variables would undo the session's read-only mode
+        let reopens = words().any(|word| word == "WRITE" || word.contains("READ_ONLY"));
+        return reopens.then(|| "statements that allow writes are not allowed".to_string());
+    }
+    if first == "PRAGMA" {
+        // SQLite pragmas read without a value and change a setting with one
+        let assigns = statement.contains(&Token::Symbol('='));
+        return assigns.then(|| "PRAGMA assignments are not allowed".to_string());
+    }
+    Some(format!("{} statements are not allowed", first))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    /// A keyword or unquoted name, upper-cased
+    Word(String),
+    Symbol(char),
+}
+
+/// Split SQL into statements of tokens, leaving out comments, literals and quoted names
+fn statements(db_type: DbType, sql: &str) -> Vec<Vec<Token>> {
+    let hash_comments = matches!(db_type, DbType::MySql | DbType::ClickHouse);
+    let backslash_escapes = matches!(db_type, DbType::MySql | DbType::ClickHouse);
+    let bracket_names = matches!(db_type, DbType::MsSql | DbType::Sqlite);
+    let dollar_quotes = matches!(db_type, DbType::Postgres | DbType::DuckDb);
+
+    let chars: Vec<char> = sql.chars().collect();
+    let mut statements = Vec::new();
+    let mut current = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '-' if next == Some('-') => i = skip_line(&chars, i),
+            '#' if hash_comments => i = skip_line(&chars, i),
+            '/' if next == Some('*') => {
+                i = find(&chars, i + 2, &['*', '/']).map_or(chars.len(), |end| end + 2);
+            }
+            '\'' | '"' | '`' => i = skip_quoted(&chars, i, c, backslash_escapes && c != '`'),
+            '[' if bracket_names => i = skip_quoted(&chars, i, ']', false),
+            '$' if dollar_quotes && dollar_tag(&chars, i).is_some() => {
+                let tag = dollar_tag(&chars, i).unwrap_or_default();
+                i = find(&chars, i + tag.len(), &tag).map_or(chars.len(), |end| end + tag.len());
+            }
+            ';' => {
+                statements.push(std::mem::take(&mut current));
+                i += 1;
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '@' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '$' | '@')) {
+                    i += 1;
+                }
+                // Numbers and variables are never keywords
+                if c.is_alphabetic() || c == '_' {
+                    let word: String = chars[start..i].iter().collect();
+                    current.push(Token::Word(word.to_uppercase()));
+                }
+            }
+            c if c.is_whitespace() => i += 1,
+            c => {
+                current.push(Token::Symbol(c));
+                i += 1;
+            }
+        }
+    }
+    statements.push(current);
+    statements.retain(|statement| !statement.is_empty());
+    statements
+}
+
+fn skip_line(chars: &[char], start: usize) -> usize {
+    find(chars, start, &['\n']).map_or(chars.len(), |end| end + 1)
+}
+
+/// The index just past a literal or quoted name opened at `start` and closed by `close`
+///
+/// A doubled `close` stands for itself, as does any character after a backslash
+/// when `backslash_escapes` is set.
+fn skip_quoted(chars: &[char], start: usize, close: char, backslash_escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        if backslash_escapes && chars[i] == '\\' {
+            i += 2;
+        } else if chars[i] == close {
+            if chars.get(i + 1) == Some(&close) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    chars.len()
+}
+
+/// The `$tag$` opening a dollar-quoted string at `start`, if there is one
+///
+/// `$1` is a parameter, not a tag, as tags cannot start with a digit.
+fn dollar_tag(chars: &[char], start: usize) -> Option<Vec<char>> {
+    let mut i = start + 1;
+    if chars.get(i).is_some_and(char::is_ascii_digit) {
+        return None;
+    }
+    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+        i += 1;
+    }
+    (chars.get(i) == Some(&'$')).then(|| chars[start..=i].to_vec())
+}
+
+fn find(chars: &[char], from: usize, needle: &[char]) -> Option<usize> {
+    (from..chars.len()).find(|&i| chars[i..].starts_with(needle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn connection(db_type: DbType) -> Connection {
+        Connection {
+            id: 1,
+            connection_name: "production".to_string(),
+            project_id: 1,
+            db_type,
+            host: "db.example.com".to_string(),
+            port: "5432".to_string(),
+            username: String::new(),
+            password: String::new(),
+            database: String::new(),
+            statement_timeout_ms: None,
+            read_only: true,
+            ssh_tunnel: None,
+            tls: None,
+            options: BTreeMap::new(),
+            server_info: None,
+            server_checked_at: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_reads_are_allowed() {
+        let postgres = connection(DbType::Postgres);
+        for sql in [
+            "SELECT * FROM orders WHERE status = 'delete me'",
+            "  -- DROP TABLE orders\n  with recent as (select 1) select * from recent;",
+            "EXPLAIN SELECT 1; SHOW search_path",
+            "SELECT $body$ UPDATE $body$, $1",
+            "SELECT \"update\" FROM t",
+            "BEGIN READ ONLY; SELECT 1; COMMIT",
+            "SET search_path = reporting",
+            "(SELECT 1) UNION (SELECT 2)",
+        ] {
+            assert!(check_statement(&postgres, sql).is_ok(), "{}", sql);
+        }
+        assert!(check_statement(&connection(DbType::MySql), "SELECT 'it\\'s'; # DELETE").is_ok());
+        assert!(check_statement(&connection(DbType::Sqlite), "PRAGMA table_info([delete])").is_ok());
+    }
+
+    #[test]
+    fn test_writes_are_refused() {
+        let postgres = connection(DbType::Postgres);
+        for sql in [
+            "DELETE FROM orders",
+            "select 1; update orders set status = 'x'",
+            "WITH gone AS (DELETE FROM orders RETURNING *) SELECT * FROM gone",
+            "SELECT * INTO archive FROM orders",
+            "EXPLAIN ANALYZE DELETE FROM orders",
+            "SET SESSION CHARACTERISTICS AS TRANSACTION READ WRITE",
+            "SET default_transaction_read_only = off",
+            "BEGIN READ WRITE",
+            "VACUUM",
+            "SELECT set_config('default_transaction_read_only', 'off', false)",
+        ] {
+            let error = check_statement(&postgres, sql).unwrap_err();
+            assert_eq!(error.category, ErrorCategory::Validation(ValidationSubcategory::InvalidType), "{}", sql);
+        }
+        assert!(check_statement(&connection(DbType::Sqlite), "PRAGMA journal_mode = WAL").is_err());
+        let mssql = connection(DbType::MsSql);
+        for sql in [
+            "DECLARE @s NVARCHAR(100) = N'DROP TABLE t'; EXEC(@s)",
+            "DECLARE @s NVARCHAR(100) = N'DROP TABLE t'; EXECUTE sp_executesql @s",
+            "EXEC dbo.purge_orders",
+        ] {
+            assert!(check_statement(&mssql, sql).is_err(), "{}", sql);
+        }
+        assert!(check_statement(&mssql, "DECLARE @n INT = 1; SELECT @n").is_ok());
+
+        let mut writable = connection(DbType::Postgres);
+        writable.read_only = false;
+        assert!(check_statement(&writable, "DROP TABLE orders").is_ok());
+    }
+
+    #[test]
+    fn test_pipelines_and_commands_are_checked() {
+        let mongodb = connection(DbType::MongoDb);
+        assert!(check_pipeline(&mongodb, &[json!({"$match": {"status": "open"}})]).is_ok());
+        assert!(check_pipeline(&mongodb, &[json!({"$match": {}}), json!({"$out": "archive"})]).is_err());
+
+        let redis = connection(DbType::Redis);
+        assert!(check_command(&redis, "HGETALL user:1").is_ok());
+        assert!(check_command(&redis, "config get maxmemory").is_ok());
+        assert!(check_command(&redis, "CONFIG SET maxmemory 1").is_err());
+        assert!(check_command(&redis, "DEL user:1").is_err());
+    }
+}
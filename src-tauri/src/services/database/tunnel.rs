@@ -0,0 +1,531 @@
+//! SSH tunnels for connections that are only reachable through a bastion host.
+//!
+//! A [`Tunnel`] logs in to the bastion, listens on an ephemeral loopback port
+//! and forwards every connection made to that port through the SSH session to
+//! the database server, so drivers connect to `127.0.0.1` as if the server were
+//! local. libssh2 is blocking, so the session runs in non-blocking mode on a
+//! dedicated thread that multiplexes all forwarded connections.
+
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::constants::tunnels::{BUFFER_SIZE, CONNECT_TIMEOUT_SECS, IDLE_POLL_MS};
+use crate::error::categories::{
+    AuthSubcategory, ConnectionSubcategory, ErrorCategory, UnknownSubcategory,
+    ValidationSubcategory,
+};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::storage::repositories::connections::{DbType, SshAuth, SshTunnel};
+
+use super::driver;
+use super::is_local_file;
+
+/// A forwarded loopback port, open until the tunnel is dropped
+#[derive(Debug)]
+pub struct Tunnel {
+    local_port: u16,
+    stop: Arc<AtomicBool>,
+}
+
+impl Tunnel {
+    /// Log in to the bastion and start forwarding a loopback port to `target_host:target_port`
+    ///
+    /// `target_host` is resolved by the bastion, so it may be a name only reachable from there.
+    ///
+    /// # Errors
+    /// Returns an error if the bastion could not be reached, its host key could not be
+    /// verified or the login was rejected
+    pub async fn open(config: &SshTunnel, target_host: &str, target_port: u16) -> AppResult<Self> {
+        let config = config.clone();
+        let target_host = target_host.to_string();
+        tokio::task::spawn_blocking(move || Self::open_blocking(&config, target_host, target_port))
+            .await
+            .map_err(|e| {
+                AppError::new(
+                    format!("SSH tunnel task failed: {}", e),
+                    ErrorCategory::Unknown(UnknownSubcategory::Unexpected),
+                    ErrorSeverity::Error,
+                )
+            })?
+    }
+
+    /// Loopback port that drivers should connect to
+    #[must_use]
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    fn open_blocking(config: &SshTunnel, target_host: String, target_port: u16) -> AppResult<Self> {
+        let session = login(config)?;
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).map_err(tunnel_error)?;
+        listener.set_nonblocking(true).map_err(tunnel_error)?;
+        let local_port = listener.local_addr().map_err(tunnel_error)?.port();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let forwarder = Forwarder {
+            session,
+            listener,
+            target_host,
+            target_port,
+            links: Vec::new(),
+            stop: Arc::clone(&stop),
+        };
+        thread::Builder::new()
+            .name(format!("ssh-tunnel-{}", local_port))
+            .spawn(move || forwarder.run())
+            .map_err(tunnel_error)?;
+
+        info!(
+            "SSH tunnel through {}:{} listening on 127.0.0.1:{}",
+            config.host, config.port, local_port
+        );
+        Ok(Self { local_port, stop })
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Open the tunnel a connection needs, if any
+///
+/// Connections without SSH settings and local SQLite or DuckDB files connect directly.
+/// SQL Server instances need an explicit port to be tunnelled.
+///
+/// # Errors
+/// Returns an error if the target port is invalid or the tunnel could not be opened
+pub async fn open_for(
+    db_type: DbType,
+    host: &str,
+    port: &str,
+    config: Option<&SshTunnel>,
+) -> AppResult<Option<Tunnel>> {
+    let Some(config) = config else {
+        return Ok(None);
+    };
+    if driver::driver(db_type).capabilities().local_file && is_local_file(host, port) {
+        return Ok(None);
+    }
+    // A named SQL Server instance is reached through its port; the browser is not forwarded
+    let host = match host.split_once('\\') {
+        Some((host, _)) if db_type == DbType::MsSql => host,
+        _ => host,
+    };
+
+    let port: u16 = port.trim().parse().map_err(|_| {
+        AppError::new(
+            "Invalid port number",
+            ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+            ErrorSeverity::Error,
+        )
+    })?;
+    Tunnel::open(config, host.trim(), port).await.map(Some)
+}
+
+/// Connect to the bastion, verify its host key and log in
+fn login(config: &SshTunnel) -> AppResult<Session> {
+    let host = config.host.trim();
+    let timeout = Duration::from_secs(CONNECT_TIMEOUT_SECS);
+
+    let addresses = (host, config.port).to_socket_addrs().map_err(|e| {
+        AppError::new(
+            format!("Could not resolve SSH host {}: {}", host, e),
+            ErrorCategory::Connection(ConnectionSubcategory::ConnectionFailed),
+            ErrorSeverity::Error,
+        )
+    })?;
+    let mut last_error = None;
+    let stream = addresses
+        .into_iter()
+        .find_map(|address| {
+            TcpStream::connect_timeout(&address, timeout)
+                .map_err(|e| last_error = Some(e))
+                .ok()
+        })
+        .ok_or_else(|| {
+            let e = last_error.unwrap_or_else(|| io::ErrorKind::AddrNotAvailable.into());
+            let subcategory = match e.kind() {
+                io::ErrorKind::ConnectionRefused => ConnectionSubcategory::Refused,
+                io::ErrorKind::TimedOut => ConnectionSubcategory::Timeout,
+                _ => ConnectionSubcategory::ConnectionFailed,
+            };
+            AppError::new(
+                format!("Could not reach SSH host {}:{}: {}", host, config.port, e),
+                ErrorCategory::Connection(subcategory),
+                ErrorSeverity::Error,
+            )
+        })?;
+
+    let mut session = Session::new().map_err(ssh_error)?;
+    session.set_timeout(u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX));
+    session.set_tcp_stream(stream);
+    session.handshake().map_err(ssh_error)?;
+
+    if config.verify_host_key {
+        verify_host_key(&session, config)?;
+    } else {
+        warn!("Host key verification is disabled for SSH host {}", host);
+    }
+
+    let result = match &config.auth {
+        SshAuth::Password { password } => session.userauth_password(&config.username, password),
+        SshAuth::PrivateKey {
+            private_key_path,
+            passphrase,
+        } => session.userauth_pubkey_file(
+            &config.username,
+            None,
+            &expand_home(private_key_path),
+            passphrase.as_deref(),
+        ),
+    };
+    if let Err(e) = result.as_ref() {
+        debug!("SSH login to {} failed: {}", host, e);
+    }
+    if result.is_err() || !session.authenticated() {
+        return Err(AppError::new(
+            format!("SSH login to {} as {} was rejected", host, config.username),
+            ErrorCategory::Auth(AuthSubcategory::InvalidCredentials),
+            ErrorSeverity::Error,
+        ));
+    }
+
+    Ok(session)
+}
+
+/// Check the bastion's host key against the configured `known_hosts` file
+fn verify_host_key(session: &Session, config: &SshTunnel) -> AppResult<()> {
+    let host = config.host.trim();
+    let path = match &config.known_hosts_path {
+        Some(path) => expand_home(path),
+        None => dirs::home_dir()
+            .unwrap_or_default()
+            .join(".ssh")
+            .join("known_hosts"),
+    };
+
+    let mut known_hosts = session.known_hosts().map_err(ssh_error)?;
+    if path.exists() {
+        known_hosts
+            .read_file(&path, KnownHostFileKind::OpenSSH)
+            .map_err(ssh_error)?;
+    }
+    let (key, _) = session.host_key().ok_or_else(|| {
+        host_key_error(format!("SSH host {} did not present a host key", host))
+    })?;
+
+    match known_hosts.check_port(host, config.port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => Err(host_key_error(format!(
+            "SSH host {} is not listed in {}; add its key or disable host key verification",
+            host,
+            path.display()
+        ))),
+        CheckResult::Mismatch => Err(host_key_error(format!(
+            "SSH host key for {} does not match the one in {}; the host may be impersonated",
+            host,
+            path.display()
+        ))),
+        CheckResult::Failure => Err(host_key_error(format!(
+            "Could not check the SSH host key for {}",
+            host
+        ))),
+    }
+}
+
+/// Expand a leading `~/` to the user's home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => Path::new(path).to_path_buf(),
+    }
+}
+
+/// Accepts loopback connections and shuttles their bytes over SSH channels
+struct Forwarder {
+    session: Session,
+    listener: TcpListener,
+    target_host: String,
+    target_port: u16,
+    links: Vec<Link>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Forwarder {
+    fn run(mut self) {
+        self.session.set_blocking(false);
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+
+        while !self.stop.load(Ordering::Relaxed) {
+            let mut busy = self.accept();
+            self.links.retain_mut(|link| match link.pump(&mut buffer) {
+                Ok(moved) => {
+                    busy |= moved;
+                    true
+                }
+                Err(e) => {
+                    debug!("Closing forwarded connection: {}", e);
+                    link.close();
+                    false
+                }
+            });
+            if !busy {
+                thread::sleep(Duration::from_millis(IDLE_POLL_MS));
+            }
+        }
+
+        for link in &mut self.links {
+            link.close();
+        }
+        self.session.set_blocking(true);
+        let _ = self.session.disconnect(None, "Tunnel closed", None);
+        debug!("SSH tunnel to {}:{} stopped", self.target_host, self.target_port);
+    }
+
+    /// Open a channel for a newly accepted connection; returns whether one was waiting
+    fn accept(&mut self) -> bool {
+        let socket = match self.listener.accept() {
+            Ok((socket, _)) => socket,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return false,
+            Err(e) => {
+                warn!("SSH tunnel failed to accept a connection: {}", e);
+                return false;
+            }
+        };
+
+        // Opening a channel is a single round trip, so block for it
+        self.session.set_blocking(true);
+        let channel = self
+            .session
+            .channel_direct_tcpip(&self.target_host, self.target_port, None);
+        self.session.set_blocking(false);
+
+        match (channel, socket.set_nonblocking(true)) {
+            (Ok(channel), Ok(())) => {
+                let _ = socket.set_nodelay(true);
+                self.links.push(Link {
+                    socket,
+                    channel,
+                    upstream: Vec::new(),
+                    downstream: Vec::new(),
+                });
+            }
+            (Err(e), _) => warn!(
+                "SSH host refused to forward to {}:{}: {}",
+                self.target_host, self.target_port, e
+            ),
+            (_, Err(e)) => warn!("SSH tunnel failed to set up a connection: {}", e),
+        }
+        true
+    }
+}
+
+/// One forwarded connection
+struct Link {
+    socket: TcpStream,
+    channel: ssh2::Channel,
+    /// Read from the socket, not yet accepted by the channel
+    upstream: Vec<u8>,
+    /// Read from the channel, not yet accepted by the socket
+    downstream: Vec<u8>,
+}
+
+impl Link {
+    /// Move whatever is ready in either direction; returns whether anything moved
+    ///
+    /// Fails once either side has closed or errored.
+    fn pump(&mut self, buffer: &mut [u8]) -> io::Result<bool> {
+        let up = transfer(&mut self.socket, &mut self.channel, &mut self.upstream, buffer)?;
+        let down = transfer(&mut self.channel, &mut self.socket, &mut self.downstream, buffer)?;
+        Ok(up || down)
+    }
+
+    fn close(&mut self) {
+        let _ = self.socket.shutdown(std::net::Shutdown::Both);
+        let _ = self.channel.close();
+    }
+}
+
+/// Copy ready bytes from `from` to `to`, holding back what `to` cannot take yet
+///
+/// Returns whether anything moved, or an error once `from` reaches end of stream.
+fn transfer(
+    from: &mut impl Read,
+    to: &mut impl Write,
+    pending: &mut Vec<u8>,
+    buffer: &mut [u8],
+) -> io::Result<bool> {
+    let mut moved = false;
+    if pending.is_empty() {
+        match from.read(buffer) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(read) => {
+                pending.extend_from_slice(&buffer[..read]);
+                moved = true;
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if !pending.is_empty() {
+        match to.write(pending) {
+            Ok(written) => {
+                pending.drain(..written);
+                moved = true;
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(moved)
+}
+
+fn ssh_error(e: ssh2::Error) -> AppError {
+    AppError::new(
+        format!("SSH error: {}", e),
+        ErrorCategory::Connection(ConnectionSubcategory::ProtocolError),
+        ErrorSeverity::Error,
+    )
+}
+
+fn tunnel_error(e: io::Error) -> AppError {
+    AppError::new(
+        format!("Failed to open SSH tunnel: {}", e),
+        ErrorCategory::Connection(ConnectionSubcategory::ConnectionFailed),
+        ErrorSeverity::Error,
+    )
+}
+
+fn host_key_error(message: String) -> AppError {
+    AppError::new(
+        message,
+        ErrorCategory::Auth(AuthSubcategory::PermissionDenied),
+        ErrorSeverity::Error,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn password_tunnel(host: &str, port: u16, username: &str, password: &str) -> SshTunnel {
+        SshTunnel {
+            host: host.to_string(),
+            port,
+            username: username.to_string(),
+            auth: SshAuth::Password {
+                password: password.to_string(),
+            },
+            verify_host_key: false,
+            known_hosts_path: None,
+        }
+    }
+
+    /// A writer that takes at most `limit` bytes per call, then blocks
+    struct Trickle {
+        written: Vec<u8>,
+        limit: usize,
+        blocked: bool,
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            if self.blocked {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let taken = data.len().min(self.limit);
+            self.written.extend_from_slice(&data[..taken]);
+            Ok(taken)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_transfer_holds_back_what_the_writer_cannot_take() {
+        let mut from: &[u8] = b"hello world";
+        let mut to = Trickle {
+            written: Vec::new(),
+            limit: 4,
+            blocked: false,
+        };
+        let mut pending = Vec::new();
+        let mut buffer = [0u8; 64];
+
+        assert!(transfer(&mut from, &mut to, &mut pending, &mut buffer).unwrap());
+        assert_eq!(pending, b"o world");
+
+        to.blocked = true;
+        assert!(!transfer(&mut from, &mut to, &mut pending, &mut buffer).unwrap());
+        to.blocked = false;
+        to.limit = 64;
+        assert!(transfer(&mut from, &mut to, &mut pending, &mut buffer).unwrap());
+        assert_eq!(to.written, b"hello world");
+
+        let eof = transfer(&mut from, &mut to, &mut pending, &mut buffer).unwrap_err();
+        assert_eq!(eof.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_bastion_is_refused() {
+        let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = password_tunnel("127.0.0.1", port, "nobody", "secret");
+
+        let e = Tunnel::open(&config, "127.0.0.1", 5432).await.unwrap_err();
+        assert_eq!(
+            e.category,
+            ErrorCategory::Connection(ConnectionSubcategory::Refused)
+        );
+    }
+
+    /// Forwards to an echo server through a real sshd, e.g. a local
+    /// `linuxserver/openssh-server` container with `AllowTcpForwarding yes`.
+    /// Set `DEWEY_TEST_SSH` to `user:password@host:port`, and `DEWEY_TEST_SSH_TARGET`
+    /// to the address the sshd reaches this machine on, e.g. `host.docker.internal`
+    /// for a container; it defaults to `127.0.0.1` for an sshd running locally.
+    #[tokio::test]
+    #[ignore = "needs an sshd, see DEWEY_TEST_SSH"]
+    async fn test_forwards_through_local_sshd() {
+        let target = std::env::var("DEWEY_TEST_SSH").expect("DEWEY_TEST_SSH is not set");
+        let target_host = std::env::var("DEWEY_TEST_SSH_TARGET").unwrap_or_else(|_| "127.0.0.1".to_string());
+        let (credentials, address) = target.rsplit_once('@').unwrap();
+        let (username, password) = credentials.split_once(':').unwrap();
+        let (host, port) = address.rsplit_once(':').unwrap();
+        let config = password_tunnel(host, port.parse().unwrap(), username, password);
+
+        // Listen on every interface so an sshd in a container can reach the echo server
+        let echo = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        let echo_port = echo.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for mut stream in echo.incoming().flatten() {
+                let mut reader = stream.try_clone().unwrap();
+                thread::spawn(move || io::copy(&mut reader, &mut stream));
+            }
+        });
+
+        let tunnel = Tunnel::open(&config, &target_host, echo_port).await.unwrap();
+        for message in [&b"first"[..], &b"second"[..]] {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, tunnel.local_port())).unwrap();
+            stream.write_all(message).unwrap();
+            let mut reply = vec![0u8; message.len()];
+            stream.read_exact(&mut reply).unwrap();
+            assert_eq!(reply, message);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! CSV and TSV files.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::error::AppResult;
+use crate::services::database::query::{ColumnInfo, DbValue};
+
+use super::{create_file, invalid_options, text, write_failed, DelimitedOptions, QuoteStyle, RowWriter};
+
+pub(super) struct DelimitedWriter {
+    path: PathBuf,
+    out: csv::Writer<BufWriter<File>>,
+    header: bool,
+    null: String,
+}
+
+impl DelimitedWriter {
+    /// Create the file, separating fields with the configured delimiter or `default_delimiter`
+    ///
+    /// # Errors
+    /// Returns an error if the delimiter is not a single ASCII character or the file
+    /// could not be created
+    pub(super) fn create(path: &Path, options: &DelimitedOptions, default_delimiter: char) -> AppResult<Self> {
+        let delimiter = options.delimiter.unwrap_or(default_delimiter);
+        let delimiter = u8::try_from(delimiter)
+            .ok()
+            .filter(|delimiter| delimiter.is_ascii() && !matches!(delimiter, b'"' | b'\r' | b'\n'))
+            .ok_or_else(|| invalid_options(format!("{:?} cannot separate fields", delimiter)))?;
+
+        let out = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .quote_style(match options.quote {
+                QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+                QuoteStyle::Always => csv::QuoteStyle::Always,
+                QuoteStyle::NonNumeric => csv::QuoteStyle::NonNumeric,
+                QuoteStyle::Never => csv::QuoteStyle::Never,
+            })
+            .from_writer(BufWriter::new(create_file(path)?));
+        Ok(Self {
+            path: path.to_path_buf(),
+            out,
+            header: options.header,
+            null: options.null.clone(),
+        })
+    }
+}
+
+impl RowWriter for DelimitedWriter {
+    fn begin(&mut self, columns: &[ColumnInfo]) -> AppResult<()> {
+        if !self.header {
+            return Ok(());
+        }
+        self.out
+            .write_record(columns.iter().map(|column| column.name.as_str()))
+            .map_err(|e| write_failed(&self.path, e))
+    }
+
+    fn write_rows(&mut self, rows: &[Vec<DbValue>]) -> AppResult<()> {
+        for row in rows {
+            for value in row {
+                let field = text(value);
+                let field = field.as_deref().unwrap_or(self.null.as_str());
+                self.out.write_field(field).map_err(|e| write_failed(&self.path, e))?;
+            }
+            // An empty record ends the one whose fields were just written
+            self.out
+                .write_record(None::<&[u8]>)
+                .map_err(|e| write_failed(&self.path, e))?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> AppResult<()> {
+        self.out.flush().map_err(|e| write_failed(&self.path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str) -> ColumnInfo {
+        ColumnInfo {
+            name: name.to_string(),
+            type_name: "TEXT".to_string(),
+            nullable: None,
+        }
+    }
+
+    #[test]
+    fn test_delimited_options_are_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.tsv");
+        let options = DelimitedOptions {
+            null: "\\N".to_string(),
+            ..DelimitedOptions::default()
+        };
+
+        let mut writer = Box::new(DelimitedWriter::create(&path, &options, '\t').unwrap());
+        writer.begin(&[column("id"), column("note")]).unwrap();
+        writer
+            .write_rows(&[
+                vec![DbValue::Int(1), DbValue::Text("tab\there".to_string())],
+                vec![DbValue::Int(2), DbValue::Null],
+            ])
+            .unwrap();
+        writer.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "id\tnote\n1\t\"tab\there\"\n2\t\\N\n");
+
+        let options = DelimitedOptions {
+            delimiter: Some('é'),
+            ..DelimitedOptions::default()
+        };
+        assert!(DelimitedWriter::create(&path, &options, ',').is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! JSON arrays and newline-delimited JSON.
+
+use serde_json::Value as JsonValue;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::AppResult;
+use crate::services::database::query::{ColumnInfo, DbValue};
+
+use super::{create_file, write_failed, RowWriter};
+
+/// How rows are laid out in the file
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Layout {
+    /// One array holding every row, with each row indented over several lines if `pretty`
+    Array { pretty: bool },
+    /// One row per line
+    Lines,
+}
+
+pub(super) struct JsonWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+    layout: Layout,
+    /// Object keys for the columns, made unique
+    keys: Vec<String>,
+    rows_written: u64,
+}
+
+impl JsonWriter {
+    /// Create the file
+    ///
+    /// # Errors
+    /// Returns an error if the file could not be created
+    pub(super) fn create(path: &Path, layout: Layout) -> AppResult<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            out: BufWriter::new(create_file(path)?),
+            layout,
+            keys: Vec::new(),
+            rows_written: 0,
+        })
+    }
+
+    /// Write a row as an object whose keys follow the column order
+    fn write_row(&mut self, row: &[DbValue]) -> std::io::Result<()> {
+        let (open, separator, close) = match self.layout {
+            Layout::Array { pretty: true } => ("  {\n    ", ",\n    ", "\n  }"),
+            Layout::Array { pretty: false } | Layout::Lines => ("{", ",", "}"),
+        };
+        let colon = if let Layout::Array { pretty: true } = self.layout { ": " } else { ":" };
+
+        if let Layout::Array { .. } = self.layout {
+            self.out.write_all(if self.rows_written == 0 { b"\n" } else { b",\n" })?;
+        }
+        self.out.write_all(open.as_bytes())?;
+        for (index, (key, value)) in self.keys.iter().zip(row).enumerate() {
+            if index > 0 {
+                self.out.write_all(separator.as_bytes())?;
+            }
+            serde_json::to_writer(&mut self.out, key)?;
+            self.out.write_all(colon.as_bytes())?;
+            serde_json::to_writer(&mut self.out, &json_value(value))?;
+        }
+        self.out.write_all(close.as_bytes())?;
+        if let Layout::Lines = self.layout {
+            self.out.write_all(b"\n")?;
+        }
+        self.rows_written += 1;
+        Ok(())
+    }
+}
+
+impl RowWriter for JsonWriter {
+    fn begin(&mut self, columns: &[ColumnInfo]) -> AppResult<()> {
+        self.keys = unique_keys(columns);
+        if let Layout::Array { .. } = self.layout {
+            self.out.write_all(b"[").map_err(|e| write_failed(&self.path, e))?;
+        }
+        Ok(())
+    }
+
+    fn write_rows(&mut self, rows: &[Vec<DbValue>]) -> AppResult<()> {
+        for row in rows {
+            self.write_row(row).map_err(|e| write_failed(&self.path, e))?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> AppResult<()> {
+        let end: &[u8] = match self.layout {
+            Layout::Array { .. } if self.rows_written == 0 => b"]\n",
+            Layout::Array { .. } => b"\n]\n",
+            Layout::Lines => b"",
+        };
+        self.out
+            .write_all(end)
+            .and_then(|()| self.out.flush())
+            .map_err(|e| write_failed(&self.path, e))
+    }
+}
+
+/// Column names as object keys, numbering repeats so no column is lost (`id`, `id_2`, ...)
+fn unique_keys(columns: &[ColumnInfo]) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::with_capacity(columns.len());
+    for column in columns {
+        let mut key = column.name.clone();
+        let mut suffix = 1;
+        while keys.contains(&key) {
+            suffix += 1;
+            key = format!("{}_{}", column.name, suffix);
+        }
+        keys.push(key);
+    }
+    keys
+}
+
+/// A value as JSON; decimals stay strings so they are not rounded
+fn json_value(value: &DbValue) -> JsonValue {
+    match value {
+        DbValue::Null => JsonValue::Null,
+        DbValue::Bool(value) => JsonValue::Bool(*value),
+        DbValue::Int(value) => JsonValue::from(*value),
+        DbValue::UInt(value) => JsonValue::from(*value),
+        DbValue::Float(value) => serde_json::Number::from_f64(*value).map_or(JsonValue::Null, JsonValue::Number),
+        DbValue::Json(value) => value.clone(),
+        DbValue::Decimal(value)
+        | DbValue::Text(value)
+        | DbValue::Bytes(value)
+        | DbValue::Uuid(value)
+        | DbValue::Date(value)
+        | DbValue::Time(value)
+        | DbValue::DateTime(value) => JsonValue::String(value.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(layout: Layout, rows: &[Vec<DbValue>]) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.json");
+        let columns: Vec<ColumnInfo> = ["id", "id", "doc"]
+            .iter()
+            .map(|name| ColumnInfo {
+                name: name.to_string(),
+                type_name: String::new(),
+                nullable: None,
+            })
+            .collect();
+
+        let mut writer = Box::new(JsonWriter::create(&path, layout).unwrap());
+        writer.begin(&columns).unwrap();
+        writer.write_rows(rows).unwrap();
+        writer.finish().unwrap();
+        std::fs::read_to_string(&path).unwrap()
+    }
+
+    #[test]
+    fn test_json_layouts() {
+        let rows = vec![
+            vec![DbValue::Int(1), DbValue::Decimal("1.10".to_string()), DbValue::Json(serde_json::json!({"a": 1}))],
+            vec![DbValue::Int(2), DbValue::Null, DbValue::Float(f64::NAN)],
+        ];
+
+        assert_eq!(
+            export(Layout::Lines, &rows),
+            "{\"id\":1,\"id_2\":\"1.10\",\"doc\":{\"a\":1}}\n{\"id\":2,\"id_2\":null,\"doc\":null}\n"
+        );
+        let array = export(Layout::Array { pretty: true }, &rows);
+        let parsed: JsonValue = serde_json::from_str(&array).unwrap();
+        assert_eq!(parsed[1]["id"], 2);
+        assert!(array.starts_with("[\n  {\n    \"id\": 1,\n    \"id_2\": \"1.10\","));
+        assert_eq!(export(Layout::Array { pretty: false }, &[]), "[]\n");
+    }
+}
@@ -0,0 +1,464 @@
+//! Export of query results to files.
+//!
+//! An export runs its statement as a cursor query (see
+//! [`cursor`](crate::services::database::cursor)) and writes each page to the
+//! file before the page after next is read, so only a couple of pages are held
+//! in memory however large the result is. Progress is reported after every
+//! page, and the export is cancelled like any other query, by its handle.
+//!
+//! Rows are written to a `.part` file next to the destination, which is only
+//! renamed into place once every row has been written; a failed or cancelled
+//! export removes it.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::constants;
+use crate::error::categories::{ErrorCategory, IoSubcategory, ValidationSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::database::cursor::{CursorOptions, QueryEvent, QueryHandle, QueryRegistry};
+use crate::services::database::driver::driver;
+use crate::services::database::pool::{ConnectionManager, DatabasePool};
+use crate::services::database::query::{ColumnInfo, DbValue};
+use crate::services::storage::repositories::connections::DbType;
+
+mod delimited;
+mod json;
+mod sql;
+mod xlsx;
+
+/// What to export from a connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExportSource {
+    /// The results of a statement, with parameters bound as for `execute_query`
+    Query {
+        sql: String,
+        #[serde(default)]
+        params: Vec<DbValue>,
+    },
+    /// Every row of a table or view
+    Table {
+        schema: Option<String>,
+        table: String,
+    },
+}
+
+impl ExportSource {
+    /// The statement to run and its parameters
+    #[must_use]
+    pub fn statement(&self, db_type: DbType) -> (String, Vec<DbValue>) {
+        match self {
+            Self::Query { sql, params } => (sql.clone(), params.clone()),
+            Self::Table { schema, table } => {
+                (format!("SELECT * FROM {}", qualified_name(db_type, schema.as_deref(), table)), Vec::new())
+            }
+        }
+    }
+}
+
+/// The file format rows are exported in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv(DelimitedOptions),
+    Tsv(DelimitedOptions),
+    /// A single JSON array with one object per row
+    Json {
+        #[serde(default)]
+        pretty: bool,
+    },
+    /// One JSON object per line
+    Ndjson,
+    /// `INSERT` statements that recreate the rows
+    Sql(SqlOptions),
+    /// An Excel workbook, continued on further worksheets past Excel's row limit
+    Xlsx {
+        sheet_name: Option<String>,
+    },
+}
+
+/// How CSV and TSV files are written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DelimitedOptions {
+    /// Separates fields; a comma for CSV and a tab for TSV when not set
+    pub delimiter: Option<char>,
+    pub quote: QuoteStyle,
+    /// Write the column names as the first row
+    pub header: bool,
+    /// Written in place of NULL values
+    pub null: String,
+}
+
+impl Default for DelimitedOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: None,
+            quote: QuoteStyle::default(),
+            header: true,
+            null: String::new(),
+        }
+    }
+}
+
+/// Which fields of a CSV or TSV file are quoted
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteStyle {
+    /// Only fields containing the delimiter, a quote or a line break
+    #[default]
+    Necessary,
+    Always,
+    /// Every field that does not look like a number
+    NonNumeric,
+    /// No fields; values containing the delimiter will not read back correctly
+    Never,
+}
+
+/// How `INSERT` statements are written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SqlOptions {
+    /// The table the statements insert into; the exported table's own name when not set
+    pub table: Option<String>,
+    pub schema: Option<String>,
+    /// The engine whose syntax the statements are written in
+    pub dialect: DbType,
+    /// Rows inserted by each statement
+    pub rows_per_statement: Option<usize>,
+}
+
+/// Messages pushed to the frontend while an export runs
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ExportEvent {
+    /// Another page of rows was written
+    Progress {
+        handle: QueryHandle,
+        rows_written: u64,
+    },
+    /// Every row was written and the file is in place
+    Done {
+        handle: QueryHandle,
+        rows_written: u64,
+        duration_ms: u64,
+    },
+    /// The export failed and its partial file was removed
+    Error {
+        handle: QueryHandle,
+        error: AppError,
+    },
+    /// The export was cancelled and its partial file was removed
+    Cancelled {
+        handle: QueryHandle,
+    },
+}
+
+/// A statement whose results are exported, and where to
+#[derive(Debug, Clone)]
+pub struct ExportJob {
+    pub connection_id: i64,
+    pub sql: String,
+    pub params: Vec<DbValue>,
+    /// Longest the server may spend producing a single page
+    pub timeout: Option<Duration>,
+    pub format: ExportFormat,
+    pub path: PathBuf,
+}
+
+/// Writes rows to an export file in one format
+trait RowWriter: Send {
+    /// Write whatever comes before the rows, once the columns are known
+    fn begin(&mut self, columns: &[ColumnInfo]) -> AppResult<()>;
+
+    fn write_rows(&mut self, rows: &[Vec<DbValue>]) -> AppResult<()>;
+
+    /// Write whatever follows the rows and flush the file
+    fn finish(self: Box<Self>) -> AppResult<()>;
+}
+
+/// Start exporting a statement's results in the background
+///
+/// Events are sent on `sink` as pages are written. The returned handle cancels the
+/// export with `QueryRegistry::cancel`.
+///
+/// # Errors
+/// Returns an error if the format's options are invalid, the file could not be created,
+/// or the pool does not speak SQL
+pub async fn start<F>(
+    registry: &Arc<QueryRegistry>,
+    sessions: Arc<ConnectionManager>,
+    pool: DatabasePool,
+    job: ExportJob,
+    sink: F,
+) -> AppResult<QueryHandle>
+where
+    F: Fn(ExportEvent) + Send + Sync + 'static,
+{
+    let part_path = part_path(&job.path)?;
+    let writer = open(&job.format, &part_path)?;
+
+    let (events, mut received) = mpsc::unbounded_channel();
+    let options = CursorOptions {
+        page_size: constants::export::PAGE_SIZE,
+        timeout: job.timeout,
+    };
+    let started = registry
+        .start(job.connection_id, pool, job.sql, job.params, options, move |event| {
+            let _ = events.send(event);
+        })
+        .await;
+    let handle = match started {
+        Ok(handle) => handle,
+        Err(e) => {
+            remove_part(&part_path);
+            return Err(e);
+        }
+    };
+
+    let registry = registry.clone();
+    let path = job.path;
+    let connection_id = job.connection_id;
+    tokio::spawn(async move {
+        let started = Instant::now();
+        let pages = Pages {
+            registry: &registry,
+            sessions: &sessions,
+            handle,
+            connection_id,
+            sink: &sink,
+        };
+        let outcome = match pages.write(writer, &mut received).await {
+            Ok(Some(rows_written)) => std::fs::rename(&part_path, &path)
+                .map(|()| Some(rows_written))
+                .map_err(|e| write_failed(&path, e)),
+            outcome => outcome,
+        };
+
+        match outcome {
+            Ok(Some(rows_written)) => {
+                info!("Exported {} rows to {}", rows_written, path.display());
+                sink(ExportEvent::Done {
+                    handle,
+                    rows_written,
+                    duration_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+                });
+            }
+            Ok(None) => {
+                remove_part(&part_path);
+                sink(ExportEvent::Cancelled { handle });
+            }
+            Err(error) => {
+                remove_part(&part_path);
+                // Stop the statement if writing failed while it was still running
+                let _ = registry.cancel(handle).await;
+                sink(ExportEvent::Error { handle, error });
+            }
+        }
+    });
+
+    Ok(handle)
+}
+
+/// An export's task-local state
+struct Pages<'a, F> {
+    registry: &'a QueryRegistry,
+    sessions: &'a ConnectionManager,
+    handle: QueryHandle,
+    connection_id: i64,
+    sink: &'a F,
+}
+
+impl<F: Fn(ExportEvent)> Pages<'_, F> {
+    /// Write every page the cursor delivers
+    ///
+    /// Returns the number of rows written, or `None` if the cursor was stopped first.
+    async fn write(
+        &self,
+        mut writer: Box<dyn RowWriter>,
+        received: &mut mpsc::UnboundedReceiver<QueryEvent>,
+    ) -> AppResult<Option<u64>> {
+        let mut rows_written = 0;
+        let mut begun = false;
+
+        while let Some(event) = received.recv().await {
+            match event {
+                QueryEvent::Page { columns, rows, .. } => {
+                    // The server reads the next page while this one is written. Past the
+                    // last page the query has finished, so the request is refused.
+                    let _ = self.registry.request_page(self.handle).await;
+                    self.sessions.touch(self.connection_id).await;
+
+                    let first = !begun;
+                    begun = true;
+                    let count = rows.len() as u64;
+                    writer = blocking(move || {
+                        if first {
+                            writer.begin(&columns.unwrap_or_default())?;
+                        }
+                        writer.write_rows(&rows)?;
+                        Ok(writer)
+                    })
+                    .await?;
+
+                    rows_written += count;
+                    (self.sink)(ExportEvent::Progress {
+                        handle: self.handle,
+                        rows_written,
+                    });
+                }
+                QueryEvent::Done { .. } => {
+                    blocking(move || {
+                        if !begun {
+                            writer.begin(&[])?;
+                        }
+                        writer.finish()
+                    })
+                    .await?;
+                    return Ok(Some(rows_written));
+                }
+                QueryEvent::Error { error, .. } => return Err(error),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Run file writes off the async runtime
+async fn blocking<T, W>(write: W) -> AppResult<T>
+where
+    T: Send + 'static,
+    W: FnOnce() -> AppResult<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(write).await.map_err(|e| {
+        AppError::new(
+            format!("Export stopped unexpectedly: {}", e),
+            ErrorCategory::Io(IoSubcategory::WriteFailed),
+            ErrorSeverity::Error,
+        )
+    })?
+}
+
+/// Open the writer for a format, creating its file
+fn open(format: &ExportFormat, path: &Path) -> AppResult<Box<dyn RowWriter>> {
+    Ok(match format {
+        ExportFormat::Csv(options) => Box::new(delimited::DelimitedWriter::create(path, options, ',')?),
+        ExportFormat::Tsv(options) => Box::new(delimited::DelimitedWriter::create(path, options, '\t')?),
+        ExportFormat::Json { pretty } => {
+            Box::new(json::JsonWriter::create(path, json::Layout::Array { pretty: *pretty })?)
+        }
+        ExportFormat::Ndjson => Box::new(json::JsonWriter::create(path, json::Layout::Lines)?),
+        ExportFormat::Sql(options) => Box::new(sql::SqlWriter::create(path, options)?),
+        ExportFormat::Xlsx { sheet_name } => Box::new(xlsx::XlsxWriter::create(path, sheet_name.as_deref())?),
+    })
+}
+
+/// The file an export is written to before it is complete
+fn part_path(path: &Path) -> AppResult<PathBuf> {
+    let file_name = path.file_name().ok_or_else(|| {
+        AppError::new(
+            format!("{} is not a file path", path.display()),
+            ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+            ErrorSeverity::Error,
+        )
+    })?;
+    let mut part = file_name.to_os_string();
+    part.push(".part");
+    Ok(path.with_file_name(part))
+}
+
+fn remove_part(part_path: &Path) {
+    if let Err(e) = std::fs::remove_file(part_path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove partial export {}: {}", part_path.display(), e);
+        }
+    }
+}
+
+/// Create an export file, failing with an `Io` error that names it
+fn create_file(path: &Path) -> AppResult<std::fs::File> {
+    std::fs::File::create(path).map_err(|e| {
+        AppError::new(
+            format!("Failed to create {}: {}", path.display(), e),
+            ErrorCategory::Io(IoSubcategory::CreateFailed),
+            ErrorSeverity::Error,
+        )
+    })
+}
+
+fn write_failed(path: &Path, error: impl Display) -> AppError {
+    AppError::new(
+        format!("Failed to write {}: {}", path.display(), error),
+        ErrorCategory::Io(IoSubcategory::WriteFailed),
+        ErrorSeverity::Error,
+    )
+}
+
+fn invalid_options(message: String) -> AppError {
+    AppError::new(
+        message,
+        ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+        ErrorSeverity::Error,
+    )
+}
+
+/// A table name quoted for an engine, qualified by its schema when there is one
+pub(crate) fn qualified_name(db_type: DbType, schema: Option<&str>, table: &str) -> String {
+    let driver = driver(db_type);
+    match schema.filter(|schema| !schema.is_empty()) {
+        Some(schema) => format!("{}.{}", driver.quote_identifier(schema), driver.quote_identifier(table)),
+        None => driver.quote_identifier(table),
+    }
+}
+
+/// A value as plain text, or `None` for NULL
+fn text(value: &DbValue) -> Option<Cow<'_, str>> {
+    Some(match value {
+        DbValue::Null => return None,
+        DbValue::Bool(value) => Cow::Borrowed(if *value { "true" } else { "false" }),
+        DbValue::Int(value) => Cow::Owned(value.to_string()),
+        DbValue::UInt(value) => Cow::Owned(value.to_string()),
+        DbValue::Float(value) => Cow::Owned(value.to_string()),
+        DbValue::Json(value) => Cow::Owned(value.to_string()),
+        DbValue::Decimal(value)
+        | DbValue::Text(value)
+        | DbValue::Bytes(value)
+        | DbValue::Uuid(value)
+        | DbValue::Date(value)
+        | DbValue::Time(value)
+        | DbValue::DateTime(value) => Cow::Borrowed(value.as_str()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_part_path_sits_next_to_the_file() {
+        assert_eq!(
+            part_path(Path::new("/tmp/out/orders.csv")).unwrap(),
+            PathBuf::from("/tmp/out/orders.csv.part")
+        );
+        assert!(part_path(Path::new("/")).is_err());
+    }
+
+    #[test]
+    fn test_table_source_selects_every_row() {
+        let source = ExportSource::Table {
+            schema: Some("sales".to_string()),
+            table: "order \"lines\"".to_string(),
+        };
+        assert_eq!(
+            source.statement(DbType::Postgres).0,
+            "SELECT * FROM \"sales\".\"order \"\"lines\"\"\""
+        );
+        assert_eq!(source.statement(DbType::MySql).0, "SELECT * FROM `sales`.`order \"lines\"`");
+    }
+}
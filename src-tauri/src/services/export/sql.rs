@@ -0,0 +1,227 @@
+//! SQL `INSERT` statements in the syntax of a chosen engine.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::constants;
+use crate::error::AppResult;
+use crate::services::database::driver::driver;
+use crate::services::database::query::{ColumnInfo, DbValue};
+use crate::services::storage::repositories::connections::DbType;
+
+use super::{create_file, invalid_options, qualified_name, write_failed, RowWriter, SqlOptions};
+
+pub(super) struct SqlWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+    dialect: DbType,
+    /// `INSERT INTO table (columns) VALUES`, written at the start of each statement
+    insert: String,
+    table: String,
+    rows_per_statement: usize,
+    /// Rows written to the statement being built
+    pending: usize,
+}
+
+impl SqlWriter {
+    /// Create the file
+    ///
+    /// # Errors
+    /// Returns an error if there is no table name, the dialect does not speak SQL, or the
+    /// file could not be created
+    pub(super) fn create(path: &Path, options: &SqlOptions) -> AppResult<Self> {
+        let table = options
+            .table
+            .as_deref()
+            .filter(|table| !table.trim().is_empty())
+            .ok_or_else(|| invalid_options("Choose the table the INSERT statements write to".to_string()))?;
+        if !driver(options.dialect).capabilities().sql {
+            return Err(invalid_options(format!("{} does not use SQL INSERT statements", options.dialect)));
+        }
+        let rows_per_statement = options
+            .rows_per_statement
+            .unwrap_or(constants::export::DEFAULT_ROWS_PER_INSERT)
+            .clamp(1, constants::export::MAX_ROWS_PER_INSERT);
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            out: BufWriter::new(create_file(path)?),
+            dialect: options.dialect,
+            insert: String::new(),
+            table: qualified_name(options.dialect, options.schema.as_deref(), table),
+            rows_per_statement,
+            pending: 0,
+        })
+    }
+
+    fn write_row(&mut self, row: &[DbValue]) -> std::io::Result<()> {
+        if self.pending == 0 {
+            write!(self.out, "{}\n  (", self.insert)?;
+        } else {
+            self.out.write_all(b",\n  (")?;
+        }
+        for (index, value) in row.iter().enumerate() {
+            if index > 0 {
+                self.out.write_all(b", ")?;
+            }
+            self.out.write_all(literal(self.dialect, value).as_bytes())?;
+        }
+        self.out.write_all(b")")?;
+
+        self.pending += 1;
+        if self.pending == self.rows_per_statement {
+            self.out.write_all(b";\n")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
+
+impl RowWriter for SqlWriter {
+    fn begin(&mut self, columns: &[ColumnInfo]) -> AppResult<()> {
+        let driver = driver(self.dialect);
+        let columns: Vec<String> = columns.iter().map(|column| driver.quote_identifier(&column.name)).collect();
+        self.insert = format!("INSERT INTO {} ({}) VALUES", self.table, columns.join(", "));
+        Ok(())
+    }
+
+    fn write_rows(&mut self, rows: &[Vec<DbValue>]) -> AppResult<()> {
+        for row in rows {
+            self.write_row(row).map_err(|e| write_failed(&self.path, e))?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> AppResult<()> {
+        let end: &[u8] = if self.pending > 0 { b";\n" } else { b"" };
+        self.out
+            .write_all(end)
+            .and_then(|()| self.out.flush())
+            .map_err(|e| write_failed(&self.path, e))
+    }
+}
+
+/// A value written as a literal in `dialect`
+fn literal(dialect: DbType, value: &DbValue) -> String {
+    match value {
+        DbValue::Null => "NULL".to_string(),
+        // SQL Server has no boolean literals; BIT columns take 1 and 0
+        DbValue::Bool(value) if dialect == DbType::MsSql => if *value { "1" } else { "0" }.to_string(),
+        DbValue::Bool(value) => if *value { "TRUE" } else { "FALSE" }.to_string(),
+        DbValue::Int(value) => value.to_string(),
+        DbValue::UInt(value) => value.to_string(),
+        // NaN and the infinities have no literal, but Postgres and DuckDB parse them from text
+        DbValue::Float(value) if value.is_finite() => value.to_string(),
+        DbValue::Float(value) => string_literal(dialect, &value.to_string()),
+        DbValue::Decimal(value) => value.clone(),
+        DbValue::Bytes(value) => match BASE64.decode(value) {
+            Ok(bytes) => bytes_literal(dialect, &bytes),
+            Err(_) => string_literal(dialect, value),
+        },
+        DbValue::Json(value) => string_literal(dialect, &value.to_string()),
+        DbValue::Text(value)
+        | DbValue::Uuid(value)
+        | DbValue::Date(value)
+        | DbValue::Time(value)
+        | DbValue::DateTime(value) => string_literal(dialect, value),
+    }
+}
+
+/// Quote text, escaping what the dialect treats specially inside quotes
+fn string_literal(dialect: DbType, text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 3);
+    // Without the N prefix SQL Server reads the text in the database's code page
+    if dialect == DbType::MsSql {
+        quoted.push('N');
+    }
+    quoted.push('\'');
+    for c in text.chars() {
+        match c {
+            '\'' => quoted.push_str("''"),
+            // MySQL and ClickHouse read backslashes in strings as escapes
+            '\\' if matches!(dialect, DbType::MySql | DbType::ClickHouse) => quoted.push_str("\\\\"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+fn bytes_literal(dialect: DbType, bytes: &[u8]) -> String {
+    let hex = hex::encode_upper(bytes);
+    match dialect {
+        DbType::Postgres => format!("'\\x{}'::bytea", hex),
+        DbType::DuckDb => format!("unhex('{}')", hex),
+        DbType::MsSql => format!("0x{}", hex),
+        DbType::ClickHouse => format!("unhex('{}')", hex),
+        _ => format!("X'{}'", hex),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literals_follow_the_dialect() {
+        let text = DbValue::Text("it's C:\\temp".to_string());
+        assert_eq!(literal(DbType::Postgres, &text), "'it''s C:\\temp'");
+        assert_eq!(literal(DbType::MySql, &text), "'it''s C:\\\\temp'");
+        assert_eq!(literal(DbType::MsSql, &text), "N'it''s C:\\temp'");
+
+        let bytes = DbValue::Bytes(BASE64.encode([0xde, 0xad]));
+        assert_eq!(literal(DbType::Postgres, &bytes), "'\\xDEAD'::bytea");
+        assert_eq!(literal(DbType::Sqlite, &bytes), "X'DEAD'");
+        assert_eq!(literal(DbType::MsSql, &bytes), "0xDEAD");
+
+        assert_eq!(literal(DbType::MsSql, &DbValue::Bool(true)), "1");
+        assert_eq!(literal(DbType::Sqlite, &DbValue::Bool(false)), "FALSE");
+        assert_eq!(literal(DbType::Postgres, &DbValue::Float(f64::INFINITY)), "'inf'");
+        assert_eq!(literal(DbType::Postgres, &DbValue::Decimal("10.50".to_string())), "10.50");
+    }
+
+    #[test]
+    fn test_rows_are_batched_into_statements() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.sql");
+        let options = SqlOptions {
+            table: Some("orders".to_string()),
+            schema: None,
+            dialect: DbType::MySql,
+            rows_per_statement: Some(2),
+        };
+        let columns: Vec<ColumnInfo> = ["id", "note"]
+            .iter()
+            .map(|name| ColumnInfo {
+                name: name.to_string(),
+                type_name: String::new(),
+                nullable: None,
+            })
+            .collect();
+
+        let mut writer = Box::new(SqlWriter::create(&path, &options).unwrap());
+        writer.begin(&columns).unwrap();
+        writer
+            .write_rows(&[
+                vec![DbValue::Int(1), DbValue::Text("a".to_string())],
+                vec![DbValue::Int(2), DbValue::Null],
+                vec![DbValue::Int(3), DbValue::Text("c".to_string())],
+            ])
+            .unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "INSERT INTO `orders` (`id`, `note`) VALUES\n  (1, 'a'),\n  (2, NULL);\n\
+             INSERT INTO `orders` (`id`, `note`) VALUES\n  (3, 'c');\n"
+        );
+
+        let options = SqlOptions {
+            dialect: DbType::Redis,
+            ..options
+        };
+        assert!(SqlWriter::create(&path, &options).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Excel workbooks.
+//!
+//! Worksheets are written in constant memory mode, which flushes each row to a
+//! temporary file once the next row is started, so a workbook of a million rows
+//! is not held in memory before it is saved.
+
+use rust_xlsxwriter::{ColNum, Format, RowNum, Workbook, XlsxError};
+use std::path::{Path, PathBuf};
+
+use crate::constants;
+use crate::error::AppResult;
+use crate::services::database::query::{ColumnInfo, DbValue};
+
+use super::{create_file, invalid_options, text, write_failed, RowWriter};
+
+/// Largest integer a worksheet cell holds exactly, as Excel stores numbers as doubles
+const MAX_EXACT_INTEGER: u64 = 1 << 53;
+/// Longest worksheet name Excel accepts
+const MAX_SHEET_NAME_CHARS: usize = 31;
+
+pub(super) struct XlsxWriter {
+    path: PathBuf,
+    workbook: Workbook,
+    sheet_name: String,
+    /// Worksheets started so far; the last is being written
+    sheets: usize,
+    /// The next row of the current worksheet
+    row: RowNum,
+    columns: Vec<String>,
+    header: Format,
+}
+
+impl XlsxWriter {
+    /// Prepare a workbook whose first worksheet is named `sheet_name`, or `Sheet1`
+    ///
+    /// The file is created empty straight away, but only written by `finish`.
+    ///
+    /// # Errors
+    /// Returns an error if the worksheet name is not one Excel accepts or the file
+    /// could not be created
+    pub(super) fn create(path: &Path, sheet_name: Option<&str>) -> AppResult<Self> {
+        let sheet_name = sheet_name.filter(|name| !name.trim().is_empty()).unwrap_or("Sheet1");
+        // Check the name up front rather than once rows are already arriving
+        Workbook::new()
+            .add_worksheet()
+            .set_name(sheet_name)
+            .map_err(|e| invalid_options(format!("{:?} cannot name a worksheet: {}", sheet_name, e)))?;
+        create_file(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            workbook: Workbook::new(),
+            sheet_name: sheet_name.to_string(),
+            sheets: 0,
+            row: 0,
+            columns: Vec::new(),
+            header: Format::new().set_bold(),
+        })
+    }
+
+    /// Start the next worksheet and write the header row to it
+    ///
+    /// Worksheets after the first are named after it with their number appended.
+    fn add_sheet(&mut self) -> Result<(), XlsxError> {
+        self.sheets += 1;
+        let name = if self.sheets == 1 {
+            self.sheet_name.clone()
+        } else {
+            let suffix = format!(" ({})", self.sheets);
+            let base: String = self
+                .sheet_name
+                .chars()
+                .take(MAX_SHEET_NAME_CHARS - suffix.chars().count())
+                .collect();
+            base + &suffix
+        };
+
+        let sheet = self.workbook.add_worksheet_with_constant_memory();
+        sheet.set_name(name)?;
+        for (col, name) in self.columns.iter().enumerate() {
+            sheet.write_string_with_format(0, col as ColNum, name, &self.header)?;
+        }
+        self.row = 1;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &[DbValue]) -> Result<(), XlsxError> {
+        if self.row == constants::export::XLSX_MAX_ROWS {
+            self.add_sheet()?;
+        }
+        let sheet = self.workbook.worksheet_from_index(self.sheets - 1)?;
+        for (col, value) in row.iter().enumerate() {
+            let col = col as ColNum;
+            match (value, number(value)) {
+                (DbValue::Null, _) => {}
+                (DbValue::Bool(value), _) => {
+                    sheet.write_boolean(self.row, col, *value)?;
+                }
+                (_, Some(number)) => {
+                    sheet.write_number(self.row, col, number)?;
+                }
+                (value, None) => {
+                    let text = text(value).unwrap_or_default();
+                    let text: String = text.chars().take(constants::export::XLSX_MAX_STRING_CHARS).collect();
+                    sheet.write_string(self.row, col, text)?;
+                }
+            }
+        }
+        self.row += 1;
+        Ok(())
+    }
+}
+
+impl RowWriter for XlsxWriter {
+    fn begin(&mut self, columns: &[ColumnInfo]) -> AppResult<()> {
+        if columns.len() > constants::export::XLSX_MAX_COLUMNS {
+            return Err(invalid_options(format!(
+                "Excel worksheets hold at most {} columns, but the result has {}",
+                constants::export::XLSX_MAX_COLUMNS,
+                columns.len()
+            )));
+        }
+        self.columns = columns.iter().map(|column| column.name.clone()).collect();
+        self.add_sheet().map_err(|e| write_failed(&self.path, e))
+    }
+
+    fn write_rows(&mut self, rows: &[Vec<DbValue>]) -> AppResult<()> {
+        for row in rows {
+            self.write_row(row).map_err(|e| write_failed(&self.path, e))?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> AppResult<()> {
+        self.workbook.save(&self.path).map_err(|e| write_failed(&self.path, e))
+    }
+}
+
+/// A value as a worksheet number, if a double holds it exactly
+fn number(value: &DbValue) -> Option<f64> {
+    match value {
+        DbValue::Int(value) if value.unsigned_abs() <= MAX_EXACT_INTEGER => Some(*value as f64),
+        DbValue::UInt(value) if *value <= MAX_EXACT_INTEGER => Some(*value as f64),
+        DbValue::Float(value) if value.is_finite() => Some(*value),
+        DbValue::Decimal(value) => exact_decimal(value),
+        _ => None,
+    }
+}
+
+/// A decimal as a number, if a double holds all of its significant digits
+fn exact_decimal(value: &str) -> Option<f64> {
+    let digits = value.trim_start_matches(['-', '+', '0', '.']).chars().filter(char::is_ascii_digit).count();
+    if digits > 15 {
+        return None;
+    }
+    value.parse::<f64>().ok().filter(|value| value.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decimals_become_numbers_only_when_exact() {
+        assert_eq!(exact_decimal("10.50"), Some(10.5));
+        assert_eq!(exact_decimal("-0.000123"), Some(-0.000123));
+        assert_eq!(exact_decimal("12345678901234567.89"), None);
+        assert_eq!(exact_decimal("NaN"), None);
+    }
+
+    #[test]
+    fn test_workbook_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.xlsx");
+        assert!(XlsxWriter::create(&path, Some("bad/name")).is_err());
+
+        let mut writer = Box::new(XlsxWriter::create(&path, Some("Orders")).unwrap());
+        let columns = vec![ColumnInfo {
+            name: "id".to_string(),
+            type_name: String::new(),
+            nullable: None,
+        }];
+        writer.begin(&columns).unwrap();
+        writer.write_rows(&[vec![DbValue::Int(1)], vec![DbValue::Text("x".repeat(40_000))]]).unwrap();
+        writer.finish().unwrap();
+
+        // An XLSX file is a zip archive
+        assert!(std::fs::read(&path).unwrap().starts_with(b"PK"));
+    }
+}
@@ -0,0 +1,259 @@
+//! Column types inferred from source values, and conversion to them.
+//!
+//! CSV fields are text, so their type is guessed from what the text looks
+//! like; NDJSON values bring their own JSON type, and only strings are looked
+//! at more closely. A column takes the narrowest type every sampled value fits.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::services::database::query::DbValue;
+use crate::services::storage::repositories::connections::DbType;
+
+/// Layouts accepted for dates and times without an offset
+const DATETIME_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M"];
+
+/// The type a source column's values are read as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnType {
+    Boolean,
+    Integer,
+    /// Numbers written with a decimal point, kept exact
+    Decimal,
+    Float,
+    Date,
+    DateTime,
+    Json,
+    Text,
+}
+
+impl ColumnType {
+    /// The column type a new table uses for this type on `db_type`
+    #[must_use]
+    pub fn sql_type(self, db_type: DbType) -> &'static str {
+        match (self, db_type) {
+            (Self::Boolean, DbType::MsSql) => "BIT",
+            (Self::Boolean, _) => "BOOLEAN",
+            (Self::Integer, DbType::Sqlite) => "INTEGER",
+            (Self::Integer, _) => "BIGINT",
+            (Self::Decimal, DbType::MySql) => "DECIMAL(38, 10)",
+            (Self::Decimal, _) => "NUMERIC",
+            (Self::Float, DbType::Postgres) => "DOUBLE PRECISION",
+            (Self::Float, DbType::Sqlite) => "REAL",
+            (Self::Float, DbType::MsSql) => "FLOAT",
+            (Self::Float, _) => "DOUBLE",
+            (Self::Date, DbType::Sqlite) => "TEXT",
+            (Self::Date, _) => "DATE",
+            (Self::DateTime, DbType::Postgres) => "TIMESTAMP",
+            (Self::DateTime, DbType::MySql) => "DATETIME(6)",
+            (Self::DateTime, DbType::MsSql) => "DATETIME2",
+            (Self::DateTime, _) => "TEXT",
+            (Self::Json, DbType::Postgres) => "JSONB",
+            (Self::Json, DbType::MySql) => "JSON",
+            (Self::Json | Self::Text, DbType::MsSql) => "NVARCHAR(MAX)",
+            (Self::Json | Self::Text, _) => "TEXT",
+        }
+    }
+}
+
+/// Infers a column's type from the values seen so far
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct TypeGuess {
+    /// `None` until a value other than NULL is seen
+    column_type: Option<ColumnType>,
+    nullable: bool,
+}
+
+impl TypeGuess {
+    pub(super) fn add(&mut self, value: &JsonValue) {
+        let Some(value_type) = value_type(value) else {
+            self.nullable = true;
+            return;
+        };
+        self.column_type = Some(match self.column_type {
+            None => value_type,
+            Some(column_type) => widen(column_type, value_type),
+        });
+    }
+
+    /// The inferred type, text if every value was NULL, and whether any value was
+    pub(super) fn finish(self) -> (ColumnType, bool) {
+        (self.column_type.unwrap_or(ColumnType::Text), self.nullable)
+    }
+}
+
+/// The narrowest type a single value fits, or `None` for NULL
+fn value_type(value: &JsonValue) -> Option<ColumnType> {
+    Some(match value {
+        JsonValue::Null => return None,
+        JsonValue::Bool(_) => ColumnType::Boolean,
+        JsonValue::Number(number) if number.is_i64() || number.is_u64() => ColumnType::Integer,
+        JsonValue::Number(_) => ColumnType::Float,
+        JsonValue::Array(_) | JsonValue::Object(_) => ColumnType::Json,
+        JsonValue::String(text) => text_type(text),
+    })
+}
+
+/// The narrowest type a piece of text reads as
+fn text_type(text: &str) -> ColumnType {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("true") || text.eq_ignore_ascii_case("false") {
+        ColumnType::Boolean
+    } else if text.parse::<i64>().is_ok() {
+        ColumnType::Integer
+    } else if is_decimal(text) {
+        ColumnType::Decimal
+    } else if text.parse::<f64>().is_ok_and(f64::is_finite) && text.chars().any(|c| c.is_ascii_digit()) {
+        ColumnType::Float
+    } else if NaiveDate::parse_from_str(text, "%Y-%m-%d").is_ok() {
+        ColumnType::Date
+    } else if parse_datetime(text).is_some() {
+        ColumnType::DateTime
+    } else {
+        ColumnType::Text
+    }
+}
+
+/// The narrowest type that holds values of both types
+fn widen(a: ColumnType, b: ColumnType) -> ColumnType {
+    use ColumnType::{Date, DateTime, Decimal, Float, Integer, Text};
+    match (a, b) {
+        (a, b) if a == b => a,
+        (Integer, Decimal) | (Decimal, Integer) => Decimal,
+        (Integer | Decimal, Float) | (Float, Integer | Decimal) => Float,
+        (Date, DateTime) | (DateTime, Date) => DateTime,
+        _ => Text,
+    }
+}
+
+/// Whether text is a plain decimal number: an optional sign, digits and a decimal point
+fn is_decimal(text: &str) -> bool {
+    let digits = text.strip_prefix(['-', '+']).unwrap_or(text);
+    let Some((whole, fraction)) = digits.split_once('.') else {
+        return false;
+    };
+    !(whole.is_empty() && fraction.is_empty())
+        && whole.chars().all(|c| c.is_ascii_digit())
+        && fraction.chars().all(|c| c.is_ascii_digit())
+}
+
+/// A date and time in ISO 8601 form without an offset
+///
+/// The created columns hold no time zone, so a value with an offset is converted to UTC
+/// rather than having its offset dropped or rejected.
+fn parse_datetime(text: &str) -> Option<String> {
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|datetime| datetime.naive_utc())
+        .or_else(|| DATETIME_FORMATS.iter().find_map(|format| NaiveDateTime::parse_from_str(text, format).ok()))
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)))
+        .map(|datetime| datetime.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+}
+
+/// Read a source value as `column_type`
+///
+/// # Errors
+/// Returns why the value cannot be read as the type
+pub(super) fn convert(value: &JsonValue, column_type: ColumnType) -> Result<DbValue, String> {
+    let text = match value {
+        JsonValue::Null => return Ok(DbValue::Null),
+        JsonValue::String(text) => Some(text.trim()),
+        _ => None,
+    };
+    let invalid = || format!("{} is not a valid {}", value, type_label(column_type));
+
+    match (column_type, value, text) {
+        (ColumnType::Boolean, JsonValue::Bool(value), _) => Ok(DbValue::Bool(*value)),
+        (ColumnType::Boolean, _, Some(text)) => match text.to_ascii_lowercase().as_str() {
+            "true" | "t" | "yes" | "y" | "1" => Ok(DbValue::Bool(true)),
+            "false" | "f" | "no" | "n" | "0" => Ok(DbValue::Bool(false)),
+            _ => Err(invalid()),
+        },
+        (ColumnType::Integer, JsonValue::Number(number), _) => {
+            number.as_i64().map(DbValue::Int).or_else(|| number.as_u64().map(DbValue::UInt)).ok_or_else(invalid)
+        }
+        (ColumnType::Integer, _, Some(text)) => text.parse().map(DbValue::Int).map_err(|_| invalid()),
+        (ColumnType::Decimal, JsonValue::Number(number), _) => Ok(DbValue::Decimal(number.to_string())),
+        (ColumnType::Decimal, _, Some(text)) if is_decimal(text) || text.parse::<i64>().is_ok() => {
+            Ok(DbValue::Decimal(text.to_string()))
+        }
+        (ColumnType::Float, JsonValue::Number(number), _) => number.as_f64().map(DbValue::Float).ok_or_else(invalid),
+        (ColumnType::Float, _, Some(text)) => text.parse().map(DbValue::Float).map_err(|_| invalid()),
+        (ColumnType::Date, _, Some(text)) => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .map(|date| DbValue::Date(date.to_string()))
+            .map_err(|_| invalid()),
+        (ColumnType::DateTime, _, Some(text)) => parse_datetime(text).map(DbValue::DateTime).ok_or_else(invalid),
+        (ColumnType::Json, JsonValue::String(text), _) => {
+            serde_json::from_str(text).map(DbValue::Json).map_err(|_| invalid())
+        }
+        (ColumnType::Json, value, _) => Ok(DbValue::Json(value.clone())),
+        (ColumnType::Text, JsonValue::String(text), _) => Ok(DbValue::Text(text.clone())),
+        (ColumnType::Text, value, _) => Ok(DbValue::Text(value.to_string())),
+        _ => Err(invalid()),
+    }
+}
+
+fn type_label(column_type: ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Boolean => "boolean",
+        ColumnType::Integer => "integer",
+        ColumnType::Decimal => "decimal number",
+        ColumnType::Float => "number",
+        ColumnType::Date => "date",
+        ColumnType::DateTime => "date and time",
+        ColumnType::Json => "JSON value",
+        ColumnType::Text => "text",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn infer(values: &[JsonValue]) -> (ColumnType, bool) {
+        let mut guess = TypeGuess::default();
+        for value in values {
+            guess.add(value);
+        }
+        guess.finish()
+    }
+
+    #[test]
+    fn test_columns_take_the_narrowest_type_that_fits() {
+        assert_eq!(infer(&[json!("1"), json!("-20")]), (ColumnType::Integer, false));
+        assert_eq!(infer(&[json!("1"), json!("10.50"), JsonValue::Null]), (ColumnType::Decimal, true));
+        assert_eq!(infer(&[json!("1"), json!("1e3")]), (ColumnType::Float, false));
+        assert_eq!(infer(&[json!("2024-07-01"), json!("2024-07-01 10:30:00")]), (ColumnType::DateTime, false));
+        assert_eq!(infer(&[json!("TRUE"), json!(false)]), (ColumnType::Boolean, false));
+        assert_eq!(infer(&[json!("1"), json!("n/a")]), (ColumnType::Text, false));
+        assert_eq!(infer(&[json!({"a": 1}), json!([1])]), (ColumnType::Json, false));
+        assert_eq!(infer(&[JsonValue::Null]), (ColumnType::Text, true));
+        assert_eq!(infer(&[json!("inf")]), (ColumnType::Text, false));
+    }
+
+    #[test]
+    fn test_values_are_converted_or_rejected() {
+        assert_eq!(convert(&json!(" 42 "), ColumnType::Integer), Ok(DbValue::Int(42)));
+        assert_eq!(convert(&json!("10.50"), ColumnType::Decimal), Ok(DbValue::Decimal("10.50".to_string())));
+        assert_eq!(convert(&json!("yes"), ColumnType::Boolean), Ok(DbValue::Bool(true)));
+        assert_eq!(
+            convert(&json!("2024-07-01 10:30"), ColumnType::DateTime),
+            Ok(DbValue::DateTime("2024-07-01T10:30:00".to_string()))
+        );
+        assert_eq!(
+            convert(&json!("2024-07-01T23:30:00.5-02:00"), ColumnType::DateTime),
+            Ok(DbValue::DateTime("2024-07-02T01:30:00.500".to_string()))
+        );
+        assert_eq!(convert(&json!("{\"a\":1}"), ColumnType::Json), Ok(DbValue::Json(json!({"a": 1}))));
+        assert_eq!(convert(&json!(7), ColumnType::Text), Ok(DbValue::Text("7".to_string())));
+        assert_eq!(convert(&JsonValue::Null, ColumnType::Integer), Ok(DbValue::Null));
+        assert_eq!(
+            convert(&json!("abc"), ColumnType::Integer),
+            Err("\"abc\" is not a valid integer".to_string())
+        );
+        assert!(convert(&json!("2024-02-30"), ColumnType::Date).is_err());
+    }
+}
@@ -0,0 +1,566 @@
+//! Writing rows to the target with each engine's bulk path.
+//!
+//! Postgres rows are streamed with `COPY ... FROM STDIN`, MySQL and SQLite
+//! rows are written with multi-row `INSERT`s in one transaction per batch, and
+//! MongoDB documents with an unordered `insertMany`. Each engine's path is a
+//! [`BulkLoader`], looked up from the target's [`DbType`]. When the server
+//! rejects a SQL batch, its rows are written again one at a time, so only the
+//! rows at fault are rejected and the rest of the batch is still loaded.
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use mongodb::bson::{self, Bson, Decimal128, Document};
+use mongodb::error::ErrorKind;
+use mongodb::options::InsertManyOptions;
+use mongodb::Collection;
+use sqlx::database::HasArguments;
+use sqlx::pool::PoolConnection;
+use sqlx::{Connection as _, Database, Executor, IntoArguments, Postgres};
+use std::fmt::Write as _;
+
+use crate::constants;
+use crate::error::{AppError, AppResult};
+use crate::services::database::cancel::{CancelGuard, CancelSlot, CancelTarget};
+use crate::services::database::documents;
+use crate::services::database::pool::DatabasePool;
+use crate::services::database::query::{bind_all, not_sql, query_error, BindValues, DbValue};
+use crate::services::storage::repositories::connections::DbType;
+
+use super::{qualified_name, ImportTarget};
+
+/// A row of a batch the server refused, by its index in the batch, and why
+pub(super) type Rejection = (usize, String);
+
+/// Writes batches of rows to the import target
+pub(super) struct Loader {
+    writer: Box<dyn BatchWriter>,
+    _guard: CancelGuard,
+}
+
+impl Loader {
+    /// Check out the connection the import writes on and record it in `slot`
+    ///
+    /// `columns` are the target columns, or document fields, in the order rows hold them.
+    /// MongoDB writes are tagged with `comment` so they can be found and stopped.
+    ///
+    /// # Errors
+    /// Returns an error if no connection could be checked out, or the engine has no bulk path
+    pub(super) async fn open(
+        pool: &DatabasePool,
+        target: &ImportTarget,
+        columns: &[String],
+        slot: &CancelSlot,
+        comment: String,
+    ) -> AppResult<Self> {
+        let loader = bulk_loader(pool.db_type()).ok_or_else(|| super::unsupported(pool.db_type()))?;
+        let (writer, cancel) = loader.open(pool, target, columns, comment).await?;
+        Ok(Self {
+            writer,
+            _guard: slot.arm(cancel),
+        })
+    }
+
+    /// Write a batch, returning the rows the server rejected
+    ///
+    /// # Errors
+    /// Returns an error if the batch could not be written for a reason other than its
+    /// rows, such as a lost connection
+    pub(super) async fn write(&mut self, rows: &[Vec<DbValue>]) -> AppResult<Vec<Rejection>> {
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.writer.write(rows).await
+    }
+}
+
+/// One engine's bulk path
+#[async_trait]
+trait BulkLoader: Send + Sync {
+    /// Check out a connection ready to write `columns` of `target`, and the target that cancels it
+    async fn open(
+        &self,
+        pool: &DatabasePool,
+        target: &ImportTarget,
+        columns: &[String],
+        comment: String,
+    ) -> AppResult<(Box<dyn BatchWriter>, CancelTarget)>;
+}
+
+/// The bulk path for an engine, or `None` if rows cannot be imported into it
+fn bulk_loader(db_type: DbType) -> Option<&'static dyn BulkLoader> {
+    match db_type {
+        DbType::Postgres => Some(&PostgresLoader),
+        DbType::MySql => Some(&MySqlLoader),
+        DbType::Sqlite => Some(&SqliteLoader),
+        DbType::MongoDb => Some(&MongoDbLoader),
+        DbType::MsSql | DbType::Redis | DbType::ClickHouse | DbType::DuckDb => None,
+    }
+}
+
+/// Writes batches on the connection a [`BulkLoader`] checked out
+#[async_trait]
+trait BatchWriter: Send {
+    /// Write a batch, returning the rows the server rejected
+    async fn write(&mut self, rows: &[Vec<DbValue>]) -> AppResult<Vec<Rejection>>;
+}
+
+/// Writes rows to a SQL table in one go, refusing all of them if any is refused
+#[async_trait]
+trait SqlBatch: Send {
+    async fn write_rows(&mut self, rows: &[Vec<DbValue>]) -> Result<(), WriteError>;
+}
+
+#[async_trait]
+impl<B: SqlBatch> BatchWriter for B {
+    async fn write(&mut self, rows: &[Vec<DbValue>]) -> AppResult<Vec<Rejection>> {
+        match self.write_rows(rows).await {
+            Ok(()) => return Ok(Vec::new()),
+            Err(WriteError::Row(_)) => {}
+            Err(WriteError::Other(e)) => return Err(query_error(e)),
+        }
+        // Write the rows one at a time to find those at fault
+        let mut rejected = Vec::new();
+        for (index, row) in rows.iter().enumerate() {
+            match self.write_rows(std::slice::from_ref(row)).await {
+                Ok(()) => {}
+                Err(WriteError::Row(message)) => rejected.push((index, message)),
+                Err(WriteError::Other(e)) => return Err(query_error(e)),
+            }
+        }
+        Ok(rejected)
+    }
+}
+
+/// The target table and its quoted column list, as statements name them
+fn table_columns(pool: &DatabasePool, target: &ImportTarget, columns: &[String]) -> AppResult<(String, String)> {
+    let (schema, table) = target.table().ok_or_else(not_sql)?;
+    let driver = pool.driver();
+    let quoted: Vec<String> = columns.iter().map(|column| driver.quote_identifier(column)).collect();
+    Ok((qualified_name(pool.db_type(), schema, table), quoted.join(", ")))
+}
+
+struct PostgresLoader;
+
+#[async_trait]
+impl BulkLoader for PostgresLoader {
+    async fn open(
+        &self,
+        pool: &DatabasePool,
+        target: &ImportTarget,
+        columns: &[String],
+        _comment: String,
+    ) -> AppResult<(Box<dyn BatchWriter>, CancelTarget)> {
+        let (table, quoted) = table_columns(pool, target, columns)?;
+        let DatabasePool::Postgres(pool) = pool else {
+            return Err(super::unsupported(pool.db_type()));
+        };
+        let mut conn = pool.acquire().await.map_err(query_error)?;
+        let cancel = CancelTarget::postgres(pool, &mut conn).await?;
+        let copy = format!("COPY {} ({}) FROM STDIN", table, quoted);
+        Ok((Box::new(CopyWriter { conn, copy }), cancel))
+    }
+}
+
+/// Streams rows to Postgres with `COPY`
+struct CopyWriter {
+    conn: PoolConnection<Postgres>,
+    copy: String,
+}
+
+#[async_trait]
+impl SqlBatch for CopyWriter {
+    async fn write_rows(&mut self, rows: &[Vec<DbValue>]) -> Result<(), WriteError> {
+        let mut copy = self.conn.copy_in_raw(&self.copy).await?;
+        copy.send(copy_text(rows).into_bytes()).await?;
+        copy.finish().await?;
+        Ok(())
+    }
+}
+
+struct MySqlLoader;
+
+#[async_trait]
+impl BulkLoader for MySqlLoader {
+    async fn open(
+        &self,
+        pool: &DatabasePool,
+        target: &ImportTarget,
+        columns: &[String],
+        _comment: String,
+    ) -> AppResult<(Box<dyn BatchWriter>, CancelTarget)> {
+        let (table, quoted) = table_columns(pool, target, columns)?;
+        let DatabasePool::MySql(pool) = pool else {
+            return Err(super::unsupported(pool.db_type()));
+        };
+        let mut conn = pool.acquire().await.map_err(query_error)?;
+        let cancel = CancelTarget::mysql(pool, &mut conn).await?;
+        Ok((Box::new(InsertWriter::new(conn, &table, &quoted, columns.len())), cancel))
+    }
+}
+
+struct SqliteLoader;
+
+#[async_trait]
+impl BulkLoader for SqliteLoader {
+    async fn open(
+        &self,
+        pool: &DatabasePool,
+        target: &ImportTarget,
+        columns: &[String],
+        _comment: String,
+    ) -> AppResult<(Box<dyn BatchWriter>, CancelTarget)> {
+        let (table, quoted) = table_columns(pool, target, columns)?;
+        let DatabasePool::Sqlite(pool) = pool else {
+            return Err(super::unsupported(pool.db_type()));
+        };
+        let mut conn = pool.acquire().await.map_err(query_error)?;
+        let cancel = CancelTarget::sqlite(&mut conn).await?;
+        Ok((Box::new(InsertWriter::new(conn, &table, &quoted, columns.len())), cancel))
+    }
+}
+
+/// Writes rows with multi-row `INSERT`s, one transaction per batch
+struct InsertWriter<DB: Database> {
+    conn: PoolConnection<DB>,
+    /// `INSERT INTO table (columns) VALUES`
+    insert: String,
+    columns: usize,
+}
+
+impl<DB: Database> InsertWriter<DB> {
+    fn new(conn: PoolConnection<DB>, table: &str, quoted: &str, columns: usize) -> Self {
+        Self {
+            conn,
+            insert: format!("INSERT INTO {} ({}) VALUES", table, quoted),
+            columns,
+        }
+    }
+}
+
+#[async_trait]
+impl<DB> SqlBatch for InsertWriter<DB>
+where
+    DB: BindValues,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+{
+    async fn write_rows(&mut self, rows: &[Vec<DbValue>]) -> Result<(), WriteError> {
+        let mut tx = self.conn.begin().await?;
+        for (sql, params) in inserts(&self.insert, self.columns, rows) {
+            bind_all(sqlx::query(&sql), &params).map_err(bind_error)?.execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+struct MongoDbLoader;
+
+#[async_trait]
+impl BulkLoader for MongoDbLoader {
+    async fn open(
+        &self,
+        pool: &DatabasePool,
+        target: &ImportTarget,
+        columns: &[String],
+        comment: String,
+    ) -> AppResult<(Box<dyn BatchWriter>, CancelTarget)> {
+        let ImportTarget::Collection { database, collection } = target else {
+            return Err(super::unsupported(pool.db_type()));
+        };
+        let client = documents::client(pool)?;
+        let mut options = InsertManyOptions::default();
+        options.ordered = Some(false);
+        options.comment = Some(Bson::String(comment.clone()));
+        let writer = InsertManyWriter {
+            collection: client.database(database).collection(collection),
+            fields: columns.to_vec(),
+            options,
+        };
+        let cancel = CancelTarget::MongoDb {
+            client: client.clone(),
+            comment,
+        };
+        Ok((Box::new(writer), cancel))
+    }
+}
+
+/// Writes documents with an unordered `insertMany`, which reports the documents it refused
+struct InsertManyWriter {
+    collection: Collection<Document>,
+    fields: Vec<String>,
+    options: InsertManyOptions,
+}
+
+#[async_trait]
+impl BatchWriter for InsertManyWriter {
+    async fn write(&mut self, rows: &[Vec<DbValue>]) -> AppResult<Vec<Rejection>> {
+        let documents = rows.iter().map(|row| document(&self.fields, row));
+        match self.collection.insert_many(documents, self.options.clone()).await {
+            Ok(_) => Ok(Vec::new()),
+            Err(e) => match *e.kind {
+                ErrorKind::BulkWrite(failure) if failure.write_concern_error.is_none() => Ok(failure
+                    .write_errors
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|error| (error.index, error.message))
+                    .collect()),
+                _ => Err(e.into()),
+            },
+        }
+    }
+}
+
+/// Why a write failed: a row the server or the driver refused, or anything else
+enum WriteError {
+    Row(String),
+    Other(sqlx::Error),
+}
+
+impl From<sqlx::Error> for WriteError {
+    fn from(error: sqlx::Error) -> Self {
+        match error {
+            sqlx::Error::Database(e) => Self::Row(e.message().to_string()),
+            e => Self::Other(e),
+        }
+    }
+}
+
+fn bind_error(error: AppError) -> WriteError {
+    WriteError::Row(error.message)
+}
+
+/// Multi-row `INSERT` statements for `rows`, with their parameters
+///
+/// Each statement holds as many rows as fit under the parameter limit.
+fn inserts<'a>(
+    insert: &'a str,
+    columns: usize,
+    rows: &'a [Vec<DbValue>],
+) -> impl Iterator<Item = (String, Vec<DbValue>)> + 'a {
+    let rows_per_insert = (constants::import::MAX_PARAMS_PER_INSERT / columns.max(1))
+        .clamp(1, constants::import::MAX_ROWS_PER_INSERT);
+    let placeholders = format!("({})", vec!["?"; columns].join(", "));
+    rows.chunks(rows_per_insert).map(move |chunk| {
+        let values = vec![placeholders.as_str(); chunk.len()].join(", ");
+        let params = chunk.iter().flatten().cloned().collect();
+        (format!("{} {}", insert, values), params)
+    })
+}
+
+/// Rows in the text format of `COPY`: tab-separated, one per line, `\N` for NULL
+fn copy_text(rows: &[Vec<DbValue>]) -> String {
+    let mut text = String::new();
+    for row in rows {
+        for (index, value) in row.iter().enumerate() {
+            if index > 0 {
+                text.push('\t');
+            }
+            match value {
+                DbValue::Null => text.push_str("\\N"),
+                DbValue::Bool(value) => text.push(if *value { 't' } else { 'f' }),
+                DbValue::Int(value) => {
+                    let _ = write!(text, "{}", value);
+                }
+                DbValue::UInt(value) => {
+                    let _ = write!(text, "{}", value);
+                }
+                DbValue::Float(value) => {
+                    let _ = write!(text, "{}", value);
+                }
+                // bytea's hex form, with its backslash escaped for COPY
+                DbValue::Bytes(value) => match BASE64.decode(value) {
+                    Ok(bytes) => {
+                        text.push_str("\\\\x");
+                        text.push_str(&hex::encode(bytes));
+                    }
+                    Err(_) => copy_escape(&mut text, value),
+                },
+                DbValue::Json(value) => copy_escape(&mut text, &value.to_string()),
+                DbValue::Decimal(value)
+                | DbValue::Text(value)
+                | DbValue::Uuid(value)
+                | DbValue::Date(value)
+                | DbValue::Time(value)
+                | DbValue::DateTime(value) => copy_escape(&mut text, value),
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Append a value, escaping what `COPY` reads as separators or escapes
+fn copy_escape(text: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '\\' => text.push_str("\\\\"),
+            '\t' => text.push_str("\\t"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            c => text.push(c),
+        }
+    }
+}
+
+/// A row as a document, with a field for each value
+fn document(fields: &[String], row: &[DbValue]) -> Document {
+    fields.iter().cloned().zip(row.iter().map(bson_value)).collect()
+}
+
+/// A value as BSON; dates become BSON dates, read as UTC when they have no offset
+fn bson_value(value: &DbValue) -> Bson {
+    match value {
+        DbValue::Null => Bson::Null,
+        DbValue::Bool(value) => Bson::Boolean(*value),
+        DbValue::Int(value) => Bson::Int64(*value),
+        DbValue::UInt(value) => i64::try_from(*value).map_or_else(|_| Bson::String(value.to_string()), Bson::Int64),
+        DbValue::Float(value) => Bson::Double(*value),
+        DbValue::Decimal(value) => value
+            .parse::<Decimal128>()
+            .map_or_else(|_| Bson::String(value.clone()), Bson::Decimal128),
+        DbValue::Date(value) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|datetime| datetime.and_utc().timestamp_millis())
+            .map_or_else(|| Bson::String(value.clone()), bson_datetime),
+        DbValue::DateTime(value) => DateTime::parse_from_rfc3339(value)
+            .map(|datetime| datetime.timestamp_millis())
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+                    .map(|datetime| datetime.and_utc().timestamp_millis())
+            })
+            .map_or_else(|_| Bson::String(value.clone()), bson_datetime),
+        // Extended JSON keeps types such as ObjectId and Date that plain JSON cannot express
+        DbValue::Json(value) => Bson::try_from(value.clone()).unwrap_or_else(|_| Bson::String(value.to_string())),
+        DbValue::Bytes(value) => match BASE64.decode(value) {
+            Ok(bytes) => Bson::Binary(bson::Binary {
+                subtype: bson::spec::BinarySubtype::Generic,
+                bytes,
+            }),
+            Err(_) => Bson::String(value.clone()),
+        },
+        DbValue::Text(value) | DbValue::Uuid(value) | DbValue::Time(value) => Bson::String(value.clone()),
+    }
+}
+
+fn bson_datetime(millis: i64) -> Bson {
+    Bson::DateTime(bson::DateTime::from_millis(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_copy_text_escapes_separators() {
+        let rows = vec![
+            vec![DbValue::Int(1), DbValue::Text("a\tb\\c\nd".to_string()), DbValue::Null],
+            vec![DbValue::Bool(true), DbValue::Bytes(BASE64.encode([0xde, 0xad])), DbValue::Json(json!({"a": 1}))],
+        ];
+        assert_eq!(copy_text(&rows), "1\ta\\tb\\\\c\\nd\t\\N\nt\t\\\\xdead\t{\"a\":1}\n");
+    }
+
+    #[test]
+    fn test_inserts_stay_under_the_parameter_limit() {
+        let rows: Vec<Vec<DbValue>> = (0..5).map(|i| vec![DbValue::Int(i); 10_000]).collect();
+        let statements: Vec<_> = inserts("INSERT INTO t (...) VALUES", 10_000, &rows).collect();
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].1.len(), 30_000);
+
+        let rows = vec![vec![DbValue::Int(1), DbValue::Null], vec![DbValue::Int(2), DbValue::Null]];
+        let statements: Vec<_> = inserts("INSERT INTO t (a, b) VALUES", 2, &rows).collect();
+        assert_eq!(statements[0].0, "INSERT INTO t (a, b) VALUES (?, ?), (?, ?)");
+    }
+
+    #[tokio::test]
+    async fn test_rejected_rows_do_not_stop_the_batch() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE orders (id INTEGER PRIMARY KEY, note TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let pool = DatabasePool::Sqlite(pool);
+        let target = ImportTarget::Table {
+            schema: None,
+            table: "orders".to_string(),
+        };
+        let columns = vec!["id".to_string(), "note".to_string()];
+        let slot = CancelSlot::new();
+        let mut loader = Loader::open(&pool, &target, &columns, &slot, String::new()).await.unwrap();
+
+        let rows = vec![
+            vec![DbValue::Int(1), DbValue::Text("a".to_string())],
+            vec![DbValue::Int(2), DbValue::Null],
+            vec![DbValue::Int(1), DbValue::Text("again".to_string())],
+            vec![DbValue::Int(3), DbValue::Text("c".to_string())],
+        ];
+        let rejected: Vec<usize> = loader.write(&rows).await.unwrap().into_iter().map(|(index, _)| index).collect();
+        assert_eq!(rejected, [1, 2]);
+        drop(loader);
+
+        let DatabasePool::Sqlite(pool) = pool else { unreachable!() };
+        let ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM orders ORDER BY id").fetch_all(&pool).await.unwrap();
+        assert_eq!(ids, [1, 3]);
+    }
+
+    /// Set `DEWEY_TEST_POSTGRES` to a connection URL, e.g. `postgres://postgres@127.0.0.1/postgres`
+    #[tokio::test]
+    #[ignore = "needs a Postgres server, see DEWEY_TEST_POSTGRES"]
+    async fn test_postgres_copy_rejects_only_bad_rows() {
+        let url = std::env::var("DEWEY_TEST_POSTGRES").expect("DEWEY_TEST_POSTGRES is not set");
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+        for statement in [
+            "DROP SCHEMA IF EXISTS dewey_import CASCADE",
+            "CREATE SCHEMA dewey_import",
+            "CREATE TABLE dewey_import.orders (id int PRIMARY KEY, note text NOT NULL)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        let pool = DatabasePool::Postgres(pool);
+        let target = ImportTarget::Table {
+            schema: Some("dewey_import".to_string()),
+            table: "orders".to_string(),
+        };
+        let columns = vec!["id".to_string(), "note".to_string()];
+        let slot = CancelSlot::new();
+        let mut loader = Loader::open(&pool, &target, &columns, &slot, String::new()).await.unwrap();
+
+        let rows = vec![
+            vec![DbValue::Int(1), DbValue::Text("a\tb".to_string())],
+            vec![DbValue::Int(2), DbValue::Null],
+            vec![DbValue::Int(3), DbValue::Text("c".to_string())],
+        ];
+        let rejected: Vec<usize> = loader.write(&rows).await.unwrap().into_iter().map(|(index, _)| index).collect();
+        assert_eq!(rejected, [1]);
+        drop(loader);
+
+        let DatabasePool::Postgres(pool) = pool else { unreachable!() };
+        let notes: Vec<String> = sqlx::query_scalar("SELECT note FROM dewey_import.orders ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(notes, ["a\tb", "c"]);
+        sqlx::query("DROP SCHEMA dewey_import CASCADE").execute(&pool).await.unwrap();
+    }
+
+    #[test]
+    fn test_documents_keep_bson_types() {
+        let fields = vec!["when".to_string(), "price".to_string(), "ref".to_string()];
+        let row = vec![
+            DbValue::Date("2024-07-01".to_string()),
+            DbValue::Decimal("10.50".to_string()),
+            DbValue::Json(json!({"$oid": "507f1f77bcf86cd799439011"})),
+        ];
+        let document = document(&fields, &row);
+        assert!(matches!(document.get("when"), Some(Bson::DateTime(_))));
+        assert!(matches!(document.get("price"), Some(Bson::Decimal128(_))));
+        assert!(matches!(document.get("ref"), Some(Bson::ObjectId(_))));
+    }
+}
@@ -0,0 +1,632 @@
+//! Import of CSV and NDJSON files into tables and collections.
+//!
+//! A preview reads the first rows of a file and infers a type for each of its
+//! columns. The import then maps source columns to target columns, each read
+//! as a chosen type, and loads the rows in batches: a reader thread reads and
+//! converts the next batches while the current one is written, each in its
+//! own transaction (see [`load`]).
+//!
+//! Rows that cannot be read, converted or written are rejected with their line
+//! and reported with the progress of the batch they were in; the rest of the
+//! file is still loaded. A dry run reads and converts every row without writing
+//! any. Imports are cancelled like queries, by their handle, and batches that
+//! were already written stay written.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::constants;
+use crate::error::categories::{ErrorCategory, IoSubcategory, ValidationSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::database::cancel::CancelSlot;
+use crate::services::database::cursor::{QueryHandle, QueryRegistry};
+use crate::services::database::pool::{ConnectionManager, DatabasePool};
+use crate::services::database::query::{self, DbValue};
+use crate::services::export::qualified_name;
+use crate::services::storage::repositories::connections::DbType;
+
+pub use infer::ColumnType;
+
+mod infer;
+mod load;
+mod source;
+
+use load::Loader;
+use source::SourceReader;
+
+/// A file to import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportSource {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub format: ImportFormat,
+}
+
+/// The format of an import file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum ImportFormat {
+    Csv(CsvOptions),
+    /// One JSON object per line
+    Ndjson,
+}
+
+/// How a CSV file is read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvOptions {
+    /// Separates fields; a comma when not set
+    pub delimiter: Option<char>,
+    /// The first row names the columns
+    pub header: bool,
+    /// Fields holding exactly this text are read as NULL
+    pub null: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: None,
+            header: true,
+            null: String::new(),
+        }
+    }
+}
+
+/// A column of the file, as found by a preview
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceColumn {
+    pub name: String,
+    /// The narrowest type every sampled value fits
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    /// Whether any sampled value was NULL
+    pub nullable: bool,
+}
+
+/// The columns and first rows of a file
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportPreview {
+    pub columns: Vec<SourceColumn>,
+    /// The first rows, with a value per column
+    pub rows: Vec<Vec<JsonValue>>,
+    /// Rows the column types were inferred from
+    pub sampled: u64,
+}
+
+/// Where a source column is written, and the type its values are read as
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnMapping {
+    pub source: String,
+    /// The target column, or document field
+    pub target: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+}
+
+/// Where rows are imported to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ImportTarget {
+    /// An existing table
+    Table {
+        schema: Option<String>,
+        table: String,
+    },
+    /// A table created first, with a column of the mapped type for each mapping
+    NewTable {
+        schema: Option<String>,
+        table: String,
+    },
+    /// A MongoDB collection, created by the first write if it does not exist
+    Collection {
+        database: String,
+        collection: String,
+    },
+}
+
+impl ImportTarget {
+    /// The schema and name of a target table
+    fn table(&self) -> Option<(Option<&str>, &str)> {
+        match self {
+            Self::Table { schema, table } | Self::NewTable { schema, table } => Some((schema.as_deref(), table)),
+            Self::Collection { .. } => None,
+        }
+    }
+}
+
+/// How an import runs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportOptions {
+    /// Rows written per transaction
+    pub batch_size: Option<usize>,
+    /// Read and convert every row, but write none
+    pub dry_run: bool,
+    /// Stop the import once more rows than this were rejected
+    pub max_errors: Option<u64>,
+}
+
+/// A row that was not imported
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorRow {
+    /// The line of the file the row starts on
+    pub line: u64,
+    /// The row's values as read from the file, when it could be read
+    pub values: Vec<JsonValue>,
+    pub message: String,
+}
+
+/// Messages pushed to the frontend while an import runs
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ImportEvent {
+    /// Another batch was written
+    Progress {
+        handle: QueryHandle,
+        rows_read: u64,
+        rows_written: u64,
+        rows_failed: u64,
+        /// Rows of this batch that were rejected
+        errors: Vec<ErrorRow>,
+    },
+    /// Every row was read; in a dry run, `rows_written` counts the rows that would have been
+    Done {
+        handle: QueryHandle,
+        rows_read: u64,
+        rows_written: u64,
+        rows_failed: u64,
+        /// The `CREATE TABLE` statement run, or that would have been, for a new table
+        statement: Option<String>,
+        dry_run: bool,
+        duration_ms: u64,
+    },
+    /// The import stopped; batches written before it stay written
+    Error {
+        handle: QueryHandle,
+        error: AppError,
+    },
+    /// The import was cancelled; batches written before it stay written
+    Cancelled {
+        handle: QueryHandle,
+    },
+}
+
+/// A file and where its rows are imported to
+#[derive(Debug, Clone)]
+pub struct ImportJob {
+    pub connection_id: i64,
+    pub source: ImportSource,
+    pub target: ImportTarget,
+    pub mapping: Vec<ColumnMapping>,
+    pub options: ImportOptions,
+}
+
+/// Read the columns of a file, with their inferred types, and its first rows
+///
+/// Reads from disk, so call it off the async runtime.
+///
+/// # Errors
+/// Returns an error if the file could not be opened or read
+pub fn preview(source: &ImportSource) -> AppResult<ImportPreview> {
+    let (mut reader, names) = SourceReader::open(source)?;
+    let chunk = reader.read(&source.path, constants::import::SAMPLE_ROWS)?;
+
+    let mut guesses = vec![infer::TypeGuess::default(); names.len()];
+    for record in &chunk.records {
+        for (guess, value) in guesses.iter_mut().zip(&record.values) {
+            guess.add(value);
+        }
+    }
+    let columns = names
+        .into_iter()
+        .zip(guesses)
+        .map(|(name, guess)| {
+            let (column_type, nullable) = guess.finish();
+            SourceColumn {
+                name,
+                column_type,
+                nullable,
+            }
+        })
+        .collect();
+    let sampled = chunk.records.len() as u64;
+    let rows = chunk
+        .records
+        .into_iter()
+        .take(constants::import::PREVIEW_ROWS)
+        .map(|record| record.values)
+        .collect();
+
+    Ok(ImportPreview { columns, rows, sampled })
+}
+
+/// The statement that creates a table with a column for each mapping
+#[must_use]
+pub fn create_statement(db_type: DbType, schema: Option<&str>, table: &str, mapping: &[ColumnMapping]) -> String {
+    let driver = crate::services::database::driver::driver(db_type);
+    let columns: Vec<String> = mapping
+        .iter()
+        .map(|column| format!("{} {}", driver.quote_identifier(&column.target), column.column_type.sql_type(db_type)))
+        .collect();
+    format!("CREATE TABLE {} ({})", qualified_name(db_type, schema, table), columns.join(", "))
+}
+
+/// Start importing a file in the background
+///
+/// The file's header and the target are checked before this returns: the mapping must
+/// name columns of the file, an existing table must have the mapped columns, and a new
+/// table is created unless this is a dry run. Events are sent on `sink` as batches are
+/// written. The returned handle cancels the import with `QueryRegistry::cancel`.
+///
+/// # Errors
+/// Returns an error if the file could not be opened, the mapping is invalid, the target
+/// does not suit the engine, or the target table could not be read or created
+pub async fn start<F>(
+    registry: &Arc<QueryRegistry>,
+    sessions: Arc<ConnectionManager>,
+    pool: DatabasePool,
+    job: ImportJob,
+    sink: F,
+) -> AppResult<QueryHandle>
+where
+    F: Fn(ImportEvent) + Send + Sync + 'static,
+{
+    check_target(pool.db_type(), &job.target)?;
+    let source = job.source.clone();
+    let (reader, names) = blocking(move || SourceReader::open(&source)).await?;
+    let plan = plan(&names, &job.mapping)?;
+    let targets: Vec<String> = job.mapping.iter().map(|column| column.target.clone()).collect();
+
+    let statement = match &job.target {
+        ImportTarget::NewTable { schema, table } => {
+            let statement = create_statement(pool.db_type(), schema.as_deref(), table, &job.mapping);
+            if !job.options.dry_run {
+                query::execute(&pool, &statement, &[], &CancelSlot::new(), None).await?;
+                info!("Created table {} for import", table);
+            }
+            Some(statement)
+        }
+        ImportTarget::Table { schema, table } => {
+            // Reading no rows is enough to learn that the table and its columns exist
+            let driver = pool.driver();
+            let columns: Vec<String> = targets.iter().map(|column| driver.quote_identifier(column)).collect();
+            let probe = format!(
+                "SELECT {} FROM {} WHERE 1 = 0",
+                columns.join(", "),
+                qualified_name(pool.db_type(), schema.as_deref(), table)
+            );
+            query::execute(&pool, &probe, &[], &CancelSlot::new(), None).await?;
+            None
+        }
+        ImportTarget::Collection { .. } => None,
+    };
+
+    let batch_size = job
+        .options
+        .batch_size
+        .unwrap_or(constants::import::DEFAULT_BATCH_SIZE)
+        .clamp(1, constants::import::MAX_BATCH_SIZE);
+    let batches = read_batches(reader, job.source.path.clone(), names, plan, batch_size);
+
+    let (handle, slot) = registry.track(job.connection_id, None).await?;
+    let sink = Arc::new(sink);
+    let batches_sink = sink.clone();
+    let connection_id = job.connection_id;
+    let task = tokio::spawn(async move {
+        let started = Instant::now();
+        let loader = if job.options.dry_run {
+            None
+        } else {
+            let comment = format!("dewey:import:{}", handle);
+            Some(Loader::open(&pool, &job.target, &targets, &slot, comment).await?)
+        };
+        let load = Batches {
+            sessions: &sessions,
+            handle,
+            connection_id,
+            max_errors: job.options.max_errors,
+            sink: &*batches_sink,
+        };
+        let (rows_read, rows_written, rows_failed) = load.write(loader, batches).await?;
+        Ok::<_, AppError>(ImportEvent::Done {
+            handle,
+            rows_read,
+            rows_written,
+            rows_failed,
+            statement,
+            dry_run: job.options.dry_run,
+            duration_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+        })
+    });
+    registry.attach(handle, task.abort_handle()).await;
+
+    let registry = registry.clone();
+    tokio::spawn(async move {
+        let outcome = task.await;
+        registry.untrack(handle).await;
+        match outcome {
+            Ok(Ok(done)) => {
+                info!("Import {} finished", handle);
+                sink(done);
+            }
+            Ok(Err(error)) => sink(ImportEvent::Error { handle, error }),
+            Err(e) if e.is_cancelled() => sink(ImportEvent::Cancelled { handle }),
+            Err(e) => sink(ImportEvent::Error {
+                handle,
+                error: AppError::new(
+                    format!("Import stopped unexpectedly: {}", e),
+                    ErrorCategory::Io(IoSubcategory::ReadFailed),
+                    ErrorSeverity::Error,
+                ),
+            }),
+        }
+    });
+
+    Ok(handle)
+}
+
+/// Where each target column's values come from: a source column and the type it is read as
+type Plan = Vec<(usize, ColumnType)>;
+
+/// Rows read and converted from the file
+#[derive(Default)]
+struct Batch {
+    /// Converted rows, in the order of the mapping
+    rows: Vec<Vec<DbValue>>,
+    /// The line and source values each row came from
+    sources: Vec<(u64, Vec<JsonValue>)>,
+    rejected: Vec<ErrorRow>,
+}
+
+/// An import's task-local state
+struct Batches<'a, F> {
+    sessions: &'a ConnectionManager,
+    handle: QueryHandle,
+    connection_id: i64,
+    max_errors: Option<u64>,
+    sink: &'a F,
+}
+
+impl<F: Fn(ImportEvent)> Batches<'_, F> {
+    /// Write every batch the reader delivers, or only count them without a loader
+    ///
+    /// Returns the rows read, written and rejected.
+    async fn write(
+        &self,
+        mut loader: Option<Loader>,
+        mut batches: mpsc::Receiver<AppResult<Batch>>,
+    ) -> AppResult<(u64, u64, u64)> {
+        let (mut rows_read, mut rows_written, mut rows_failed) = (0, 0, 0);
+
+        while let Some(batch) = batches.recv().await {
+            let Batch {
+                rows,
+                mut sources,
+                mut rejected,
+            } = batch?;
+            rows_read += (rows.len() + rejected.len()) as u64;
+
+            let mut written = rows.len();
+            if let Some(loader) = loader.as_mut() {
+                for (index, message) in loader.write(&rows).await? {
+                    if let Some((line, values)) = sources.get_mut(index) {
+                        written -= 1;
+                        rejected.push(ErrorRow {
+                            line: *line,
+                            values: std::mem::take(values),
+                            message,
+                        });
+                    }
+                }
+                self.sessions.touch(self.connection_id).await;
+            }
+            rejected.sort_by_key(|row| row.line);
+            rows_written += written as u64;
+            rows_failed += rejected.len() as u64;
+
+            (self.sink)(ImportEvent::Progress {
+                handle: self.handle,
+                rows_read,
+                rows_written,
+                rows_failed,
+                errors: rejected,
+            });
+            if let Some(max_errors) = self.max_errors.filter(|max_errors| rows_failed > *max_errors) {
+                return Err(AppError::new(
+                    format!("Stopped after more than {} rows were rejected", max_errors),
+                    ErrorCategory::Validation(ValidationSubcategory::InvalidRange),
+                    ErrorSeverity::Error,
+                ));
+            }
+        }
+        Ok((rows_read, rows_written, rows_failed))
+    }
+}
+
+/// Read and convert batches on a blocking thread, a few batches ahead of the writer
+///
+/// The thread stops once the file is read, or when the receiver is dropped.
+fn read_batches(
+    mut reader: SourceReader,
+    path: PathBuf,
+    names: Vec<String>,
+    plan: Plan,
+    batch_size: usize,
+) -> mpsc::Receiver<AppResult<Batch>> {
+    let (batches, received) = mpsc::channel(constants::import::READ_AHEAD_BATCHES);
+    tokio::task::spawn_blocking(move || loop {
+        let chunk = match reader.read(&path, batch_size) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                let _ = batches.blocking_send(Err(e));
+                return;
+            }
+        };
+        let finished = chunk.finished;
+        let mut batch = Batch {
+            rejected: chunk.rejected,
+            ..Batch::default()
+        };
+        for record in chunk.records {
+            match convert(&record.values, &plan, &names) {
+                Ok(values) => {
+                    batch.rows.push(values);
+                    batch.sources.push((record.line, record.values));
+                }
+                Err(message) => batch.rejected.push(ErrorRow {
+                    line: record.line,
+                    values: record.values,
+                    message,
+                }),
+            }
+        }
+        if batches.blocking_send(Ok(batch)).is_err() || finished {
+            return;
+        }
+    });
+    received
+}
+
+/// Convert a record's values to the mapped types, naming the first column whose value does not fit
+fn convert(values: &[JsonValue], plan: &Plan, names: &[String]) -> Result<Vec<DbValue>, String> {
+    plan.iter()
+        .map(|(index, column_type)| {
+            infer::convert(&values[*index], *column_type).map_err(|reason| format!("{}: {}", names[*index], reason))
+        })
+        .collect()
+}
+
+/// Find each mapped source column in the file
+fn plan(names: &[String], mapping: &[ColumnMapping]) -> AppResult<Plan> {
+    if mapping.is_empty() {
+        return Err(invalid_options("Map at least one column to import".to_string()));
+    }
+    let mut plan = Vec::with_capacity(mapping.len());
+    for (position, column) in mapping.iter().enumerate() {
+        if column.target.trim().is_empty() {
+            return Err(invalid_options(format!("Choose where {} is imported to", column.source)));
+        }
+        if mapping[..position].iter().any(|other| other.target == column.target) {
+            return Err(invalid_options(format!("{} is mapped more than once", column.target)));
+        }
+        let index = names
+            .iter()
+            .position(|name| *name == column.source)
+            .ok_or_else(|| invalid_options(format!("The file has no column {}", column.source)))?;
+        plan.push((index, column.column_type));
+    }
+    Ok(plan)
+}
+
+/// Check the target suits the engine the rows are written to
+fn check_target(db_type: DbType, target: &ImportTarget) -> AppResult<()> {
+    match target {
+        ImportTarget::Collection { .. } if db_type == DbType::MongoDb => Ok(()),
+        ImportTarget::Collection { .. } => {
+            Err(invalid_options("Rows can only be imported to a collection on MongoDB".to_string()))
+        }
+        ImportTarget::Table { .. } | ImportTarget::NewTable { .. } => match db_type {
+            DbType::Postgres | DbType::MySql | DbType::Sqlite => Ok(()),
+            _ => Err(unsupported(db_type)),
+        },
+    }
+}
+
+/// Run file reads off the async runtime
+async fn blocking<T, R>(read: R) -> AppResult<T>
+where
+    T: Send + 'static,
+    R: FnOnce() -> AppResult<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(read).await.map_err(|e| {
+        AppError::new(
+            format!("Import stopped unexpectedly: {}", e),
+            ErrorCategory::Io(IoSubcategory::ReadFailed),
+            ErrorSeverity::Error,
+        )
+    })?
+}
+
+fn unsupported(db_type: DbType) -> AppError {
+    AppError::new(
+        format!("Importing into {} is not supported", db_type),
+        ErrorCategory::Validation(ValidationSubcategory::InvalidType),
+        ErrorSeverity::Error,
+    )
+}
+
+fn invalid_options(message: String) -> AppError {
+    AppError::new(
+        message,
+        ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+        ErrorSeverity::Error,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(source: &str, target: &str, column_type: ColumnType) -> ColumnMapping {
+        ColumnMapping {
+            source: source.to_string(),
+            target: target.to_string(),
+            column_type,
+        }
+    }
+
+    #[test]
+    fn test_preview_infers_column_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("orders.csv");
+        std::fs::write(&path, "id;total;placed\n1;10.50;2024-07-01\n2;;2024-07-02\n").unwrap();
+        let source = ImportSource {
+            path,
+            format: ImportFormat::Csv(CsvOptions {
+                delimiter: Some(';'),
+                ..CsvOptions::default()
+            }),
+        };
+
+        let preview = preview(&source).unwrap();
+        let types: Vec<_> = preview.columns.iter().map(|column| (column.column_type, column.nullable)).collect();
+        assert_eq!(
+            types,
+            [(ColumnType::Integer, false), (ColumnType::Decimal, true), (ColumnType::Date, false)]
+        );
+        assert_eq!(preview.sampled, 2);
+        assert_eq!(preview.rows[1][1], JsonValue::Null);
+    }
+
+    #[test]
+    fn test_mapping_is_checked_against_the_file() {
+        let names = vec!["id".to_string(), "note".to_string()];
+        let plan = plan(&names, &[mapping("note", "body", ColumnType::Text)]).unwrap();
+        assert_eq!(plan, [(1, ColumnType::Text)]);
+
+        assert!(super::plan(&names, &[]).is_err());
+        assert!(super::plan(&names, &[mapping("missing", "x", ColumnType::Text)]).is_err());
+        let twice = [mapping("id", "x", ColumnType::Integer), mapping("note", "x", ColumnType::Text)];
+        assert!(super::plan(&names, &twice).is_err());
+    }
+
+    #[test]
+    fn test_new_tables_use_each_engines_types() {
+        let mapping = [mapping("id", "id", ColumnType::Integer), mapping("at", "placed at", ColumnType::DateTime)];
+        assert_eq!(
+            create_statement(DbType::Postgres, Some("sales"), "orders", &mapping),
+            "CREATE TABLE \"sales\".\"orders\" (\"id\" BIGINT, \"placed at\" TIMESTAMP)"
+        );
+        assert_eq!(
+            create_statement(DbType::MySql, None, "orders", &mapping),
+            "CREATE TABLE `orders` (`id` BIGINT, `placed at` DATETIME(6))"
+        );
+        assert!(check_target(DbType::Redis, &ImportTarget::Table { schema: None, table: "t".to_string() }).is_err());
+    }
+}
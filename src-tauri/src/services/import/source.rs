@@ -0,0 +1,311 @@
+//! Reading CSV and NDJSON files a batch of records at a time.
+
+use serde_json::Value as JsonValue;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::Path;
+
+use crate::constants;
+use crate::error::categories::{ErrorCategory, IoSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+
+use super::{invalid_options, CsvOptions, ErrorRow, ImportFormat, ImportSource};
+
+/// One row of the file, with a value for every source column
+#[derive(Debug)]
+pub(super) struct Record {
+    /// The line the row starts on, counting from 1
+    pub line: u64,
+    pub values: Vec<JsonValue>,
+}
+
+/// Records read from the file, and lines that could not be read as one
+#[derive(Debug, Default)]
+pub(super) struct Chunk {
+    pub records: Vec<Record>,
+    pub rejected: Vec<ErrorRow>,
+    /// Whether the end of the file was reached
+    pub finished: bool,
+}
+
+/// Reads records from an import file
+pub(super) enum SourceReader {
+    Csv {
+        reader: csv::Reader<File>,
+        null: String,
+        columns: usize,
+    },
+    Ndjson {
+        lines: Lines<BufReader<File>>,
+        line: u64,
+        columns: Vec<String>,
+    },
+}
+
+impl SourceReader {
+    /// Open a file and find its columns
+    ///
+    /// CSV columns are named by the header row, or `column_1`, `column_2`, ... without
+    /// one. NDJSON columns are the keys seen in the first sampled objects; keys that
+    /// only appear further into the file are not read.
+    ///
+    /// # Errors
+    /// Returns an error if the file could not be opened or its header could not be read
+    pub(super) fn open(source: &ImportSource) -> AppResult<(Self, Vec<String>)> {
+        match &source.format {
+            ImportFormat::Csv(options) => open_csv(&source.path, options),
+            ImportFormat::Ndjson => {
+                let columns = ndjson_columns(&source.path)?;
+                let reader = Self::Ndjson {
+                    lines: BufReader::new(open_file(&source.path)?).lines(),
+                    line: 0,
+                    columns: columns.clone(),
+                };
+                Ok((reader, columns))
+            }
+        }
+    }
+
+    /// Read up to `size` rows
+    ///
+    /// Rows that cannot be read, such as malformed JSON, are rejected rather than
+    /// failing the read. Rejected rows count toward `size`, so a file of bad rows is
+    /// still read a chunk at a time.
+    ///
+    /// # Errors
+    /// Returns an error if the file could not be read
+    pub(super) fn read(&mut self, path: &Path, size: usize) -> AppResult<Chunk> {
+        let mut chunk = Chunk::default();
+        while chunk.records.len() + chunk.rejected.len() < size {
+            let read = match self {
+                Self::Csv { reader, null, columns } => read_csv(reader, null, *columns, path)?,
+                Self::Ndjson { lines, line, columns } => read_ndjson(lines, line, columns, path)?,
+            };
+            match read {
+                Some(Ok(record)) => chunk.records.push(record),
+                Some(Err(rejected)) => chunk.rejected.push(rejected),
+                None => {
+                    chunk.finished = true;
+                    break;
+                }
+            }
+        }
+        Ok(chunk)
+    }
+}
+
+fn open_csv(path: &Path, options: &CsvOptions) -> AppResult<(SourceReader, Vec<String>)> {
+    let delimiter = options.delimiter.unwrap_or(',');
+    let delimiter = u8::try_from(delimiter)
+        .ok()
+        .filter(u8::is_ascii)
+        .ok_or_else(|| invalid_options(format!("{:?} cannot separate CSV fields", delimiter)))?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(options.header)
+        .flexible(true)
+        .from_reader(open_file(path)?);
+
+    // Without a header row this is the first record, which is still read as one
+    let header = reader.headers().map_err(|e| read_failed(path, e))?.clone();
+    let columns: Vec<String> = if options.header {
+        header.iter().map(|name| name.trim().to_string()).collect()
+    } else {
+        (1..=header.len()).map(|index| format!("column_{}", index)).collect()
+    };
+    let reader = SourceReader::Csv {
+        reader,
+        null: options.null.clone(),
+        columns: columns.len(),
+    };
+    Ok((reader, columns))
+}
+
+fn read_csv(
+    reader: &mut csv::Reader<File>,
+    null: &str,
+    columns: usize,
+    path: &Path,
+) -> AppResult<Option<Result<Record, ErrorRow>>> {
+    let mut record = csv::StringRecord::new();
+    match reader.read_record(&mut record) {
+        Ok(false) => Ok(None),
+        Ok(true) => {
+            let line = record.position().map_or(0, csv::Position::line);
+            // Short rows are padded with NULL and fields past the last column are ignored
+            let mut values: Vec<JsonValue> = record
+                .iter()
+                .take(columns)
+                .map(|field| if field == null { JsonValue::Null } else { JsonValue::String(field.to_string()) })
+                .collect();
+            values.resize(columns, JsonValue::Null);
+            Ok(Some(Ok(Record { line, values })))
+        }
+        Err(e) if e.is_io_error() => Err(read_failed(path, e)),
+        Err(e) => {
+            let line = e.position().map_or(0, csv::Position::line);
+            Ok(Some(Err(ErrorRow {
+                line,
+                values: Vec::new(),
+                message: e.to_string(),
+            })))
+        }
+    }
+}
+
+fn read_ndjson(
+    lines: &mut Lines<BufReader<File>>,
+    line: &mut u64,
+    columns: &[String],
+    path: &Path,
+) -> AppResult<Option<Result<Record, ErrorRow>>> {
+    loop {
+        let Some(text) = lines.next() else {
+            return Ok(None);
+        };
+        let text = text.map_err(|e| read_failed(path, e))?;
+        *line += 1;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let object = match serde_json::from_str::<JsonValue>(&text) {
+            Ok(JsonValue::Object(object)) => object,
+            Ok(_) => return Ok(Some(Err(not_an_object(*line, text)))),
+            Err(e) => {
+                return Ok(Some(Err(ErrorRow {
+                    line: *line,
+                    values: vec![JsonValue::String(text)],
+                    message: e.to_string(),
+                })))
+            }
+        };
+        let values = columns
+            .iter()
+            .map(|column| object.get(column).cloned().unwrap_or(JsonValue::Null))
+            .collect();
+        return Ok(Some(Ok(Record { line: *line, values })));
+    }
+}
+
+/// The keys of the objects on the first sampled lines of an NDJSON file
+fn ndjson_columns(path: &Path) -> AppResult<Vec<String>> {
+    let mut columns: Vec<String> = Vec::new();
+    let lines = BufReader::new(open_file(path)?).lines();
+    let mut sampled = 0;
+    for text in lines {
+        let text = text.map_err(|e| read_failed(path, e))?;
+        let Ok(JsonValue::Object(object)) = serde_json::from_str::<JsonValue>(&text) else {
+            continue;
+        };
+        for key in object.keys() {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+        sampled += 1;
+        if sampled == constants::import::SAMPLE_ROWS {
+            break;
+        }
+    }
+    Ok(columns)
+}
+
+fn not_an_object(line: u64, text: String) -> ErrorRow {
+    ErrorRow {
+        line,
+        values: vec![JsonValue::String(text)],
+        message: "Each line must hold a JSON object".to_string(),
+    }
+}
+
+fn open_file(path: &Path) -> AppResult<File> {
+    File::open(path).map_err(|e| {
+        let subcategory = match e.kind() {
+            std::io::ErrorKind::NotFound => IoSubcategory::PathNotFound,
+            std::io::ErrorKind::PermissionDenied => IoSubcategory::PermissionDenied,
+            _ => IoSubcategory::ReadFailed,
+        };
+        AppError::new(
+            format!("Failed to open {}: {}", path.display(), e),
+            ErrorCategory::Io(subcategory),
+            ErrorSeverity::Error,
+        )
+    })
+}
+
+fn read_failed(path: &Path, error: impl std::fmt::Display) -> AppError {
+    AppError::new(
+        format!("Failed to read {}: {}", path.display(), error),
+        ErrorCategory::Io(IoSubcategory::ReadFailed),
+        ErrorSeverity::Error,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn source(dir: &tempfile::TempDir, contents: &str, format: ImportFormat) -> ImportSource {
+        let path = dir.path().join("in");
+        std::fs::write(&path, contents).unwrap();
+        ImportSource { path, format }
+    }
+
+    #[test]
+    fn test_csv_records_are_read_in_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&dir, "id,note\n1,a\n2,\n3,c,extra\n4\n", ImportFormat::Csv(CsvOptions::default()));
+        let (mut reader, columns) = SourceReader::open(&source).unwrap();
+        assert_eq!(columns, ["id", "note"]);
+
+        let chunk = reader.read(&source.path, 2).unwrap();
+        assert!(!chunk.finished);
+        assert_eq!(chunk.records[0].line, 2);
+        assert_eq!(chunk.records[1].values, [json!("2"), JsonValue::Null]);
+
+        let chunk = reader.read(&source.path, 10).unwrap();
+        assert!(chunk.finished);
+        assert_eq!(chunk.records[0].values, [json!("3"), json!("c")]);
+        assert_eq!(chunk.records[1].values, [json!("4"), JsonValue::Null]);
+    }
+
+    #[test]
+    fn test_ndjson_lines_become_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&dir, "{\"a\":1}\n\n{\"b\":[2],\"a\":null}\nnot json\n[1]\n", ImportFormat::Ndjson);
+        let (mut reader, columns) = SourceReader::open(&source).unwrap();
+        assert_eq!(columns, ["a", "b"]);
+
+        let chunk = reader.read(&source.path, 10).unwrap();
+        assert_eq!(chunk.records.len(), 2);
+        assert_eq!(chunk.records[1].line, 3);
+        assert_eq!(chunk.records[1].values, [JsonValue::Null, json!([2])]);
+        assert_eq!(chunk.rejected.iter().map(|row| row.line).collect::<Vec<_>>(), [4, 5]);
+    }
+
+    #[test]
+    fn test_rejected_rows_count_toward_the_chunk_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&dir, "{\"a\":1}\nnot json\n[1]\n{\"a\":2}\n", ImportFormat::Ndjson);
+        let (mut reader, _) = SourceReader::open(&source).unwrap();
+
+        let chunk = reader.read(&source.path, 2).unwrap();
+        assert!(!chunk.finished);
+        assert_eq!((chunk.records.len(), chunk.rejected.len()), (1, 1));
+
+        let chunk = reader.read(&source.path, 2).unwrap();
+        assert_eq!(chunk.rejected[0].line, 3);
+        assert_eq!(chunk.records[0].line, 4);
+    }
+
+    #[test]
+    fn test_missing_file_is_reported() {
+        let source = ImportSource {
+            path: PathBuf::from("/nonexistent/in.ndjson"),
+            format: ImportFormat::Ndjson,
+        };
+        assert!(SourceReader::open(&source).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! ClickHouse catalog, read from the `system` tables.
+
+use std::collections::BTreeMap;
+use tracing::debug;
+
+use crate::error::AppResult;
+use crate::services::database::clickhouse::ClickHouseClient;
+use crate::services::database::query::DbValue;
+
+use super::{
+    Catalog, ConstraintInfo, ConstraintKind, DatabaseInfo, IndexInfo, SchemaTree, TableColumn,
+    TableInfo, TableKind,
+};
+
+/// Databases owned by the server itself
+const SYSTEM_DATABASES: &str = "('system', 'INFORMATION_SCHEMA', 'information_schema')";
+
+/// Read the catalog of every user database on the server
+///
+/// Each database is reported with a single schema of the same name. The sorting key
+/// is reported as the primary key, and data skipping indexes as indexes.
+pub(crate) async fn introspect(client: &ClickHouseClient) -> AppResult<Catalog> {
+    let mut trees: BTreeMap<String, SchemaTree> = BTreeMap::new();
+
+    let (_, databases, _) = client
+        .fetch(
+            &format!("SELECT name FROM system.databases WHERE name NOT IN {}", SYSTEM_DATABASES),
+            &[],
+            None,
+        )
+        .await?;
+    for row in databases {
+        let database = text(&row[0]);
+        trees.entry(database.clone()).or_default().add_schema(database);
+    }
+
+    let (_, tables, _) = client
+        .fetch(
+            &format!(
+                "SELECT database, name, engine, comment, primary_key \
+                 FROM system.tables WHERE database NOT IN {} AND NOT is_temporary",
+                SYSTEM_DATABASES
+            ),
+            &[],
+            None,
+        )
+        .await?;
+    let mut primary_keys: Vec<(String, String, String)> = Vec::new();
+    for row in tables {
+        let (schema, name) = (text(&row[0]), text(&row[1]));
+        let kind = match text(&row[2]).as_str() {
+            "MaterializedView" => TableKind::MaterializedView,
+            "View" | "LiveView" | "WindowView" => TableKind::View,
+            _ => TableKind::Table,
+        };
+        let comment = Some(text(&row[3])).filter(|comment| !comment.is_empty());
+        let primary_key = text(&row[4]);
+        if !primary_key.is_empty() {
+            primary_keys.push((schema.clone(), name.clone(), primary_key));
+        }
+        trees
+            .entry(schema.clone())
+            .or_default()
+            .add_table(schema, TableInfo::new(name, kind, comment));
+    }
+
+    let (_, columns, _) = client
+        .fetch(
+            &format!(
+                "SELECT database, table, name, toInt64(position), type, default_kind, \
+                        default_expression, is_in_primary_key, comment \
+                 FROM system.columns WHERE database NOT IN {} ORDER BY database, table, position",
+                SYSTEM_DATABASES
+            ),
+            &[],
+            None,
+        )
+        .await?;
+    let mut key_columns: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for row in columns {
+        let (schema, table, name) = (text(&row[0]), text(&row[1]), text(&row[2]));
+        let data_type = text(&row[4]);
+        let default = match (text(&row[5]).as_str(), text(&row[6])) {
+            (_, expression) if expression.is_empty() => None,
+            ("DEFAULT", expression) => Some(expression),
+            (kind, expression) => Some(format!("{} {}", kind, expression)),
+        };
+        if flag(&row[7]) {
+            key_columns
+                .entry((schema.clone(), table.clone()))
+                .or_default()
+                .push(name.clone());
+        }
+        if let Some(table) = trees
+            .get_mut(&schema)
+            .and_then(|tree| tree.table_mut(&schema, &table))
+        {
+            table.columns.push(TableColumn {
+                name,
+                ordinal: number(&row[3]),
+                nullable: is_nullable(&data_type),
+                data_type,
+                base_type: None,
+                default,
+                primary_key: false,
+                comment: Some(text(&row[8])).filter(|comment| !comment.is_empty()),
+            });
+        }
+    }
+    for (schema, table_name, definition) in primary_keys {
+        let columns = key_columns.remove(&(schema.clone(), table_name.clone())).unwrap_or_default();
+        if let Some(table) = trees
+            .get_mut(&schema)
+            .and_then(|tree| tree.table_mut(&schema, &table_name))
+        {
+            table.constraints.push(ConstraintInfo {
+                name: "PRIMARY KEY".to_string(),
+                kind: ConstraintKind::PrimaryKey,
+                columns,
+                definition: Some(definition),
+            });
+        }
+    }
+
+    let (_, indexes, _) = client
+        .fetch(
+            &format!(
+                "SELECT database, table, name, expr FROM system.data_skipping_indices \
+                 WHERE database NOT IN {}",
+                SYSTEM_DATABASES
+            ),
+            &[],
+            None,
+        )
+        .await?;
+    for row in indexes {
+        let (schema, table) = (text(&row[0]), text(&row[1]));
+        if let Some(table) = trees
+            .get_mut(&schema)
+            .and_then(|tree| tree.table_mut(&schema, &table))
+        {
+            table.indexes.push(IndexInfo {
+                name: text(&row[2]),
+                columns: vec![text(&row[3])],
+                unique: false,
+                primary: false,
+            });
+        }
+    }
+
+    debug!("Introspected {} ClickHouse databases", trees.len());
+    Ok(Catalog {
+        databases: trees
+            .into_iter()
+            .map(|(name, tree)| DatabaseInfo {
+                name,
+                schemas: tree.into_schemas(),
+            })
+            .collect(),
+    })
+}
+
+/// Whether a ClickHouse type admits NULL, looking through `LowCardinality`
+fn is_nullable(data_type: &str) -> bool {
+    data_type
+        .strip_prefix("LowCardinality(")
+        .unwrap_or(data_type)
+        .starts_with("Nullable(")
+}
+
+fn text(value: &DbValue) -> String {
+    match value {
+        DbValue::Text(text) => text.clone(),
+        _ => String::new(),
+    }
+}
+
+fn number(value: &DbValue) -> i64 {
+    match value {
+        DbValue::Int(number) => *number,
+        DbValue::UInt(number) => i64::try_from(*number).unwrap_or(i64::MAX),
+        _ => 0,
+    }
+}
+
+fn flag(value: &DbValue) -> bool {
+    match value {
+        DbValue::Bool(flag) => *flag,
+        other => number(other) != 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nullable_types_look_through_low_cardinality() {
+        assert!(is_nullable("Nullable(String)"));
+        assert!(is_nullable("LowCardinality(Nullable(String))"));
+        assert!(!is_nullable("LowCardinality(String)"));
+        assert!(!is_nullable("Array(Nullable(String))"));
+    }
+}
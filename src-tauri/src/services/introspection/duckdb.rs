@@ -0,0 +1,204 @@
+//! DuckDB catalog, read from the `duckdb_*` metadata functions.
+
+use std::collections::BTreeMap;
+use tracing::debug;
+
+use crate::error::AppResult;
+use crate::services::database::duckdb::DuckDbPool;
+use crate::services::database::query::DbValue;
+
+use super::{
+    Catalog, ConstraintInfo, ConstraintKind, DatabaseInfo, ForeignKeyInfo, IndexInfo, SchemaTree,
+    TableColumn, TableInfo, TableKind,
+};
+
+/// Leaves out DuckDB's own objects and its built-in `system` and `temp` catalogs
+const USER_OBJECTS: &str = "NOT internal AND database_name NOT IN ('system', 'temp')";
+
+/// Read the catalog of the open database and any attached ones
+///
+/// Data files opened directly show up as a single view in the `memory` database.
+pub(crate) async fn introspect(pool: &DuckDbPool) -> AppResult<Catalog> {
+    let mut trees: BTreeMap<String, SchemaTree> = BTreeMap::new();
+
+    let (_, schemas) = pool
+        .fetch(&format!(
+            "SELECT database_name, schema_name FROM duckdb_schemas() WHERE {}",
+            USER_OBJECTS
+        ))
+        .await?;
+    for row in schemas {
+        trees.entry(text(&row[0])).or_default().add_schema(text(&row[1]));
+    }
+
+    let (_, tables) = pool
+        .fetch(&format!(
+            "SELECT database_name, schema_name, table_name, comment, 'table' FROM duckdb_tables() \
+             WHERE {0} AND NOT temporary \
+             UNION ALL \
+             SELECT database_name, schema_name, view_name, comment, 'view' FROM duckdb_views() \
+             WHERE {0} AND NOT temporary",
+            USER_OBJECTS
+        ))
+        .await?;
+    for row in tables {
+        let kind = if text(&row[4]) == "view" {
+            TableKind::View
+        } else {
+            TableKind::Table
+        };
+        let comment = Some(text(&row[3])).filter(|comment| !comment.is_empty());
+        trees
+            .entry(text(&row[0]))
+            .or_default()
+            .add_table(text(&row[1]), TableInfo::new(text(&row[2]), kind, comment));
+    }
+
+    let (_, columns) = pool
+        .fetch(&format!(
+            "SELECT database_name, schema_name, table_name, column_name, column_index, data_type, \
+                    is_nullable, column_default, comment \
+             FROM duckdb_columns() WHERE {}",
+            USER_OBJECTS
+        ))
+        .await?;
+    for row in columns {
+        if let Some(table) = table_mut(&mut trees, &row) {
+            table.columns.push(TableColumn {
+                name: text(&row[3]),
+                ordinal: number(&row[4]),
+                data_type: text(&row[5]),
+                base_type: None,
+                nullable: matches!(row[6], DbValue::Bool(true)),
+                default: Some(text(&row[7])).filter(|default| !default.is_empty()),
+                primary_key: false,
+                comment: Some(text(&row[8])).filter(|comment| !comment.is_empty()),
+            });
+        }
+    }
+
+    let (_, constraints) = pool
+        .fetch(
+            "SELECT database_name, schema_name, table_name, constraint_name, constraint_type, \
+                    constraint_column_names, constraint_text, referenced_table, referenced_column_names \
+             FROM duckdb_constraints() \
+             WHERE constraint_type IN ('PRIMARY KEY', 'UNIQUE', 'CHECK', 'FOREIGN KEY') \
+               AND database_name NOT IN ('system', 'temp')",
+        )
+        .await?;
+    for row in constraints {
+        let schema = text(&row[1]);
+        let Some(table) = table_mut(&mut trees, &row) else {
+            continue;
+        };
+        let (name, columns) = (text(&row[3]), list(&row[5]));
+        let kind = match text(&row[4]).as_str() {
+            "PRIMARY KEY" => ConstraintKind::PrimaryKey,
+            "UNIQUE" => ConstraintKind::Unique,
+            "CHECK" => ConstraintKind::Check,
+            _ => {
+                // DuckDB only references tables of the same schema and has no referential actions
+                table.foreign_keys.push(ForeignKeyInfo {
+                    name,
+                    columns,
+                    referenced_schema: schema,
+                    referenced_table: text(&row[7]),
+                    referenced_columns: list(&row[8]),
+                    on_update: "NO ACTION".to_string(),
+                    on_delete: "NO ACTION".to_string(),
+                });
+                continue;
+            }
+        };
+        table.constraints.push(ConstraintInfo {
+            name,
+            kind,
+            columns,
+            definition: Some(text(&row[6])).filter(|definition| !definition.is_empty()),
+        });
+    }
+
+    let (_, indexes) = pool
+        .fetch(
+            "SELECT database_name, schema_name, table_name, index_name, expressions, is_unique, is_primary \
+             FROM duckdb_indexes() WHERE database_name NOT IN ('system', 'temp')",
+        )
+        .await?;
+    for row in indexes {
+        if let Some(table) = table_mut(&mut trees, &row) {
+            table.indexes.push(IndexInfo {
+                name: text(&row[3]),
+                columns: expressions(&text(&row[4])),
+                unique: matches!(row[5], DbValue::Bool(true)),
+                primary: matches!(row[6], DbValue::Bool(true)),
+            });
+        }
+    }
+
+    debug!("Introspected {} DuckDB databases", trees.len());
+    Ok(Catalog {
+        databases: trees
+            .into_iter()
+            .map(|(name, tree)| DatabaseInfo {
+                name,
+                schemas: tree.into_schemas(),
+            })
+            .collect(),
+    })
+}
+
+/// The table a catalog row names in its database, schema and table columns
+fn table_mut<'a>(trees: &'a mut BTreeMap<String, SchemaTree>, row: &[DbValue]) -> Option<&'a mut TableInfo> {
+    trees
+        .get_mut(&text(&row[0]))?
+        .table_mut(&text(&row[1]), &text(&row[2]))
+}
+
+/// Index expressions, which DuckDB reports as a single `[a, b]` text
+fn expressions(text: &str) -> Vec<String> {
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+        .unwrap_or(text);
+    inner
+        .split(", ")
+        .map(|expression| expression.trim().to_string())
+        .filter(|expression| !expression.is_empty())
+        .collect()
+}
+
+fn text(value: &DbValue) -> String {
+    match value {
+        DbValue::Text(text) => text.clone(),
+        _ => String::new(),
+    }
+}
+
+fn number(value: &DbValue) -> i64 {
+    match value {
+        DbValue::Int(number) => *number,
+        DbValue::UInt(number) => i64::try_from(*number).unwrap_or(i64::MAX),
+        _ => 0,
+    }
+}
+
+fn list(value: &DbValue) -> Vec<String> {
+    match value {
+        DbValue::Json(serde_json::Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_expressions_are_split() {
+        assert_eq!(expressions("[customer_id, lower(email)]"), vec!["customer_id", "lower(email)"]);
+        assert_eq!(expressions("[]"), Vec::<String>::new());
+    }
+}
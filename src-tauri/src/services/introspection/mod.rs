@@ -0,0 +1,239 @@
+//! Schema introspection for saved connections.
+//!
+//! Each SQL driver reads its own system catalog and reports it in a single,
+//! normalized [`Catalog`] model: databases contain schemas, which contain
+//! tables, views and materialized views with their columns, indexes, foreign
+//! keys, constraints and triggers. Engines without a separate schema level
+//! (MySQL, SQLite, ClickHouse) report one schema per database, named after
+//! the database.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::error::categories::{ErrorCategory, ValidationSubcategory};
+use crate::error::{AppError, AppResult, ErrorSeverity};
+use crate::services::database::pool::DatabasePool;
+
+pub(crate) mod clickhouse;
+pub(crate) mod duckdb;
+pub(crate) mod mssql;
+pub(crate) mod mysql;
+pub(crate) mod postgres;
+pub(crate) mod sqlite;
+
+/// Everything visible through one connection
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Catalog {
+    pub databases: Vec<DatabaseInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseInfo {
+    pub name: String,
+    pub schemas: Vec<SchemaInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaInfo {
+    pub name: String,
+    pub tables: Vec<TableInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TableKind {
+    Table,
+    View,
+    MaterializedView,
+}
+
+/// A table, view or materialized view
+#[derive(Debug, Clone, Serialize)]
+pub struct TableInfo {
+    pub name: String,
+    pub kind: TableKind,
+    pub comment: Option<String>,
+    pub columns: Vec<TableColumn>,
+    pub indexes: Vec<IndexInfo>,
+    pub foreign_keys: Vec<ForeignKeyInfo>,
+    /// Primary key, unique, check and exclusion constraints
+    pub constraints: Vec<ConstraintInfo>,
+    pub triggers: Vec<TriggerInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableColumn {
+    pub name: String,
+    /// 1-based position in the table
+    pub ordinal: i64,
+    /// Type as the server spells it, including length and precision
+    pub data_type: String,
+    /// Type without its length, precision or other modifiers, where the server reports one (Postgres)
+    pub base_type: Option<String>,
+    pub nullable: bool,
+    /// Default expression as SQL text
+    pub default: Option<String>,
+    pub primary_key: bool,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexInfo {
+    pub name: String,
+    /// Indexed columns in key order; expressions are reported as SQL text
+    pub columns: Vec<String>,
+    pub unique: bool,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ForeignKeyInfo {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub referenced_schema: String,
+    pub referenced_table: String,
+    /// Empty when the key implicitly references the primary key
+    pub referenced_columns: Vec<String>,
+    pub on_update: String,
+    pub on_delete: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstraintKind {
+    PrimaryKey,
+    Unique,
+    Check,
+    Exclusion,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConstraintInfo {
+    pub name: String,
+    pub kind: ConstraintKind,
+    pub columns: Vec<String>,
+    /// Constraint definition as SQL text, where the server reports one
+    pub definition: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TriggerInfo {
+    pub name: String,
+    /// `BEFORE`, `AFTER` or `INSTEAD OF`
+    pub timing: String,
+    /// `INSERT`, `UPDATE`, `DELETE` or `TRUNCATE`
+    pub events: Vec<String>,
+    pub definition: Option<String>,
+}
+
+impl Catalog {
+    /// A table, view or materialized view by its schema and name, in whichever database lists it first
+    #[must_use]
+    pub fn table(&self, schema: &str, name: &str) -> Option<&TableInfo> {
+        self.databases
+            .iter()
+            .flat_map(|database| &database.schemas)
+            .filter(|candidate| candidate.name == schema)
+            .flat_map(|schema| &schema.tables)
+            .find(|table| table.name == name)
+    }
+}
+
+/// Read the catalog of everything the connection can see
+///
+/// # Errors
+/// Returns an error if a catalog query failed, or the connection does not speak SQL
+pub async fn introspect(pool: &DatabasePool) -> AppResult<Catalog> {
+    pool.driver().introspect(pool).await
+}
+
+/// Read one table, view or materialized view by its schema and name, or `None` if there is none
+///
+/// Unlike [`introspect`], this only queries the catalog rows of that table where the engine
+/// allows it, so it stays quick on databases with many tables.
+///
+/// # Errors
+/// Returns an error if a catalog query failed, or the connection does not speak SQL
+pub async fn table(pool: &DatabasePool, schema: &str, name: &str) -> AppResult<Option<TableInfo>> {
+    pool.driver().introspect_table(pool, schema, name).await
+}
+
+/// The error reported when a catalog is asked of a MongoDB or Redis connection
+pub(crate) fn not_sql() -> AppError {
+    AppError::new(
+        "Schema introspection is only available for SQL connections",
+        ErrorCategory::Validation(ValidationSubcategory::InvalidType),
+        ErrorSeverity::Error,
+    )
+}
+
+impl TableInfo {
+    fn new(name: String, kind: TableKind, comment: Option<String>) -> Self {
+        Self {
+            name,
+            kind,
+            comment,
+            columns: Vec::new(),
+            indexes: Vec::new(),
+            foreign_keys: Vec::new(),
+            constraints: Vec::new(),
+            triggers: Vec::new(),
+        }
+    }
+
+    /// Flag the columns named by the primary key constraint
+    fn mark_primary_key(&mut self) {
+        let Some(primary_key) = self
+            .constraints
+            .iter()
+            .find(|constraint| constraint.kind == ConstraintKind::PrimaryKey)
+        else {
+            return;
+        };
+        for column in &mut self.columns {
+            column.primary_key = primary_key.columns.contains(&column.name);
+        }
+    }
+}
+
+/// Collects the tables of a database as the catalog queries return them
+#[derive(Debug, Default)]
+struct SchemaTree {
+    schemas: BTreeMap<String, BTreeMap<String, TableInfo>>,
+}
+
+impl SchemaTree {
+    /// Make sure a schema is listed even if it holds no tables
+    fn add_schema(&mut self, schema: String) {
+        self.schemas.entry(schema).or_default();
+    }
+
+    fn add_table(&mut self, schema: String, table: TableInfo) {
+        self.schemas
+            .entry(schema)
+            .or_default()
+            .insert(table.name.clone(), table);
+    }
+
+    /// A table added earlier; rows for objects not listed as tables are ignored
+    fn table_mut(&mut self, schema: &str, table: &str) -> Option<&mut TableInfo> {
+        self.schemas.get_mut(schema)?.get_mut(table)
+    }
+
+    fn into_schemas(self) -> Vec<SchemaInfo> {
+        self.schemas
+            .into_iter()
+            .map(|(name, tables)| SchemaInfo {
+                name,
+                tables: tables
+                    .into_values()
+                    .map(|mut table| {
+                        table.columns.sort_by_key(|column| column.ordinal);
+                        table.mark_primary_key();
+                        table
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,338 @@
+//! SQL Server catalog, read from the `sys` catalog views.
+
+use tiberius::Row;
+
+use crate::error::AppResult;
+use crate::services::database::mssql::{column, fetch_all, MssqlClient, MssqlPool};
+
+use super::{
+    Catalog, ConstraintInfo, ConstraintKind, DatabaseInfo, ForeignKeyInfo, IndexInfo, SchemaTree,
+    TableColumn, TableInfo, TableKind, TriggerInfo,
+};
+
+/// Schemas owned by the server itself, including the fixed database role schemas
+const SYSTEM_SCHEMAS: &str =
+    "s.name NOT IN ('sys', 'INFORMATION_SCHEMA', 'guest') AND s.schema_id < 16384";
+
+/// Read the catalog of the database the pool is connected to
+///
+/// Other databases on the server need a connection of their own.
+pub(crate) async fn introspect(pool: &MssqlPool) -> AppResult<Catalog> {
+    let mut conn = pool.acquire().await?;
+    let catalog = read_catalog(&mut conn).await?;
+    conn.release();
+    Ok(catalog)
+}
+
+async fn read_catalog(conn: &mut MssqlClient) -> AppResult<Catalog> {
+    let database = fetch_all(conn, "SELECT DB_NAME()").await?;
+    let database = database.first().map(|row| text(row, 0)).transpose()?.unwrap_or_default();
+    let mut tree = SchemaTree::default();
+
+    let schemas = fetch_all(
+        conn,
+        &format!("SELECT s.name FROM sys.schemas s WHERE {} ORDER BY 1", SYSTEM_SCHEMAS),
+    )
+    .await?;
+    for row in &schemas {
+        tree.add_schema(text(row, 0)?);
+    }
+
+    let tables = fetch_all(
+        conn,
+        &format!(
+            "SELECT s.name, o.name, RTRIM(o.type), CAST(ep.value AS NVARCHAR(4000)) \
+             FROM sys.objects o \
+             JOIN sys.schemas s ON s.schema_id = o.schema_id \
+             LEFT JOIN sys.extended_properties ep \
+                    ON ep.class = 1 AND ep.major_id = o.object_id AND ep.minor_id = 0 \
+                   AND ep.name = 'MS_Description' \
+             WHERE o.type IN ('U', 'V') AND o.is_ms_shipped = 0 AND {}",
+            SYSTEM_SCHEMAS
+        ),
+    )
+    .await?;
+    for row in &tables {
+        let kind = if text(row, 2)? == "V" {
+            TableKind::View
+        } else {
+            TableKind::Table
+        };
+        tree.add_table(text(row, 0)?, TableInfo::new(text(row, 1)?, kind, optional_text(row, 3)?));
+    }
+
+    let columns = fetch_all(
+        conn,
+        &format!(
+            "SELECT s.name, o.name, c.name, c.column_id, t.name, CAST(c.max_length AS INT), \
+                    CAST(c.precision AS INT), CAST(c.scale AS INT), c.is_nullable, \
+                    OBJECT_DEFINITION(c.default_object_id), CAST(ep.value AS NVARCHAR(4000)) \
+             FROM sys.columns c \
+             JOIN sys.objects o ON o.object_id = c.object_id \
+             JOIN sys.schemas s ON s.schema_id = o.schema_id \
+             JOIN sys.types t ON t.user_type_id = c.user_type_id \
+             LEFT JOIN sys.extended_properties ep \
+                    ON ep.class = 1 AND ep.major_id = c.object_id AND ep.minor_id = c.column_id \
+                   AND ep.name = 'MS_Description' \
+             WHERE o.type IN ('U', 'V') AND o.is_ms_shipped = 0 AND {}",
+            SYSTEM_SCHEMAS
+        ),
+    )
+    .await?;
+    for row in &columns {
+        let Some(table) = tree.table_mut(&text(row, 0)?, &text(row, 1)?) else {
+            continue;
+        };
+        table.columns.push(TableColumn {
+            name: text(row, 2)?,
+            ordinal: number(row, 3)?.into(),
+            data_type: format_type(&text(row, 4)?, number(row, 5)?, number(row, 6)?, number(row, 7)?),
+            base_type: None,
+            nullable: flag(row, 8)?,
+            default: optional_text(row, 9)?,
+            primary_key: false,
+            comment: optional_text(row, 10)?,
+        });
+    }
+
+    // One row per key column, in key order; included columns are not part of the key
+    let indexes = fetch_all(
+        conn,
+        &format!(
+            "SELECT s.name, o.name, i.name, i.is_unique, i.is_primary_key, c.name \
+             FROM sys.indexes i \
+             JOIN sys.objects o ON o.object_id = i.object_id \
+             JOIN sys.schemas s ON s.schema_id = o.schema_id \
+             JOIN sys.index_columns ic \
+               ON ic.object_id = i.object_id AND ic.index_id = i.index_id AND ic.is_included_column = 0 \
+             JOIN sys.columns c ON c.object_id = ic.object_id AND c.column_id = ic.column_id \
+             WHERE i.type > 0 AND i.is_hypothetical = 0 AND o.is_ms_shipped = 0 AND {} \
+             ORDER BY s.name, o.name, i.name, ic.key_ordinal",
+            SYSTEM_SCHEMAS
+        ),
+    )
+    .await?;
+    for row in &indexes {
+        let Some(table) = tree.table_mut(&text(row, 0)?, &text(row, 1)?) else {
+            continue;
+        };
+        let name = text(row, 2)?;
+        if table.indexes.last().map(|index| &index.name) != Some(&name) {
+            table.indexes.push(IndexInfo {
+                name,
+                columns: Vec::new(),
+                unique: flag(row, 3)?,
+                primary: flag(row, 4)?,
+            });
+        }
+        if let Some(index) = table.indexes.last_mut() {
+            index.columns.push(text(row, 5)?);
+        }
+    }
+
+    // Primary key and unique constraints are backed by an index holding their columns
+    let keys = fetch_all(
+        conn,
+        &format!(
+            "SELECT s.name, o.name, kc.name, RTRIM(kc.type), c.name \
+             FROM sys.key_constraints kc \
+             JOIN sys.objects o ON o.object_id = kc.parent_object_id \
+             JOIN sys.schemas s ON s.schema_id = o.schema_id \
+             JOIN sys.index_columns ic \
+               ON ic.object_id = kc.parent_object_id AND ic.index_id = kc.unique_index_id \
+             JOIN sys.columns c ON c.object_id = ic.object_id AND c.column_id = ic.column_id \
+             WHERE {} \
+             ORDER BY s.name, o.name, kc.name, ic.key_ordinal",
+            SYSTEM_SCHEMAS
+        ),
+    )
+    .await?;
+    for row in &keys {
+        let Some(table) = tree.table_mut(&text(row, 0)?, &text(row, 1)?) else {
+            continue;
+        };
+        let name = text(row, 2)?;
+        let kind = if text(row, 3)? == "PK" {
+            ConstraintKind::PrimaryKey
+        } else {
+            ConstraintKind::Unique
+        };
+        if table.constraints.last().map(|constraint| &constraint.name) != Some(&name) {
+            table.constraints.push(ConstraintInfo {
+                name,
+                kind,
+                columns: Vec::new(),
+                definition: None,
+            });
+        }
+        if let Some(constraint) = table.constraints.last_mut() {
+            constraint.columns.push(text(row, 4)?);
+        }
+    }
+
+    // Table-level checks have no parent column
+    let checks = fetch_all(
+        conn,
+        &format!(
+            "SELECT s.name, o.name, cc.name, cc.definition, c.name \
+             FROM sys.check_constraints cc \
+             JOIN sys.objects o ON o.object_id = cc.parent_object_id \
+             JOIN sys.schemas s ON s.schema_id = o.schema_id \
+             LEFT JOIN sys.columns c \
+                    ON c.object_id = cc.parent_object_id AND c.column_id = cc.parent_column_id \
+             WHERE {}",
+            SYSTEM_SCHEMAS
+        ),
+    )
+    .await?;
+    for row in &checks {
+        if let Some(table) = tree.table_mut(&text(row, 0)?, &text(row, 1)?) {
+            table.constraints.push(ConstraintInfo {
+                name: text(row, 2)?,
+                kind: ConstraintKind::Check,
+                columns: optional_text(row, 4)?.into_iter().collect(),
+                definition: Some(format!("CHECK {}", text(row, 3)?)),
+            });
+        }
+    }
+
+    // One row per column pair, in key order
+    let foreign_keys = fetch_all(
+        conn,
+        &format!(
+            "SELECT s.name, o.name, fk.name, pc.name, rs.name, ro.name, rc.name, \
+                    fk.update_referential_action_desc, fk.delete_referential_action_desc \
+             FROM sys.foreign_keys fk \
+             JOIN sys.foreign_key_columns fkc ON fkc.constraint_object_id = fk.object_id \
+             JOIN sys.objects o ON o.object_id = fk.parent_object_id \
+             JOIN sys.schemas s ON s.schema_id = o.schema_id \
+             JOIN sys.columns pc \
+               ON pc.object_id = fkc.parent_object_id AND pc.column_id = fkc.parent_column_id \
+             JOIN sys.objects ro ON ro.object_id = fk.referenced_object_id \
+             JOIN sys.schemas rs ON rs.schema_id = ro.schema_id \
+             JOIN sys.columns rc \
+               ON rc.object_id = fkc.referenced_object_id AND rc.column_id = fkc.referenced_column_id \
+             WHERE {} \
+             ORDER BY s.name, o.name, fk.name, fkc.constraint_column_id",
+            SYSTEM_SCHEMAS
+        ),
+    )
+    .await?;
+    for row in &foreign_keys {
+        let Some(table) = tree.table_mut(&text(row, 0)?, &text(row, 1)?) else {
+            continue;
+        };
+        let name = text(row, 2)?;
+        if table.foreign_keys.last().map(|key| &key.name) != Some(&name) {
+            table.foreign_keys.push(ForeignKeyInfo {
+                name,
+                columns: Vec::new(),
+                referenced_schema: text(row, 4)?,
+                referenced_table: text(row, 5)?,
+                referenced_columns: Vec::new(),
+                on_update: referential_action(&text(row, 7)?),
+                on_delete: referential_action(&text(row, 8)?),
+            });
+        }
+        if let Some(key) = table.foreign_keys.last_mut() {
+            key.columns.push(text(row, 3)?);
+            key.referenced_columns.push(text(row, 6)?);
+        }
+    }
+
+    // One row per triggering event
+    let triggers = fetch_all(
+        conn,
+        &format!(
+            "SELECT s.name, o.name, tr.name, \
+                    CASE WHEN tr.is_instead_of_trigger = 1 THEN 'INSTEAD OF' ELSE 'AFTER' END, \
+                    te.type_desc, OBJECT_DEFINITION(tr.object_id) \
+             FROM sys.triggers tr \
+             JOIN sys.trigger_events te ON te.object_id = tr.object_id \
+             JOIN sys.objects o ON o.object_id = tr.parent_id \
+             JOIN sys.schemas s ON s.schema_id = o.schema_id \
+             WHERE tr.parent_class = 1 AND {} \
+             ORDER BY tr.name, te.type",
+            SYSTEM_SCHEMAS
+        ),
+    )
+    .await?;
+    for row in &triggers {
+        let Some(table) = tree.table_mut(&text(row, 0)?, &text(row, 1)?) else {
+            continue;
+        };
+        let name = text(row, 2)?;
+        if table.triggers.last().map(|trigger| &trigger.name) != Some(&name) {
+            table.triggers.push(TriggerInfo {
+                name,
+                timing: text(row, 3)?,
+                events: Vec::new(),
+                // NULL for encrypted triggers
+                definition: optional_text(row, 5)?,
+            });
+        }
+        if let Some(trigger) = table.triggers.last_mut() {
+            trigger.events.push(text(row, 4)?);
+        }
+    }
+
+    Ok(Catalog {
+        databases: vec![DatabaseInfo {
+            name: database,
+            schemas: tree.into_schemas(),
+        }],
+    })
+}
+
+/// Spell out a column type with its length or precision, as `sp_help` would
+fn format_type(name: &str, max_length: i32, precision: i32, scale: i32) -> String {
+    let length = |bytes_per_char: i32| {
+        if max_length == -1 {
+            "max".to_string()
+        } else {
+            (max_length / bytes_per_char).to_string()
+        }
+    };
+    match name {
+        "varchar" | "char" | "varbinary" | "binary" => format!("{}({})", name, length(1)),
+        "nvarchar" | "nchar" => format!("{}({})", name, length(2)),
+        "decimal" | "numeric" => format!("{}({}, {})", name, precision, scale),
+        "datetime2" | "time" | "datetimeoffset" => format!("{}({})", name, scale),
+        _ => name.to_string(),
+    }
+}
+
+/// Spell out a `*_referential_action_desc` value, e.g. `SET_NULL`
+fn referential_action(desc: &str) -> String {
+    desc.replace('_', " ")
+}
+
+fn text(row: &Row, index: usize) -> AppResult<String> {
+    Ok(optional_text(row, index)?.unwrap_or_default())
+}
+
+fn optional_text(row: &Row, index: usize) -> AppResult<Option<String>> {
+    Ok(column::<&str>(row, index)?.map(str::to_string))
+}
+
+fn number(row: &Row, index: usize) -> AppResult<i32> {
+    Ok(column::<i32>(row, index)?.unwrap_or_default())
+}
+
+fn flag(row: &Row, index: usize) -> AppResult<bool> {
+    Ok(column::<bool>(row, index)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_type_spells_out_lengths() {
+        assert_eq!(format_type("nvarchar", 100, 0, 0), "nvarchar(50)");
+        assert_eq!(format_type("varbinary", -1, 0, 0), "varbinary(max)");
+        assert_eq!(format_type("decimal", 9, 18, 2), "decimal(18, 2)");
+        assert_eq!(format_type("datetime2", 8, 27, 7), "datetime2(7)");
+        assert_eq!(format_type("int", 4, 10, 0), "int");
+    }
+}
@@ -0,0 +1,414 @@
+//! MySQL catalog, read from `information_schema`.
+
+use sqlx::MySqlPool;
+use std::collections::BTreeMap;
+use tracing::debug;
+
+use crate::error::AppResult;
+use crate::services::database::query::query_error;
+
+use super::{
+    Catalog, ConstraintInfo, ConstraintKind, DatabaseInfo, ForeignKeyInfo, IndexInfo, SchemaTree,
+    TableColumn, TableInfo, TableKind, TriggerInfo,
+};
+
+/// Databases owned by the server itself
+const SYSTEM_DATABASES: &str = "('information_schema', 'mysql', 'performance_schema', 'sys')";
+
+type ColumnRow = (String, String, String, i64, String, String, Option<String>, String);
+type IndexRow = (String, String, String, i64, Option<String>);
+type KeyRow = (
+    String,
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+type TriggerRow = (String, String, String, String, String, String);
+
+/// Read the catalog of every user database on the server
+///
+/// Each database is reported with a single schema of the same name.
+pub(crate) async fn introspect(pool: &MySqlPool) -> AppResult<Catalog> {
+    read(pool, None).await
+}
+
+/// Read one table or view, querying only its catalog rows
+pub(crate) async fn table(pool: &MySqlPool, schema: &str, name: &str) -> AppResult<Option<TableInfo>> {
+    let catalog = read(pool, Some((schema, name))).await?;
+    Ok(catalog.table(schema, name).cloned())
+}
+
+/// Limits a catalog query to one table when its schema is bound twice, then its name
+///
+/// All three parameters bound as `NULL` select every table.
+fn in_scope(schema_column: &str, table_column: &str) -> String {
+    format!("(? IS NULL OR ({} = ? AND {} = ?))", schema_column, table_column)
+}
+
+/// Read the catalog, or only the rows of the table `scope` names
+async fn read(pool: &MySqlPool, scope: Option<(&str, &str)>) -> AppResult<Catalog> {
+    let mut trees: BTreeMap<String, SchemaTree> = BTreeMap::new();
+    let (scope_schema, scope_table) = scope.unzip();
+
+    if scope.is_none() {
+        let databases: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT SCHEMA_NAME FROM information_schema.SCHEMATA WHERE SCHEMA_NAME NOT IN {}",
+            SYSTEM_DATABASES
+        ))
+        .fetch_all(pool)
+        .await
+        .map_err(query_error)?;
+        for database in databases {
+            trees.entry(database.clone()).or_default().add_schema(database);
+        }
+    }
+
+    let tables: Vec<(String, String, String, String)> = sqlx::query_as(&format!(
+        "SELECT TABLE_SCHEMA, TABLE_NAME, TABLE_TYPE, TABLE_COMMENT \
+         FROM information_schema.TABLES WHERE TABLE_SCHEMA NOT IN {} AND {}",
+        SYSTEM_DATABASES,
+        in_scope("TABLE_SCHEMA", "TABLE_NAME")
+    ))
+    .bind(scope_schema)
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    for (schema, name, table_type, comment) in tables {
+        let kind = table_kind(&table_type);
+        let comment = Some(comment).filter(|comment| !comment.is_empty());
+        trees
+            .entry(schema.clone())
+            .or_default()
+            .add_table(schema, TableInfo::new(name, kind, comment));
+    }
+
+    let columns: Vec<ColumnRow> = sqlx::query_as(&format!(
+        "SELECT TABLE_SCHEMA, TABLE_NAME, COLUMN_NAME, CAST(ORDINAL_POSITION AS SIGNED), \
+                COLUMN_TYPE, IS_NULLABLE, COLUMN_DEFAULT, COLUMN_COMMENT \
+         FROM information_schema.COLUMNS WHERE TABLE_SCHEMA NOT IN {} AND {}",
+        SYSTEM_DATABASES,
+        in_scope("TABLE_SCHEMA", "TABLE_NAME")
+    ))
+    .bind(scope_schema)
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    for (schema, table, name, ordinal, data_type, is_nullable, default, comment) in columns {
+        if let Some(table) = table_mut(&mut trees, &schema, &table) {
+            table.columns.push(TableColumn {
+                name,
+                ordinal,
+                data_type,
+                base_type: None,
+                nullable: is_nullable == "YES",
+                default,
+                primary_key: false,
+                comment: Some(comment).filter(|comment| !comment.is_empty()),
+            });
+        }
+    }
+
+    // One row per indexed column, in key order
+    let indexes: Vec<IndexRow> = sqlx::query_as(&format!(
+        "SELECT TABLE_SCHEMA, TABLE_NAME, INDEX_NAME, CAST(NON_UNIQUE AS SIGNED), COLUMN_NAME \
+         FROM information_schema.STATISTICS WHERE TABLE_SCHEMA NOT IN {} AND {} \
+         ORDER BY TABLE_SCHEMA, TABLE_NAME, INDEX_NAME, SEQ_IN_INDEX",
+        SYSTEM_DATABASES,
+        in_scope("TABLE_SCHEMA", "TABLE_NAME")
+    ))
+    .bind(scope_schema)
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    for row in indexes {
+        if let Some(table) = table_mut(&mut trees, &row.0, &row.1) {
+            add_index_column(table, row);
+        }
+    }
+
+    // One row per constrained column, in key order; CHECK constraints have no key columns
+    let keys: Vec<KeyRow> = sqlx::query_as(&format!(
+        "SELECT tc.TABLE_SCHEMA, tc.TABLE_NAME, tc.CONSTRAINT_NAME, tc.CONSTRAINT_TYPE, \
+                k.COLUMN_NAME, k.REFERENCED_TABLE_SCHEMA, k.REFERENCED_TABLE_NAME, k.REFERENCED_COLUMN_NAME, \
+                rc.UPDATE_RULE, rc.DELETE_RULE \
+         FROM information_schema.TABLE_CONSTRAINTS tc \
+         LEFT JOIN information_schema.KEY_COLUMN_USAGE k \
+                ON k.CONSTRAINT_SCHEMA = tc.CONSTRAINT_SCHEMA AND k.TABLE_NAME = tc.TABLE_NAME \
+               AND k.CONSTRAINT_NAME = tc.CONSTRAINT_NAME \
+         LEFT JOIN information_schema.REFERENTIAL_CONSTRAINTS rc \
+                ON rc.CONSTRAINT_SCHEMA = tc.CONSTRAINT_SCHEMA AND rc.TABLE_NAME = tc.TABLE_NAME \
+               AND rc.CONSTRAINT_NAME = tc.CONSTRAINT_NAME \
+         WHERE tc.TABLE_SCHEMA NOT IN {} AND {} \
+         ORDER BY tc.TABLE_SCHEMA, tc.TABLE_NAME, tc.CONSTRAINT_NAME, k.ORDINAL_POSITION",
+        SYSTEM_DATABASES,
+        in_scope("tc.TABLE_SCHEMA", "tc.TABLE_NAME")
+    ))
+    .bind(scope_schema)
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    for row in keys {
+        if let Some(table) = table_mut(&mut trees, &row.0, &row.1) {
+            add_key_column(table, row);
+        }
+    }
+
+    // CHECK_CONSTRAINTS only exists from MySQL 8.0.16
+    let checks: Vec<(String, String, String, String)> = sqlx::query_as(&format!(
+        "SELECT tc.TABLE_SCHEMA, tc.TABLE_NAME, cc.CONSTRAINT_NAME, cc.CHECK_CLAUSE \
+         FROM information_schema.CHECK_CONSTRAINTS cc \
+         JOIN information_schema.TABLE_CONSTRAINTS tc \
+           ON tc.CONSTRAINT_SCHEMA = cc.CONSTRAINT_SCHEMA AND tc.CONSTRAINT_NAME = cc.CONSTRAINT_NAME \
+          AND tc.CONSTRAINT_TYPE = 'CHECK' \
+         WHERE tc.TABLE_SCHEMA NOT IN {} AND {}",
+        SYSTEM_DATABASES,
+        in_scope("tc.TABLE_SCHEMA", "tc.TABLE_NAME")
+    ))
+    .bind(scope_schema)
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_else(|e| {
+        debug!("Check constraints unavailable: {}", e);
+        Vec::new()
+    });
+    for (schema, table, name, clause) in checks {
+        let Some(table) = table_mut(&mut trees, &schema, &table) else {
+            continue;
+        };
+        if let Some(check) = table
+            .constraints
+            .iter_mut()
+            .find(|constraint| constraint.kind == ConstraintKind::Check && constraint.name == name)
+        {
+            check.definition = Some(format!("CHECK ({})", clause));
+        }
+    }
+
+    let triggers: Vec<TriggerRow> = sqlx::query_as(&format!(
+        "SELECT EVENT_OBJECT_SCHEMA, EVENT_OBJECT_TABLE, TRIGGER_NAME, ACTION_TIMING, \
+                EVENT_MANIPULATION, ACTION_STATEMENT \
+         FROM information_schema.TRIGGERS WHERE EVENT_OBJECT_SCHEMA NOT IN {} AND {} \
+         ORDER BY TRIGGER_NAME",
+        SYSTEM_DATABASES,
+        in_scope("EVENT_OBJECT_SCHEMA", "EVENT_OBJECT_TABLE")
+    ))
+    .bind(scope_schema)
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    for (schema, table, name, timing, event, statement) in triggers {
+        if let Some(table) = table_mut(&mut trees, &schema, &table) {
+            table.triggers.push(TriggerInfo {
+                name,
+                timing,
+                events: vec![event],
+                definition: Some(statement),
+            });
+        }
+    }
+
+    Ok(Catalog {
+        databases: trees
+            .into_iter()
+            .map(|(name, tree)| DatabaseInfo {
+                name,
+                schemas: tree.into_schemas(),
+            })
+            .collect(),
+    })
+}
+
+/// The kind of a table from its `TABLE_TYPE`: `BASE TABLE`, `VIEW` or `SYSTEM VIEW`
+fn table_kind(table_type: &str) -> TableKind {
+    if table_type.contains("VIEW") {
+        TableKind::View
+    } else {
+        TableKind::Table
+    }
+}
+
+/// Add a `STATISTICS` row to its index, starting the index at its first column
+fn add_index_column(table: &mut TableInfo, row: IndexRow) {
+    let (_, _, name, non_unique, column) = row;
+    if table.indexes.last().map(|index| &index.name) != Some(&name) {
+        table.indexes.push(IndexInfo {
+            primary: name == "PRIMARY",
+            name,
+            columns: Vec::new(),
+            unique: non_unique == 0,
+        });
+    }
+    if let (Some(index), Some(column)) = (table.indexes.last_mut(), column) {
+        index.columns.push(column);
+    }
+}
+
+/// Add a constrained column to its foreign key or constraint, starting it at its first column
+fn add_key_column(table: &mut TableInfo, row: KeyRow) {
+    let (
+        _,
+        _,
+        name,
+        constraint_type,
+        column,
+        referenced_schema,
+        referenced_table,
+        referenced_column,
+        on_update,
+        on_delete,
+    ) = row;
+    if constraint_type == "FOREIGN KEY" {
+        if table.foreign_keys.last().map(|key| &key.name) != Some(&name) {
+            table.foreign_keys.push(ForeignKeyInfo {
+                name,
+                columns: Vec::new(),
+                referenced_schema: referenced_schema.unwrap_or_default(),
+                referenced_table: referenced_table.unwrap_or_default(),
+                referenced_columns: Vec::new(),
+                on_update: on_update.unwrap_or_else(|| "NO ACTION".to_string()),
+                on_delete: on_delete.unwrap_or_else(|| "NO ACTION".to_string()),
+            });
+        }
+        if let Some(key) = table.foreign_keys.last_mut() {
+            key.columns.extend(column);
+            key.referenced_columns.extend(referenced_column);
+        }
+        return;
+    }
+
+    let kind = match constraint_type.as_str() {
+        "PRIMARY KEY" => ConstraintKind::PrimaryKey,
+        "UNIQUE" => ConstraintKind::Unique,
+        "CHECK" => ConstraintKind::Check,
+        _ => return,
+    };
+    if table.constraints.last().map(|constraint| &constraint.name) != Some(&name) {
+        table.constraints.push(ConstraintInfo {
+            name,
+            kind,
+            columns: Vec::new(),
+            definition: None,
+        });
+    }
+    if let (Some(constraint), Some(column)) = (table.constraints.last_mut(), column) {
+        constraint.columns.push(column);
+    }
+}
+
+fn table_mut<'a>(
+    trees: &'a mut BTreeMap<String, SchemaTree>,
+    schema: &str,
+    table: &str,
+) -> Option<&'a mut TableInfo> {
+    trees.get_mut(schema)?.table_mut(schema, table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_row(name: &str, non_unique: i64, column: Option<&str>) -> IndexRow {
+        ("shop".to_string(), "orders".to_string(), name.to_string(), non_unique, column.map(str::to_string))
+    }
+
+    fn key_row(name: &str, constraint_type: &str, column: Option<&str>, referenced: Option<&str>) -> KeyRow {
+        let foreign = constraint_type == "FOREIGN KEY";
+        (
+            "shop".to_string(),
+            "orders".to_string(),
+            name.to_string(),
+            constraint_type.to_string(),
+            column.map(str::to_string),
+            foreign.then(|| "crm".to_string()),
+            foreign.then(|| "customers".to_string()),
+            referenced.map(str::to_string),
+            foreign.then(|| "CASCADE".to_string()),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_table_types_map_to_table_kinds() {
+        assert_eq!(table_kind("BASE TABLE"), TableKind::Table);
+        assert_eq!(table_kind("VIEW"), TableKind::View);
+        assert_eq!(table_kind("SYSTEM VIEW"), TableKind::View);
+    }
+
+    #[test]
+    fn test_index_columns_are_grouped_in_order() {
+        let mut table = TableInfo::new("orders".to_string(), TableKind::Table, None);
+        for row in [
+            index_row("PRIMARY", 0, Some("tenant")),
+            index_row("PRIMARY", 0, Some("id")),
+            index_row("by_expression", 1, None),
+            index_row("number", 0, Some("number")),
+        ] {
+            add_index_column(&mut table, row);
+        }
+
+        let indexes: Vec<(&str, bool, bool, usize)> = table
+            .indexes
+            .iter()
+            .map(|index| (index.name.as_str(), index.primary, index.unique, index.columns.len()))
+            .collect();
+        assert_eq!(
+            indexes,
+            [("PRIMARY", true, true, 2), ("by_expression", false, false, 0), ("number", false, true, 1)]
+        );
+        assert_eq!(table.indexes[0].columns, ["tenant", "id"]);
+    }
+
+    #[test]
+    fn test_key_columns_are_grouped_by_constraint() {
+        let mut table = TableInfo::new("orders".to_string(), TableKind::Table, None);
+        for row in [
+            key_row("PRIMARY", "PRIMARY KEY", Some("tenant"), None),
+            key_row("PRIMARY", "PRIMARY KEY", Some("id"), None),
+            key_row("orders_customer", "FOREIGN KEY", Some("tenant"), Some("tenant")),
+            key_row("orders_customer", "FOREIGN KEY", Some("customer_id"), Some("id")),
+            key_row("total_positive", "CHECK", None, None),
+            key_row("number", "UNIQUE", Some("number"), None),
+        ] {
+            add_key_column(&mut table, row);
+        }
+
+        let constraints: Vec<(&str, ConstraintKind, usize)> = table
+            .constraints
+            .iter()
+            .map(|constraint| (constraint.name.as_str(), constraint.kind, constraint.columns.len()))
+            .collect();
+        assert_eq!(
+            constraints,
+            [
+                ("PRIMARY", ConstraintKind::PrimaryKey, 2),
+                ("total_positive", ConstraintKind::Check, 0),
+                ("number", ConstraintKind::Unique, 1),
+            ]
+        );
+
+        let key = &table.foreign_keys[0];
+        assert_eq!(table.foreign_keys.len(), 1);
+        assert_eq!(key.columns, ["tenant", "customer_id"]);
+        assert_eq!(key.referenced_columns, ["tenant", "id"]);
+        assert_eq!((key.referenced_schema.as_str(), key.referenced_table.as_str()), ("crm", "customers"));
+        assert_eq!((key.on_update.as_str(), key.on_delete.as_str()), ("CASCADE", "NO ACTION"));
+    }
+}
@@ -0,0 +1,415 @@
+//! Postgres catalog, read from `pg_catalog`.
+
+use sqlx::PgPool;
+
+use crate::error::AppResult;
+use crate::services::database::query::query_error;
+
+use super::{
+    Catalog, ConstraintInfo, ConstraintKind, DatabaseInfo, ForeignKeyInfo, IndexInfo, SchemaTree,
+    TableColumn, TableInfo, TableKind, TriggerInfo,
+};
+
+/// Schemas owned by the server itself
+const SYSTEM_SCHEMAS: &str =
+    "n.nspname NOT IN ('pg_catalog', 'information_schema') AND n.nspname NOT LIKE 'pg\\_toast%' AND n.nspname NOT LIKE 'pg\\_temp\\_%'";
+
+type ColumnRow = (String, String, String, i32, String, String, bool, Option<String>, Option<String>);
+type IndexRow = (String, String, String, bool, bool, Vec<String>);
+type ConstraintRow = (
+    String,
+    String,
+    String,
+    String,
+    Vec<String>,
+    Option<String>,
+    Option<String>,
+    Vec<String>,
+    String,
+    String,
+    String,
+);
+type TriggerRow = (String, String, String, String, Vec<String>, String);
+
+/// Read the catalog of the database the pool is connected to
+///
+/// Other databases on the server need a connection of their own.
+pub(crate) async fn introspect(pool: &PgPool) -> AppResult<Catalog> {
+    read(pool, None).await
+}
+
+/// Read one table, view or materialized view, querying only its catalog rows
+pub(crate) async fn table(pool: &PgPool, schema: &str, name: &str) -> AppResult<Option<TableInfo>> {
+    let catalog = read(pool, Some((schema, name))).await?;
+    Ok(catalog.table(schema, name).cloned())
+}
+
+/// Limits a catalog query to one table when its schema and name are bound as `$1` and `$2`
+///
+/// `relation` is the query's alias for `pg_class`; both parameters bound as `NULL` select every table.
+fn in_scope(relation: &str) -> String {
+    format!("($1::text IS NULL OR (n.nspname = $1 AND {}.relname = $2::text))", relation)
+}
+
+/// Read the catalog, or only the rows of the table `scope` names
+async fn read(pool: &PgPool, scope: Option<(&str, &str)>) -> AppResult<Catalog> {
+    let database: String = sqlx::query_scalar("SELECT current_database()::text")
+        .fetch_one(pool)
+        .await
+        .map_err(query_error)?;
+    let mut tree = SchemaTree::default();
+    let (scope_schema, scope_table) = scope.unzip();
+
+    if scope.is_none() {
+        let schemas: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT n.nspname::text FROM pg_namespace n WHERE {} ORDER BY 1",
+            SYSTEM_SCHEMAS
+        ))
+        .fetch_all(pool)
+        .await
+        .map_err(query_error)?;
+        for schema in schemas {
+            tree.add_schema(schema);
+        }
+    }
+
+    let tables: Vec<(String, String, String, Option<String>)> = sqlx::query_as(&format!(
+        "SELECT n.nspname::text, c.relname::text, c.relkind::text, obj_description(c.oid, 'pg_class') \
+         FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace \
+         WHERE c.relkind IN ('r', 'p', 'f', 'v', 'm') AND {} AND {}",
+        SYSTEM_SCHEMAS,
+        in_scope("c")
+    ))
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    for (schema, name, relkind, comment) in tables {
+        tree.add_table(schema, TableInfo::new(name, table_kind(&relkind), comment));
+    }
+
+    let columns: Vec<ColumnRow> = sqlx::query_as(&format!(
+        "SELECT n.nspname::text, c.relname::text, a.attname::text, a.attnum::int4, \
+                format_type(a.atttypid, a.atttypmod), format_type(a.atttypid, NULL), NOT a.attnotnull, \
+                pg_get_expr(d.adbin, d.adrelid), col_description(c.oid, a.attnum) \
+         FROM pg_attribute a \
+         JOIN pg_class c ON c.oid = a.attrelid \
+         JOIN pg_namespace n ON n.oid = c.relnamespace \
+         LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum \
+         WHERE a.attnum > 0 AND NOT a.attisdropped AND c.relkind IN ('r', 'p', 'f', 'v', 'm') AND {} AND {}",
+        SYSTEM_SCHEMAS,
+        in_scope("c")
+    ))
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    for (schema, table, name, ordinal, data_type, base_type, nullable, default, comment) in columns {
+        if let Some(table) = tree.table_mut(&schema, &table) {
+            table.columns.push(TableColumn {
+                name,
+                ordinal: ordinal.into(),
+                data_type,
+                base_type: Some(base_type),
+                nullable,
+                default,
+                primary_key: false,
+                comment,
+            });
+        }
+    }
+
+    let indexes: Vec<IndexRow> = sqlx::query_as(&format!(
+        "SELECT n.nspname::text, t.relname::text, i.relname::text, ix.indisunique, ix.indisprimary, \
+                ARRAY(SELECT pg_get_indexdef(ix.indexrelid, k + 1, true) \
+                      FROM generate_subscripts(ix.indkey, 1) AS k \
+                      WHERE k < ix.indnkeyatts ORDER BY k) \
+         FROM pg_index ix \
+         JOIN pg_class i ON i.oid = ix.indexrelid \
+         JOIN pg_class t ON t.oid = ix.indrelid \
+         JOIN pg_namespace n ON n.oid = t.relnamespace \
+         WHERE {} AND {} ORDER BY 3",
+        SYSTEM_SCHEMAS,
+        in_scope("t")
+    ))
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    for (schema, table, name, unique, primary, columns) in indexes {
+        if let Some(table) = tree.table_mut(&schema, &table) {
+            table.indexes.push(IndexInfo {
+                name,
+                columns,
+                unique,
+                primary,
+            });
+        }
+    }
+
+    let constraints: Vec<ConstraintRow> = sqlx::query_as(&format!(
+        "SELECT n.nspname::text, t.relname::text, con.conname::text, con.contype::text, \
+                ARRAY(SELECT a.attname::text FROM unnest(con.conkey) WITH ORDINALITY AS k(attnum, ord) \
+                      JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum ORDER BY k.ord), \
+                rn.nspname::text, rt.relname::text, \
+                ARRAY(SELECT a.attname::text FROM unnest(con.confkey) WITH ORDINALITY AS k(attnum, ord) \
+                      JOIN pg_attribute a ON a.attrelid = con.confrelid AND a.attnum = k.attnum ORDER BY k.ord), \
+                con.confupdtype::text, con.confdeltype::text, pg_get_constraintdef(con.oid, true) \
+         FROM pg_constraint con \
+         JOIN pg_class t ON t.oid = con.conrelid \
+         JOIN pg_namespace n ON n.oid = t.relnamespace \
+         LEFT JOIN pg_class rt ON rt.oid = con.confrelid \
+         LEFT JOIN pg_namespace rn ON rn.oid = rt.relnamespace \
+         WHERE con.contype IN ('p', 'u', 'c', 'x', 'f') AND {} AND {} ORDER BY 3",
+        SYSTEM_SCHEMAS,
+        in_scope("t")
+    ))
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    for row in constraints {
+        if let Some(table) = tree.table_mut(&row.0, &row.1) {
+            add_constraint(table, row);
+        }
+    }
+
+    // tgtype bits: 2 = BEFORE, 4 = INSERT, 8 = DELETE, 16 = UPDATE, 32 = TRUNCATE, 64 = INSTEAD OF
+    let triggers: Vec<TriggerRow> = sqlx::query_as(&format!(
+        "SELECT n.nspname::text, c.relname::text, tg.tgname::text, \
+                CASE WHEN tg.tgtype & 2 <> 0 THEN 'BEFORE' WHEN tg.tgtype & 64 <> 0 THEN 'INSTEAD OF' ELSE 'AFTER' END, \
+                array_remove(ARRAY[ \
+                    CASE WHEN tg.tgtype & 4 <> 0 THEN 'INSERT' END, \
+                    CASE WHEN tg.tgtype & 16 <> 0 THEN 'UPDATE' END, \
+                    CASE WHEN tg.tgtype & 8 <> 0 THEN 'DELETE' END, \
+                    CASE WHEN tg.tgtype & 32 <> 0 THEN 'TRUNCATE' END], NULL), \
+                pg_get_triggerdef(tg.oid, true) \
+         FROM pg_trigger tg \
+         JOIN pg_class c ON c.oid = tg.tgrelid \
+         JOIN pg_namespace n ON n.oid = c.relnamespace \
+         WHERE NOT tg.tgisinternal AND {} AND {} ORDER BY 3",
+        SYSTEM_SCHEMAS,
+        in_scope("c")
+    ))
+    .bind(scope_schema)
+    .bind(scope_table)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    for (schema, table, name, timing, events, definition) in triggers {
+        if let Some(table) = tree.table_mut(&schema, &table) {
+            table.triggers.push(TriggerInfo {
+                name,
+                timing,
+                events,
+                definition: Some(definition),
+            });
+        }
+    }
+
+    Ok(Catalog {
+        databases: vec![DatabaseInfo {
+            name: database,
+            schemas: tree.into_schemas(),
+        }],
+    })
+}
+
+/// The kind of a relation from its `pg_class.relkind`; partitioned and foreign tables are tables
+fn table_kind(relkind: &str) -> TableKind {
+    match relkind {
+        "v" => TableKind::View,
+        "m" => TableKind::MaterializedView,
+        _ => TableKind::Table,
+    }
+}
+
+/// File a `pg_constraint` row under the table's constraints, or its foreign keys for `contype` `f`
+fn add_constraint(table: &mut TableInfo, row: ConstraintRow) {
+    let (
+        _,
+        _,
+        name,
+        contype,
+        columns,
+        referenced_schema,
+        referenced_table,
+        referenced_columns,
+        on_update,
+        on_delete,
+        definition,
+    ) = row;
+    let kind = match contype.as_str() {
+        "p" => ConstraintKind::PrimaryKey,
+        "u" => ConstraintKind::Unique,
+        "c" => ConstraintKind::Check,
+        "x" => ConstraintKind::Exclusion,
+        _ => {
+            table.foreign_keys.push(ForeignKeyInfo {
+                name,
+                columns,
+                referenced_schema: referenced_schema.unwrap_or_default(),
+                referenced_table: referenced_table.unwrap_or_default(),
+                referenced_columns,
+                on_update: referential_action(&on_update).to_string(),
+                on_delete: referential_action(&on_delete).to_string(),
+            });
+            return;
+        }
+    };
+    table.constraints.push(ConstraintInfo {
+        name,
+        kind,
+        columns,
+        definition: Some(definition),
+    });
+}
+
+/// Spell out a `pg_constraint.confupdtype`/`confdeltype` code
+fn referential_action(code: &str) -> &'static str {
+    match code {
+        "r" => "RESTRICT",
+        "c" => "CASCADE",
+        "n" => "SET NULL",
+        "d" => "SET DEFAULT",
+        _ => "NO ACTION",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constraint(name: &str, contype: &str, columns: &[&str]) -> ConstraintRow {
+        (
+            "public".to_string(),
+            "orders".to_string(),
+            name.to_string(),
+            contype.to_string(),
+            columns.iter().map(|column| column.to_string()).collect(),
+            None,
+            None,
+            Vec::new(),
+            "a".to_string(),
+            "a".to_string(),
+            format!("definition of {}", name),
+        )
+    }
+
+    fn column(name: &str, ordinal: i64) -> TableColumn {
+        TableColumn {
+            name: name.to_string(),
+            ordinal,
+            data_type: "integer".to_string(),
+            base_type: Some("integer".to_string()),
+            nullable: false,
+            default: None,
+            primary_key: false,
+            comment: None,
+        }
+    }
+
+    #[test]
+    fn test_relkinds_map_to_table_kinds() {
+        assert_eq!(table_kind("r"), TableKind::Table);
+        assert_eq!(table_kind("p"), TableKind::Table);
+        assert_eq!(table_kind("f"), TableKind::Table);
+        assert_eq!(table_kind("v"), TableKind::View);
+        assert_eq!(table_kind("m"), TableKind::MaterializedView);
+    }
+
+    #[test]
+    fn test_constraint_rows_are_filed_by_contype() {
+        let mut table = TableInfo::new("orders".to_string(), TableKind::Table, None);
+        for row in [
+            constraint("orders_pkey", "p", &["tenant", "id"]),
+            constraint("orders_number_key", "u", &["number"]),
+            constraint("orders_total_check", "c", &["total"]),
+            constraint("orders_during_excl", "x", &["during"]),
+        ] {
+            add_constraint(&mut table, row);
+        }
+        let mut key = constraint("orders_customer_fkey", "f", &["tenant", "customer_id"]);
+        key.5 = Some("sales".to_string());
+        key.6 = Some("customers".to_string());
+        key.7 = vec!["tenant".to_string(), "id".to_string()];
+        key.8 = "c".to_string();
+        key.9 = "n".to_string();
+        add_constraint(&mut table, key);
+
+        let kinds: Vec<ConstraintKind> = table.constraints.iter().map(|constraint| constraint.kind).collect();
+        assert_eq!(
+            kinds,
+            [ConstraintKind::PrimaryKey, ConstraintKind::Unique, ConstraintKind::Check, ConstraintKind::Exclusion]
+        );
+        assert_eq!(table.constraints[0].columns, ["tenant", "id"]);
+        assert_eq!(table.constraints[2].definition.as_deref(), Some("definition of orders_total_check"));
+
+        let key = &table.foreign_keys[0];
+        assert_eq!(key.name, "orders_customer_fkey");
+        assert_eq!((key.referenced_schema.as_str(), key.referenced_table.as_str()), ("sales", "customers"));
+        assert_eq!(key.columns, ["tenant", "customer_id"]);
+        assert_eq!(key.referenced_columns, ["tenant", "id"]);
+        assert_eq!((key.on_update.as_str(), key.on_delete.as_str()), ("CASCADE", "SET NULL"));
+    }
+
+    #[test]
+    fn test_primary_key_columns_are_flagged() {
+        let mut tree = SchemaTree::default();
+        let mut table = TableInfo::new("orders".to_string(), TableKind::Table, None);
+        table.columns = vec![column("total", 3), column("id", 2), column("tenant", 1)];
+        add_constraint(&mut table, constraint("orders_pkey", "p", &["tenant", "id"]));
+        tree.add_table("public".to_string(), table);
+
+        let schemas = tree.into_schemas();
+        let columns: Vec<(&str, bool)> = schemas[0].tables[0]
+            .columns
+            .iter()
+            .map(|column| (column.name.as_str(), column.primary_key))
+            .collect();
+        assert_eq!(columns, [("tenant", true), ("id", true), ("total", false)]);
+    }
+
+    /// Set `DEWEY_TEST_POSTGRES` to a connection URL, e.g. `postgres://postgres@127.0.0.1/postgres`
+    #[tokio::test]
+    #[ignore = "needs a Postgres server, see DEWEY_TEST_POSTGRES"]
+    async fn test_introspects_a_live_server() {
+        let url = std::env::var("DEWEY_TEST_POSTGRES").expect("DEWEY_TEST_POSTGRES is not set");
+        let pool = PgPool::connect(&url).await.unwrap();
+        for statement in [
+            "DROP SCHEMA IF EXISTS dewey_introspection CASCADE",
+            "CREATE SCHEMA dewey_introspection",
+            "CREATE TABLE dewey_introspection.customers (id int PRIMARY KEY, email varchar(80) UNIQUE)",
+            "CREATE TABLE dewey_introspection.orders (id int PRIMARY KEY, \
+             customer_id int REFERENCES dewey_introspection.customers ON DELETE CASCADE, \
+             total numeric(10, 2) CHECK (total >= 0))",
+            "CREATE VIEW dewey_introspection.totals AS SELECT total FROM dewey_introspection.orders",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        let catalog = introspect(&pool).await;
+        let customers = table(&pool, "dewey_introspection", "customers").await;
+        sqlx::query("DROP SCHEMA dewey_introspection CASCADE").execute(&pool).await.unwrap();
+        let catalog = catalog.unwrap();
+        let customers = customers.unwrap().unwrap();
+
+        let orders = catalog.table("dewey_introspection", "orders").unwrap();
+        assert!(orders.columns[0].primary_key);
+        assert_eq!(orders.columns[2].data_type, "numeric(10,2)");
+        assert_eq!(orders.foreign_keys[0].referenced_table, "customers");
+        assert_eq!(orders.foreign_keys[0].on_delete, "CASCADE");
+        assert!(orders.constraints.iter().any(|constraint| constraint.kind == ConstraintKind::Check));
+        assert!(orders.indexes.iter().any(|index| index.primary && index.columns == ["id"]));
+        assert_eq!(catalog.table("dewey_introspection", "totals").unwrap().kind, TableKind::View);
+
+        assert!(customers.columns[0].primary_key);
+        assert_eq!(customers.columns[1].data_type, "character varying(80)");
+        assert_eq!(customers.columns[1].base_type.as_deref(), Some("character varying"));
+        assert!(customers.constraints.iter().any(|constraint| constraint.kind == ConstraintKind::Unique));
+    }
+}
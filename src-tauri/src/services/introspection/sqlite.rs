@@ -0,0 +1,281 @@
+//! SQLite catalog, read from `sqlite_master` and the table-valued pragmas.
+
+use sqlx::SqlitePool;
+
+use crate::error::AppResult;
+use crate::services::database::query::query_error;
+
+use super::{
+    Catalog, ConstraintInfo, ConstraintKind, DatabaseInfo, ForeignKeyInfo, IndexInfo, SchemaTree,
+    TableColumn, TableInfo, TableKind, TriggerInfo,
+};
+
+type ColumnRow = (i64, String, String, bool, Option<String>, i64);
+type ForeignKeyRow = (i64, String, String, Option<String>, String, String);
+
+/// Read the catalog of the main database and any attached ones
+///
+/// SQLite does not catalog CHECK constraints, so none are reported.
+pub(crate) async fn introspect(pool: &SqlitePool) -> AppResult<Catalog> {
+    read(pool, None).await
+}
+
+/// Read one table or view of a database, leaving the others undescribed
+pub(crate) async fn table(pool: &SqlitePool, schema: &str, name: &str) -> AppResult<Option<TableInfo>> {
+    let catalog = read(pool, Some((schema, name))).await?;
+    Ok(catalog.table(schema, name).cloned())
+}
+
+/// Read the catalog, or only the table `scope` names and its triggers
+async fn read(pool: &SqlitePool, scope: Option<(&str, &str)>) -> AppResult<Catalog> {
+    let (scope_schema, scope_table) = scope.unzip();
+    let databases: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM pragma_database_list WHERE name <> 'temp' AND (?1 IS NULL OR name = ?1) ORDER BY seq",
+    )
+    .bind(scope_schema)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+
+    let mut catalog = Catalog::default();
+    for database in databases {
+        let mut tree = SchemaTree::default();
+        tree.add_schema(database.clone());
+
+        let objects: Vec<(String, String, String, Option<String>)> = sqlx::query_as(&format!(
+            "SELECT type, name, tbl_name, sql FROM {}.sqlite_master \
+             WHERE type IN ('table', 'view', 'trigger') AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+               AND (?1 IS NULL OR tbl_name = ?1) \
+             ORDER BY type = 'trigger', name",
+            quote_identifier(&database)
+        ))
+        .bind(scope_table)
+        .fetch_all(pool)
+        .await
+        .map_err(query_error)?;
+
+        // Tables and views sort before the triggers that reference them
+        for (object_type, name, table_name, sql) in objects {
+            match object_type.as_str() {
+                "trigger" => {
+                    if let Some(table) = tree.table_mut(&database, &table_name) {
+                        let (timing, events) = trigger_shape(sql.as_deref().unwrap_or_default());
+                        table.triggers.push(TriggerInfo {
+                            name,
+                            timing,
+                            events,
+                            definition: sql,
+                        });
+                    }
+                }
+                object_type => {
+                    let kind = if object_type == "view" {
+                        TableKind::View
+                    } else {
+                        TableKind::Table
+                    };
+                    let mut table = TableInfo::new(name, kind, None);
+                    describe_table(pool, &database, &mut table).await?;
+                    tree.add_table(database.clone(), table);
+                }
+            }
+        }
+
+        catalog.databases.push(DatabaseInfo {
+            name: database,
+            schemas: tree.into_schemas(),
+        });
+    }
+
+    Ok(catalog)
+}
+
+/// Fill in a table's columns, keys and indexes
+async fn describe_table(pool: &SqlitePool, database: &str, table: &mut TableInfo) -> AppResult<()> {
+    let columns: Vec<ColumnRow> = sqlx::query_as(
+        "SELECT cid, name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(?1, ?2)",
+    )
+    .bind(&table.name)
+    .bind(database)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+
+    let mut primary_key: Vec<(i64, String)> = Vec::new();
+    for (cid, name, data_type, not_null, default, pk) in columns {
+        if pk > 0 {
+            primary_key.push((pk, name.clone()));
+        }
+        table.columns.push(TableColumn {
+            name,
+            ordinal: cid + 1,
+            data_type,
+            base_type: None,
+            nullable: !not_null,
+            default,
+            primary_key: false,
+            comment: None,
+        });
+    }
+    if !primary_key.is_empty() {
+        primary_key.sort();
+        table.constraints.push(ConstraintInfo {
+            name: format!("pk_{}", table.name),
+            kind: ConstraintKind::PrimaryKey,
+            columns: primary_key.into_iter().map(|(_, name)| name).collect(),
+            definition: None,
+        });
+    }
+
+    let indexes: Vec<(String, bool, String)> =
+        sqlx::query_as("SELECT name, \"unique\", origin FROM pragma_index_list(?1, ?2) ORDER BY name")
+            .bind(&table.name)
+            .bind(database)
+            .fetch_all(pool)
+            .await
+            .map_err(query_error)?;
+    for (name, unique, origin) in indexes {
+        let columns: Vec<Option<String>> =
+            sqlx::query_scalar("SELECT name FROM pragma_index_info(?1, ?2) ORDER BY seqno")
+                .bind(&name)
+                .bind(database)
+                .fetch_all(pool)
+                .await
+                .map_err(query_error)?;
+        let columns: Vec<String> = columns
+            .into_iter()
+            .map(|column| column.unwrap_or_else(|| "<expression>".to_string()))
+            .collect();
+
+        if origin == "u" {
+            table.constraints.push(ConstraintInfo {
+                name: name.clone(),
+                kind: ConstraintKind::Unique,
+                columns: columns.clone(),
+                definition: None,
+            });
+        }
+        table.indexes.push(IndexInfo {
+            name,
+            columns,
+            unique,
+            primary: origin == "pk",
+        });
+    }
+
+    let foreign_keys: Vec<ForeignKeyRow> = sqlx::query_as(
+        "SELECT id, \"from\", \"table\", \"to\", on_update, on_delete \
+         FROM pragma_foreign_key_list(?1, ?2) ORDER BY id, seq",
+    )
+    .bind(&table.name)
+    .bind(database)
+    .fetch_all(pool)
+    .await
+    .map_err(query_error)?;
+    let mut current_id = None;
+    for (id, column, referenced_table, referenced_column, on_update, on_delete) in foreign_keys {
+        if current_id != Some(id) {
+            current_id = Some(id);
+            table.foreign_keys.push(ForeignKeyInfo {
+                name: format!("fk_{}_{}", table.name, id),
+                columns: Vec::new(),
+                referenced_schema: database.to_string(),
+                referenced_table,
+                referenced_columns: Vec::new(),
+                on_update,
+                on_delete,
+            });
+        }
+        if let Some(key) = table.foreign_keys.last_mut() {
+            key.columns.push(column);
+            key.referenced_columns.extend(referenced_column);
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the timing and events from a `CREATE TRIGGER` statement
+fn trigger_shape(sql: &str) -> (String, Vec<String>) {
+    let mut timing = "BEFORE";
+    let mut events = Vec::new();
+    let mut previous = String::new();
+
+    for word in sql.split_whitespace() {
+        let word = word.to_ascii_uppercase();
+        match word.as_str() {
+            "ON" => break,
+            "AFTER" => timing = "AFTER",
+            "OF" if previous == "INSTEAD" => timing = "INSTEAD OF",
+            "INSERT" | "UPDATE" | "DELETE" if events.is_empty() => events.push(word.clone()),
+            _ => {}
+        }
+        previous = word;
+    }
+
+    (timing.to_string(), events)
+}
+
+/// Quote a schema name for use in a statement
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_introspects_tables_keys_and_triggers() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        for statement in [
+            "CREATE TABLE authors (id INTEGER PRIMARY KEY, email TEXT NOT NULL UNIQUE)",
+            "CREATE TABLE books (id INTEGER PRIMARY KEY, author_id INTEGER REFERENCES authors(id) ON DELETE CASCADE, title TEXT DEFAULT 'untitled')",
+            "CREATE INDEX books_title ON books (title)",
+            "CREATE VIEW titles AS SELECT title FROM books",
+            "CREATE TRIGGER books_audit AFTER UPDATE OF title ON books BEGIN SELECT 1; END",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        let catalog = introspect(&pool).await.unwrap();
+        let schema = &catalog.databases[0].schemas[0];
+        assert_eq!(catalog.databases[0].name, "main");
+        let names: Vec<&str> = schema.tables.iter().map(|table| table.name.as_str()).collect();
+        assert_eq!(names, vec!["authors", "books", "titles"]);
+
+        let authors = &schema.tables[0];
+        assert!(authors.columns[0].primary_key);
+        assert!(!authors.columns[1].nullable);
+        assert!(authors
+            .constraints
+            .iter()
+            .any(|constraint| constraint.kind == ConstraintKind::Unique
+                && constraint.columns == vec!["email".to_string()]));
+
+        let books = &schema.tables[1];
+        assert_eq!(books.columns[2].default.as_deref(), Some("'untitled'"));
+        assert_eq!(books.indexes[0].name, "books_title");
+        let key = &books.foreign_keys[0];
+        assert_eq!(key.referenced_table, "authors");
+        assert_eq!(key.columns, vec!["author_id".to_string()]);
+        assert_eq!(key.on_delete, "CASCADE");
+        assert_eq!(books.triggers[0].timing, "AFTER");
+        assert_eq!(books.triggers[0].events, vec!["UPDATE".to_string()]);
+
+        assert_eq!(schema.tables[2].kind, TableKind::View);
+        assert_eq!(schema.tables[2].columns[0].name, "title");
+
+        let books = table(&pool, "main", "books").await.unwrap().unwrap();
+        assert!(books.columns[0].primary_key);
+        assert_eq!(books.foreign_keys[0].referenced_table, "authors");
+        assert_eq!(books.triggers[0].name, "books_audit");
+        assert!(table(&pool, "main", "missing").await.unwrap().is_none());
+        assert!(table(&pool, "other", "books").await.unwrap().is_none());
+    }
+}
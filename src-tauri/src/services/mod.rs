@@ -3,6 +3,9 @@ pub mod storage;
 pub mod encryption;
 pub mod key_management;
 pub mod database;
+pub mod introspection;
+pub mod export;
+pub mod import;
 
 // Re-export storage types for convenience
 pub use storage::LocalStorage; 
\ No newline at end of file
@@ -1,9 +1,15 @@
 use crate::types::AppResult;
 use crate::services::encryption::{decrypt_string, encrypt_string};
-use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, Row, SqlitePool, Transaction};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::query::Query;
+use sqlx::sqlite::SqliteArguments;
+use sqlx::types::Json;
+use sqlx::{FromRow, QueryBuilder, Row, Sqlite, SqlitePool, Transaction};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
-use tracing::debug;
+use tracing::{debug, warn};
 use crate::error::{AppError, AppResult as ErrorAppResult, ErrorSeverity};
 use crate::error::categories::{
     DatabaseSubcategory, EncryptionSubcategory, ErrorCategory,
@@ -15,12 +21,23 @@ struct ConnectionRow {
     id: i64,
     connection_name: String,
     project_id: i64,
+    /// Kept as text so a row with a type this build does not know can be skipped
     db_type: String,
     encrypted_host: Vec<u8>,
     encrypted_port: Vec<u8>,
     encrypted_username: Vec<u8>,
     encrypted_password: Vec<u8>,
     encrypted_database: Option<Vec<u8>>,
+    statement_timeout_ms: Option<u32>,
+    read_only: bool,
+    encrypted_ssh_tunnel: Option<Vec<u8>>,
+    tls_mode: Option<TlsMode>,
+    tls_ca_cert_path: Option<String>,
+    tls_client_cert_path: Option<String>,
+    tls_client_key_path: Option<String>,
+    options: Option<Json<BTreeMap<String, String>>>,
+    server_info: Option<Json<ServerInfo>>,
+    server_checked_at: Option<i64>,
     created_at: i64,
     updated_at: i64,
 }
@@ -36,18 +53,121 @@ fn decrypt_blob_field(label: &str, blob: &[u8]) -> ErrorAppResult<String> {
     decrypt_string(s)
 }
 
+fn encrypt_ssh_tunnel(tunnel: Option<&SshTunnel>) -> ErrorAppResult<Option<String>> {
+    let Some(tunnel) = tunnel else {
+        return Ok(None);
+    };
+    let json = serde_json::to_string(tunnel).map_err(|e| {
+        AppError::new(
+            format!("Failed to serialize SSH tunnel settings: {e}"),
+            ErrorCategory::Encryption(EncryptionSubcategory::SerializationFailed),
+            ErrorSeverity::Error,
+        )
+    })?;
+    encrypt_string(&json).map(Some)
+}
+
+/// Bind the four TLS columns, all `NULL` when the connection keeps the driver defaults
+fn bind_tls<'q>(
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    tls: Option<&TlsSettings>,
+) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    query
+        .bind(tls.map(|tls| tls.mode))
+        .bind(tls.and_then(|tls| tls.ca_cert_path.clone()))
+        .bind(tls.and_then(|tls| tls.client_cert_path.clone()))
+        .bind(tls.and_then(|tls| tls.client_key_path.clone()))
+}
+
+/// Driver options as stored, `NULL` when there are none
+fn options_json(options: &BTreeMap<String, String>) -> Option<Json<&BTreeMap<String, String>>> {
+    (!options.is_empty()).then_some(Json(options))
+}
+
+fn decrypt_ssh_tunnel(blob: Option<&[u8]>) -> ErrorAppResult<Option<SshTunnel>> {
+    let Some(blob) = blob.filter(|blob| !blob.is_empty()) else {
+        return Ok(None);
+    };
+    let json = decrypt_blob_field("encrypted_ssh_tunnel", blob)?;
+    serde_json::from_str(&json).map(Some).map_err(|e| {
+        AppError::new(
+            format!("Failed to read SSH tunnel settings: {e}"),
+            ErrorCategory::Encryption(EncryptionSubcategory::DeserializationFailed),
+            ErrorSeverity::Error,
+        )
+    })
+}
+
+fn decrypt_row(row: ConnectionRow) -> ErrorAppResult<Connection> {
+    let db_type = row.db_type.parse::<DbType>().map_err(|message| {
+        AppError::new(
+            format!("Connection {}: {}", row.id, message),
+            ErrorCategory::Database(DatabaseSubcategory::InvalidData),
+            ErrorSeverity::Error,
+        )
+    })?;
+    let database = match row.encrypted_database.as_deref() {
+        Some(blob) if !blob.is_empty() => decrypt_blob_field("encrypted_database", blob)?,
+        _ => String::new(),
+    };
+    Ok(Connection {
+        id: row.id,
+        connection_name: row.connection_name,
+        project_id: row.project_id,
+        db_type,
+        host: decrypt_blob_field("encrypted_host", &row.encrypted_host)?,
+        port: decrypt_blob_field("encrypted_port", &row.encrypted_port)?,
+        username: decrypt_blob_field("encrypted_username", &row.encrypted_username)?,
+        password: decrypt_blob_field("encrypted_password", &row.encrypted_password)?,
+        database,
+        statement_timeout_ms: row.statement_timeout_ms,
+        read_only: row.read_only,
+        ssh_tunnel: decrypt_ssh_tunnel(row.encrypted_ssh_tunnel.as_deref())?,
+        tls: row.tls_mode.map(|mode| TlsSettings {
+            mode,
+            ca_cert_path: row.tls_ca_cert_path,
+            client_cert_path: row.tls_client_cert_path,
+            client_key_path: row.tls_client_key_path,
+        }),
+        options: row.options.map(|options| options.0).unwrap_or_default(),
+        server_info: row.server_info.map(|info| info.0),
+        server_checked_at: row.server_checked_at,
+        created_at: Some(row.created_at),
+        updated_at: Some(row.updated_at),
+    })
+}
+
 /// Represents a database connection in the application (decrypted for API use).
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Connection {
     pub id: i64,
     pub connection_name: String,
     pub project_id: i64,
-    pub db_type: String,
+    pub db_type: DbType,
     pub host: String,
     pub port: String,
     pub username: String,
     pub password: String,
     pub database: String,
+    /// Default statement timeout in milliseconds, `None` for no limit
+    pub statement_timeout_ms: Option<u32>,
+    /// Refuse writes: sessions are opened read-only where the engine allows it,
+    /// and statements that would write are rejected before they are sent
+    #[serde(default)]
+    pub read_only: bool,
+    /// Bastion host the connection is forwarded through, `None` to connect directly
+    pub ssh_tunnel: Option<SshTunnel>,
+    /// TLS settings, `None` to keep the driver defaults
+    pub tls: Option<TlsSettings>,
+    /// Extra driver options as URI query parameters, used by MongoDB, for SQL Server logins and for SQLite open modes
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+    /// What the last successful connection test learned about the server
+    #[serde(default)]
+    pub server_info: Option<ServerInfo>,
+    /// When `server_info` was recorded, in seconds since the epoch
+    #[serde(default)]
+    pub server_checked_at: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -60,12 +180,219 @@ pub struct NewConnection {
     pub connection_name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub project_id: Option<i64>,
-    pub db_type: String,
+    pub db_type: DbType,
     pub host: String,
     pub port: String,
     pub username: String,
     pub password: String,
     pub database: String,
+    #[serde(default)]
+    pub statement_timeout_ms: Option<u32>,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub ssh_tunnel: Option<SshTunnel>,
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+/// SSH bastion settings for a connection that is only reachable through a jump host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SshTunnel {
+    pub host: String,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    pub username: String,
+    pub auth: SshAuth,
+    /// Check the bastion's host key against `known_hosts_path` before logging in
+    #[serde(default = "default_verify_host_key")]
+    pub verify_host_key: bool,
+    /// `known_hosts` file to check against, `~/.ssh/known_hosts` when unset
+    #[serde(default)]
+    pub known_hosts_path: Option<String>,
+}
+
+/// How to log in to the bastion host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum SshAuth {
+    Password {
+        password: String,
+    },
+    PrivateKey {
+        private_key_path: String,
+        #[serde(default)]
+        passphrase: Option<String>,
+    },
+}
+
+/// The database engine a connection speaks to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum DbType {
+    Postgres,
+    MySql,
+    Sqlite,
+    MongoDb,
+    MsSql,
+    Redis,
+    ClickHouse,
+    DuckDb,
+}
+
+impl DbType {
+    /// The name the type is stored and sent to the frontend as
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Postgres => "postgres",
+            Self::MySql => "mysql",
+            Self::Sqlite => "sqlite",
+            Self::MongoDb => "mongodb",
+            Self::MsSql => "mssql",
+            Self::Redis => "redis",
+            Self::ClickHouse => "clickhouse",
+            Self::DuckDb => "duckdb",
+        }
+    }
+}
+
+impl FromStr for DbType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "postgres" => Ok(Self::Postgres),
+            "mysql" => Ok(Self::MySql),
+            "sqlite" => Ok(Self::Sqlite),
+            "mongodb" => Ok(Self::MongoDb),
+            "mssql" => Ok(Self::MsSql),
+            "redis" => Ok(Self::Redis),
+            "clickhouse" => Ok(Self::ClickHouse),
+            "duckdb" => Ok(Self::DuckDb),
+            other => Err(format!("Unknown database type '{}'", other)),
+        }
+    }
+}
+
+impl fmt::Display for DbType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How a connection negotiates TLS with its server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "kebab-case")]
+#[sqlx(rename_all = "kebab-case")]
+pub enum TlsMode {
+    /// Never use TLS
+    Disable,
+    /// Use TLS if the server offers it, without verifying the certificate
+    Prefer,
+    /// Always use TLS, without verifying the certificate
+    Require,
+    /// Always use TLS and check the certificate is signed by a trusted CA
+    VerifyCa,
+    /// Like `VerifyCa`, and also check the certificate matches the host name
+    VerifyFull,
+}
+
+/// Per-connection TLS settings; paths point at PEM files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsSettings {
+    pub mode: TlsMode,
+    /// CA bundle to verify the server against, the system roots when unset
+    #[serde(default)]
+    pub ca_cert_path: Option<String>,
+    #[serde(default)]
+    pub client_cert_path: Option<String>,
+    #[serde(default)]
+    pub client_key_path: Option<String>,
+}
+
+/// Server details read by a connection test; fields a server does not report are `None`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// Server product, e.g. `PostgreSQL` or `MariaDB`
+    pub product: String,
+    /// Short version number, e.g. `16.2`
+    pub version: String,
+    /// Full version banner as the server reports it
+    #[serde(default)]
+    pub version_string: Option<String>,
+    #[serde(default)]
+    pub current_user: Option<String>,
+    /// Schema or database unqualified names resolve in
+    #[serde(default)]
+    pub default_schema: Option<String>,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Whether the session cannot write, e.g. on a replica
+    #[serde(default)]
+    pub read_only: Option<bool>,
+}
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+fn default_verify_host_key() -> bool {
+    true
+}
+
+/// Changes to a saved connection; fields left as `None` keep their current value
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ConnectionUpdate {
+    pub connection_name: Option<String>,
+    pub db_type: Option<DbType>,
+    pub host: Option<String>,
+    pub port: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub database: Option<String>,
+    /// `Some(None)` clears the timeout
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub statement_timeout_ms: Option<Option<u32>>,
+    pub read_only: Option<bool>,
+    /// `Some(None)` removes the tunnel
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub ssh_tunnel: Option<Option<SshTunnel>>,
+    /// `Some(None)` goes back to the driver defaults
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub tls: Option<Option<TlsSettings>>,
+    /// Replaces all driver options
+    pub options: Option<BTreeMap<String, String>>,
+}
+
+impl ConnectionUpdate {
+    /// Whether the update changes where or how the connection reaches its server
+    #[must_use]
+    pub fn changes_target(&self) -> bool {
+        self.db_type.is_some()
+            || self.host.is_some()
+            || self.port.is_some()
+            || self.username.is_some()
+            || self.password.is_some()
+            || self.database.is_some()
+            || self.ssh_tunnel.is_some()
+            || self.tls.is_some()
+            || self.options.is_some()
+    }
+}
+
+/// Distinguishes a field explicitly set to `null` from one that was left out
+pub(crate) fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
 }
 
 pub struct ConnectionRepository {
@@ -82,27 +409,33 @@ impl ConnectionRepository {
         connection: &NewConnection,
         tx: Option<&mut Transaction<'_, sqlx::Sqlite>>,
     ) -> AppResult<i64> {
-        debug!("Creating new connection: {:?}", connection);
+        debug!("Creating new connection: {}", connection.connection_name);
         
         let query = sqlx::query(
             r#"
             INSERT INTO connections (
                 connection_name, project_id, db_type, 
                 encrypted_host, encrypted_port, encrypted_username, 
-                encrypted_password, encrypted_database
+                encrypted_password, encrypted_database, statement_timeout_ms,
+                read_only, encrypted_ssh_tunnel, tls_mode, tls_ca_cert_path,
+                tls_client_cert_path, tls_client_key_path, options
             )
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING id
             "#
         )
         .bind(&connection.connection_name)
         .bind(connection.project_id.expect("project_id is required for database insertion"))
-        .bind(&connection.db_type)
+        .bind(connection.db_type)
         .bind(encrypt_string(&connection.host)?)
         .bind(encrypt_string(&connection.port)?)
         .bind(encrypt_string(&connection.username)?)
         .bind(encrypt_string(&connection.password)?)
-        .bind(encrypt_string(&connection.database)?);
+        .bind(encrypt_string(&connection.database)?)
+        .bind(connection.statement_timeout_ms)
+        .bind(connection.read_only)
+        .bind(encrypt_ssh_tunnel(connection.ssh_tunnel.as_ref())?);
+        let query = bind_tls(query, connection.tls.as_ref()).bind(options_json(&connection.options));
 
         let result = if let Some(tx) = tx {
             query.fetch_one(&mut **tx).await
@@ -130,6 +463,16 @@ impl ConnectionRepository {
                 encrypted_username,
                 encrypted_password,
                 encrypted_database,
+                statement_timeout_ms,
+                read_only,
+                encrypted_ssh_tunnel,
+                tls_mode,
+                tls_ca_cert_path,
+                tls_client_cert_path,
+                tls_client_key_path,
+                options,
+                server_info,
+                server_checked_at,
                 created_at,
                 updated_at
             FROM connections
@@ -150,60 +493,407 @@ impl ConnectionRepository {
 
         let mut connections = Vec::with_capacity(rows.len());
         for row in rows {
-            let database = match row.encrypted_database.as_deref() {
-                Some(blob) if !blob.is_empty() => decrypt_blob_field("encrypted_database", blob)?,
-                _ => String::new(),
-            };
-            connections.push(Connection {
-                id: row.id,
-                connection_name: row.connection_name,
-                project_id: row.project_id,
-                db_type: row.db_type,
-                host: decrypt_blob_field("encrypted_host", &row.encrypted_host)?,
-                port: decrypt_blob_field("encrypted_port", &row.encrypted_port)?,
-                username: decrypt_blob_field("encrypted_username", &row.encrypted_username)?,
-                password: decrypt_blob_field("encrypted_password", &row.encrypted_password)?,
-                database,
-                created_at: Some(row.created_at),
-                updated_at: Some(row.updated_at),
-            });
+            if row.db_type.parse::<DbType>().is_err() {
+                warn!("Skipping connection {} with unknown database type '{}'", row.id, row.db_type);
+                continue;
+            }
+            connections.push(decrypt_row(row)?);
         }
 
         debug!("Found {} connections", connections.len());
         Ok(connections)
     }
 
+    /// Get a single connection by its ID, or `None` if it does not exist
+    pub async fn get_by_id(&self, id: i64) -> AppResult<Option<Connection>> {
+        debug!("Fetching connection: {}", id);
+
+        let row = sqlx::query_as::<_, ConnectionRow>(
+            r#"
+            SELECT
+                id,
+                connection_name,
+                project_id,
+                db_type,
+                encrypted_host,
+                encrypted_port,
+                encrypted_username,
+                encrypted_password,
+                encrypted_database,
+                statement_timeout_ms,
+                read_only,
+                encrypted_ssh_tunnel,
+                tls_mode,
+                tls_ca_cert_path,
+                tls_client_cert_path,
+                tls_client_key_path,
+                options,
+                server_info,
+                server_checked_at,
+                created_at,
+                updated_at
+            FROM connections
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(|e| {
+            AppError::new(
+                e.to_string(),
+                ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+                ErrorSeverity::Error,
+            )
+        })?;
+
+        match row {
+            Some(row) => Ok(Some(decrypt_row(row)?)),
+            None => Ok(None),
+        }
+    }
+
     pub async fn create_with_transaction(
         &self,
         connection: &NewConnection,
         tx: &mut Transaction<'_, sqlx::Sqlite>,
     ) -> AppResult<i64> {
-        debug!("Creating new connection: {:?}", connection);
+        debug!("Creating new connection: {}", connection.connection_name);
         
-        let result = sqlx::query(
+        let query = sqlx::query(
             r#"
             INSERT INTO connections (
                 connection_name, project_id, db_type, 
                 encrypted_host, encrypted_port, encrypted_username, 
-                encrypted_password, encrypted_database
+                encrypted_password, encrypted_database, statement_timeout_ms,
+                read_only, encrypted_ssh_tunnel, tls_mode, tls_ca_cert_path,
+                tls_client_cert_path, tls_client_key_path, options
             )
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING id
             "#
         )
         .bind(&connection.connection_name)
         .bind(connection.project_id.expect("project_id is required for database insertion"))
-        .bind(&connection.db_type)
+        .bind(connection.db_type)
         .bind(encrypt_string(&connection.host)?)
         .bind(encrypt_string(&connection.port)?)
         .bind(encrypt_string(&connection.username)?)
         .bind(encrypt_string(&connection.password)?)
         .bind(encrypt_string(&connection.database)?)
-        .fetch_one(&mut **tx)
-        .await?;
+        .bind(connection.statement_timeout_ms)
+        .bind(connection.read_only)
+        .bind(encrypt_ssh_tunnel(connection.ssh_tunnel.as_ref())?);
+        let result = bind_tls(query, connection.tls.as_ref())
+            .bind(options_json(&connection.options))
+            .fetch_one(&mut **tx)
+            .await?;
         
         let id = result.get(0);
         debug!("Created connection with ID: {}", id);
         Ok(id)
     }
-}
\ No newline at end of file
+
+    /// Apply changes to a saved connection, re-encrypting changed credentials
+    ///
+    /// Returns `false` if the connection does not exist.
+    pub async fn update(&self, id: i64, update: &ConnectionUpdate) -> AppResult<bool> {
+        debug!("Updating connection: {}", id);
+
+        let mut query = QueryBuilder::new("UPDATE connections SET updated_at = unixepoch()");
+        if let Some(name) = &update.connection_name {
+            query.push(", connection_name = ").push_bind(name.clone());
+        }
+        if let Some(db_type) = &update.db_type {
+            query.push(", db_type = ").push_bind(*db_type);
+        }
+        for (column, value) in [
+            ("encrypted_host", &update.host),
+            ("encrypted_port", &update.port),
+            ("encrypted_username", &update.username),
+            ("encrypted_password", &update.password),
+            ("encrypted_database", &update.database),
+        ] {
+            if let Some(value) = value {
+                query
+                    .push(format!(", {} = ", column))
+                    .push_bind(encrypt_string(value)?);
+            }
+        }
+        if let Some(timeout) = update.statement_timeout_ms {
+            query.push(", statement_timeout_ms = ").push_bind(timeout);
+        }
+        if let Some(read_only) = update.read_only {
+            query.push(", read_only = ").push_bind(read_only);
+        }
+        if let Some(tunnel) = &update.ssh_tunnel {
+            query
+                .push(", encrypted_ssh_tunnel = ")
+                .push_bind(encrypt_ssh_tunnel(tunnel.as_ref())?);
+        }
+        if let Some(tls) = &update.tls {
+            let tls = tls.as_ref();
+            query
+                .push(", tls_mode = ")
+                .push_bind(tls.map(|tls| tls.mode))
+                .push(", tls_ca_cert_path = ")
+                .push_bind(tls.and_then(|tls| tls.ca_cert_path.clone()))
+                .push(", tls_client_cert_path = ")
+                .push_bind(tls.and_then(|tls| tls.client_cert_path.clone()))
+                .push(", tls_client_key_path = ")
+                .push_bind(tls.and_then(|tls| tls.client_key_path.clone()));
+        }
+        if let Some(options) = &update.options {
+            query.push(", options = ").push_bind(options_json(options));
+        }
+        if update.changes_target() {
+            // What was learned about the old target no longer applies
+            query.push(", server_info = NULL, server_checked_at = NULL");
+        }
+        query.push(" WHERE id = ").push_bind(id);
+
+        let result = query.build().execute(&*self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record what a successful connection test learned about the server
+    ///
+    /// Returns `false` if the connection does not exist.
+    pub async fn set_server_info(&self, id: i64, server_info: &ServerInfo) -> AppResult<bool> {
+        debug!("Recording server info for connection: {}", id);
+
+        let result = sqlx::query(
+            "UPDATE connections SET server_info = ?, server_checked_at = unixepoch() WHERE id = ?",
+        )
+        .bind(Json(server_info))
+        .bind(id)
+        .execute(&*self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Copy a saved connection within its project
+    ///
+    /// The copy is named `name`, or the original name with " (copy)" appended.
+    /// Returns the new connection's ID, or `None` if the original does not exist.
+    pub async fn duplicate(&self, id: i64, name: Option<&str>) -> AppResult<Option<i64>> {
+        debug!("Duplicating connection: {}", id);
+
+        let row = sqlx::query(
+            r#"
+            INSERT INTO connections (
+                connection_name, project_id, db_type,
+                encrypted_host, encrypted_port, encrypted_username,
+                encrypted_password, encrypted_database, statement_timeout_ms,
+                read_only, encrypted_ssh_tunnel, tls_mode, tls_ca_cert_path,
+                tls_client_cert_path, tls_client_key_path, options,
+                server_info, server_checked_at
+            )
+            SELECT
+                COALESCE(?, connection_name || ' (copy)'), project_id, db_type,
+                encrypted_host, encrypted_port, encrypted_username,
+                encrypted_password, encrypted_database, statement_timeout_ms,
+                read_only, encrypted_ssh_tunnel, tls_mode, tls_ca_cert_path,
+                tls_client_cert_path, tls_client_key_path, options,
+                server_info, server_checked_at
+            FROM connections
+            WHERE id = ?
+            RETURNING id
+            "#,
+        )
+        .bind(name)
+        .bind(id)
+        .fetch_optional(&*self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    /// Delete a saved connection
+    ///
+    /// Returns `false` if the connection does not exist.
+    pub async fn delete(&self, id: i64) -> AppResult<bool> {
+        debug!("Deleting connection: {}", id);
+
+        let result = sqlx::query("DELETE FROM connections WHERE id = ?")
+            .bind(id)
+            .execute(&*self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn repository() -> (ConnectionRepository, i64) {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        let project_id: i64 = sqlx::query_scalar("INSERT INTO projects (name, user_id) VALUES ('p', 'u') RETURNING id")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        (ConnectionRepository::new(Arc::new(pool)), project_id)
+    }
+
+    fn new_connection(project_id: i64) -> NewConnection {
+        NewConnection {
+            connection_name: "warehouse".to_string(),
+            project_id: Some(project_id),
+            db_type: DbType::Postgres,
+            host: "db.internal".to_string(),
+            port: "5432".to_string(),
+            username: "analyst".to_string(),
+            password: "first secret".to_string(),
+            database: "sales".to_string(),
+            statement_timeout_ms: Some(30_000),
+            read_only: true,
+            ssh_tunnel: Some(SshTunnel {
+                host: "bastion".to_string(),
+                port: 2222,
+                username: "jump".to_string(),
+                auth: SshAuth::Password {
+                    password: "tunnel secret".to_string(),
+                },
+                verify_host_key: false,
+                known_hosts_path: None,
+            }),
+            tls: Some(TlsSettings {
+                mode: TlsMode::VerifyFull,
+                ca_cert_path: Some("/certs/ca.pem".to_string()),
+                client_cert_path: None,
+                client_key_path: None,
+            }),
+            options: BTreeMap::from([("application_name".to_string(), "dewey".to_string())]),
+        }
+    }
+
+    async fn encrypted_password(repo: &ConnectionRepository, id: i64) -> Vec<u8> {
+        sqlx::query_scalar("SELECT encrypted_password FROM connections WHERE id = ?")
+            .bind(id)
+            .fetch_one(&*repo.pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_update_re_encrypts_credentials_and_bumps_updated_at() {
+        let (repo, project_id) = repository().await;
+        let id = repo.create(&new_connection(project_id), None).await.unwrap();
+        sqlx::query("UPDATE connections SET updated_at = 0 WHERE id = ?")
+            .bind(id)
+            .execute(&*repo.pool)
+            .await
+            .unwrap();
+        let before = encrypted_password(&repo, id).await;
+
+        let update = ConnectionUpdate {
+            password: Some("second secret".to_string()),
+            statement_timeout_ms: Some(None),
+            ..ConnectionUpdate::default()
+        };
+        assert!(repo.update(id, &update).await.unwrap());
+        assert!(!repo.update(id + 1, &update).await.unwrap());
+
+        let after = encrypted_password(&repo, id).await;
+        assert_ne!(after, before);
+        assert!(!String::from_utf8_lossy(&after).contains("second secret"));
+        let connection = repo.get_by_id(id).await.unwrap().unwrap();
+        assert_eq!(connection.password, "second secret");
+        assert_eq!(connection.username, "analyst");
+        assert_eq!(connection.statement_timeout_ms, None);
+        assert!(connection.updated_at.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_copies_every_setting() {
+        let (repo, project_id) = repository().await;
+        let id = repo.create(&new_connection(project_id), None).await.unwrap();
+        let server_info = ServerInfo {
+            product: "PostgreSQL".to_string(),
+            version: "16.2".to_string(),
+            version_string: None,
+            current_user: Some("analyst".to_string()),
+            default_schema: Some("public".to_string()),
+            timezone: None,
+            encoding: None,
+            read_only: Some(false),
+        };
+        repo.set_server_info(id, &server_info).await.unwrap();
+
+        let copy_id = repo.duplicate(id, None).await.unwrap().unwrap();
+        let named_id = repo.duplicate(id, Some("replica")).await.unwrap().unwrap();
+        assert_eq!(repo.duplicate(named_id + 1, None).await.unwrap(), None);
+
+        let original = repo.get_by_id(id).await.unwrap().unwrap();
+        let copy = repo.get_by_id(copy_id).await.unwrap().unwrap();
+        assert_eq!(copy.connection_name, "warehouse (copy)");
+        assert_eq!(repo.get_by_id(named_id).await.unwrap().unwrap().connection_name, "replica");
+        assert_eq!(copy.project_id, original.project_id);
+        assert_eq!(copy.db_type, original.db_type);
+        assert_eq!(
+            (&copy.host, &copy.port, &copy.username, &copy.password, &copy.database),
+            (&original.host, &original.port, &original.username, &original.password, &original.database)
+        );
+        assert_eq!(copy.statement_timeout_ms, Some(30_000));
+        assert!(copy.read_only);
+        assert_eq!(copy.ssh_tunnel, original.ssh_tunnel);
+        assert_eq!(copy.tls, original.tls);
+        assert_eq!(copy.options, original.options);
+        assert_eq!(copy.server_info, Some(server_info));
+    }
+
+    #[tokio::test]
+    async fn test_delete_removes_only_that_connection() {
+        let (repo, project_id) = repository().await;
+        let id = repo.create(&new_connection(project_id), None).await.unwrap();
+        let other = repo.create(&new_connection(project_id), None).await.unwrap();
+
+        assert!(repo.delete(id).await.unwrap());
+        assert!(!repo.delete(id).await.unwrap());
+        assert!(repo.get_by_id(id).await.unwrap().is_none());
+        assert_eq!(repo.get_by_project(project_id).await.unwrap()[0].id, other);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_database_types_are_rejected_and_skipped() {
+        let (repo, project_id) = repository().await;
+        let insert = "INSERT INTO connections (connection_name, project_id, db_type, encrypted_host, encrypted_port, \
+                      encrypted_username, encrypted_password) VALUES ('legacy', ?, ?, x'', x'', x'', x'') RETURNING id";
+
+        let rejected = sqlx::query_scalar::<_, i64>(insert)
+            .bind(project_id)
+            .bind("oracle")
+            .fetch_one(&*repo.pool)
+            .await;
+        assert!(rejected.unwrap_err().to_string().contains("unknown database type"));
+
+        // A row written before the check existed
+        sqlx::query("DROP TRIGGER connections_db_type_insert").execute(&*repo.pool).await.unwrap();
+        let legacy: i64 = sqlx::query_scalar(insert)
+            .bind(project_id)
+            .bind("oracle")
+            .fetch_one(&*repo.pool)
+            .await
+            .unwrap();
+
+        assert!(repo.get_by_project(project_id).await.unwrap().is_empty());
+        assert_eq!(
+            repo.get_by_id(legacy).await.unwrap_err(),
+            ErrorCategory::Database(DatabaseSubcategory::InvalidData)
+        );
+    }
+
+    #[test]
+    fn test_database_types_parse_from_their_stored_names() {
+        for db_type in [DbType::Postgres, DbType::MySql, DbType::MsSql, DbType::DuckDb] {
+            assert_eq!(db_type.as_str().parse::<DbType>(), Ok(db_type));
+        }
+        assert!("Postgres".parse::<DbType>().is_err());
+    }
+}
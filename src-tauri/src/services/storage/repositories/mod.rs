@@ -4,4 +4,6 @@
 /// access to database entities and tables.
 pub mod projects; 
 pub mod connections;
-pub mod onboarding;
\ No newline at end of file
+pub mod onboarding;
+pub mod query_history;
+pub mod saved_queries;
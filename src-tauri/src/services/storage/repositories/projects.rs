@@ -15,6 +15,8 @@ pub struct Project {
     pub created_at: i64,
     pub updated_at: i64,
     pub icon_path: Option<String>,
+    /// When the project was archived, `None` for active projects
+    pub archived_at: Option<i64>,
 }
 
 /// Repository for handling project operations in the database
@@ -77,20 +79,25 @@ impl ProjectRepository {
 
     /// Get all projects for a user
     ///
+    /// # Arguments
+    /// * `user_id` - The ID of the user
+    /// * `include_archived` - Whether archived projects are included
+    ///
     /// # Errors
     /// Returns an error if there was a problem executing the query
-    pub async fn get_by_user(&self, user_id: &str) -> AppResult<Vec<Project>> {
+    pub async fn get_by_user(&self, user_id: &str, include_archived: bool) -> AppResult<Vec<Project>> {
         debug!("Fetching projects for user: {}", user_id);
         
         let projects = sqlx::query_as::<_, Project>(
             r"
-            SELECT id, name, user_id, created_at, updated_at, icon_path
+            SELECT id, name, user_id, created_at, updated_at, icon_path, archived_at
             FROM projects
-            WHERE user_id = ?
+            WHERE user_id = ? AND (? OR archived_at IS NULL)
             ORDER BY created_at ASC
             "
         )
         .bind(user_id)
+        .bind(include_archived)
         .fetch_all(&*self.pool)
         .await
         .map_err(|e| AppError::new(
@@ -138,4 +145,225 @@ impl ProjectRepository {
         debug!("Project created successfully");
         Ok(id)
     }
-} 
\ No newline at end of file
+
+    /// Get a single project by its ID
+    ///
+    /// # Errors
+    /// Returns an error if there was a problem executing the query
+    pub async fn get_by_id(&self, id: i64) -> AppResult<Option<Project>> {
+        debug!("Fetching project: {}", id);
+
+        sqlx::query_as::<_, Project>(
+            r"
+            SELECT id, name, user_id, created_at, updated_at, icon_path, archived_at
+            FROM projects
+            WHERE id = ?
+            "
+        )
+        .bind(id)
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(|e| AppError::new(
+            e.to_string(),
+            ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+            ErrorSeverity::Error,
+        ).into())
+    }
+
+    /// Rename a project
+    ///
+    /// Returns `false` if the project does not exist.
+    ///
+    /// # Errors
+    /// Returns an error if there was a problem executing the query
+    pub async fn rename(&self, id: i64, name: &str) -> AppResult<bool> {
+        debug!("Renaming project {} to '{}'", id, name);
+
+        self.execute_update(
+            sqlx::query("UPDATE projects SET name = ?, updated_at = unixepoch() WHERE id = ?")
+                .bind(name)
+                .bind(id),
+        )
+        .await
+    }
+
+    /// Point a project at a different icon file
+    ///
+    /// Returns `false` if the project does not exist.
+    ///
+    /// # Errors
+    /// Returns an error if there was a problem executing the query
+    pub async fn set_icon_path(&self, id: i64, icon_path: &str) -> AppResult<bool> {
+        debug!("Changing icon of project {}", id);
+
+        self.execute_update(
+            sqlx::query("UPDATE projects SET icon_path = ?, updated_at = unixepoch() WHERE id = ?")
+                .bind(icon_path)
+                .bind(id),
+        )
+        .await
+    }
+
+    /// Archive or restore a project
+    ///
+    /// Returns `false` if the project does not exist.
+    ///
+    /// # Errors
+    /// Returns an error if there was a problem executing the query
+    pub async fn set_archived(&self, id: i64, archived: bool) -> AppResult<bool> {
+        debug!("Setting archived = {} for project {}", archived, id);
+
+        self.execute_update(
+            sqlx::query(
+                r"
+                UPDATE projects
+                SET archived_at = CASE WHEN ? THEN COALESCE(archived_at, unixepoch()) END,
+                    updated_at = unixepoch()
+                WHERE id = ?
+                "
+            )
+            .bind(archived)
+            .bind(id),
+        )
+        .await
+    }
+
+    /// Delete a project; its connections are removed with it
+    ///
+    /// Returns `false` if the project does not exist.
+    ///
+    /// # Errors
+    /// Returns an error if there was a problem executing the query
+    pub async fn delete(&self, id: i64) -> AppResult<bool> {
+        debug!("Deleting project: {}", id);
+
+        self.execute_update(sqlx::query("DELETE FROM projects WHERE id = ?").bind(id))
+            .await
+    }
+
+    async fn execute_update<'q>(
+        &self,
+        query: sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    ) -> AppResult<bool> {
+        let result = query
+            .execute(&*self.pool)
+            .await
+            .map_err(|e| AppError::new(
+                e.to_string(),
+                ErrorCategory::Database(DatabaseSubcategory::QueryFailed),
+                ErrorSeverity::Error,
+            ))?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::Executor;
+
+    /// Version of the migration that ties connections to their project
+    const PROJECT_FK_MIGRATION: i64 = 20240601000000;
+
+    async fn memory_pool() -> SqlitePool {
+        sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    /// Add a connection row directly, without encrypting its credentials
+    async fn add_connection(pool: &SqlitePool, project_id: i64, name: &str) {
+        sqlx::query(
+            "INSERT INTO connections (connection_name, project_id, db_type, encrypted_host, encrypted_port, \
+             encrypted_username, encrypted_password) VALUES (?, ?, 'sqlite', x'00', x'00', x'00', x'00')",
+        )
+        .bind(name)
+        .bind(project_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    async fn connection_names(pool: &SqlitePool) -> Vec<String> {
+        sqlx::query_scalar("SELECT connection_name FROM connections ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_project_changes_are_saved() {
+        let pool = memory_pool().await;
+        sqlx::migrate!().run(&pool).await.unwrap();
+        let repo = ProjectRepository::new(Arc::new(pool));
+        let id = repo.create("Draft", "user", None, None).await.unwrap();
+
+        assert!(repo.rename(id, "Launch").await.unwrap());
+        assert!(repo.set_icon_path(id, "/icons/launch.png").await.unwrap());
+        assert!(repo.set_archived(id, true).await.unwrap());
+        let project = repo.get_by_id(id).await.unwrap().unwrap();
+        assert_eq!(project.name, "Launch");
+        assert_eq!(project.icon_path.as_deref(), Some("/icons/launch.png"));
+        let archived_at = project.archived_at.unwrap();
+
+        // Archiving again keeps the original time; archived projects are listed only on request
+        assert!(repo.set_archived(id, true).await.unwrap());
+        assert_eq!(repo.get_by_id(id).await.unwrap().unwrap().archived_at, Some(archived_at));
+        assert!(repo.get_by_user("user", false).await.unwrap().is_empty());
+        assert_eq!(repo.get_by_user("user", true).await.unwrap().len(), 1);
+
+        assert!(repo.set_archived(id, false).await.unwrap());
+        assert_eq!(repo.get_by_id(id).await.unwrap().unwrap().archived_at, None);
+        assert!(!repo.rename(id + 1, "Missing").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_deleting_a_project_removes_its_connections() {
+        let pool = memory_pool().await;
+        sqlx::migrate!().run(&pool).await.unwrap();
+        let repo = ProjectRepository::new(Arc::new(pool.clone()));
+        let doomed = repo.create("Doomed", "user", None, None).await.unwrap();
+        let kept = repo.create("Kept", "user", None, None).await.unwrap();
+        add_connection(&pool, doomed, "doomed db").await;
+        add_connection(&pool, kept, "kept db").await;
+
+        assert!(repo.delete(doomed).await.unwrap());
+        assert!(!repo.delete(doomed).await.unwrap());
+        assert_eq!(connection_names(&pool).await, ["kept db"]);
+    }
+
+    #[tokio::test]
+    async fn test_project_key_migration_keeps_valid_connections() {
+        let pool = memory_pool().await;
+        let migrator = sqlx::migrate!();
+        for migration in migrator.iter().filter(|migration| migration.version < PROJECT_FK_MIGRATION) {
+            pool.execute(&*migration.sql).await.unwrap();
+        }
+        sqlx::query("INSERT INTO projects (id, name, user_id) VALUES (1, 'Live', 'user')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        add_connection(&pool, 1, "valid").await;
+        add_connection(&pool, 99, "orphan").await;
+        sqlx::query("UPDATE connections SET statement_timeout_ms = 500, created_at = 10 WHERE project_id = 1")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let migration = migrator
+            .iter()
+            .find(|migration| migration.version == PROJECT_FK_MIGRATION)
+            .unwrap();
+        pool.execute(&*migration.sql).await.unwrap();
+
+        let rows: Vec<(i64, String, i64, Option<i64>, i64)> = sqlx::query_as(
+            "SELECT id, connection_name, project_id, statement_timeout_ms, created_at FROM connections",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(rows, [(1, "valid".to_string(), 1, Some(500), 10)]);
+    }
+}
@@ -0,0 +1,309 @@
+use crate::types::AppResult;
+use crate::error::ErrorCategory;
+use crate::services::database::query::DbValue;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Matches the `query_history` table
+#[derive(Debug, FromRow)]
+struct HistoryRow {
+    id: i64,
+    connection_id: i64,
+    statement: String,
+    params: Option<Json<Vec<DbValue>>>,
+    started_at: i64,
+    duration_ms: i64,
+    rows_affected: Option<i64>,
+    rows_returned: Option<i64>,
+    success: bool,
+    error_category: Option<Json<ErrorCategory>>,
+    error_message: Option<String>,
+}
+
+impl From<HistoryRow> for HistoryEntry {
+    fn from(row: HistoryRow) -> Self {
+        Self {
+            id: row.id,
+            connection_id: row.connection_id,
+            statement: row.statement,
+            params: row.params.map(|params| params.0).unwrap_or_default(),
+            started_at: row.started_at,
+            duration_ms: row.duration_ms,
+            rows_affected: row.rows_affected,
+            rows_returned: row.rows_returned,
+            success: row.success,
+            error_category: row.error_category.map(|category| category.0),
+            error_message: row.error_message,
+        }
+    }
+}
+
+/// A statement that was run on a saved connection
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub connection_id: i64,
+    pub statement: String,
+    /// Parameters bound to the statement, in order
+    pub params: Vec<DbValue>,
+    /// When the statement started, in seconds since the Unix epoch
+    pub started_at: i64,
+    pub duration_ms: i64,
+    pub rows_affected: Option<i64>,
+    /// Rows sent back, or `None` if the statement failed before returning any
+    pub rows_returned: Option<i64>,
+    pub success: bool,
+    /// What kind of error the statement failed with
+    pub error_category: Option<ErrorCategory>,
+    pub error_message: Option<String>,
+}
+
+/// A statement to add to the history
+#[derive(Debug, Clone)]
+pub struct NewHistoryEntry {
+    pub connection_id: i64,
+    pub statement: String,
+    pub params: Vec<DbValue>,
+    pub started_at: i64,
+    pub duration_ms: i64,
+    pub rows_affected: Option<i64>,
+    pub rows_returned: Option<i64>,
+    /// The error the statement failed with, or `None` if it succeeded
+    pub error: Option<(ErrorCategory, String)>,
+}
+
+/// Narrows which history entries are listed
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HistoryFilter {
+    /// Only statements run on this connection
+    pub connection_id: Option<i64>,
+    /// Words that must all appear in the statement; the last may be a prefix
+    pub text: Option<String>,
+    /// Only statements started at or after this time, in seconds since the Unix epoch
+    pub started_after: Option<i64>,
+    /// Only statements started before this time, in seconds since the Unix epoch
+    pub started_before: Option<i64>,
+    /// Only entries older than this one, to page through long histories
+    pub before_id: Option<i64>,
+}
+
+/// Turn free text into an FTS5 query that matches every word
+///
+/// Each word is quoted so punctuation and FTS5 operators are searched for literally,
+/// and matched as a prefix so a search can be typed incrementally. Words without
+/// letters or digits are not indexed and are skipped.
+/// Returns `None` if there is nothing to search for.
+fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Repository for the statements run on saved connections
+pub struct QueryHistoryRepository {
+    pool: Arc<SqlitePool>,
+}
+
+impl QueryHistoryRepository {
+    pub fn new(pool: Arc<SqlitePool>) -> Self {
+        Self { pool }
+    }
+
+    /// Add a statement to the history
+    ///
+    /// Returns the new entry's ID.
+    pub async fn record(&self, entry: &NewHistoryEntry) -> AppResult<i64> {
+        let id = sqlx::query_scalar(
+            r#"
+            INSERT INTO query_history (
+                connection_id, statement, params, started_at, duration_ms,
+                rows_affected, rows_returned, success, error_category, error_message
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING id
+            "#,
+        )
+        .bind(entry.connection_id)
+        .bind(&entry.statement)
+        .bind((!entry.params.is_empty()).then_some(Json(&entry.params)))
+        .bind(entry.started_at)
+        .bind(entry.duration_ms)
+        .bind(entry.rows_affected)
+        .bind(entry.rows_returned)
+        .bind(entry.error.is_none())
+        .bind(entry.error.as_ref().map(|(category, _)| Json(category)))
+        .bind(entry.error.as_ref().map(|(_, message)| message))
+        .fetch_one(&*self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Get a history entry by its ID
+    pub async fn get(&self, id: i64) -> AppResult<Option<HistoryEntry>> {
+        let row = sqlx::query_as::<_, HistoryRow>("SELECT * FROM query_history WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&*self.pool)
+            .await?;
+
+        Ok(row.map(HistoryEntry::from))
+    }
+
+    /// List history entries matching `filter`, most recent first
+    pub async fn list(&self, filter: &HistoryFilter, limit: u32) -> AppResult<Vec<HistoryEntry>> {
+        debug!("Listing query history: {:?}", filter);
+
+        let mut query = QueryBuilder::<Sqlite>::new("SELECT h.* FROM query_history h");
+        let text = filter.text.as_deref().and_then(fts_query);
+        if text.is_some() {
+            query.push(" JOIN query_history_fts f ON f.rowid = h.id");
+        }
+        query.push(" WHERE 1 = 1");
+        if let Some(text) = text {
+            query.push(" AND f.query_history_fts MATCH ").push_bind(text);
+        }
+        if let Some(connection_id) = filter.connection_id {
+            query.push(" AND h.connection_id = ").push_bind(connection_id);
+        }
+        if let Some(started_after) = filter.started_after {
+            query.push(" AND h.started_at >= ").push_bind(started_after);
+        }
+        if let Some(started_before) = filter.started_before {
+            query.push(" AND h.started_at < ").push_bind(started_before);
+        }
+        if let Some(before_id) = filter.before_id {
+            query.push(" AND h.id < ").push_bind(before_id);
+        }
+        query.push(" ORDER BY h.id DESC LIMIT ").push_bind(limit);
+
+        let rows = query
+            .build_query_as::<HistoryRow>()
+            .fetch_all(&*self.pool)
+            .await?;
+        Ok(rows.into_iter().map(HistoryEntry::from).collect())
+    }
+
+    /// Delete history entries
+    ///
+    /// Only entries for `connection_id` are deleted when it is set, and only those started
+    /// before `started_before` when that is set. Returns the number of entries deleted.
+    pub async fn purge(&self, connection_id: Option<i64>, started_before: Option<i64>) -> AppResult<u64> {
+        debug!("Purging query history for connection: {:?}", connection_id);
+
+        let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM query_history WHERE 1 = 1");
+        if let Some(connection_id) = connection_id {
+            query.push(" AND connection_id = ").push_bind(connection_id);
+        }
+        if let Some(started_before) = started_before {
+            query.push(" AND started_at < ").push_bind(started_before);
+        }
+
+        let result = query.build().execute(&*self.pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Delete entries older than `max_age_secs` and all but the newest `keep_latest` per connection
+    ///
+    /// Returns the number of entries deleted.
+    pub async fn apply_retention(&self, max_age_secs: i64, keep_latest: u32) -> AppResult<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM query_history
+            WHERE started_at < unixepoch() - ?
+               OR id IN (
+                   SELECT id FROM (
+                       SELECT id, ROW_NUMBER() OVER (PARTITION BY connection_id ORDER BY id DESC) AS position
+                       FROM query_history
+                   )
+                   WHERE position > ?
+               )
+            "#,
+        )
+        .bind(max_age_secs)
+        .bind(keep_latest)
+        .execute(&*self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::categories::DatabaseSubcategory;
+    use sqlx::sqlite::SqliteConnectOptions;
+    use std::str::FromStr;
+
+    fn entry(connection_id: i64, statement: &str, started_at: i64) -> NewHistoryEntry {
+        NewHistoryEntry {
+            connection_id,
+            statement: statement.to_string(),
+            params: Vec::new(),
+            started_at,
+            duration_ms: 5,
+            rows_affected: Some(0),
+            rows_returned: Some(1),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_fts_query_quotes_every_word() {
+        assert_eq!(fts_query("  = "), None);
+        assert_eq!(fts_query("orders"), Some("\"orders\"*".to_string()));
+        assert_eq!(
+            fts_query("select o.id = NOT \"x\""),
+            Some("\"select\"* \"o.id\"* \"NOT\"* \"\"\"x\"\"\"*".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_history_is_searchable_and_purged() {
+        // Entries reference connections that this test does not create
+        let options = SqliteConnectOptions::from_str("sqlite::memory:")
+            .unwrap()
+            .foreign_keys(false);
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        let repo = QueryHistoryRepository::new(Arc::new(pool));
+
+        let first = repo.record(&entry(1, "SELECT * FROM orders WHERE id = 7", 100)).await.unwrap();
+        repo.record(&entry(1, "SELECT * FROM customers", 200)).await.unwrap();
+        let mut failed = entry(2, "DELETE FROM orders", 300);
+        failed.error = Some((ErrorCategory::Database(DatabaseSubcategory::QueryFailed), "nope".into()));
+        repo.record(&failed).await.unwrap();
+
+        let search = |text: &str, connection_id: Option<i64>| HistoryFilter {
+            connection_id,
+            text: Some(text.to_string()),
+            ..HistoryFilter::default()
+        };
+        let found = repo.list(&search("ord", None), 10).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(!found[0].success);
+        assert_eq!(found[0].error_message.as_deref(), Some("nope"));
+        assert_eq!(repo.list(&search("orders", Some(1)), 10).await.unwrap()[0].id, first);
+        // Quotes and operators are searched for as words, and `OR` is a prefix of `orders`
+        let literal = repo.list(&search("\"id = 7\" OR", None), 10).await.unwrap();
+        assert_eq!(literal.iter().map(|entry| entry.id).collect::<Vec<_>>(), vec![first]);
+
+        let older = HistoryFilter { before_id: Some(first + 1), ..HistoryFilter::default() };
+        assert_eq!(repo.list(&older, 10).await.unwrap().len(), 1);
+
+        assert_eq!(repo.purge(Some(1), Some(150)).await.unwrap(), 1);
+        assert!(repo.get(first).await.unwrap().is_none());
+        assert!(repo.list(&search("orders", Some(1)), 10).await.unwrap().is_empty());
+        assert_eq!(repo.apply_retention(i64::MAX / 2, 0).await.unwrap(), 2);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::types::AppResult;
+use crate::error::categories::{DatabaseSubcategory, ErrorCategory, ValidationSubcategory};
+use crate::error::{AppError, AppResult as ErrorAppResult, ErrorSeverity};
+use crate::services::database::query::DbValue;
+use super::connections::deserialize_some;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::debug;
+
+/// A value the statement of a saved query is run with
+///
+/// Parameters are listed in the order of the engine's own placeholders in the statement
+/// (`$1` for Postgres, `?` for MySQL and SQLite). Parameters that share a name are bound
+/// to the same value, so a value can fill several `?` placeholders.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryParameter {
+    pub name: String,
+    pub description: Option<String>,
+    /// Bound when the query is run without a value for this parameter
+    pub default: Option<DbValue>,
+}
+
+/// A query saved to a project
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SavedQuery {
+    pub id: i64,
+    pub project_id: i64,
+    /// Slash-separated path of the folder holding the query, empty at the top level
+    pub folder: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub statement: String,
+    #[sqlx(json)]
+    pub tags: Vec<String>,
+    /// The connection the query runs on unless another is chosen
+    pub connection_id: Option<i64>,
+    #[sqlx(json)]
+    pub parameters: Vec<QueryParameter>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl SavedQuery {
+    /// The values to bind to the statement's placeholders, in order
+    ///
+    /// Each parameter takes its value from `values` by name, or else its default.
+    ///
+    /// # Errors
+    /// Returns a `Validation` error if a parameter has no value, or `values` names a
+    /// parameter the query does not have
+    pub fn bind(&self, values: &BTreeMap<String, DbValue>) -> ErrorAppResult<Vec<DbValue>> {
+        if let Some(unknown) = values.keys().find(|name| !self.parameters.iter().any(|p| p.name == **name)) {
+            return Err(AppError::new(
+                format!("Saved query '{}' has no parameter named '{}'", self.name, unknown),
+                ErrorCategory::Validation(ValidationSubcategory::InvalidFormat),
+                ErrorSeverity::Error,
+            ));
+        }
+        self.parameters
+            .iter()
+            .map(|parameter| {
+                values
+                    .get(&parameter.name)
+                    .or(parameter.default.as_ref())
+                    .cloned()
+                    .ok_or_else(|| {
+                        AppError::new(
+                            format!("No value given for parameter '{}'", parameter.name),
+                            ErrorCategory::Validation(ValidationSubcategory::MissingRequired),
+                            ErrorSeverity::Error,
+                        )
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A query to save to a project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewSavedQuery {
+    pub project_id: i64,
+    #[serde(default)]
+    pub folder: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub statement: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub connection_id: Option<i64>,
+    #[serde(default)]
+    pub parameters: Vec<QueryParameter>,
+}
+
+/// Changes to a saved query; fields left as `None` keep their current value
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SavedQueryUpdate {
+    pub folder: Option<String>,
+    pub name: Option<String>,
+    /// `Some(None)` clears the description
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub description: Option<Option<String>>,
+    pub statement: Option<String>,
+    /// Replaces all tags
+    pub tags: Option<Vec<String>>,
+    /// `Some(None)` clears the default connection
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub connection_id: Option<Option<i64>>,
+    /// Replaces all parameters
+    pub parameters: Option<Vec<QueryParameter>>,
+}
+
+/// Narrows which of a project's saved queries are listed
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SavedQueryFilter {
+    /// Only queries in this folder or the folders below it
+    pub folder: Option<String>,
+    /// Only queries with this tag
+    pub tag: Option<String>,
+}
+
+/// A unique constraint failure means another query in the folder has the name
+fn name_taken(error: sqlx::Error) -> ErrorCategory {
+    match &error {
+        sqlx::Error::Database(e) if e.is_unique_violation() => {
+            ErrorCategory::Database(DatabaseSubcategory::ConstraintViolation)
+        }
+        _ => error.into(),
+    }
+}
+
+/// Repository for the queries saved to projects
+pub struct SavedQueryRepository {
+    pool: Arc<SqlitePool>,
+}
+
+impl SavedQueryRepository {
+    pub fn new(pool: Arc<SqlitePool>) -> Self {
+        Self { pool }
+    }
+
+    /// Save a query to its project
+    ///
+    /// Returns the new query's ID.
+    ///
+    /// # Errors
+    /// Returns a `Database(ConstraintViolation)` error if the folder already holds a query
+    /// with the same name
+    pub async fn create(&self, query: &NewSavedQuery) -> AppResult<i64> {
+        debug!("Saving query '{}' to project {}", query.name, query.project_id);
+
+        let id = sqlx::query_scalar(
+            r#"
+            INSERT INTO saved_queries (
+                project_id, folder, name, description, statement, tags, connection_id, parameters
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING id
+            "#,
+        )
+        .bind(query.project_id)
+        .bind(&query.folder)
+        .bind(&query.name)
+        .bind(&query.description)
+        .bind(&query.statement)
+        .bind(Json(&query.tags))
+        .bind(query.connection_id)
+        .bind(Json(&query.parameters))
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(name_taken)?;
+
+        Ok(id)
+    }
+
+    pub async fn get_by_id(&self, id: i64) -> AppResult<Option<SavedQuery>> {
+        let query = sqlx::query_as::<_, SavedQuery>("SELECT * FROM saved_queries WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&*self.pool)
+            .await?;
+
+        Ok(query)
+    }
+
+    /// List a project's saved queries matching `filter`, by folder and then name
+    pub async fn get_by_project(&self, project_id: i64, filter: &SavedQueryFilter) -> AppResult<Vec<SavedQuery>> {
+        debug!("Fetching saved queries for project: {}", project_id);
+
+        let mut query = QueryBuilder::<Sqlite>::new("SELECT * FROM saved_queries WHERE project_id = ");
+        query.push_bind(project_id);
+        if let Some(folder) = filter.folder.as_deref().filter(|folder| !folder.is_empty()) {
+            query
+                .push(" AND (folder = ")
+                .push_bind(folder.to_string())
+                .push(" OR substr(folder, 1, ")
+                .push_bind(folder.chars().count() as i64 + 1)
+                .push(") = ")
+                .push_bind(format!("{}/", folder))
+                .push(")");
+        }
+        if let Some(tag) = &filter.tag {
+            query
+                .push(" AND EXISTS (SELECT 1 FROM json_each(tags) WHERE value = ")
+                .push_bind(tag.clone())
+                .push(")");
+        }
+        query.push(" ORDER BY folder, name");
+
+        let queries = query
+            .build_query_as::<SavedQuery>()
+            .fetch_all(&*self.pool)
+            .await?;
+        Ok(queries)
+    }
+
+    /// Apply changes to a saved query
+    ///
+    /// Returns `false` if the query does not exist.
+    ///
+    /// # Errors
+    /// Returns a `Database(ConstraintViolation)` error if the query would take the name of
+    /// another query in its folder
+    pub async fn update(&self, id: i64, update: &SavedQueryUpdate) -> AppResult<bool> {
+        debug!("Updating saved query: {}", id);
+
+        let mut query = QueryBuilder::<Sqlite>::new("UPDATE saved_queries SET updated_at = unixepoch()");
+        if let Some(folder) = &update.folder {
+            query.push(", folder = ").push_bind(folder.clone());
+        }
+        if let Some(name) = &update.name {
+            query.push(", name = ").push_bind(name.clone());
+        }
+        if let Some(description) = &update.description {
+            query.push(", description = ").push_bind(description.clone());
+        }
+        if let Some(statement) = &update.statement {
+            query.push(", statement = ").push_bind(statement.clone());
+        }
+        if let Some(tags) = &update.tags {
+            query.push(", tags = ").push_bind(Json(tags.clone()));
+        }
+        if let Some(connection_id) = update.connection_id {
+            query.push(", connection_id = ").push_bind(connection_id);
+        }
+        if let Some(parameters) = &update.parameters {
+            query.push(", parameters = ").push_bind(Json(parameters.clone()));
+        }
+        query.push(" WHERE id = ").push_bind(id);
+
+        let result = query.build().execute(&*self.pool).await.map_err(name_taken)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete a saved query
+    ///
+    /// Returns `false` if the query does not exist.
+    pub async fn delete(&self, id: i64) -> AppResult<bool> {
+        debug!("Deleting saved query: {}", id);
+
+        let result = sqlx::query("DELETE FROM saved_queries WHERE id = ?")
+            .bind(id)
+            .execute(&*self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameter(name: &str, default: Option<DbValue>) -> QueryParameter {
+        QueryParameter {
+            name: name.to_string(),
+            description: None,
+            default,
+        }
+    }
+
+    #[test]
+    fn test_bind_fills_placeholders_by_name() {
+        let query = SavedQuery {
+            id: 1,
+            project_id: 1,
+            folder: String::new(),
+            name: "Orders in range".to_string(),
+            description: None,
+            statement: "SELECT * FROM orders WHERE placed_at >= ? AND placed_at < ? AND status = ?".to_string(),
+            tags: Vec::new(),
+            connection_id: None,
+            parameters: vec![
+                parameter("from", None),
+                parameter("to", None),
+                parameter("status", Some(DbValue::Text("open".to_string()))),
+            ],
+            created_at: 0,
+            updated_at: 0,
+        };
+
+        let mut values = BTreeMap::from([
+            ("from".to_string(), DbValue::Int(1)),
+            ("to".to_string(), DbValue::Int(2)),
+        ]);
+        assert_eq!(
+            query.bind(&values).unwrap(),
+            vec![DbValue::Int(1), DbValue::Int(2), DbValue::Text("open".to_string())]
+        );
+
+        values.insert("status".to_string(), DbValue::Null);
+        assert_eq!(query.bind(&values).unwrap()[2], DbValue::Null);
+
+        values.remove("to");
+        assert!(query.bind(&values).unwrap_err().message.contains("'to'"));
+
+        values.insert("too".to_string(), DbValue::Int(2));
+        assert!(query.bind(&values).unwrap_err().message.contains("'too'"));
+    }
+}
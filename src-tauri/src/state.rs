@@ -6,13 +6,17 @@
 
 use sqlx::SqlitePool;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::types::AppResult;
 use crate::services::storage::LocalStorage;
+use crate::services::database::cursor::QueryRegistry;
+use crate::services::database::pool::ConnectionManager;
+use crate::services::storage::repositories::query_history::QueryHistoryRepository;
 use crate::utils;
 use crate::constants;
 
-use tracing::{info, error};
+use tracing::{info, error, warn};
 
 /// Global application state that can be shared with Tauri command handlers
 /// 
@@ -22,6 +26,10 @@ use tracing::{info, error};
 pub struct AppState {
     /// Database connection pool for SQLite
     pub db: Arc<SqlitePool>,
+    /// Open sessions to the user's saved database connections
+    pub connection_manager: Arc<ConnectionManager>,
+    /// Running statements, cancellable by handle
+    pub queries: Arc<QueryRegistry>,
 }
 
 /// Initialize the application state by setting up the database
@@ -56,8 +64,30 @@ pub async fn initialize_app_state() -> AppResult<AppState> {
             e
         })?;
 
+    // Drop query history that has outlived the retention policy
+    let history_repo = QueryHistoryRepository::new(storage.pool());
+    match history_repo
+        .apply_retention(
+            constants::history::RETENTION_DAYS * 24 * 60 * 60,
+            constants::history::MAX_ENTRIES_PER_CONNECTION,
+        )
+        .await
+    {
+        Ok(purged) if purged > 0 => info!("Purged {} expired query history entries", purged),
+        Ok(_) => {}
+        Err(e) => warn!("Failed to purge expired query history: {}", e),
+    }
+
+    // Start the session registry and its idle reaper
+    let connection_manager = Arc::new(ConnectionManager::new(
+        Duration::from_secs(constants::sessions::IDLE_TIMEOUT_SECS),
+    ));
+    connection_manager.spawn_reaper(Duration::from_secs(constants::sessions::REAPER_INTERVAL_SECS));
+
     // Create app state for dependency injection
     Ok(AppState {
         db: storage.pool(),
+        connection_manager,
+        queries: Arc::new(QueryRegistry::new()),
     })
 }
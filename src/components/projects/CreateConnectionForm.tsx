@@ -38,6 +38,7 @@ export default function CreateConnectionForm() {
                             password: "",
                             database: "",
                             sqliteType: undefined,
+                            sqliteMode: undefined,
                         });
                         return next;
                     });
@@ -1,8 +1,8 @@
-import { SiMysql, SiPostgresql, SiSqlite, SiMongodb } from "@icons-pack/react-simple-icons";
+import { SiMysql, SiPostgresql, SiSqlite, SiMongodb, SiRedis, SiClickhouse, SiDuckdb } from "@icons-pack/react-simple-icons";
 import { Card } from "../../ui/card";
 import { ValidatedFormField } from "../../ui/form-field";
 import { TabsContent } from "../../ui/tabs";
-import { Check, Server, Loader2 } from "lucide-react";
+import { Check, Database, Server, Loader2 } from "lucide-react";
 import { cn } from "@/lib/utils";
 import {
     createProjectConnectionDetailFields,
@@ -40,6 +40,30 @@ const databaseTypes = [
         description: "Connect to a MongoDB database",
         icon: () => <SiMongodb className="h-5 w-5 text-primary" />,
     },
+    {
+        id: "mssql",
+        name: "SQL Server",
+        description: "Connect to a Microsoft SQL Server or Azure SQL database",
+        icon: () => <Database className="h-5 w-5 text-primary" />,
+    },
+    {
+        id: "redis",
+        name: "Redis",
+        description: "Connect to a Redis or Valkey server",
+        icon: () => <SiRedis className="h-5 w-5 text-primary" />,
+    },
+    {
+        id: "clickhouse",
+        name: "ClickHouse",
+        description: "Connect to a ClickHouse server over HTTP(S)",
+        icon: () => <SiClickhouse className="h-5 w-5 text-primary" />,
+    },
+    {
+        id: "duckdb",
+        name: "DuckDB",
+        description: "Open a DuckDB database or query a Parquet, CSV or JSON file",
+        icon: () => <SiDuckdb className="h-5 w-5 text-primary" />,
+    },
     {
         id: "sqlite",
         name: "SQLite",
@@ -48,11 +72,37 @@ const databaseTypes = [
     },
 ];
 
+type SqliteMode = "rw" | "ro" | "rwc" | "memory";
+
+const sqliteModes: { id: SqliteMode; name: string; description: string }[] = [
+    {
+        id: "rw",
+        name: "Read-write",
+        description: "Opens an existing SQLite database file. A missing file is reported, never created.",
+    },
+    {
+        id: "ro",
+        name: "Read-only",
+        description: "Opens an existing SQLite database file without ever writing to it.",
+    },
+    {
+        id: "rwc",
+        name: "Create if missing",
+        description: "Opens the SQLite database file, creating it on first connect if it doesn't exist. Its directory must already exist.",
+    },
+    {
+        id: "memory",
+        name: "In memory",
+        description: "Opens an empty database that lives in memory until the connection is closed.",
+    },
+];
+
 export default function ConnectionDetailsTab() {
     const { form } = useCreateProjectContext();
     const row = useWatch({ control: form.control, name: [...createProjectConnectionDetailFields] }) ?? [];
     const databaseType = row[0] ?? "";
     const sqliteType = row[6] || "file";
+    const sqliteMode = row[7] || "rw";
     const { testConnection, isLoading, cancelTest } = useTestConnection();
     const isLoadingRef = useRef(isLoading);
     isLoadingRef.current = isLoading;
@@ -97,6 +147,7 @@ export default function ConnectionDetailsTab() {
                             form.setValue("password", "", o);
                             form.setValue("database", "", o);
                             form.setValue("sqliteType", type.id === "sqlite" ? "file" : undefined, o);
+                            form.setValue("sqliteMode", type.id === "sqlite" ? "rw" : undefined, o);
                             form.clearErrors([...connectionFormFieldsToReset, "databaseType"]);
                         }}
                     >
@@ -179,23 +230,56 @@ export default function ConnectionDetailsTab() {
 
                     {sqliteType === "file" && (
                         <div className="grid grid-cols-1 gap-4">
-                            <ValidatedFormField
-                                name="database"
-                                label="Database File Path"
-                                className="col-span-1"
-                                inputProps={{
-                                    placeholder: "/path/to/database.sqlite"
-                                }}
-                            />
+                            <RadioGroup
+                                value={sqliteMode}
+                                onValueChange={(value: SqliteMode) =>
+                                    form.setValue("sqliteMode", value, { shouldDirty: true, shouldValidate: false })
+                                }
+                            >
+                                <div className="flex flex-wrap items-center gap-6">
+                                    {sqliteModes.map((mode) => (
+                                        <div key={mode.id} className="flex items-center space-x-2">
+                                            <RadioGroupItem value={mode.id} id={`sqlite-mode-${mode.id}`} />
+                                            <Label htmlFor={`sqlite-mode-${mode.id}`}>{mode.name}</Label>
+                                        </div>
+                                    ))}
+                                </div>
+                            </RadioGroup>
+                            {sqliteMode !== "memory" && (
+                                <ValidatedFormField
+                                    name="database"
+                                    label="Database File Path"
+                                    className="col-span-1"
+                                    inputProps={{
+                                        placeholder: "/path/to/database.sqlite"
+                                    }}
+                                />
+                            )}
                             <p className="text-sm text-muted-foreground">
-                                Enter the path to your SQLite database file. If the file doesn't exist, it will be created.
+                                {sqliteModes.find((mode) => mode.id === sqliteMode)?.description}
                             </p>
                         </div>
                     )}
                 </div>
             )}
 
-            {databaseType &&
+            {databaseType === "duckdb" && (
+                <div className="grid grid-cols-1 gap-4">
+                    <ValidatedFormField
+                        name="database"
+                        label="File Path"
+                        className="col-span-1"
+                        inputProps={{
+                            placeholder: "/path/to/database.duckdb"
+                        }}
+                    />
+                    <p className="text-sm text-muted-foreground">
+                        Enter the path to a DuckDB database, or to a Parquet, CSV or JSON file to query it as a view.
+                    </p>
+                </div>
+            )}
+
+            {databaseType && databaseType !== "duckdb" &&
                 (databaseType !== "sqlite" || sqliteType === "hosted") && (
                 <div className="grid grid-cols-1 md:grid-cols-2 gap-4">
                     <ValidatedFormField
@@ -33,7 +33,7 @@ export default function ConnectionStringTab({
     const databaseType = useWatch({ control: form.control, name: "databaseType" }) ?? "";
     const sqliteType = useWatch({ control: form.control, name: "sqliteType" }) || "file";
     const showServerCredentials =
-        Boolean(databaseType) && (databaseType !== "sqlite" || sqliteType === "hosted");
+        Boolean(databaseType) && databaseType !== "duckdb" && (databaseType !== "sqlite" || sqliteType === "hosted");
     const [connectionString, setConnectionString] = useState("");
     const debouncedConnectionString = useDebounce(connectionString);
     const { handleError } = useErrorHandler();
@@ -7,6 +7,7 @@ const baseConnectionSchema = z.object({
   connectionName: z.string(),
   databaseType: z.string(),
   sqliteType: z.enum(["file", "hosted"]).optional(),
+  sqliteMode: z.enum(["rw", "ro", "rwc", "memory"]).optional(),
   host: z.string(),
   port: z.string(),
   username: z.string(),
@@ -22,6 +23,7 @@ const completeConnectionSchema = z
     connectionName: z.string().min(1, "Connection name is required"),
     databaseType: z.string().min(1, "Database type is required"),
     sqliteType: z.enum(["file", "hosted"]).optional(),
+    sqliteMode: z.enum(["rw", "ro", "rwc", "memory"]).optional(),
     database: z.string().min(1, "Database path/name is required"),
     host: z.string(),
     port: z.string(),
@@ -37,8 +39,15 @@ const completeConnectionSchema = z
     username: data.username,
     password: data.password,
     database: data.database,
+    options: sqliteOptions(data),
   }))
 
+/** SQLite open mode as a connection option; read-write is the backend default. */
+function sqliteOptions(data: { databaseType: string; sqliteType?: string; sqliteMode?: string }) {
+  const isSqliteFile = data.databaseType === "sqlite" && data.sqliteType === "file"
+  return isSqliteFile && data.sqliteMode && data.sqliteMode !== "rw" ? { mode: data.sqliteMode } : {}
+}
+
 function isNonEmpty(v: unknown): boolean {
   return v !== undefined && v !== null && String(v).trim() !== ""
 }
@@ -64,6 +73,7 @@ function pickConnectionFields(data: ConnectionFields & Record<string, unknown>):
     connectionName: data.connectionName,
     databaseType: data.databaseType,
     sqliteType: data.sqliteType,
+    sqliteMode: data.sqliteMode,
     host: data.host,
     port: data.port,
     username: data.username,
@@ -114,6 +124,7 @@ function validateConnectionDetails(data: ConnectionFields, ctx: z.RefinementCtx)
       return
     }
     if (data.sqliteType === "file") {
+      if (data.sqliteMode === "memory") return
       requirePresent(ctx, data, [
         { key: "database", message: "Database file path is required" },
       ])
@@ -123,6 +134,13 @@ function validateConnectionDetails(data: ConnectionFields, ctx: z.RefinementCtx)
     return
   }
 
+  if (data.databaseType === "duckdb") {
+    requirePresent(ctx, data, [
+      { key: "database", message: "File path is required" },
+    ])
+    return
+  }
+
   requirePresent(ctx, data, NETWORK_FIELD_RULES)
 }
 
@@ -162,6 +180,7 @@ export const createProjectConnectionDetailFields = [
   "password",
   "database",
   "sqliteType",
+  "sqliteMode",
 ] as const satisfies ReadonlyArray<keyof CreateProjectFormData>
 
 export const validateAndTransformConnection = (data: CreateProjectFormData) => {
@@ -170,6 +189,15 @@ export const validateAndTransformConnection = (data: CreateProjectFormData) => {
 
   let payload: CreateProjectFormData = { ...data }
   if (data.databaseType === "sqlite" && data.sqliteType === "file") {
+    payload = {
+      ...payload,
+      host: "localhost",
+      port: "0",
+      username: "",
+      password: "",
+      database: data.sqliteMode === "memory" ? ":memory:" : data.database,
+    }
+  } else if (data.databaseType === "duckdb") {
     payload = {
       ...payload,
       host: "localhost",
@@ -200,6 +228,7 @@ export const CreateProjectProvider = ({ children }: { children: React.ReactNode
       connectionName: "",
       databaseType: "",
       sqliteType: "file",
+      sqliteMode: "rw",
       host: "",
       port: "",
       username: "",
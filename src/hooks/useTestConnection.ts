@@ -8,6 +8,7 @@ import { useToast } from '@/hooks/use-toast';
 type FormValues = {
     databaseType?: string;
     sqliteType?: string;
+    sqliteMode?: string;
     host?: string;
     port?: string;
     username?: string;
@@ -15,6 +16,32 @@ type FormValues = {
     database?: string;
 };
 
+type DiagnosticStep = {
+    stage: 'tunnel' | 'dns' | 'tcp' | 'tls' | 'authentication' | 'server' | 'database';
+    status: 'passed' | 'warning' | 'skipped' | 'failed';
+    detail: string;
+    duration_ms: number | null;
+    category: Record<string, string> | null;
+};
+
+export type ServerInfo = {
+    product: string;
+    version: string;
+    version_string: string | null;
+    current_user: string | null;
+    default_schema: string | null;
+    timezone: string | null;
+    encoding: string | null;
+    read_only: boolean | null;
+};
+
+export type ConnectionReport = {
+    success: boolean;
+    steps: DiagnosticStep[];
+    server: ServerInfo | null;
+    error: unknown;
+};
+
 export function useTestConnection() {
     const [isLoading, setIsLoading] = useState(false);
     const abortControllerRef = useRef<AbortController | null>(null);
@@ -53,16 +80,21 @@ export function useTestConnection() {
             });
 
             // Race between the connection test and abort signal
-            await Promise.race([
-                invoke('test_connection', params),
+            const report = await Promise.race([
+                invoke<ConnectionReport>('test_connection', params),
                 abortPromise
-            ]);
+            ]) as ConnectionReport;
 
-            if (!signal.aborted) {
-                showToast('Connection successful!', 'success');
-                return true;
+            if (signal.aborted) {
+                return false;
             }
-            return false;
+            if (!report.success) {
+                // The report's error names the layer that failed
+                await handleError(report.error);
+                return false;
+            }
+            showToast('Connection successful!', 'success');
+            return true;
         } catch (error) {
             let msg = '';
             if (error instanceof Error) msg = error.message;
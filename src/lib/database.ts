@@ -4,21 +4,25 @@
 export function prepareConnectionTestParams(values: {
     databaseType?: string;
     sqliteType?: string;
+    sqliteMode?: string;
     host?: string;
     port?: string;
     username?: string;
     password?: string;
     database?: string;
 }) {
-    const isFileSqlite = values.databaseType === "sqlite" && values.sqliteType === "file";
+    const isSqliteFile = values.databaseType === "sqlite" && values.sqliteType === "file";
+    const isLocalFile = isSqliteFile || values.databaseType === "duckdb";
+    const sqliteMode = isSqliteFile ? values.sqliteMode || "rw" : "rw";
 
     return {
         dbType: values.databaseType || "",
-        host: isFileSqlite ? "localhost" : (values.host || ""),
+        host: isLocalFile ? "localhost" : (values.host || ""),
         // Tauri expects strings for Rust `String` args; number inputs must not break IPC deserialization.
-        port: isFileSqlite ? "0" : String(values.port ?? ""),
-        username: isFileSqlite ? "" : (values.username || ""),
-        password: isFileSqlite ? "" : (values.password || ""),
-        database: values.database || ""
+        port: isLocalFile ? "0" : String(values.port ?? ""),
+        username: isLocalFile ? "" : (values.username || ""),
+        password: isLocalFile ? "" : (values.password || ""),
+        database: sqliteMode === "memory" ? ":memory:" : (values.database || ""),
+        options: sqliteMode === "rw" ? {} : { mode: sqliteMode }
     };
 } 
\ No newline at end of file
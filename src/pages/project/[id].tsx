@@ -2,7 +2,8 @@ import { useParams } from 'react-router-dom';
 import { useGetProjectsQuery, useGetProjectConnectionsQuery } from '@/store/api/projects.api';
 import { LoadingSpinner } from '@/components/ui';
 import { useAuth } from '@/hooks/useAuth';
-import { SiMysql, SiPostgresql, SiSqlite, SiMongodb } from "@icons-pack/react-simple-icons";
+import { SiMysql, SiPostgresql, SiSqlite, SiMongodb, SiRedis, SiClickhouse, SiDuckdb } from "@icons-pack/react-simple-icons";
+import { Database } from "lucide-react";
 
 const DatabaseIcon = ({ type }: { type: string }) => {
   switch (type.toLowerCase()) {
@@ -14,6 +15,14 @@ const DatabaseIcon = ({ type }: { type: string }) => {
       return <SiMongodb className="h-5 w-5" />;
     case 'sqlite':
       return <SiSqlite className="h-5 w-5" />;
+    case 'duckdb':
+      return <SiDuckdb className="h-5 w-5" />;
+    case 'redis':
+      return <SiRedis className="h-5 w-5" />;
+    case 'clickhouse':
+      return <SiClickhouse className="h-5 w-5" />;
+    case 'mssql':
+      return <Database className="h-5 w-5" />;
     default:
       return null;
   }
@@ -1,6 +1,16 @@
+export type DbType =
+    | "postgres"
+    | "mysql"
+    | "sqlite"
+    | "mongodb"
+    | "mssql"
+    | "redis"
+    | "clickhouse"
+    | "duckdb";
+
 export interface Connection {
     connection_name: string;
-    db_type: string;
+    db_type: DbType;
     host: string;
     port: string;
     username: string;